@@ -0,0 +1,61 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[allow(dead_code)]
+#[path = "../src/quantile_brute.rs"]
+mod quantile_brute;
+#[allow(dead_code)]
+#[path = "../src/quantile_tdigest.rs"]
+mod quantile_tdigest;
+
+use quantile_brute::BruteQuantileState;
+use quantile_tdigest::TDigestQuantileState;
+
+const QUANTILES: [f64; 9] = [0.1, 0.3, 0.5, 0.8, 0.9, 0.95, 0.99, 0.999, 1.0];
+
+/// Latency-like samples: mostly around a second with a long tail.
+fn samples(n: usize) -> Vec<f64> {
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    (0..n)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let u = (state >> 11) as f64 / (1u64 << 53) as f64;
+            -(1.0 - u).ln()
+        })
+        .collect()
+}
+
+// One aggregate per (block, key) in the analyzer: insert every node's sample, then read each
+// reported percentile.
+fn bench_aggregate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("aggregate");
+
+    for n in [200, 2000, 100_000].iter() {
+        let values = samples(*n);
+        group.bench_with_input(format!("brute/n={}", n), &values, |b, values| {
+            b.iter(|| {
+                let mut state = BruteQuantileState::new();
+                for &v in values {
+                    state.insert(black_box(v));
+                }
+                QUANTILES.map(|q| state.quantile(q))
+            });
+        });
+        group.bench_with_input(format!("tdigest/n={}", n), &values, |b, values| {
+            b.iter(|| {
+                let mut state = TDigestQuantileState::new(values.len(), 0.1, 0.1);
+                for &v in values {
+                    state.insert(black_box(v));
+                }
+                state.merge();
+                QUANTILES.map(|q| state.quantile(q))
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_aggregate);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+//! A blocks.log read the way `io_utils::load_host_log` reads one: deserialized, explained if it
+//! fails to, and normalized. Host logs are multi-gigabyte files from machines of a test run, so
+//! none of this may panic or hang on any input.
+
+#![no_main]
+#![allow(dead_code)]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/block_latencies.rs"]
+mod block_latencies;
+#[path = "../../src/config.rs"]
+mod config;
+#[path = "../../src/model.rs"]
+mod model;
+#[path = "../../src/quantile.rs"]
+mod quantile;
+#[path = "../../src/quantile_brute.rs"]
+mod quantile_brute;
+#[path = "../../src/quantile_tdigest.rs"]
+mod quantile_tdigest;
+#[path = "../../src/schema_check.rs"]
+mod schema_check;
+#[path = "../../src/tx_sketch.rs"]
+mod tx_sketch;
+#[path = "../../src/warnings.rs"]
+mod warnings;
+
+use model::HostBlocksLog;
+
+fuzz_target!(|data: &[u8]| {
+    let mut host: HostBlocksLog = match serde_json::from_slice(data) {
+        Ok(host) => host,
+        Err(e) => {
+            let _ = schema_check::explain_parse_error(data, e).to_string();
+            return;
+        }
+    };
+    if host.normalize().is_err() {
+        return;
+    }
+    for block in host.blocks.values() {
+        for (_, samples) in block.latencies.iter() {
+            std::hint::black_box(samples);
+        }
+    }
+});
@@ -2,7 +2,13 @@ use ethereum_types::H256;
 use std::cmp::Ordering;
 use std::collections::{BTreeSet, HashMap, HashSet};
 
-use crate::model::{AnalysisData, BlockScalars, NodePercentile, TxAnalysis};
+use crate::config::{
+    EVENT_STAGES, RESTART_GAP_FACTOR, RESTART_GAP_MIN_SECS, SIZE_SERIES_WINDOW, STALE_REFEREE_AGE,
+};
+use crate::model::{
+    AnalysisData, BlockInfo, BlockRowValues, BlockScalars, DagMetrics, NodePercentile, SizeBucket,
+    StageWaterfall, TimestampGap, TxAnalysis, TxReach, TxRowValues, TxTimestamps,
+};
 
 fn collect_tx_node_percentiles(latencies: &[f64]) -> HashMap<NodePercentile, f64> {
     let pick = |q: f64| -> f64 {
@@ -20,7 +26,7 @@ fn collect_tx_node_percentiles(latencies: &[f64]) -> HashMap<NodePercentile, f64
         latencies[lo] + (latencies[hi] - latencies[lo]) * w
     };
     let sum: f64 = latencies.iter().sum();
-    let avg = (sum / (latencies.len() as f64) * 100.0).round() / 100.0;
+    let avg = sum / (latencies.len() as f64);
 
     let mut out = HashMap::new();
     for p in NodePercentile::all_in_order() {
@@ -41,9 +47,32 @@ fn min_recv_and_latency(values: &[f64], baseline: f64) -> Vec<f64> {
     latencies
 }
 
+/// Node percentiles of a tx's timestamps since `baseline`; approximate for a sketch.
+fn tx_node_percentiles(timestamps: &TxTimestamps, baseline: f64) -> HashMap<NodePercentile, f64> {
+    let sketch = match timestamps {
+        TxTimestamps::Full(values) => {
+            return collect_tx_node_percentiles(&min_recv_and_latency(values, baseline))
+        }
+        TxTimestamps::Sketch(sketch) => sketch,
+    };
+    NodePercentile::all_in_order()
+        .iter()
+        .map(|p| {
+            let v = match p {
+                NodePercentile::Min => sketch.min(),
+                NodePercentile::Max => sketch.max(),
+                NodePercentile::Avg => sketch.mean(),
+                _ => sketch.quantile(p.q().unwrap()),
+            };
+            (*p, v - baseline)
+        })
+        .collect()
+}
+
 pub fn analyze_txs(data: &AnalysisData) -> TxAnalysis {
     let mut missing_tx = 0usize;
     let mut unpacked_tx = 0usize;
+    let mut repacked_tx = 0usize;
     let mut result = TxAnalysis::default();
     let mut best: Option<(H256, f64)> = None;
 
@@ -58,22 +87,29 @@ pub fn analyze_txs(data: &AnalysisData) -> TxAnalysis {
             continue;
         }
 
-        let min_recv = tx.received.iter().copied().fold(f64::INFINITY, f64::min);
-        let min_packed = tx.packed.iter().copied().fold(f64::INFINITY, f64::min);
+        let min_recv = tx.received.min();
+        let min_packed = tx.packed.min();
+        result.packings.push(tx.packed.len() as f64);
+        if tx.packed.len() > 1 {
+            repacked_tx += 1;
+            result.repack_spread.push(tx.packed.max() - min_packed);
+        }
         let latency = min_packed - min_recv;
         result.min_tx_packed_to_block_latency.push(latency);
 
         match best {
             None => best = Some((*h, latency)),
-            Some((_, cur)) if latency > cur => best = Some((*h, latency)),
+            // Ties go to the smallest hash, whatever order the txs are visited in.
+            Some((best_hash, cur)) if latency > cur || (latency == cur && *h < best_hash) => {
+                best = Some((*h, latency))
+            }
             _ => {}
         }
 
         if !tx.ready.is_empty() {
-            let min_ready = tx.ready.iter().copied().fold(f64::INFINITY, f64::min);
             result
                 .min_tx_to_ready_pool_latency
-                .push(min_ready - min_recv);
+                .push(tx.ready.min() - min_recv);
         }
     }
 
@@ -82,11 +118,88 @@ pub fn analyze_txs(data: &AnalysisData) -> TxAnalysis {
         missing_tx
     );
     println!("Unpacked tx count {}", unpacked_tx);
+    if !result.packings.is_empty() {
+        println!(
+            "Multiply packed tx count {} (avg packings {:.2})",
+            repacked_tx,
+            result.packings.iter().sum::<f64>() / result.packings.len() as f64
+        );
+    }
     println!("Total tx count {}", data.txs.len());
+    result.missing_tx = missing_tx;
     result.slowest_packed_hash = best.map(|(h, _)| h);
     result
 }
 
+/// Nodes reached by every tx, and receptions missed by every host. Unlike the removed tx
+/// count, this tells a few hosts missing most txs from most hosts missing a few.
+pub fn build_tx_reach(data: &AnalysisData, top: usize) -> TxReach {
+    let mut reach = TxReach::default();
+    let mut by_reach: Vec<(usize, H256)> = Vec::with_capacity(data.txs.len());
+    for (h, tx) in &data.txs {
+        let nodes = tx.received.len();
+        *reach.histogram.entry(nodes).or_insert(0) += 1;
+        reach.per_tx.push(nodes as f64);
+        by_reach.push((nodes, *h));
+    }
+    by_reach.sort_unstable();
+    reach.least_propagated = by_reach
+        .into_iter()
+        .take(top)
+        .map(|(nodes, h)| (h, nodes))
+        .collect();
+
+    let tx_count = data.txs.len();
+    reach.host_missing_pct = data
+        .hosts
+        .iter()
+        .zip(&data.host_tx_receptions)
+        .filter(|(_, r)| r.nodes > 0 && tx_count > 0)
+        .map(|(host, r)| {
+            let expected = (r.nodes * tx_count) as f64;
+            let missing = (expected - r.receptions as f64).max(0.0) / expected * 100.0;
+            (host.clone(), missing)
+        })
+        .collect();
+    reach
+        .host_missing_pct
+        .sort_by(|(ha, a), (hb, b)| b.total_cmp(a).then_with(|| ha.cmp(hb)));
+    reach
+}
+
+/// Prints the histogram of `build_tx_reach`, and up to `top` of the hosts missing the most.
+pub fn print_tx_reach(reach: &TxReach, node_count: usize, top: usize) {
+    let total: usize = reach.histogram.values().sum();
+    println!("Tx count by nodes reached (of {} nodes)", node_count);
+    for (nodes, count) in &reach.histogram {
+        println!(
+            "  {:>6} nodes: {:>8} txs ({:.2}%)",
+            nodes,
+            count,
+            *count as f64 / total as f64 * 100.0
+        );
+    }
+    let missing_hosts: Vec<_> = reach
+        .host_missing_pct
+        .iter()
+        .filter(|(_, p)| *p > 0.0)
+        .collect();
+    println!(
+        "{} of {} hosts missed some tx receptions",
+        missing_hosts.len(),
+        reach.host_missing_pct.len()
+    );
+    for (host, pct) in missing_hosts.into_iter().take(top) {
+        println!("  {} missed {:.2}%", host, pct);
+    }
+    if !reach.least_propagated.is_empty() {
+        println!("Least propagated txs");
+        for (h, nodes) in &reach.least_propagated {
+            println!("  {:#x} reached {} nodes", h, nodes);
+        }
+    }
+}
+
 fn should_require_90pct(k: &str, is_default: bool, pivot_keys: &HashSet<&'static str>) -> bool {
     if is_default {
         pivot_keys.contains(k)
@@ -99,8 +212,19 @@ pub fn build_block_row_values(
     data: &AnalysisData,
     default_keys: &HashSet<&'static str>,
     pivot_keys: &HashSet<&'static str>,
-) -> (HashMap<String, Vec<f64>>, BTreeSet<String>) {
-    let mut row_values: HashMap<String, Vec<f64>> = HashMap::new();
+) -> (BlockRowValues, BTreeSet<String>) {
+    build_block_row_values_where(data, default_keys, pivot_keys, |_| true)
+}
+
+/// `build_block_row_values` over the blocks `keep` accepts. Custom keys are still collected from
+/// every block, so each subset reports the same rows.
+pub fn build_block_row_values_where(
+    data: &AnalysisData,
+    default_keys: &HashSet<&'static str>,
+    pivot_keys: &HashSet<&'static str>,
+    keep: impl Fn(&BlockInfo) -> bool,
+) -> (BlockRowValues, BTreeSet<String>) {
+    let mut row_values = BlockRowValues::default();
     let mut custom_keys: BTreeSet<String> = BTreeSet::new();
 
     for per_key in data.block_dists.values() {
@@ -111,7 +235,10 @@ pub fn build_block_row_values(
         }
     }
 
-    for per_key in data.block_dists.values() {
+    for (h, per_key) in &data.block_dists {
+        let Some(block) = data.blocks.get(h).filter(|b| keep(b)) else {
+            continue;
+        };
         for (k, agg) in per_key {
             let is_default = default_keys.contains(k.as_str());
             if should_require_90pct(k, is_default, pivot_keys) {
@@ -121,9 +248,20 @@ pub fn build_block_row_values(
                 }
             }
 
+            row_values
+                .samples
+                .entry(k.clone())
+                .or_default()
+                .push(agg.count);
+            row_values
+                .timestamps
+                .entry(k.clone())
+                .or_default()
+                .push(block.timestamp);
             for p in NodePercentile::all_in_order() {
                 let row_key = format!("{}::{}", k, p.name());
                 row_values
+                    .values
                     .entry(row_key)
                     .or_insert_with(Vec::new)
                     .push(agg.value_for(*p));
@@ -134,22 +272,148 @@ pub fn build_block_row_values(
     (row_values, custom_keys)
 }
 
-pub fn build_tx_rows(
-    data: &AnalysisData,
-) -> (
-    HashMap<NodePercentile, Vec<f64>>,
-    HashMap<NodePercentile, Vec<f64>>,
-) {
-    let mut tx_latency_rows: HashMap<NodePercentile, Vec<f64>> = HashMap::new();
-    let mut tx_packed_rows: HashMap<NodePercentile, Vec<f64>> = HashMap::new();
+/// Receive/Sync/Cons rows measured from the earliest receive of each block across nodes instead
+/// of the block's own timestamp, keyed like `build_block_row_values`. A shift moves every node
+/// percentile by the same amount, so the aggregates are reused as they are.
+pub fn build_earliest_receive_row_values(data: &AnalysisData) -> BlockRowValues {
+    let mut row_values = BlockRowValues::default();
+    for (h, per_key) in &data.block_dists {
+        let Some(block) = data.blocks.get(h) else {
+            continue;
+        };
+        let Some(baseline) = per_key
+            .get("Receive")
+            .map(|agg| agg.value_for(NodePercentile::Min))
+        else {
+            continue;
+        };
+        for k in ["Receive", "Sync", "Cons"] {
+            let Some(agg) = per_key.get(k) else {
+                continue;
+            };
+            row_values
+                .samples
+                .entry(k.to_string())
+                .or_default()
+                .push(agg.count);
+            row_values
+                .timestamps
+                .entry(k.to_string())
+                .or_default()
+                .push(block.timestamp);
+            for p in NodePercentile::all_in_order() {
+                row_values
+                    .values
+                    .entry(format!("{}::{}", k, p.name()))
+                    .or_default()
+                    .push(agg.value_for(*p) - baseline);
+            }
+        }
+    }
+    row_values
+}
+
+/// Chains the event stages of every block that reached the consensus graph. Stages a block has no
+/// samples for (the pivot-only ones on non-pivot blocks) are left out of its chain.
+pub fn build_stage_waterfall(data: &AnalysisData) -> StageWaterfall {
+    let mut waterfall = StageWaterfall::default();
+    for per_key in data.block_dists.values() {
+        if !per_key.contains_key("ConsensusGraphReady") {
+            continue;
+        }
+        let stages: Vec<(&'static str, f64)> = EVENT_STAGES
+            .iter()
+            .filter_map(|&t| {
+                let p90 = per_key.get(t)?.value_for(NodePercentile::P90);
+                p90.is_finite().then_some((t, p90.max(0.0)))
+            })
+            .collect();
+        let total: f64 = stages.iter().map(|(_, v)| v).sum();
+        if total <= 0.0 {
+            continue;
+        }
+
+        waterfall.blocks += 1;
+        for &(t, v) in &stages {
+            waterfall
+                .shares
+                .entry(t)
+                .or_default()
+                .push(v / total * 100.0);
+        }
+        let (dominant, _) = stages.iter().fold(
+            stages[0],
+            |best, &cur| if cur.1 > best.1 { cur } else { best },
+        );
+        *waterfall.dominant.entry(dominant).or_default() += 1;
+    }
+    waterfall
+}
+
+pub fn print_stage_waterfall(waterfall: &StageWaterfall) {
+    println!(
+        "Stage dominating P90 block event latency ({} blocks):",
+        waterfall.blocks
+    );
+    for t in EVENT_STAGES {
+        let n = waterfall.dominant.get(t).copied().unwrap_or(0);
+        if n > 0 {
+            println!(
+                "  {}: {} blocks ({:.2}%)",
+                t,
+                n,
+                n as f64 / waterfall.blocks as f64 * 100.0
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxClass {
+    Empty,
+    Small,
+    Medium,
+    Large,
+}
+
+impl TxClass {
+    pub fn all_in_order() -> &'static [TxClass] {
+        &[
+            TxClass::Empty,
+            TxClass::Small,
+            TxClass::Medium,
+            TxClass::Large,
+        ]
+    }
+
+    /// Blocks with at least one tx are small below `small`, large from `large` on.
+    pub fn of(txs: i64, small: i64, large: i64) -> TxClass {
+        match txs {
+            t if t <= 0 => TxClass::Empty,
+            t if t < small => TxClass::Small,
+            t if t < large => TxClass::Medium,
+            _ => TxClass::Large,
+        }
+    }
+
+    pub fn label(self, small: i64, large: i64) -> String {
+        match self {
+            TxClass::Empty => "empty blocks".to_string(),
+            TxClass::Small => format!("small blocks, <{} txs", small),
+            TxClass::Medium => format!("medium blocks, {}-{} txs", small, large - 1),
+            TxClass::Large => format!("large blocks, >={} txs", large),
+        }
+    }
+}
+
+pub fn build_tx_rows(data: &AnalysisData) -> TxRowValues {
+    let mut rows = TxRowValues::default();
 
     for tx in data.txs.values() {
         if tx.received.len() == data.node_count {
-            let min_recv = tx.received.iter().copied().fold(f64::INFINITY, f64::min);
-            let latencies = min_recv_and_latency(&tx.received, min_recv);
-            let per = collect_tx_node_percentiles(&latencies);
+            let per = tx_node_percentiles(&tx.received, tx.received.min());
             for p in NodePercentile::all_in_order() {
-                tx_latency_rows
+                rows.latency
                     .entry(*p)
                     .or_insert_with(Vec::new)
                     .push(*per.get(p).unwrap());
@@ -157,11 +421,19 @@ pub fn build_tx_rows(
         }
 
         if !tx.packed.is_empty() {
-            let min_recv = tx.received.iter().copied().fold(f64::INFINITY, f64::min);
-            let latencies = min_recv_and_latency(&tx.packed, min_recv);
-            let per = collect_tx_node_percentiles(&latencies);
+            let per = tx_node_percentiles(&tx.packed, tx.received.min());
             for p in NodePercentile::all_in_order() {
-                tx_packed_rows
+                rows.packed
+                    .entry(*p)
+                    .or_insert_with(Vec::new)
+                    .push(*per.get(p).unwrap());
+            }
+        }
+
+        if !tx.ready.is_empty() && !tx.received.is_empty() {
+            let per = tx_node_percentiles(&tx.ready, tx.received.min());
+            for p in NodePercentile::all_in_order() {
+                rows.ready
                     .entry(*p)
                     .or_insert_with(Vec::new)
                     .push(*per.get(p).unwrap());
@@ -169,7 +441,7 @@ pub fn build_tx_rows(
         }
     }
 
-    (tx_latency_rows, tx_packed_rows)
+    rows
 }
 
 pub fn collect_block_scalars(data: &AnalysisData) -> BlockScalars {
@@ -183,7 +455,7 @@ pub fn collect_block_scalars(data: &AnalysisData) -> BlockScalars {
     for b in data.blocks.values() {
         block_txs.push(b.txs as f64);
         block_size.push(b.size as f64);
-        block_referees.push(b.referee_count as f64);
+        block_referees.push(b.referees.len() as f64);
         block_timestamps.push(b.timestamp);
         if b.txs > 0 {
             min_time = min_time.min(b.timestamp);
@@ -207,6 +479,126 @@ pub fn collect_block_scalars(data: &AnalysisData) -> BlockScalars {
     }
 }
 
+/// Bytes of blocks generated per `SIZE_SERIES_WINDOW`, from the first block's window to the
+/// last one's. Windows without blocks are included, so the series is the chain's data rate.
+pub fn build_size_series(data: &AnalysisData) -> Vec<SizeBucket> {
+    let window_of = |b: &BlockInfo| b.timestamp.div_euclid(SIZE_SERIES_WINDOW) * SIZE_SERIES_WINDOW;
+    let (Some(first), Some(last)) = (
+        data.blocks.values().map(window_of).min(),
+        data.blocks.values().map(window_of).max(),
+    ) else {
+        return Vec::new();
+    };
+
+    let len = ((last - first) / SIZE_SERIES_WINDOW) as usize + 1;
+    let mut series: Vec<SizeBucket> = (0..len)
+        .map(|i| SizeBucket {
+            window_start: first + i as i64 * SIZE_SERIES_WINDOW,
+            ..Default::default()
+        })
+        .collect();
+    for b in data.blocks.values() {
+        let bucket = &mut series[((window_of(b) - first) / SIZE_SERIES_WINDOW) as usize];
+        bucket.blocks += 1;
+        bucket.bytes += b.size;
+    }
+    let mut cumulative = 0;
+    for bucket in &mut series {
+        cumulative += bucket.bytes;
+        bucket.cumulative_bytes = cumulative;
+    }
+    series
+}
+
+/// Rebuilds the parent/referee DAG of the blocks kept and measures how old the blocks it
+/// references are. Referee ages show how quickly miners pick up the tips of other miners, the
+/// point of the tree-graph; stale and unreferenced blocks show where they do not.
+pub fn build_dag_metrics(data: &AnalysisData) -> DagMetrics {
+    let mut dag = DagMetrics::default();
+    let mut referenced: HashSet<H256> = HashSet::new();
+    for b in data.blocks.values() {
+        if let Some(parent) = b.parent {
+            match data.blocks.get(&parent) {
+                Some(p) => {
+                    dag.parent_age.push((b.timestamp - p.timestamp) as f64);
+                    referenced.insert(parent);
+                }
+                None => dag.unresolved += 1,
+            }
+        }
+        for r in &b.referees {
+            dag.references += 1;
+            match data.blocks.get(r) {
+                Some(referee) => {
+                    let age = b.timestamp - referee.timestamp;
+                    dag.referee_age.push(age as f64);
+                    if age > STALE_REFEREE_AGE {
+                        dag.stale_references += 1;
+                    }
+                    referenced.insert(*r);
+                }
+                None => dag.unresolved += 1,
+            }
+        }
+    }
+    if referenced.is_empty() && dag.unresolved == 0 {
+        // Logs without parent or referee hashes.
+        return dag;
+    }
+    let last = data.blocks.values().map(|b| b.timestamp).max().unwrap_or(0);
+    dag.unreferenced = data
+        .blocks
+        .iter()
+        .filter(|(h, b)| b.timestamp < last - STALE_REFEREE_AGE && !referenced.contains(*h))
+        .count();
+    dag
+}
+
+pub fn print_dag_metrics(dag: &DagMetrics) {
+    if dag.parent_age.is_empty() && dag.references == 0 && dag.unresolved == 0 {
+        return;
+    }
+    println!(
+        "Stale references (referee older than {}s) {} of {}",
+        STALE_REFEREE_AGE, dag.stale_references, dag.references
+    );
+    println!(
+        "Unreferenced blocks (not parent or referee of any block) {}",
+        dag.unreferenced
+    );
+    if dag.unresolved > 0 {
+        println!(
+            "Unresolved references (to blocks not kept) {}",
+            dag.unresolved
+        );
+    }
+}
+
+/// Steps between consecutive block timestamps longer than `threshold` seconds, or by default than
+/// `RESTART_GAP_FACTOR` median intervals (and `RESTART_GAP_MIN_SECS`), in time order. Blocks
+/// either side of such a gap belong to different stretches of the test, so intervals across it
+/// are meaningless.
+pub fn find_timestamp_gaps(data: &AnalysisData, threshold: Option<i64>) -> Vec<TimestampGap> {
+    let mut timestamps: Vec<i64> = data.blocks.values().map(|b| b.timestamp).collect();
+    timestamps.sort_unstable();
+    let mut intervals: Vec<i64> = timestamps.windows(2).map(|w| w[1] - w[0]).collect();
+    if intervals.is_empty() {
+        return Vec::new();
+    }
+    let threshold = threshold.unwrap_or_else(|| {
+        intervals.sort_unstable();
+        (intervals[intervals.len() / 2] * RESTART_GAP_FACTOR).max(RESTART_GAP_MIN_SECS)
+    });
+    timestamps
+        .windows(2)
+        .filter(|w| w[1] - w[0] > threshold)
+        .map(|w| TimestampGap {
+            before: w[0],
+            after: w[1],
+        })
+        .collect()
+}
+
 pub fn print_throughput_and_slowest(scalars: &BlockScalars, slowest_packed_hash: &Option<H256>) {
     println!("{} txs generated", scalars.tx_sum);
     match scalars.duration <= 0 {
@@ -1,4 +1,4 @@
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -7,12 +7,71 @@ pub enum QuantileImplArg {
     Tdigest,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check that the logs are usable, within seconds, without analyzing them
+    ///
+    /// Prints per host whether its blocks.log is there and parses, its size, blocks, txs, nodes
+    /// and block time range. Exits with 1 if any host has a problem
+    Validate {
+        /// Log directory containing host subdirs with blocks.log, or a single such file
+        #[arg(short = 'l', long = "log-path")]
+        log_path: PathBuf,
+
+        /// Check every blocks.log inside an archive, not only the shallowest one
+        #[arg(long = "merge-archive-outputs")]
+        merge_archive_outputs: bool,
+    },
+}
+
 #[derive(Parser, Debug)]
-#[command(about = "Analyze Conflux massive-test latency logs (memory-optimized)")]
+#[command(
+    about = "Analyze Conflux massive-test latency logs (memory-optimized)",
+    subcommand_negates_reqs = true
+)]
 pub struct Args {
-    /// Log directory containing host subdirs with blocks.log or output*.7z
-    #[arg(short = 'l', long = "log-path")]
-    pub log_path: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Log directory containing host subdirs with blocks.log (plain, .gz, .zst, .7z or .tar.gz),
+    /// a single such file, or - for stdin
+    #[arg(short = 'l', long = "log-path", required_unless_present = "rpc")]
+    pub log_path: Option<PathBuf>,
+
+    /// Instead of reading logs, poll these node JSON-RPC endpoints (e.g.
+    /// http://10.0.3.17:12537,http://10.0.3.18:12537) for --rpc-duration and report the block
+    /// Sync latency as seen through RPC, to smoke-check a cluster before a logged run
+    #[arg(
+        long = "rpc",
+        value_delimiter = ',',
+        conflicts_with_all = ["log_path", "group_by_dir_depth", "full_report", "metrics"]
+    )]
+    pub rpc: Vec<String>,
+
+    /// Seconds to poll the --rpc endpoints; Ctrl-C stops early
+    #[arg(long = "rpc-duration", default_value_t = 60)]
+    pub rpc_duration: u64,
+
+    /// Milliseconds between polls of each --rpc endpoint, the resolution of its latencies
+    #[arg(long = "rpc-interval-ms", default_value_t = 500)]
+    pub rpc_interval_ms: u64,
+
+    /// Treat the directories this many levels below the log path (e.g. 1 for round1/hostA/..,
+    /// round2/hostA/..) as separate runs, and print one report per directory instead of
+    /// aggregating them all. Exports get the directory name before their extension
+    #[arg(long = "group-by-dir-depth")]
+    pub group_by_dir_depth: Option<usize>,
+
+    /// Split the report at gaps in the block timestamps (node restarts, paused tests) and print
+    /// one report per stretch between them. Exports get segment1, segment2, .. before their
+    /// extension. Gaps are reported either way
+    #[arg(long = "split-at-gaps")]
+    pub split_at_gaps: bool,
+
+    /// Seconds between consecutive blocks that make a gap; by default 20 median block intervals,
+    /// and at least 60
+    #[arg(long = "gap-threshold")]
+    pub gap_threshold: Option<i64>,
 
     /// Only analyze the earliest N blocks (optional)
     #[arg(short = 'n', long = "max-blocks")]
@@ -20,7 +79,172 @@ pub struct Args {
 
     /// Quantile implementation:
     /// brute (exact, 1.6 GB memory for 2000 hosts * 2000 blocks)
-    /// tdigest (approximate and slower, very low memory; 1%+ inaccuracy for P99, max, etc.)
+    /// tdigest (low memory; exact within --tdigest-tails, about 1% inaccuracy between them)
     #[arg(long = "quantile-impl", value_enum, default_value_t = QuantileImplArg::Brute)]
     pub quantile_impl: QuantileImplArg,
+
+    /// Fractions of each block's samples the tdigest keeps exactly at the low and high end; the
+    /// default makes Min/P10 and P90..Max exact
+    #[arg(long = "tdigest-tails", value_delimiter = ',', default_values_t = [0.1, 0.1])]
+    pub tdigest_tails: Vec<f64>,
+
+    /// Also aggregate this fraction of the (block, key) pairs exactly (e.g. 0.01) and print how
+    /// far the streaming quantiles are from the exact ones
+    #[arg(long = "verify-quantiles")]
+    pub verify_quantiles: Option<f64>,
+
+    /// JSON description of the run (test_name, conflux_commit, node_count, target_tps,
+    /// topology, and any other fields), shown in the report header and every export
+    #[arg(long = "run-meta")]
+    pub run_meta: Option<PathBuf>,
+
+    /// Also write every data-quality warning (removed blocks, clock skew, coverage shortfalls)
+    /// to this file as JSON lines, after a header line
+    #[arg(long = "warnings-out")]
+    pub warnings_out: Option<PathBuf>,
+
+    /// Digits after the decimal point of every number in the table, the CSV exports and the
+    /// summary line
+    #[arg(long = "decimals", default_value_t = 2)]
+    pub decimals: usize,
+
+    /// Write non-zero numbers smaller than this in scientific notation (e.g. 3.20e-5) instead of
+    /// as 0.00; 0 disables it
+    #[arg(long = "scientific-below", default_value_t = 0.0)]
+    pub scientific_below: f64,
+
+    /// Add the median absolute deviation and the 5%-trimmed mean to every row, which stray
+    /// corrupted samples barely move, unlike Avg and Max
+    #[arg(long = "robust")]
+    pub robust: bool,
+
+    /// Add the 95% confidence intervals of Avg, P50 and P90 to every row, taking its values
+    /// (e.g. one per block) as independent samples, to tell run-to-run noise from regressions
+    #[arg(long = "ci")]
+    pub ci: bool,
+
+    /// End every block row with a `Trend` sparkline of its values over the run, each character
+    /// the mean of the blocks generated in one slice of it
+    #[arg(long = "sparklines")]
+    pub sparklines: bool,
+
+    /// Keys of the `SUMMARY key=value ...` line printed after the report, for log scrapers. Also
+    /// available: hosts, duration, receive_p50/p90/p99, sync_p90, cons_p90, tx_p50/p90
+    #[arg(
+        long = "summary-keys",
+        value_delimiter = ',',
+        default_value = "nodes,blocks,txs,tps,sync_p50,sync_p99,cons_p50,cons_p99,tx_p99,warnings"
+    )]
+    pub summary_keys: Vec<String>,
+
+    /// Seed of every randomized choice (the --verify-quantiles sample); recorded in the report
+    #[arg(long = "seed", default_value_t = 0)]
+    pub seed: u64,
+
+    /// Directory of extracted archive members shared with other runs over the same archives
+    /// (e.g. a CI matrix), so each member is extracted once. Safe to share between concurrent
+    /// runs
+    #[arg(long = "extract-cache")]
+    pub extract_cache: Option<PathBuf>,
+
+    /// Size limit of --extract-cache in MB; the least recently read members are evicted
+    #[arg(long = "extract-cache-max-mb", default_value_t = 4096)]
+    pub extract_cache_max_mb: u64,
+
+    /// MB of host logs parsed but not yet merged, by their estimated size in memory. Loader
+    /// threads wait while over it; a single larger log still goes through alone
+    #[arg(long = "inflight-memory", default_value_t = 2048)]
+    pub inflight_memory: u64,
+
+    /// Latency keys (e.g. Sync,Cons) aggregated from their exact samples whatever
+    /// --quantile-impl is, so their percentiles and --sync-heatmap export are exact
+    #[arg(long = "retain-raw", value_delimiter = ',')]
+    pub retain_raw: Vec<String>,
+
+    /// Keep a bounded sketch of each tx's timestamps (exact count, min, max and mean,
+    /// approximate node percentiles) instead of all of them, for tx counts that would not fit
+    /// in memory
+    #[arg(long = "tx-streaming")]
+    pub tx_streaming: bool,
+
+    /// Treat every blocks.log inside an archive (output0/blocks.log .. outputN/blocks.log of a
+    /// multi-node host) as its own node, instead of reading only the shallowest one
+    #[arg(long = "merge-archive-outputs")]
+    pub merge_archive_outputs: bool,
+
+    /// Also report Receive/Sync/Cons latencies measured from the earliest receive of each block.
+    /// The default block broadcast rows are measured from the block's own timestamp (generation
+    /// time); the gap between the two sets is the generator's local processing delay
+    #[arg(long = "earliest-receive-baseline")]
+    pub earliest_receive_baseline: bool,
+
+    /// Chain the block event stages (HeaderReady -> BodyReady -> ... -> TxPoolUpdated) of every
+    /// block and report each stage's share of the block's P90 latency, and which stage dominates
+    #[arg(long = "stage-waterfall")]
+    pub stage_waterfall: bool,
+
+    /// Repeat the block latency rows for empty, small, medium and large blocks by tx count
+    #[arg(long = "by-tx-class")]
+    pub by_tx_class: bool,
+
+    /// Tx counts where small blocks end and large blocks start (with --by-tx-class)
+    #[arg(long = "tx-class-bounds", value_delimiter = ',', default_values_t = [100, 2000])]
+    pub tx_class_bounds: Vec<i64>,
+
+    /// Write a CSV of Sync latency percentiles per time window of block timestamps, for
+    /// propagation-health heatmaps. It starts with the report header as `# name: value` lines
+    #[arg(long = "sync-heatmap")]
+    pub sync_heatmap: Option<PathBuf>,
+
+    /// Report how many nodes each tx reached (histogram), how many receptions each host missed,
+    /// and the least propagated txs
+    #[arg(long = "tx-reach")]
+    pub tx_reach: bool,
+
+    /// Number of least propagated txs, and of hosts missing the most, listed by --tx-reach
+    #[arg(long = "tx-reach-top", default_value_t = 10)]
+    pub tx_reach_top: usize,
+
+    /// Write a CSV of the blocks left out of the report since not every node synced them, with
+    /// their timestamps and how many nodes did, e.g. to leave the same blocks out of a DAG
+    /// analysis
+    #[arg(long = "removed-blocks-out")]
+    pub removed_blocks_out: Option<PathBuf>,
+
+    /// Write the blocks the report covers, synced by every node, as a block set JSON with the
+    /// report header as its provenance. The tree-graph-parse binaries restrict their analyses to
+    /// them with --only-blocks
+    #[arg(long = "block-set-out")]
+    pub block_set_out: Option<PathBuf>,
+
+    /// Write a CSV of the bytes of blocks generated per minute and the cumulative chain size
+    #[arg(long = "size-series")]
+    pub size_series: Option<PathBuf>,
+
+    /// Metrics of the hosts' metrics.log to set against block Sync P99 per --heatmap-window,
+    /// as module::key or a bare key of any module, e.g. txpool::stat_unpacked_txs. Adds their
+    /// correlation with Sync P99 and rows of their per-window means to the report
+    #[arg(long = "metrics", value_delimiter = ',')]
+    pub metrics: Vec<String>,
+
+    /// Write a CSV of the --metrics and block Sync P99 per window
+    #[arg(long = "metrics-out")]
+    pub metrics_out: Option<PathBuf>,
+
+    /// Window length in seconds of --sync-heatmap and --metrics
+    #[arg(long = "heatmap-window", default_value_t = 60)]
+    pub heatmap_window: i64,
+
+    /// Also load the DAG from the largest *.log.new_blocks file under the log path (if any) and
+    /// append confirmation-time rows for every adversary power and risk threshold
+    #[arg(long = "full-report")]
+    pub full_report: bool,
+
+    /// Adversary power percentages of the confirmation-time rows (with --full-report)
+    #[arg(long = "adv-percents", value_delimiter = ',', default_values_t = [10, 20, 30])]
+    pub adv_percents: Vec<usize>,
+
+    /// Risk thresholds of the confirmation-time rows (with --full-report)
+    #[arg(long = "risk-thresholds", value_delimiter = ',', default_values_t = [1e-4, 1e-6, 1e-8])]
+    pub risk_thresholds: Vec<f64>,
 }
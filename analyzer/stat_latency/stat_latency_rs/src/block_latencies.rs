@@ -0,0 +1,159 @@
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+
+use crate::config::EVENT_STAGES;
+
+/// The `latencies` of one block in one host log: the samples of every key in one buffer, read
+/// straight from the JSON. A `HashMap<String, Vec<f64>>` costs a table, a key and a vector per
+/// key and block, all dropped as soon as the samples are in the aggregates; this is two
+/// vectors per block, and the usual keys are not allocated at all.
+#[derive(Debug, Default, Clone)]
+pub struct BlockLatencies {
+    values: Vec<f64>,
+    /// Each key with the range of its samples in `values`, in log order. A key shows up twice
+    /// when a legacy log had both its old and new name.
+    keys: Vec<(Cow<'static, str>, Range<usize>)>,
+}
+
+impl BlockLatencies {
+    /// Appends the samples of `key`.
+    pub fn push(&mut self, key: &str, values: &[f64]) {
+        let start = self.values.len();
+        self.values.extend_from_slice(values);
+        self.keys.push((intern(key), start..self.values.len()));
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.keys.iter().any(|(k, _)| k == key)
+    }
+
+    /// Every sample of `key`.
+    pub fn samples<'a>(&'a self, key: &'a str) -> impl Iterator<Item = f64> + 'a {
+        self.iter()
+            .filter(move |(k, _)| *k == key)
+            .flat_map(|(_, values)| values.iter().copied())
+    }
+
+    /// Each key with its samples.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[f64])> {
+        self.keys
+            .iter()
+            .map(|(k, range)| (k.as_ref(), &self.values[range.clone()]))
+    }
+
+    /// Renames the keys `old` of every `(old, new)`.
+    pub fn rename(&mut self, aliases: &[(&str, &'static str)]) {
+        for (k, _) in &mut self.keys {
+            if let Some((_, new)) = aliases.iter().find(|(old, _)| k == old) {
+                *k = Cow::Borrowed(*new);
+            }
+        }
+    }
+
+    /// Bytes allocated for the samples and keys.
+    pub fn heap_bytes(&self) -> usize {
+        self.values.capacity() * size_of::<f64>()
+            + self.keys.capacity() * size_of::<(Cow<'static, str>, Range<usize>)>()
+            + self
+                .keys
+                .iter()
+                .filter_map(|(k, _)| match k {
+                    Cow::Owned(k) => Some(k.capacity()),
+                    Cow::Borrowed(_) => None,
+                })
+                .sum::<usize>()
+    }
+
+    pub fn scale(&mut self, factor: f64) {
+        for v in &mut self.values {
+            *v *= factor;
+        }
+    }
+}
+
+/// The key as a static string if it is a usual one, so it is not allocated per block.
+fn intern(key: &str) -> Cow<'static, str> {
+    ["Receive", "Sync", "Cons", "Relay", "Insert"]
+        .iter()
+        .chain(EVENT_STAGES.iter())
+        .find(|k| **k == key)
+        .map_or_else(|| Cow::Owned(key.to_string()), |k| Cow::Borrowed(*k))
+}
+
+impl<'de> Deserialize<'de> for BlockLatencies {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(LatenciesVisitor)
+    }
+}
+
+struct LatenciesVisitor;
+
+impl<'de> Visitor<'de> for LatenciesVisitor {
+    type Value = BlockLatencies;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an object of number arrays")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<BlockLatencies, A::Error> {
+        let mut latencies = BlockLatencies::default();
+        while let Some(key) = map.next_key_seed(KeySeed)? {
+            let start = latencies.values.len();
+            map.next_value_seed(AppendSamples(&mut latencies.values))?;
+            latencies.keys.push((key, start..latencies.values.len()));
+        }
+        Ok(latencies)
+    }
+}
+
+/// A latency key, interned without an intermediate `String`.
+struct KeySeed;
+
+impl<'de> DeserializeSeed<'de> for KeySeed {
+    type Value = Cow<'static, str>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeySeed {
+    type Value = Cow<'static, str>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a latency key")
+    }
+
+    fn visit_str<E>(self, key: &str) -> Result<Self::Value, E> {
+        Ok(intern(key))
+    }
+}
+
+/// An array of numbers, appended to the buffer.
+struct AppendSamples<'a>(&'a mut Vec<f64>);
+
+impl<'de> DeserializeSeed<'de> for AppendSamples<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for AppendSamples<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of numbers")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(v) = seq.next_element::<f64>()? {
+            self.0.push(v);
+        }
+        Ok(())
+    }
+}
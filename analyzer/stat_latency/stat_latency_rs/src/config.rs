@@ -17,6 +17,31 @@ pub fn default_latency_key_names() -> HashSet<&'static str> {
     set
 }
 
+/// Block event keys in the order the stages happen on a node. Each value is the time spent since
+/// the previous event (`HeaderReady` since the block was first seen).
+pub const EVENT_STAGES: [&str; 8] = [
+    "HeaderReady",
+    "BodyReady",
+    "SyncGraph",
+    "ConsensusGraphStart",
+    "ConsensusGraphReady",
+    "ComputeEpoch",
+    "NotifyTxPool",
+    "TxPoolUpdated",
+];
+
+/// Window length in seconds of the block size series.
+pub const SIZE_SERIES_WINDOW: i64 = 60;
+
+/// Seconds after which a referee is stale: the referencing block's miner saw it as a tip for
+/// this long, where a well-connected network references new blocks within a few intervals.
+pub const STALE_REFEREE_AGE: i64 = 10;
+
+/// A step between consecutive block timestamps is a restart gap when longer than this many
+/// median block intervals, and than `RESTART_GAP_MIN_SECS`.
+pub const RESTART_GAP_FACTOR: i64 = 20;
+pub const RESTART_GAP_MIN_SECS: i64 = 60;
+
 pub fn pivot_event_key_names() -> HashSet<&'static str> {
     let mut set = HashSet::new();
     set.insert("ComputeEpoch");
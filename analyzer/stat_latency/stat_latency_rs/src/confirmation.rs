@@ -0,0 +1,85 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use testlog_io::{discover, Pattern, Source};
+use tree_graph_parse_rust::block_set::{BlockSet, BlockSetEntry};
+use tree_graph_parse_rust::graph::Graph;
+
+use crate::model::AnalysisData;
+
+/// Every `*.log.new_blocks` log under `log_dir`, largest file first. Each node logs the whole DAG,
+/// so the largest log is the most complete view of it.
+pub fn scan_new_blocks_logs(log_dir: &Path) -> Result<Vec<Source>> {
+    let mut logs = Vec::new();
+    for source in discover(log_dir, &Pattern::Suffix(".log.new_blocks".to_string()))? {
+        let len = match source.path() {
+            Some(path) => path.metadata()?.len(),
+            None => 0,
+        };
+        logs.push((len, source));
+    }
+    logs.sort_by(|(la, a), (lb, b)| lb.cmp(la).then_with(|| a.path().cmp(&b.path())));
+    Ok(logs.into_iter().map(|(_, source)| source).collect())
+}
+
+/// Loads the DAG from the largest `*.log.new_blocks` log under `log_dir`, if there is one.
+pub fn load_dag(log_dir: &Path) -> Result<Option<(Source, Graph)>> {
+    let Some(source) = scan_new_blocks_logs(log_dir)?.into_iter().next() else {
+        return Ok(None);
+    };
+    let graph = source
+        .open()
+        .and_then(Graph::from_log)
+        .with_context(|| format!("load DAG from {}", source))?;
+    Ok(Some((source, graph)))
+}
+
+/// Confirmation times (seconds) of every block, one row per adversary power and risk threshold.
+pub fn confirmation_time_rows(
+    graph: &Graph,
+    adv_percents: &[usize],
+    risk_thresholds: &[f64],
+) -> Vec<(String, Vec<f64>)> {
+    let mut rows = Vec::new();
+    for &adv_percent in adv_percents {
+        let times = graph.confirm_times(adv_percent, risk_thresholds);
+        for (&risk, times) in risk_thresholds.iter().zip(times) {
+            rows.push((
+                format!("confirmation time (adv {}%, risk {:e})", adv_percent, risk),
+                times.into_iter().map(|t| t as f64).collect(),
+            ));
+        }
+    }
+    rows
+}
+
+/// The blocks left in `data` after `validate_and_filter_blocks`, earliest first, with their
+/// timestamps and Sync coverage. `filter` says how they were chosen; the `header` fields follow
+/// it in the provenance.
+pub fn kept_block_set(
+    data: &AnalysisData,
+    filter: String,
+    header: &[(String, String)],
+) -> BlockSet {
+    let mut provenance = vec![
+        ("tool".to_string(), "stat_latency_rs".to_string()),
+        ("filter".to_string(), filter),
+        ("nodes".to_string(), data.node_count.to_string()),
+    ];
+    provenance.extend(header.iter().cloned());
+
+    let mut blocks: Vec<BlockSetEntry> = data
+        .blocks
+        .iter()
+        .map(|(hash, block)| BlockSetEntry {
+            hash: hash.0.into(),
+            timestamp: Some(block.timestamp),
+            synced: data
+                .block_dists
+                .get(hash)
+                .and_then(|per_key| per_key.get("Sync"))
+                .map(|sync| sync.count as usize),
+        })
+        .collect();
+    blocks.sort_by_key(|b| (b.timestamp, b.hash));
+    BlockSet { provenance, blocks }
+}
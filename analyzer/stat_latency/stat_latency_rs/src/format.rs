@@ -0,0 +1,67 @@
+use std::sync::OnceLock;
+
+static FORMATTER: OnceLock<Formatter> = OnceLock::new();
+
+/// How every number of the table, the CSV exports and the summary line is written. Values are
+/// kept unrounded until then. The output never depends on the locale: `.` is the decimal point
+/// and there are no thousands separators, so the CSVs parse anywhere.
+#[derive(Debug, Clone, Copy)]
+pub struct Formatter {
+    /// Digits after the decimal point, or of the mantissa in scientific notation.
+    pub decimals: usize,
+    /// Non-zero values smaller than this in magnitude are written as e.g. `3.20e-5`, which would
+    /// otherwise show as `0.00`. 0 disables it.
+    pub scientific_below: f64,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self {
+            decimals: 2,
+            scientific_below: 0.0,
+        }
+    }
+}
+
+impl Formatter {
+    /// `nan` for NaN, `inf`/`-inf` for infinities.
+    pub fn number(&self, v: f64) -> String {
+        self.with_decimals(v, self.decimals)
+    }
+
+    /// As `number`, with `decimals` instead of the configured ones, for values whose precision
+    /// is fixed by what they are, e.g. throughput in tx/s.
+    pub fn with_decimals(&self, v: f64, decimals: usize) -> String {
+        if v.is_nan() {
+            return "nan".to_string();
+        }
+        if v.is_infinite() {
+            return match v > 0.0 {
+                true => "inf".to_string(),
+                false => "-inf".to_string(),
+            };
+        }
+        if v != 0.0 && v.abs() < self.scientific_below {
+            return format!("{:.*e}", decimals, v);
+        }
+        format!("{:.*}", decimals, v)
+    }
+
+    /// Whole numbers (within 1e-9) without decimals, e.g. counts and sizes; others as `number`.
+    pub fn integral_or_number(&self, v: f64) -> String {
+        match v.is_finite() && (v - v.round()).abs() < 1e-9 {
+            true => format!("{}", v.round() as i64),
+            false => self.number(v),
+        }
+    }
+}
+
+/// Sets the formatter for the rest of the run; only the first call has an effect.
+pub fn set_formatter(formatter: Formatter) {
+    let _ = FORMATTER.set(formatter);
+}
+
+/// The formatter set by `set_formatter`, or the default (2 decimals).
+pub fn formatter() -> &'static Formatter {
+    FORMATTER.get_or_init(Formatter::default)
+}
@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::format::formatter;
+use crate::model::{MetricCorrelation, NodePercentile, RemovedBlock, SizeBucket};
+use crate::quantile::QuantileAgg;
+
+/// Writes one CSV row per time window: `window_start,samples` followed by a column per node
+/// percentile (Min, Avg, P10 .. P999, Max) of the Sync latency samples in the window. The
+/// report header (`ReproInfo`, `RunMeta`) goes first, as `# name: value` comment lines.
+pub fn write_sync_heatmap(
+    path: &Path,
+    buckets: &BTreeMap<i64, QuantileAgg>,
+    header: &[(String, String)],
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+
+    write_header(&mut out, header)?;
+
+    let mut header = vec!["window_start".to_string(), "samples".to_string()];
+    header.extend(
+        NodePercentile::all_in_order()
+            .iter()
+            .map(|p| p.name().to_string()),
+    );
+    writeln!(out, "{}", header.join(","))?;
+
+    for (window_start, agg) in buckets {
+        let mut row = vec![window_start.to_string(), agg.count.to_string()];
+        row.extend(
+            NodePercentile::all_in_order()
+                .iter()
+                .map(|p| formatter().number(agg.value_for(*p))),
+        );
+        writeln!(out, "{}", row.join(","))?;
+    }
+    out.flush()
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Writes one CSV row per window of `build_size_series`: `window_start,blocks,bytes,
+/// cumulative_bytes`, after the report header.
+pub fn write_size_series(
+    path: &Path,
+    series: &[SizeBucket],
+    header: &[(String, String)],
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+
+    write_header(&mut out, header)?;
+    writeln!(out, "window_start,blocks,bytes,cumulative_bytes")?;
+    for bucket in series {
+        writeln!(
+            out,
+            "{},{},{},{}",
+            bucket.window_start, bucket.blocks, bucket.bytes, bucket.cumulative_bytes
+        )?;
+    }
+    out.flush()
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Writes one CSV row per block removed by `validate_and_filter_blocks`:
+/// `block,timestamp,received,total` (nodes that synced it, of all nodes), after the report
+/// header. Other tools can read it to leave out the same blocks.
+pub fn write_removed_blocks(
+    path: &Path,
+    removed: &[RemovedBlock],
+    header: &[(String, String)],
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+
+    write_header(&mut out, header)?;
+    writeln!(out, "block,timestamp,received,total")?;
+    for block in removed {
+        writeln!(
+            out,
+            "{:#x},{},{},{}",
+            block.hash, block.timestamp, block.received, block.total
+        )?;
+    }
+    out.flush()
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Writes one CSV row per window of `correlate_metrics`: `window_start,blocks,sync_p99` and a
+/// column per metric, after the report header. Missing values are empty.
+pub fn write_metric_windows(
+    path: &Path,
+    correlation: &MetricCorrelation,
+    header: &[(String, String)],
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+
+    write_header(&mut out, header)?;
+    let mut columns = vec![
+        "window_start".to_string(),
+        "blocks".to_string(),
+        "sync_p99".to_string(),
+    ];
+    columns.extend(correlation.names.iter().cloned());
+    writeln!(out, "{}", columns.join(","))?;
+    let cell = |v: Option<f64>| v.map(|v| formatter().number(v)).unwrap_or_default();
+    for w in &correlation.windows {
+        let mut row = vec![
+            w.window_start.to_string(),
+            w.blocks.to_string(),
+            cell(w.sync_p99),
+        ];
+        row.extend(w.metrics.iter().map(|v| cell(*v)));
+        writeln!(out, "{}", row.join(","))?;
+    }
+    out.flush()
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// The report header as `# name: value` comment lines.
+fn write_header(out: &mut impl Write, header: &[(String, String)]) -> Result<()> {
+    for (name, value) in header {
+        writeln!(out, "# {}: {}", name, value)?;
+    }
+    Ok(())
+}
@@ -1,21 +1,62 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use ethereum_types::H256;
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::path::Path;
-use std::path::PathBuf;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::{mpsc, Arc};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
 use std::thread;
 
-use crate::io_utils::{load_host_log_from_archive, load_host_log_from_path, scan_logs};
-use crate::model::{AnalysisData, BlockInfo, HostBlocksLog, TxAgg};
-use crate::quantile::{QuantileAgg, QuantileImpl};
+use testlog_io::{discover, MemberCache, Pattern, Source};
+
+use crate::io_utils::{load_host_log, load_host_log_member};
+use crate::model::{
+    AnalysisData, BlockInfo, BlockJson, HostBlocksLog, HostTxReceptions, RemovedBlock,
+    SchemaVersion, TimestampGap, TxAgg,
+};
+use crate::quantile::{QuantileAgg, QuantileImpl, QuantilePolicy};
+use crate::shutdown;
 use crate::stats::f64_from_stat;
+use crate::warnings::Warning;
+
+/// Share of a host's samples each of its percentiles stands for: from halfway to the previous
+/// percentile to halfway to the next one.
+const POOLED_GAP_SHARES: [(&str, f64); 9] = [
+    ("P10", 0.2),
+    ("P30", 0.2),
+    ("P50", 0.25),
+    ("P80", 0.2),
+    ("P90", 0.075),
+    ("P95", 0.045),
+    ("P99", 0.0245),
+    ("P999", 0.005),
+    ("Max", 0.0005),
+];
 
 fn merge_sync_gap_stats(data: &mut AnalysisData, stats: Vec<HashMap<String, serde_json::Value>>) {
     data.node_count += stats.len();
     for stat_map in stats {
+        if let (Some(cnt), Some(avg)) = (
+            f64_from_stat(&stat_map, "Cnt"),
+            f64_from_stat(&stat_map, "Avg"),
+        ) {
+            let pooled = data
+                .sync_gap_pooled
+                .get_or_insert_with(|| QuantileAgg::new(QuantileImpl::Brute, 0));
+            // Older summaries lack some percentiles; the present ones share the samples.
+            let present: Vec<(f64, f64)> = POOLED_GAP_SHARES
+                .iter()
+                .filter_map(|&(key, share)| Some((f64_from_stat(&stat_map, key)?, share)))
+                .collect();
+            let total_share: f64 = present.iter().map(|(_, share)| share).sum();
+            for (v, share) in present {
+                pooled.insert_weighted(v, cnt * share / total_share);
+            }
+            data.sync_gap_pooled_sum += avg * cnt;
+        }
+
         for (key, bucket) in [
             ("Avg", &mut data.sync_gap_avg),
             ("P50", &mut data.sync_gap_p50),
@@ -33,8 +74,8 @@ fn merge_sync_gap_stats(data: &mut AnalysisData, stats: Vec<HashMap<String, serd
 
 fn merge_host_blocks(
     data: &mut AnalysisData,
-    host_blocks: HashMap<H256, crate::model::BlockJson>,
-    quantile_impl: QuantileImpl,
+    host_blocks: HashMap<H256, BlockJson>,
+    policy: &QuantilePolicy,
     expected_samples_per_block: usize,
 ) {
     for (block_hash, b) in host_blocks {
@@ -42,36 +83,61 @@ fn merge_host_blocks(
             .blocks
             .entry(block_hash)
             .or_insert_with(BlockInfo::default);
-        if entry.timestamp == 0 && b.timestamp != 0 {
+        // Hosts are merged in the order they finish loading, so where they disagree on a block
+        // (a host missing a field, or a skewed clock) the pick must not depend on who came
+        // first: the earliest timestamp, the largest counts, the smallest parent hash and the
+        // longest referee list (then the smallest), ignoring hosts without the field.
+        if b.timestamp != 0 && (entry.timestamp == 0 || b.timestamp < entry.timestamp) {
             entry.timestamp = b.timestamp;
         }
-        if entry.txs == 0 && b.txs != 0 {
-            entry.txs = b.txs;
+        entry.txs = entry.txs.max(b.txs);
+        entry.size = entry.size.max(b.size);
+        if b.parent.is_some() && (entry.parent.is_none() || b.parent < entry.parent) {
+            entry.parent = b.parent;
         }
-        if entry.size == 0 && b.size != 0 {
-            entry.size = b.size;
-        }
-        if entry.referee_count == 0 && !b.referees.is_empty() {
-            entry.referee_count = b.referees.len() as i64;
+        if (b.referees.len(), Reverse(&b.referees))
+            > (entry.referees.len(), Reverse(&entry.referees))
+        {
+            entry.referees = b.referees.clone();
         }
         let per_block = data
             .block_dists
             .entry(block_hash)
             .or_insert_with(HashMap::new);
-        for (k, vs) in b.latencies {
-            let agg = per_block
-                .entry(k)
-                .or_insert_with(|| QuantileAgg::new(quantile_impl, expected_samples_per_block));
+        for (k, vs) in b.latencies.iter() {
+            if policy.verifies(&block_hash, k) {
+                let shadow = data
+                    .quantile_shadows
+                    .entry((block_hash, k.to_string()))
+                    .or_insert_with(|| QuantileAgg::new(QuantileImpl::Brute, 0));
+                for v in vs {
+                    shadow.insert(*v);
+                }
+            }
+            if !per_block.contains_key(k) {
+                per_block.insert(
+                    k.to_string(),
+                    QuantileAgg::new(policy.for_key(k), expected_samples_per_block),
+                );
+            }
+            let agg = per_block.get_mut(k).unwrap();
             for v in vs {
-                agg.insert(v);
+                agg.insert(*v);
             }
         }
     }
 }
 
-fn merge_host_txs(data: &mut AnalysisData, host_txs: HashMap<H256, crate::model::TxJson>) {
+fn merge_host_txs(
+    data: &mut AnalysisData,
+    host_txs: HashMap<H256, crate::model::TxJson>,
+    streaming: bool,
+) {
     for (tx_hash, tx) in host_txs {
-        let tx_entry = data.txs.entry(tx_hash).or_insert_with(TxAgg::default);
+        let tx_entry = data
+            .txs
+            .entry(tx_hash)
+            .or_insert_with(|| TxAgg::new(streaming));
         let mut local_received_min: Option<f64> = None;
         for ts in tx.received_timestamps {
             tx_entry.received.push(ts);
@@ -106,56 +172,367 @@ fn merge_host_txs(data: &mut AnalysisData, host_txs: HashMap<H256, crate::model:
 fn merge_host_data(
     data: &mut AnalysisData,
     host: HostBlocksLog,
-    quantile_impl: QuantileImpl,
+    policy: &QuantilePolicy,
     expected_samples_per_block: usize,
 ) {
+    let blocks = merge_host_except_blocks(data, host, policy);
+    merge_host_blocks(data, blocks, policy, expected_samples_per_block);
+}
+
+/// Merges everything of `host` but its blocks, which are returned to be merged on their own.
+fn merge_host_except_blocks(
+    data: &mut AnalysisData,
+    host: HostBlocksLog,
+    policy: &QuantilePolicy,
+) -> HashMap<H256, BlockJson> {
+    if let Some(warning) = clock_skew_warning(&host) {
+        eprintln!("{}", warning.message);
+        data.warnings.push(warning);
+    }
+    if host.schema == SchemaVersion::Legacy {
+        data.legacy_hosts += 1;
+    }
+    data.hosts.push(host.host);
+    data.host_digests.push(host.digest);
+    data.host_tx_receptions.push(HostTxReceptions {
+        nodes: host.sync_cons_gap_stats.len(),
+        receptions: host
+            .txs
+            .values()
+            .map(|tx| tx.received_timestamps.len())
+            .sum(),
+    });
     merge_sync_gap_stats(data, host.sync_cons_gap_stats);
     data.by_block_ratio.extend(host.by_block_ratio);
-    merge_host_blocks(data, host.blocks, quantile_impl, expected_samples_per_block);
-    merge_host_txs(data, host.txs);
+    merge_host_txs(data, host.txs, policy.tx_streaming);
+    host.blocks
 }
 
+/// Hosts sent to a merge thread, and not yet merged, per shard. The merge thread waits on a
+/// full shard, which holds back the loaders through `InflightBudget`.
+const SHARD_QUEUE: usize = 2;
+
+/// Blocks merged by one thread per shard, each owning the blocks whose hash starts with its
+/// range of leading bytes. Blocks of different hashes never touch the same aggregate, so the
+/// shards need no locks, and merging blocks, the bulk of the work, is no longer bound to the
+/// single thread merging the rest of each host.
+struct ShardedBlockMerge {
+    senders: Vec<mpsc::SyncSender<HashMap<H256, BlockJson>>>,
+    /// Each ends with the `blocks`, `block_dists` and `quantile_shadows` of its shard.
+    handles: Vec<thread::JoinHandle<AnalysisData>>,
+}
+
+impl ShardedBlockMerge {
+    fn new(shards: usize, policy: &QuantilePolicy, expected_samples_per_block: usize) -> Self {
+        let (senders, handles) = (0..shards.clamp(1, 256))
+            .map(|_| {
+                let (tx, rx) = mpsc::sync_channel::<HashMap<H256, BlockJson>>(SHARD_QUEUE);
+                let policy = policy.clone();
+                let handle = thread::spawn(move || {
+                    let mut shard = AnalysisData::default();
+                    for blocks in rx {
+                        merge_host_blocks(&mut shard, blocks, &policy, expected_samples_per_block);
+                    }
+                    shard
+                });
+                (tx, handle)
+            })
+            .unzip();
+        Self { senders, handles }
+    }
+
+    /// Splits the blocks of one host among the shards.
+    fn send(&self, blocks: HashMap<H256, BlockJson>) {
+        let shards = self.senders.len();
+        let mut parts: Vec<HashMap<H256, BlockJson>> =
+            (0..shards).map(|_| HashMap::new()).collect();
+        for (h, b) in blocks {
+            parts[h[0] as usize * shards / 256].insert(h, b);
+        }
+        for (tx, part) in self.senders.iter().zip(parts) {
+            if !part.is_empty() {
+                // Only fails if the shard's thread panicked, which `finish` reports.
+                let _ = tx.send(part);
+            }
+        }
+    }
+
+    /// Waits for every shard to merge what it was sent, and moves its blocks into `data`.
+    fn finish(self, data: &mut AnalysisData) {
+        drop(self.senders);
+        for handle in self.handles {
+            let shard = handle
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            data.blocks.extend(shard.blocks);
+            data.block_dists.extend(shard.block_dists);
+            data.quantile_shadows.extend(shard.quantile_shadows);
+        }
+    }
+}
+
+/// Block latencies are log time minus block timestamp, so a negative one means the host's clock
+/// is behind the generator's.
+fn clock_skew_warning(host: &HostBlocksLog) -> Option<Warning> {
+    let negative: Vec<f64> = host
+        .blocks
+        .values()
+        .flat_map(|b| {
+            ["Receive", "Sync", "Cons"]
+                .into_iter()
+                .flat_map(|k| b.latencies.samples(k))
+        })
+        .filter(|&v| v < 0.0)
+        .collect();
+    let min = negative.iter().copied().reduce(f64::min)?;
+    Some(Warning::new(
+        "clock_skew",
+        format!(
+            "host {}: {} negative block latencies (min {:.3}s), its clock may be skewed",
+            host.host,
+            negative.len(),
+            min
+        ),
+        serde_json::json!({"host": host.host, "negative_latencies": negative.len(), "min": min}),
+    ))
+}
+
+/// One node's blocks.log: a whole source, or one member of an archive.
 #[derive(Debug, Clone)]
-enum LogSource {
-    Plain(PathBuf),
-    Archive(PathBuf),
+pub enum HostLog {
+    Whole(Source),
+    Member(Source, String),
+}
+
+impl HostLog {
+    /// Bytes on disk of the log, or of the archive holding it; `None` for stdin or an
+    /// unreadable file.
+    pub fn size(&self) -> Option<u64> {
+        let (HostLog::Whole(source) | HostLog::Member(source, _)) = self;
+        fs::metadata(source.path()?).ok().map(|m| m.len())
+    }
+}
+
+fn load_host((id, host): &(String, HostLog), cache: Option<&MemberCache>) -> Result<HostBlocksLog> {
+    let mut log = match host {
+        HostLog::Whole(source) => load_host_log(source, cache),
+        HostLog::Member(source, member) => load_host_log_member(source, member, cache),
+    }
+    .with_context(|| format!("load host {}", id))?;
+    log.host = id.clone();
+    Ok(log)
 }
 
-fn load_source(source: &LogSource) -> Result<HostBlocksLog> {
-    match source {
-        LogSource::Plain(p) => load_host_log_from_path(p),
-        LogSource::Archive(p) => load_host_log_from_archive(p),
+/// Stable name of a host: the directory of its log relative to `log_path`, e.g. `10.0.3.17` for
+/// `hosts/10.0.3.17/blocks.log` under `hosts`, then the directory of the archive member, if any
+/// (`10.0.3.17/output3`). A log given directly is named by its own directory.
+fn host_id(log_path: &Path, host: &HostLog) -> String {
+    let (source, member) = match host {
+        HostLog::Whole(source) => (source, None),
+        HostLog::Member(source, member) => (source, Some(member)),
+    };
+    let Some(path) = source.path() else {
+        return "<stdin>".to_string();
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let relative = dir
+        .strip_prefix(log_path)
+        .ok()
+        .filter(|r| !r.as_os_str().is_empty());
+    let mut id = match relative {
+        Some(r) => r
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        None => dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| ".".to_string()),
+    };
+    if let Some((member_dir, _)) = member.and_then(|m| m.rsplit_once('/')) {
+        id.push('/');
+        id.push_str(member_dir);
     }
+    id
 }
 
-fn collect_sources(log_path: &Path) -> Result<Vec<LogSource>> {
-    let (blocks_logs, archives) = scan_logs(log_path)?;
-    if blocks_logs.is_empty() && archives.is_empty() {
+/// With `merge_archive_outputs`, every blocks.log inside an archive (e.g. output0/blocks.log ..
+/// output15/blocks.log of a multi-node host) is a node of its own; otherwise an archive is one
+/// node, read from its shallowest blocks.log.
+pub fn collect_hosts(
+    log_path: &Path,
+    merge_archive_outputs: bool,
+) -> Result<Vec<(String, HostLog)>> {
+    let sources = discover(log_path, &Pattern::Name("blocks.log".to_string()))?;
+    if sources.is_empty() {
         return Err(anyhow!(
-            "No host logs found under: {} (expected blocks.log files or .7z archives)",
+            "No host logs found under: {} (expected blocks.log files, compressed or in archives)",
             log_path.display()
         ));
     }
 
-    let mut sources = Vec::with_capacity(blocks_logs.len() + archives.len());
-    for p in blocks_logs {
-        sources.push(LogSource::Plain(p));
+    let mut hosts = Vec::with_capacity(sources.len());
+    for source in sources {
+        let members = if merge_archive_outputs {
+            source.members()?
+        } else {
+            vec![]
+        };
+        if members.is_empty() {
+            hosts.push(HostLog::Whole(source));
+        } else {
+            for member in members {
+                hosts.push(HostLog::Member(source.clone(), member));
+            }
+        }
+    }
+    Ok(hosts
+        .into_iter()
+        .map(|host| (host_id(log_path, &host), host))
+        .collect())
+}
+
+/// Groups the hosts under `log_path` by the first `depth` directories of their ids, e.g. the
+/// `round1` and `round2` of `round1/hostA/blocks.log` .. `round2/hostB/blocks.log` at depth 1,
+/// as `(group name, group directory)` sorted by name.
+pub fn group_dirs(
+    log_path: &Path,
+    depth: usize,
+    merge_archive_outputs: bool,
+) -> Result<Vec<(String, PathBuf)>> {
+    if depth == 0 || !log_path.is_dir() {
+        return Err(anyhow!(
+            "grouping needs a log directory and a depth of at least 1"
+        ));
+    }
+    let mut groups = BTreeSet::new();
+    for (id, _) in collect_hosts(log_path, merge_archive_outputs)? {
+        let parts: Vec<&str> = id.split('/').collect();
+        if parts.len() <= depth {
+            return Err(anyhow!(
+                "host {} is not below {} group directories of {}",
+                id,
+                depth,
+                log_path.display()
+            ));
+        }
+        groups.insert(parts[..depth].join("/"));
+    }
+    Ok(groups
+        .into_iter()
+        .map(|name| {
+            let dir = log_path.join(&name);
+            (name, dir)
+        })
+        .collect())
+}
+
+/// Once every host is merged: buckets the Sync samples of each block by its timestamp into
+/// `sync_buckets` (with `heatmap_window`), then finalizes every aggregate. Bucketing waits for
+/// the end since a block's timestamp is only settled once every host had its say.
+fn finalize_aggs(
+    data: &mut AnalysisData,
+    policy: &QuantilePolicy,
+    expected_samples_per_block: usize,
+    heatmap_window: Option<i64>,
+) {
+    if let Some(window) = heatmap_window {
+        for (h, per_key) in &data.block_dists {
+            let Some(sync) = per_key.get("Sync") else {
+                continue;
+            };
+            let timestamp = data.blocks.get(h).map_or(0, |b| b.timestamp);
+            data.sync_buckets
+                .entry(timestamp.div_euclid(window) * window)
+                .or_insert_with(|| {
+                    QuantileAgg::new(policy.for_key("Sync"), expected_samples_per_block)
+                })
+                .merge(sync);
+        }
     }
-    for p in archives {
-        sources.push(LogSource::Archive(p));
+    data.block_dists
+        .values_mut()
+        .flat_map(HashMap::values_mut)
+        .chain(data.sync_buckets.values_mut())
+        .par_bridge()
+        .for_each(QuantileAgg::finalize);
+}
+
+/// Merges hosts read some other way than from logs under a path, e.g. by `live::poll_cluster`.
+pub fn merge_hosts(
+    data: &mut AnalysisData,
+    hosts: Vec<HostBlocksLog>,
+    policy: &QuantilePolicy,
+    heatmap_window: Option<i64>,
+) {
+    let expected_samples_per_block = hosts.len().max(1);
+    data.hosts_expected = hosts.len();
+    for host in hosts {
+        merge_host_data(data, host, policy, expected_samples_per_block);
     }
-    Ok(sources)
+    finalize_aggs(data, policy, expected_samples_per_block, heatmap_window);
 }
 
+/// Bytes of parsed logs the loader threads may hand to the merge thread before it has merged
+/// them. Each log takes its `HostBlocksLog::estimated_bytes` until merged; a log larger than
+/// the whole budget is let through once nothing else is in flight.
+struct InflightBudget {
+    limit: usize,
+    /// Bytes in flight, and whether the merge thread stopped taking logs.
+    state: Mutex<(usize, bool)>,
+    freed: Condvar,
+}
+
+impl InflightBudget {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            state: Mutex::new((0, false)),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Waits until `bytes` fit. False once the budget is closed.
+    fn acquire(&self, bytes: usize) -> bool {
+        let mut state = self.state.lock().unwrap();
+        while !state.1 && state.0 > 0 && state.0 + bytes > self.limit {
+            state = self.freed.wait(state).unwrap();
+        }
+        state.0 += bytes;
+        !state.1
+    }
+
+    fn release(&self, bytes: usize) {
+        self.state.lock().unwrap().0 -= bytes;
+        self.freed.notify_all();
+    }
+
+    /// Wakes and turns away every waiting loader.
+    fn close(&self) {
+        self.state.lock().unwrap().1 = true;
+        self.freed.notify_all();
+    }
+}
+
+/// With `heatmap_window` (seconds), Sync samples are also bucketed by block timestamp into
+/// `AnalysisData::sync_buckets`. Archives are extracted through `cache`, if given. Parsed logs
+/// waiting to be merged take at most `inflight_bytes`, see `InflightBudget`. On Ctrl-C (see
+/// `shutdown`), loading stops and `data` holds the hosts merged so far.
 pub fn load_and_merge_hosts(
     log_path: &Path,
     data: &mut AnalysisData,
-    quantile_impl: QuantileImpl,
+    policy: &QuantilePolicy,
+    merge_archive_outputs: bool,
+    heatmap_window: Option<i64>,
+    cache: Option<&MemberCache>,
+    inflight_bytes: usize,
 ) -> Result<()> {
-    let sources = collect_sources(log_path)?;
+    let mut sources = collect_hosts(log_path, merge_archive_outputs)?;
     let mut host_processed: usize = 0;
     let total_hosts = sources.len();
     let expected_samples_per_block = total_hosts.max(1);
+    data.hosts_expected = total_hosts;
 
     let mut worker_count = thread::available_parallelism()
         .map(|n| n.get())
@@ -171,40 +548,69 @@ pub fn load_and_merge_hosts(
 
     if worker_count == 1 {
         for source in &sources {
-            let host = load_source(source)?;
-            merge_host_data(data, host, quantile_impl, expected_samples_per_block);
+            if shutdown::requested() {
+                break;
+            }
+            let host = load_host(source, cache)?;
+            merge_host_data(data, host, policy, expected_samples_per_block);
             host_processed += 1;
             if host_processed % 100 == 0 {
                 eprintln!("processed {}/{} hosts...", host_processed, total_hosts);
             }
         }
+        finalize_aggs(data, policy, expected_samples_per_block, heatmap_window);
         return Ok(());
     }
 
+    // Longest first: in path order, one large archive picked up last keeps a single worker busy
+    // long after the others ran out of hosts. Members of an archive share its size and stay in
+    // path order.
+    sources.sort_by_key(|(_, host)| Reverse(host.size().unwrap_or(0)));
     let shared_sources = Arc::new(sources);
     let next_index = Arc::new(AtomicUsize::new(0));
-    let (tx, rx) = mpsc::sync_channel::<Result<HostBlocksLog>>(worker_count * 2);
+    let budget = Arc::new(InflightBudget::new(inflight_bytes));
+    // Unbounded in count; the budget bounds it in bytes.
+    let (tx, rx) = mpsc::channel::<Result<(HostBlocksLog, usize)>>();
     let mut handles = Vec::with_capacity(worker_count);
 
     for _ in 0..worker_count {
         let tx = tx.clone();
         let shared_sources = Arc::clone(&shared_sources);
         let next_index = Arc::clone(&next_index);
+        let budget = Arc::clone(&budget);
+        let cache = cache.cloned();
         handles.push(thread::spawn(move || loop {
             let idx = next_index.fetch_add(1, Ordering::Relaxed);
-            if idx >= shared_sources.len() {
+            if idx >= shared_sources.len() || shutdown::requested() {
                 break;
             }
-            if tx.send(load_source(&shared_sources[idx])).is_err() {
+            let result = load_host(&shared_sources[idx], cache.as_ref()).map(|host| {
+                let bytes = host.estimated_bytes();
+                (host, bytes)
+            });
+            let bytes = result.as_ref().map_or(0, |(_, bytes)| *bytes);
+            if !budget.acquire(bytes) || tx.send(result).is_err() {
                 break;
             }
         }));
     }
     drop(tx);
 
+    let shards = ShardedBlockMerge::new(worker_count, policy, expected_samples_per_block);
+    let mut error = None;
     for result in rx {
-        let host = result?;
-        merge_host_data(data, host, quantile_impl, expected_samples_per_block);
+        if shutdown::requested() {
+            break;
+        }
+        let (host, bytes) = match result {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                error = Some(e);
+                break;
+            }
+        };
+        shards.send(merge_host_except_blocks(data, host, policy));
+        budget.release(bytes);
         host_processed += 1;
         if host_processed % 100 == 0 {
             eprintln!("processed {}/{} hosts...", host_processed, total_hosts);
@@ -213,13 +619,13 @@ pub fn load_and_merge_hosts(
             break;
         }
     }
+    budget.close();
+    shards.finish(data);
+    if let Some(e) = error {
+        return Err(e);
+    }
 
-    data.block_dists
-        .values_mut()
-        .map(HashMap::values_mut)
-        .flatten()
-        .par_bridge()
-        .for_each(QuantileAgg::finalize);
+    finalize_aggs(data, policy, expected_samples_per_block, heatmap_window);
 
     for handle in handles {
         let _ = handle.join();
@@ -228,7 +634,62 @@ pub fn load_and_merge_hosts(
     Ok(())
 }
 
-pub fn validate_and_filter_blocks(data: &mut AnalysisData, max_blocks: Option<usize>) {
+/// Moves the blocks of `data` into one `AnalysisData` per stretch between `gaps` (in time
+/// order), with the txs first received in that stretch. Per-host values that are not tied to a
+/// time (sync/cons gap stats, by_block_ratio, tx waits, tx receptions) stay in `data`.
+pub fn split_at_gaps(data: &mut AnalysisData, gaps: &[TimestampGap]) -> Vec<AnalysisData> {
+    let segment_of = |t: f64| gaps.partition_point(|g| g.after as f64 <= t);
+    let mut segments: Vec<AnalysisData> = (0..=gaps.len())
+        .map(|_| AnalysisData {
+            node_count: data.node_count,
+            hosts: data.hosts.clone(),
+            hosts_expected: data.hosts_expected,
+            legacy_hosts: data.legacy_hosts,
+            host_digests: data.host_digests.clone(),
+            warnings: data.warnings.clone(),
+            ..Default::default()
+        })
+        .collect();
+
+    let mut block_segment = HashMap::with_capacity(data.blocks.len());
+    for (h, b) in std::mem::take(&mut data.blocks) {
+        let i = segment_of(b.timestamp as f64);
+        block_segment.insert(h, i);
+        segments[i].blocks.insert(h, b);
+    }
+    for (h, dists) in std::mem::take(&mut data.block_dists) {
+        if let Some(&i) = block_segment.get(&h) {
+            segments[i].block_dists.insert(h, dists);
+        }
+    }
+    for ((h, key), agg) in std::mem::take(&mut data.quantile_shadows) {
+        if let Some(&i) = block_segment.get(&h) {
+            segments[i].quantile_shadows.insert((h, key), agg);
+        }
+    }
+    for (window, agg) in std::mem::take(&mut data.sync_buckets) {
+        segments[segment_of(window as f64)]
+            .sync_buckets
+            .insert(window, agg);
+    }
+    for (h, tx) in std::mem::take(&mut data.txs) {
+        let first = tx.received.min();
+        let i = if first.is_finite() {
+            segment_of(first)
+        } else {
+            0
+        };
+        segments[i].txs.insert(h, tx);
+    }
+    segments
+}
+
+/// Removes the blocks not every node synced, then all but the earliest `max_blocks`. Returns
+/// the blocks removed as not synced everywhere, by hash.
+pub fn validate_and_filter_blocks(
+    data: &mut AnalysisData,
+    max_blocks: Option<usize>,
+) -> Vec<RemovedBlock> {
     let mut removed_blocks: Vec<H256> = Vec::new();
     for (block_hash, per_key) in &data.block_dists {
         if let Some(sync) = per_key.get("Sync") {
@@ -240,15 +701,33 @@ pub fn validate_and_filter_blocks(data: &mut AnalysisData, max_blocks: Option<us
         }
     }
 
+    removed_blocks.sort_unstable();
+    let mut removed = Vec::with_capacity(removed_blocks.len());
     for h in &removed_blocks {
         if let Some(per_key) = data.block_dists.get(h) {
             let sync_cnt = per_key.get("Sync").map(|a| a.count).unwrap_or(0);
-            println!(
+            removed.push(RemovedBlock {
+                hash: *h,
+                timestamp: data.blocks.get(h).map_or(0, |b| b.timestamp),
+                received: sync_cnt as usize,
+                total: data.node_count,
+            });
+            let message = format!(
                 "sync graph missed block {}: received = {}, total = {}",
                 format!("{:#x}", h),
                 sync_cnt,
                 data.node_count
             );
+            println!("{}", message);
+            data.warnings.push(Warning::new(
+                "removed_block",
+                message,
+                serde_json::json!({
+                    "block": format!("{:#x}", h),
+                    "received": sync_cnt,
+                    "total": data.node_count,
+                }),
+            ));
         }
         data.block_dists.remove(h);
         data.blocks.remove(h);
@@ -257,7 +736,7 @@ pub fn validate_and_filter_blocks(data: &mut AnalysisData, max_blocks: Option<us
     if let Some(n) = max_blocks {
         let mut pairs: Vec<(H256, i64)> =
             data.blocks.iter().map(|(h, b)| (*h, b.timestamp)).collect();
-        pairs.sort_by(|a, b| a.1.cmp(&b.1));
+        pairs.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
         if pairs.len() > n {
             let keep: std::collections::HashSet<H256> =
                 pairs.into_iter().take(n).map(|p| p.0).collect();
@@ -270,4 +749,171 @@ pub fn validate_and_filter_blocks(data: &mut AnalysisData, max_blocks: Option<us
             );
         }
     }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::NodePercentile;
+
+    /// Three hosts disagreeing on every block field a host may lack or get wrong. Samples are
+    /// multiples of 1/8, so their sums are exact in any order.
+    const HOSTS: [&str; 3] = [
+        r#"{"blocks": {
+            "0x01": {"timestamp": 100, "txs": 3, "size": 0, "parent": "0x09",
+                     "referees": ["0x07"], "latencies": {"Sync": [0.5, 0.25], "Cons": [1.0]}},
+            "0x02": {"timestamp": 0, "latencies": {"Sync": [0.125]}}},
+         "txs": {"0x0a": {"received_timestamps": [100.5, 101.0], "packed_timestamps": [102.0]}},
+         "sync_cons_gap_stats": [{"Avg": 1.0}, {"Avg": 2.0}]}"#,
+        r#"{"blocks": {
+            "0x01": {"timestamp": 99, "txs": 0, "size": 512, "parent": "0x08",
+                     "referees": ["0x06", "0x05"], "latencies": {"Sync": [0.75]}},
+            "0x02": {"timestamp": 160, "txs": 1, "latencies": {"Sync": [0.375]}}},
+         "txs": {"0x0a": {"received_timestamps": [100.25], "packed_timestamps": [101.5, null]}},
+         "sync_cons_gap_stats": [{"Avg": 3.0}]}"#,
+        r#"{"blocks": {
+            "0x01": {"timestamp": 101, "txs": 2, "size": 256,
+                     "referees": ["0x04", "0x05"], "latencies": {"Sync": [0.875], "Cons": [2.5]}},
+            "0x03": {"timestamp": 170, "latencies": {"Sync": [0.625]}}},
+         "sync_cons_gap_stats": [{"Avg": 4.0}]}"#,
+    ];
+
+    /// The hash a short hash `0x0N` of `HOSTS` stands for, spread over the leading bytes so
+    /// that the blocks fall into different shards.
+    fn hash(n: u64) -> H256 {
+        let mut h = H256::from_low_u64_be(n);
+        h.0[0] = (n * 37) as u8;
+        h
+    }
+
+    /// The host of `HOSTS`, with each short hash spelled out.
+    fn host(json: &str) -> HostBlocksLog {
+        let json = (1..=10).fold(json.to_string(), |json, n| {
+            json.replace(&format!("\"0x{:02x}\"", n), &format!("\"{:?}\"", hash(n)))
+        });
+        let mut host: HostBlocksLog = serde_json::from_str(&json).unwrap();
+        host.normalize().unwrap();
+        host
+    }
+
+    /// Everything merging leaves in `data` that a report reads, in a fixed order.
+    fn fingerprint(data: &AnalysisData) -> String {
+        let mut lines = vec![format!("nodes {}", data.node_count)];
+        let mut blocks: Vec<_> = data.blocks.iter().collect();
+        blocks.sort_by_key(|(h, _)| **h);
+        for (h, b) in blocks {
+            lines.push(format!("{:?} {:?}", h, b));
+            let mut keys: Vec<_> = data.block_dists[h].iter().collect();
+            keys.sort_by_key(|(k, _)| *k);
+            for (k, agg) in keys {
+                let values: Vec<f64> = NodePercentile::all_in_order()
+                    .iter()
+                    .map(|p| agg.value_for(*p))
+                    .collect();
+                lines.push(format!("  {} {} {:?}", k, agg.count, values));
+            }
+        }
+        for (window, agg) in &data.sync_buckets {
+            lines.push(format!("window {} {}", window, agg.count));
+        }
+        let mut txs: Vec<_> = data.txs.iter().collect();
+        txs.sort_by_key(|(h, _)| **h);
+        for (h, tx) in txs {
+            lines.push(format!(
+                "{:?} {} {} {} {} {}",
+                h,
+                tx.received.len(),
+                tx.received.min(),
+                tx.packed.len(),
+                tx.packed.min(),
+                tx.ready.len()
+            ));
+        }
+        let mut waits = data.tx_wait_to_be_packed.clone();
+        waits.sort_by(f64::total_cmp);
+        let mut gaps = data.sync_gap_avg.clone();
+        gaps.sort_by(f64::total_cmp);
+        lines.push(format!("waits {:?} gaps {:?}", waits, gaps));
+        lines.join("\n")
+    }
+
+    fn permutations(n: usize) -> Vec<Vec<usize>> {
+        match n {
+            0 => vec![vec![]],
+            _ => permutations(n - 1)
+                .into_iter()
+                .flat_map(|p| {
+                    (0..n).map(move |at| {
+                        let mut p = p.clone();
+                        p.insert(at, n - 1);
+                        p
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    fn brute_policy() -> QuantilePolicy {
+        QuantilePolicy {
+            default: QuantileImpl::Brute,
+            retain_raw: Default::default(),
+            verify_fraction: 0.0,
+            seed: 0,
+            tx_streaming: false,
+        }
+    }
+
+    #[test]
+    fn test_merge_order_does_not_change_the_result() {
+        let policy = brute_policy();
+        let merged: Vec<String> = permutations(HOSTS.len())
+            .into_iter()
+            .map(|order| {
+                let mut data = AnalysisData::default();
+                let hosts = order.iter().map(|&i| host(HOSTS[i])).collect();
+                merge_hosts(&mut data, hosts, &policy, Some(60));
+                let removed = validate_and_filter_blocks(&mut data, None);
+                let warnings: Vec<&str> =
+                    data.warnings.iter().map(|w| w.message.as_str()).collect();
+                format!("{}\n{:?}\n{:?}", fingerprint(&data), warnings, removed)
+            })
+            .collect();
+        assert_eq!(merged.len(), 6);
+        for m in &merged[1..] {
+            assert_eq!(m, &merged[0]);
+        }
+
+        let block = &merged[0];
+        assert!(block.contains(&format!(
+            "{:?} BlockInfo {{ timestamp: 99, txs: 3, size: 512, parent: Some({:?}), referees: [{:?}, {:?}] }}",
+            hash(1),
+            hash(8),
+            hash(4),
+            hash(5)
+        )), "{}", block);
+    }
+
+    #[test]
+    fn test_sharded_merge_matches_single_thread() {
+        let policy = brute_policy();
+        let mut single = AnalysisData::default();
+        merge_hosts(&mut single, HOSTS.map(host).into(), &policy, Some(60));
+
+        for shard_count in [1, 2, 3, 256] {
+            let mut data = AnalysisData::default();
+            let shards = ShardedBlockMerge::new(shard_count, &policy, HOSTS.len());
+            for json in HOSTS {
+                shards.send(merge_host_except_blocks(&mut data, host(json), &policy));
+            }
+            shards.finish(&mut data);
+            finalize_aggs(&mut data, &policy, HOSTS.len(), Some(60));
+            assert_eq!(
+                fingerprint(&data),
+                fingerprint(&single),
+                "{} shards",
+                shard_count
+            );
+        }
+    }
 }
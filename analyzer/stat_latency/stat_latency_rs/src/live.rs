@@ -0,0 +1,209 @@
+use anyhow::{anyhow, Context, Result};
+use ethereum_types::H256;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::block_latencies::BlockLatencies;
+use crate::model::{parse_h256, BlockJson, HostBlocksLog};
+use crate::shutdown;
+
+/// At most this many epochs are listed per poll when a node falls behind, newest first.
+const MAX_EPOCHS_PER_POLL: u64 = 16;
+
+/// One node's JSON-RPC endpoint, e.g. `http://10.0.3.17:12537`.
+struct Endpoint {
+    url: String,
+    agent: ureq::Agent,
+}
+
+impl Endpoint {
+    fn new(url: &str, timeout: Duration) -> Self {
+        Self {
+            url: url.to_string(),
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        }
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value> {
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        let response: Value = self
+            .agent
+            .post(&self.url)
+            .send_json(request)
+            .with_context(|| format!("{} {}", self.url, method))?
+            .into_json()
+            .with_context(|| format!("{} {}: invalid response", self.url, method))?;
+        if let Some(error) = response.get("error") {
+            return Err(anyhow!("{} {}: {}", self.url, method, error));
+        }
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+}
+
+fn now_secs() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+fn hex_u64(v: &Value) -> Option<u64> {
+    u64::from_str_radix(v.as_str()?.trim_start_matches("0x"), 16).ok()
+}
+
+/// Records the node's best block, and the blocks of the epochs after `last_epoch`, as seen now.
+fn poll_once(
+    endpoint: &Endpoint,
+    seen: &mut HashMap<H256, f64>,
+    last_epoch: &mut Option<u64>,
+) -> Result<()> {
+    let status = endpoint.call("cfx_getStatus", json!([]))?;
+    let at = now_secs();
+    if let Some(h) = status["bestHash"].as_str().and_then(|h| parse_h256(h).ok()) {
+        seen.entry(h).or_insert(at);
+    }
+    let Some(epoch) = hex_u64(&status["epochNumber"]) else {
+        return Ok(());
+    };
+    let from = match *last_epoch {
+        Some(last) => (last + 1).max(epoch.saturating_sub(MAX_EPOCHS_PER_POLL - 1)),
+        None => epoch,
+    };
+    for n in from..=epoch {
+        let hashes = endpoint.call("cfx_getBlocksByEpoch", json!([format!("{:#x}", n)]))?;
+        for h in hashes.as_array().into_iter().flatten() {
+            if let Some(h) = h.as_str().and_then(|h| parse_h256(h).ok()) {
+                seen.entry(h).or_insert(at);
+            }
+        }
+    }
+    *last_epoch = Some(epoch.max(last_epoch.unwrap_or(0)));
+    Ok(())
+}
+
+/// When each block was first listed by the node: as its best block, or in an epoch newer than
+/// the last one seen. Blocks listed by the first poll are left out, as the node had them for
+/// an unknown time already.
+fn poll_node(endpoint: &Endpoint, duration: Duration, interval: Duration) -> HashMap<H256, f64> {
+    let mut seen = HashMap::new();
+    let mut before_start: Option<HashSet<H256>> = None;
+    let mut last_epoch = None;
+    let mut errors = 0usize;
+    let end = Instant::now() + duration;
+    while Instant::now() < end && !shutdown::requested() {
+        let started = Instant::now();
+        if let Err(e) = poll_once(endpoint, &mut seen, &mut last_epoch) {
+            errors += 1;
+            if errors == 1 || errors.is_multiple_of(100) {
+                eprintln!("poll failed ({} times): {:#}", errors, e);
+            }
+        } else if before_start.is_none() {
+            before_start = Some(seen.drain().map(|(h, _)| h).collect());
+        }
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+    let before_start = before_start.unwrap_or_default();
+    seen.retain(|h, _| !before_start.contains(h));
+    seen
+}
+
+/// Generation time, tx count, size and referees of a block, from any node that has it.
+fn fetch_block(endpoints: &[Endpoint], h: &H256) -> Option<BlockJson> {
+    endpoints.iter().find_map(|endpoint| {
+        let block = endpoint
+            .call("cfx_getBlockByHash", json!([format!("{:#x}", h), false]))
+            .ok()?;
+        Some(BlockJson {
+            timestamp: hex_u64(&block["timestamp"])? as i64,
+            parent: block["parentHash"]
+                .as_str()
+                .and_then(|h| parse_h256(h).ok()),
+            txs: block["transactions"]
+                .as_array()
+                .map_or(0, |t| t.len() as i64),
+            size: hex_u64(&block["size"]).unwrap_or(0) as i64,
+            referees: block["refereeHashes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|r| parse_h256(r.as_str()?).ok())
+                .collect(),
+            latencies: BlockLatencies::default(),
+        })
+    })
+}
+
+/// Polls every node in `urls` for `duration` (or until Ctrl-C), then builds one host log per
+/// node as if it had been logged: the latency of a block is when the node's RPC first listed it
+/// minus the block timestamp, recorded as `Sync` (the block is in the node's graph by then), so
+/// it is an upper bound within `interval` and the block timestamp's one-second resolution.
+/// There are no tx, Receive/Cons or sync/cons gap samples.
+pub fn poll_cluster(
+    urls: &[String],
+    duration: Duration,
+    interval: Duration,
+) -> Result<Vec<HostBlocksLog>> {
+    let endpoints: Vec<Endpoint> = urls
+        .iter()
+        .map(|url| Endpoint::new(url, interval.max(Duration::from_secs(2))))
+        .collect();
+    for endpoint in &endpoints {
+        endpoint
+            .call("cfx_getStatus", json!([]))
+            .context("node not reachable")?;
+    }
+    println!(
+        "Polling {} nodes every {} ms for {} s...",
+        endpoints.len(),
+        interval.as_millis(),
+        duration.as_secs()
+    );
+    let seen: Vec<HashMap<H256, f64>> = thread::scope(|scope| {
+        let handles: Vec<_> = endpoints
+            .iter()
+            .map(|endpoint| scope.spawn(|| poll_node(endpoint, duration, interval)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or_default())
+            .collect()
+    });
+
+    let mut hashes: Vec<H256> = seen.iter().flat_map(|s| s.keys().copied()).collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+    let blocks: HashMap<H256, BlockJson> = hashes
+        .iter()
+        .filter_map(|h| Some((*h, fetch_block(&endpoints, h)?)))
+        .collect();
+    println!("{} blocks seen, {} fetched", hashes.len(), blocks.len());
+
+    Ok(endpoints
+        .iter()
+        .zip(seen)
+        .map(|(endpoint, seen)| HostBlocksLog {
+            blocks: seen
+                .into_iter()
+                .filter_map(|(h, at)| {
+                    let block = blocks.get(&h)?;
+                    let mut latencies = BlockLatencies::default();
+                    latencies.push("Sync", &[at - block.timestamp as f64]);
+                    Some((
+                        h,
+                        BlockJson {
+                            referees: block.referees.clone(),
+                            latencies,
+                            ..*block
+                        },
+                    ))
+                })
+                .collect(),
+            // One node per endpoint.
+            sync_cons_gap_stats: vec![HashMap::new()],
+            host: endpoint.url.clone(),
+            ..Default::default()
+        })
+        .collect())
+}
@@ -1,73 +1,437 @@
 mod analyzer;
 mod args;
+mod block_latencies;
 mod config;
+mod confirmation;
+mod format;
+mod heatmap;
 mod host_processing;
 mod io_utils;
+mod live;
+mod metrics_log;
 mod model;
 mod quantile;
 mod quantile_brute;
 mod quantile_tdigest;
+mod quantile_verify;
 mod report;
+mod repro;
+mod run_meta;
+mod schema_check;
+mod shutdown;
+mod sparkline;
 mod stats;
+mod summary;
+mod tx_sketch;
+mod validate;
+mod warnings;
 
-use anyhow::{anyhow, Result};
+// Loading thousands of hosts churns through short-lived Vecs and HashMaps, enough for the
+// allocator to show up in profiles. With both features, mimalloc is used.
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+#[cfg(all(feature = "jemalloc", not(feature = "mimalloc")))]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+use anyhow::{anyhow, Context, Result};
 use clap::Parser;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use testlog_io::MemberCache;
+use warnings::Warning;
 
 use analyzer::{
-    analyze_txs, build_block_row_values, build_tx_rows, collect_block_scalars,
-    print_throughput_and_slowest,
+    analyze_txs, build_block_row_values, build_block_row_values_where, build_dag_metrics,
+    build_earliest_receive_row_values, build_size_series, build_stage_waterfall, build_tx_reach,
+    build_tx_rows, collect_block_scalars, find_timestamp_gaps, print_dag_metrics,
+    print_stage_waterfall, print_throughput_and_slowest, print_tx_reach, TxClass,
 };
-use args::{Args, QuantileImplArg};
+use args::{Args, Command, QuantileImplArg};
 use config::{default_latency_key_names, pivot_event_key_names};
-use host_processing::{load_and_merge_hosts, validate_and_filter_blocks};
+use host_processing::{load_and_merge_hosts, split_at_gaps, validate_and_filter_blocks};
 use model::AnalysisData;
-use quantile::QuantileImpl;
+use quantile::{QuantileImpl, QuantilePolicy};
 use report::{
-    add_block_rows, add_block_scalar_rows, add_custom_block_rows, add_sync_gap_rows, add_tx_rows,
-    build_table_title,
+    add_block_rows, add_block_scalar_rows, add_confirmation_rows, add_custom_block_rows,
+    add_dag_rows, add_earliest_receive_rows, add_metric_rows, add_size_series_rows,
+    add_stage_waterfall_rows, add_sync_gap_rows, add_tx_reach_rows, add_tx_rows, build_table_title,
 };
+use run_meta::RunMeta;
 
 fn main() -> Result<()> {
-    let profile_enabled = std::env::var("STAT_LATENCY_PROFILE")
-        .ok()
-        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false);
+    let profile_enabled = profile_enabled();
     let t0 = Instant::now();
 
     let args = Args::parse();
-    if !args.log_path.exists() {
-        return Err(anyhow!("log path not found: {}", args.log_path.display()));
+    shutdown::install_handler()?;
+    if let Some(Command::Validate {
+        log_path,
+        merge_archive_outputs,
+    }) = &args.command
+    {
+        if !validate::validate(log_path, *merge_archive_outputs)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let log_path = args.log_path.clone().unwrap_or_default();
+    if args.rpc.is_empty() && log_path != Path::new("-") && !log_path.exists() {
+        return Err(anyhow!("log path not found: {}", log_path.display()));
     }
 
-    let default_keys = default_latency_key_names();
-    let pivot_keys = pivot_event_key_names();
-    let quantile_impl = match args.quantile_impl {
-        QuantileImplArg::Brute => QuantileImpl::Brute,
-        QuantileImplArg::Tdigest => QuantileImpl::TDigest,
+    if !matches!(args.tdigest_tails[..], [low, high] if (0.0..=0.5).contains(&low) && (0.0..=0.5).contains(&high))
+    {
+        return Err(anyhow!(
+            "tdigest tails must be two fractions in [0, 0.5], low then high, e.g. 0.1,0.1"
+        ));
+    }
+    if args
+        .verify_quantiles
+        .is_some_and(|f| !(f > 0.0 && f <= 1.0))
+    {
+        return Err(anyhow!("verify fraction must be in (0, 1]"));
+    }
+    let policy = QuantilePolicy {
+        default: match args.quantile_impl {
+            QuantileImplArg::Brute => QuantileImpl::Brute,
+            QuantileImplArg::Tdigest => QuantileImpl::TDigest {
+                low_tail: args.tdigest_tails[0],
+                high_tail: args.tdigest_tails[1],
+            },
+        },
+        retain_raw: args.retain_raw.iter().cloned().collect(),
+        verify_fraction: args.verify_quantiles.unwrap_or(0.0),
+        seed: args.seed,
+        tx_streaming: args.tx_streaming,
     };
+    if args.by_tx_class
+        && !matches!(args.tx_class_bounds[..], [small, large] if 0 < small && small < large)
+    {
+        return Err(anyhow!(
+            "tx class bounds must be two tx counts, small then large, e.g. 100,2000"
+        ));
+    }
+    if args.heatmap_window <= 0 {
+        return Err(anyhow!("heatmap window must be positive"));
+    }
+    if args.full_report
+        && (args.adv_percents.iter().any(|&p| p >= 50)
+            || args.risk_thresholds.iter().any(|&r| !(r > 0.0 && r < 1.0)))
+    {
+        return Err(anyhow!(
+            "adv percents must be below 50 and risk thresholds in (0, 1)"
+        ));
+    }
+
+    if let Some(key) = args
+        .summary_keys
+        .iter()
+        .find(|k| !summary::SUMMARY_KEYS.contains(&k.as_str()))
+    {
+        return Err(anyhow!(
+            "unknown summary key {}, expected some of {}",
+            key,
+            summary::SUMMARY_KEYS.join(",")
+        ));
+    }
+
+    format::set_formatter(format::Formatter {
+        decimals: args.decimals,
+        scientific_below: args.scientific_below,
+    });
+    if args.ci {
+        stats::enable_confidence_intervals();
+    }
+    if args.robust {
+        stats::enable_robust_statistics();
+    }
+    if args.sparklines {
+        sparkline::enable_sparklines();
+    }
+
+    let run_meta = args.run_meta.as_deref().map(RunMeta::load).transpose()?;
+    let cache = args
+        .extract_cache
+        .as_deref()
+        .map(|dir| MemberCache::new(dir, args.extract_cache_max_mb.saturating_mul(1 << 20)))
+        .transpose()?;
+
+    let groups = match args.group_by_dir_depth {
+        Some(depth) => host_processing::group_dirs(&log_path, depth, args.merge_archive_outputs)?,
+        None => vec![],
+    };
+    let mut incomplete = false;
+    if !args.rpc.is_empty() {
+        let hosts = live::poll_cluster(
+            &args.rpc,
+            Duration::from_secs(args.rpc_duration),
+            Duration::from_millis(args.rpc_interval_ms),
+        )?;
+        let mut data = AnalysisData::default();
+        let heatmap_window = args.sync_heatmap.as_ref().map(|_| args.heatmap_window);
+        host_processing::merge_hosts(&mut data, hosts, &policy, heatmap_window);
+        incomplete = report(&args, &log_path, None, data, run_meta.as_ref())?;
+    } else if groups.is_empty() {
+        let data = load(&args, &log_path, &policy, cache.as_ref())?;
+        incomplete = report(&args, &log_path, None, data, run_meta.as_ref())?;
+    } else {
+        println!(
+            "{} groups at depth {}: {}",
+            groups.len(),
+            args.group_by_dir_depth.unwrap_or(0),
+            groups
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        for (i, (name, dir)) in groups.iter().enumerate() {
+            if shutdown::requested() {
+                println!(
+                    "INCOMPLETE, interrupted before reporting {} of {} groups",
+                    groups.len() - i,
+                    groups.len()
+                );
+                incomplete = true;
+                break;
+            }
+            println!();
+            println!("===== group {} =====", name);
+            incomplete |= load(&args, dir, &policy, cache.as_ref())
+                .and_then(|data| report(&args, dir, Some(name), data, run_meta.as_ref()))
+                .with_context(|| format!("report group {}", name))?;
+        }
+    }
+    if profile_enabled {
+        eprintln!("[profile] total main: {:.3}s", t0.elapsed().as_secs_f64());
+    }
+
+    if incomplete {
+        // The exit status of an interrupted process, so scripts do not take this for a full run.
+        std::process::exit(130);
+    }
+    Ok(())
+}
+
+/// Loads and merges the hosts under `log_path`.
+fn load(
+    args: &Args,
+    log_path: &Path,
+    policy: &QuantilePolicy,
+    cache: Option<&MemberCache>,
+) -> Result<AnalysisData> {
+    let heatmap_window = args.sync_heatmap.as_ref().map(|_| args.heatmap_window);
     let mut data = AnalysisData::default();
     let t_load = Instant::now();
-    load_and_merge_hosts(&args.log_path, &mut data, quantile_impl)?;
-    if profile_enabled {
+    load_and_merge_hosts(
+        log_path,
+        &mut data,
+        policy,
+        args.merge_archive_outputs,
+        heatmap_window,
+        cache,
+        args.inflight_memory.saturating_mul(1 << 20) as usize,
+    )?;
+    if profile_enabled() {
         eprintln!(
             "[profile] load_and_merge_hosts: {:.3}s",
             t_load.elapsed().as_secs_f64()
         );
     }
+    Ok(data)
+}
 
+/// Prints the report of the hosts in `data`, loaded from `log_path`, and writes the exports;
+/// with `group`, every export path gets the group name before its extension and the header
+/// names the group. Returns whether loading was interrupted before every host was merged.
+fn report(
+    args: &Args,
+    log_path: &Path,
+    group: Option<&str>,
+    mut data: AnalysisData,
+    run_meta: Option<&RunMeta>,
+) -> Result<bool> {
     if data.node_count == 0 {
         return Err(anyhow!("no nodes found (sync_cons_gap_stats empty)"));
     }
 
-    validate_and_filter_blocks(&mut data, args.max_blocks);
+    let repro = repro::ReproInfo::new(args.seed, &data);
+    repro.print();
+    let mut header = repro.fields();
+    if let Some(group) = group {
+        header.push(("group".to_string(), group.to_string()));
+    }
+    let incomplete = (data.hosts.len() < data.hosts_expected).then(|| {
+        format!(
+            "INCOMPLETE report, interrupted after merging {} of {} hosts ({:.1}%)",
+            data.hosts.len(),
+            data.hosts_expected,
+            data.hosts.len() as f64 / data.hosts_expected as f64 * 100.0
+        )
+    });
+    if let Some(note) = &incomplete {
+        println!("{}", note);
+        header.push(("coverage".to_string(), note.clone()));
+        data.warnings.push(Warning::new(
+            "host_coverage",
+            note.clone(),
+            serde_json::json!({"merged": data.hosts.len(), "expected": data.hosts_expected}),
+        ));
+    }
+    if let Some(meta) = run_meta {
+        meta.print();
+        header.extend(meta.fields());
+        if let Some(n) = meta.node_count.filter(|&n| n != data.node_count) {
+            let message = format!(
+                "run metadata says {} nodes, but {} were loaded",
+                n, data.node_count
+            );
+            eprintln!("{}", message);
+            data.warnings.push(Warning::new(
+                "node_coverage",
+                message,
+                serde_json::json!({"expected": n, "loaded": data.node_count}),
+            ));
+        }
+    }
+
+    if let Some(path) = &args.sync_heatmap {
+        let path = &group_output(path, group);
+        heatmap::write_sync_heatmap(path, &data.sync_buckets, &header)?;
+        println!(
+            "Sync heatmap of {} windows written to {}",
+            data.sync_buckets.len(),
+            path.display()
+        );
+    }
+
+    if args.verify_quantiles.is_some() {
+        quantile_verify::print_quantile_errors(&data);
+    }
+
+    let removed = validate_and_filter_blocks(&mut data, args.max_blocks);
+    if let Some(path) = &args.removed_blocks_out {
+        let path = &group_output(path, group);
+        heatmap::write_removed_blocks(path, &removed, &header)?;
+        println!(
+            "{} removed blocks written to {}",
+            removed.len(),
+            path.display()
+        );
+    }
+    if let Some(path) = &args.block_set_out {
+        let path = &group_output(path, group);
+        let filter = match args.max_blocks {
+            Some(n) => format!("synced by every node, earliest {}", n),
+            None => "synced by every node".to_string(),
+        };
+        let set = confirmation::kept_block_set(&data, filter, &header);
+        set.write(path)?;
+        println!(
+            "Block set of {} blocks written to {}",
+            set.blocks.len(),
+            path.display()
+        );
+    }
+    if data.legacy_hosts > 0 {
+        println!(
+            "{} of {} hosts logged with the legacy schema (Relay/Insert keys)",
+            data.legacy_hosts,
+            data.hosts.len()
+        );
+    }
+    let gaps = find_timestamp_gaps(&data, args.gap_threshold);
+    for gap in &gaps {
+        let message = format!(
+            "block timestamps jump by {}s from {} to {} (node restart or paused test?)",
+            gap.seconds(),
+            gap.before,
+            gap.after
+        );
+        println!("{}", message);
+        data.warnings.push(Warning::new(
+            "timestamp_gap",
+            message,
+            serde_json::json!({"before": gap.before, "after": gap.after, "seconds": gap.seconds()}),
+        ));
+    }
+    if !args.split_at_gaps || gaps.is_empty() {
+        report_data(args, log_path, group, &header, data, incomplete.as_deref())?;
+        return Ok(incomplete.is_some());
+    }
+
+    let segments = split_at_gaps(&mut data, &gaps);
+    println!(
+        "Split into {} segments at the gaps above; the per-host rows (sync/cons gap, \
+         by_block_ratio, tx wait) cover the whole run and are left empty",
+        segments.len()
+    );
+    let count = segments.len();
+    for (i, segment) in segments.into_iter().enumerate() {
+        let range = segment.blocks.values().map(|b| b.timestamp);
+        let bounds = format!(
+            "{} of {}, blocks from {} to {}",
+            i + 1,
+            count,
+            range.clone().min().unwrap_or(0),
+            range.max().unwrap_or(0)
+        );
+        println!();
+        println!("===== segment {} =====", bounds);
+        let mut header = header.clone();
+        header.push(("segment".to_string(), bounds));
+        let label = match group {
+            Some(group) => format!("{}.segment{}", group, i + 1),
+            None => format!("segment{}", i + 1),
+        };
+        report_data(
+            args,
+            log_path,
+            Some(&label),
+            &header,
+            segment,
+            incomplete.as_deref(),
+        )?;
+    }
+    Ok(incomplete.is_some())
+}
+
+/// Analyzes validated `data`, prints the report and writes the exports named after `label`.
+fn report_data(
+    args: &Args,
+    log_path: &Path,
+    label: Option<&str>,
+    header: &[(String, String)],
+    mut data: AnalysisData,
+    incomplete: Option<&str>,
+) -> Result<()> {
+    let profile_enabled = profile_enabled();
+    let default_keys = default_latency_key_names();
+    let pivot_keys = pivot_event_key_names();
     println!("{} nodes in total", data.node_count);
     println!("{} blocks generated", data.blocks.len());
 
     let t_analyze = Instant::now();
     let tx_analysis = analyze_txs(&data);
+    if tx_analysis.missing_tx > 0 {
+        data.warnings.push(Warning::new(
+            "tx_coverage",
+            format!(
+                "{} of {} txs were not received by every node",
+                tx_analysis.missing_tx,
+                data.txs.len()
+            ),
+            serde_json::json!({"missing": tx_analysis.missing_tx, "total": data.txs.len()}),
+        ));
+    }
     let (mut row_values, custom_keys) = build_block_row_values(&data, &default_keys, &pivot_keys);
-    let (mut tx_latency_rows, mut tx_packed_rows) = build_tx_rows(&data);
+    let mut tx_rows = build_tx_rows(&data);
+    let tx_reach = args
+        .tx_reach
+        .then(|| build_tx_reach(&data, args.tx_reach_top));
     if profile_enabled {
         eprintln!(
             "[profile] analyze/build rows: {:.3}s",
@@ -77,20 +441,144 @@ fn main() -> Result<()> {
 
     let t_report = Instant::now();
     let scalars = collect_block_scalars(&data);
+    let summary = summary::Summary::new(&data, &scalars, &row_values, &tx_rows);
     print_throughput_and_slowest(&scalars, &tx_analysis.slowest_packed_hash);
+    if let Some(reach) = &tx_reach {
+        print_tx_reach(reach, data.node_count, args.tx_reach_top);
+    }
+    let dag = build_dag_metrics(&data);
+    print_dag_metrics(&dag);
+    let size_series = build_size_series(&data);
+    if let Some(last) = size_series.last() {
+        println!(
+            "Chain data size is {} bytes ({:.2} MB)",
+            last.cumulative_bytes,
+            last.cumulative_bytes as f64 / 1e6
+        );
+    }
+    if let Some(path) = &args.size_series {
+        let path = &group_output(path, label);
+        heatmap::write_size_series(path, &size_series, header)?;
+        println!(
+            "Block size series of {} windows written to {}",
+            size_series.len(),
+            path.display()
+        );
+    }
+    let mut metrics = None;
+    if !args.metrics.is_empty() {
+        metrics =
+            metrics_log::correlate_metrics(log_path, &args.metrics, args.heatmap_window, &data)?;
+        match &metrics {
+            Some(correlation) => {
+                metrics_log::print_metric_correlation(correlation, args.heatmap_window);
+                if let Some(path) = &args.metrics_out {
+                    let path = &group_output(path, label);
+                    heatmap::write_metric_windows(path, correlation, header)?;
+                    println!(
+                        "Metrics of {} windows written to {}",
+                        correlation.windows.len(),
+                        path.display()
+                    );
+                }
+            }
+            None => {
+                let message = format!(
+                    "no metrics.log under {}, metric rows skipped",
+                    log_path.display()
+                );
+                eprintln!("{}", message);
+                data.warnings.push(Warning::new(
+                    "missing_metrics",
+                    message,
+                    serde_json::json!({"log_path": log_path.display().to_string()}),
+                ));
+            }
+        }
+    }
+    let mut waterfall = args.stage_waterfall.then(|| build_stage_waterfall(&data));
+    if let Some(waterfall) = &waterfall {
+        print_stage_waterfall(waterfall);
+    }
 
     let mut table = build_table_title();
-    add_block_rows(&mut table, &mut row_values);
-    add_custom_block_rows(&mut table, &mut row_values, &custom_keys);
-    add_tx_rows(
-        &mut table,
-        &mut tx_latency_rows,
-        &mut tx_packed_rows,
-        &tx_analysis,
-        &data,
-    );
+    add_block_rows(&mut table, &mut row_values, None);
+    if args.earliest_receive_baseline {
+        add_earliest_receive_rows(&mut table, &mut build_earliest_receive_row_values(&data));
+    }
+    if let Some(waterfall) = &mut waterfall {
+        add_stage_waterfall_rows(&mut table, waterfall);
+    }
+    add_custom_block_rows(&mut table, &mut row_values, &custom_keys, None);
+    if args.by_tx_class {
+        let (small, large) = (args.tx_class_bounds[0], args.tx_class_bounds[1]);
+        for class in TxClass::all_in_order() {
+            let (mut class_rows, class_custom_keys) =
+                build_block_row_values_where(&data, &default_keys, &pivot_keys, |b| {
+                    TxClass::of(b.txs, small, large) == *class
+                });
+            let label = class.label(small, large);
+            add_block_rows(&mut table, &mut class_rows, Some(&label));
+            add_custom_block_rows(
+                &mut table,
+                &mut class_rows,
+                &class_custom_keys,
+                Some(&label),
+            );
+        }
+    }
+    add_tx_rows(&mut table, &mut tx_rows, &tx_analysis, &data);
+    if let Some(reach) = &tx_reach {
+        add_tx_reach_rows(&mut table, reach);
+    }
     add_block_scalar_rows(&mut table, &scalars);
+    add_dag_rows(&mut table, &dag);
+    add_size_series_rows(&mut table, &size_series);
     add_sync_gap_rows(&mut table, &data);
+    if let Some(correlation) = &metrics {
+        table.add_empty_row();
+        add_metric_rows(&mut table, correlation);
+    }
+
+    if args.full_report {
+        let t_dag = Instant::now();
+        match confirmation::load_dag(log_path)? {
+            Some((source, graph)) => {
+                println!(
+                    "DAG loaded from {} ({} blocks)",
+                    source,
+                    graph.block_count()
+                );
+                table.add_empty_row();
+                add_confirmation_rows(
+                    &mut table,
+                    confirmation::confirmation_time_rows(
+                        &graph,
+                        &args.adv_percents,
+                        &args.risk_thresholds,
+                    ),
+                );
+            }
+            None => {
+                let message = format!(
+                    "no *.log.new_blocks file under {}, confirmation rows skipped",
+                    log_path.display()
+                );
+                eprintln!("{}", message);
+                data.warnings.push(Warning::new(
+                    "missing_dag",
+                    message,
+                    serde_json::json!({"log_path": log_path.display().to_string()}),
+                ));
+            }
+        }
+        if profile_enabled {
+            eprintln!(
+                "[profile] load DAG/confirmation rows: {:.3}s",
+                t_dag.elapsed().as_secs_f64()
+            );
+        }
+    }
 
     use prettytable::format::{FormatBuilder, LinePosition, LineSeparator};
     let fmt = FormatBuilder::new()
@@ -106,13 +594,54 @@ fn main() -> Result<()> {
     table.set_format(fmt);
 
     table.printstd();
+    if let Some(note) = incomplete {
+        println!("{}", note);
+    }
+    if let Some(path) = &args.warnings_out {
+        let path = &group_output(path, label);
+        warnings::write_warnings(path, header, &data.warnings)?;
+        eprintln!(
+            "{} warnings written to {}",
+            data.warnings.len(),
+            path.display()
+        );
+    }
+    println!(
+        "{}",
+        summary.line(&args.summary_keys, label, data.warnings.len())
+    );
     if profile_enabled {
         eprintln!(
             "[profile] render table/print: {:.3}s",
             t_report.elapsed().as_secs_f64()
         );
-        eprintln!("[profile] total main: {:.3}s", t0.elapsed().as_secs_f64());
     }
 
     Ok(())
 }
+
+fn profile_enabled() -> bool {
+    std::env::var("STAT_LATENCY_PROFILE")
+        .ok()
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// `path` with the group name before its extension, e.g. `sync.round1.csv`; `/` in nested group
+/// names becomes `_`.
+fn group_output(path: &Path, group: Option<&str>) -> PathBuf {
+    let Some(group) = group else {
+        return path.to_path_buf();
+    };
+    let group = group.replace('/', "_");
+    let name = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => format!(
+            "{}.{}.{}",
+            stem.to_string_lossy(),
+            group,
+            ext.to_string_lossy()
+        ),
+        _ => format!("{}.{}", path.display(), group),
+    };
+    path.with_file_name(name)
+}
@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use std::path::Path;
+use testlog_io::{discover, Pattern, Source};
+
+use crate::model::{AnalysisData, MetricCorrelation, MetricWindow, NodePercentile};
+
+/// A metrics.log line: `<timestamp ms>, <module>, Group, {<key>: <value>, ...}`.
+struct MetricLine<'a> {
+    timestamp: i64,
+    module: &'a str,
+    values: Vec<(&'a str, f64)>,
+}
+
+/// Parses a metrics.log line. Other lines, and values that are not numbers, are skipped.
+fn parse_line(line: &str) -> Option<MetricLine<'_>> {
+    let mut parts = line.splitn(4, ", ");
+    let timestamp = parts.next()?.trim().parse::<i64>().ok()?;
+    let module = parts.next()?;
+    if parts.next()? != "Group" {
+        return None;
+    }
+    let body = parts.next()?.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let values = body
+        .split(", ")
+        .filter_map(|item| {
+            let (key, value) = item.split_once(": ")?;
+            Some((key.trim(), value.trim().parse::<f64>().ok()?))
+        })
+        .collect();
+    Some(MetricLine {
+        timestamp,
+        module,
+        values,
+    })
+}
+
+/// Whether `module::key` is the metric `name`, given as `module::key` or as a bare key of any
+/// module.
+fn matches(name: &str, module: &str, key: &str) -> bool {
+    match name.split_once("::") {
+        Some((m, k)) => m == module && k == key,
+        None => name == key,
+    }
+}
+
+/// Per window of `window` seconds, the sum and count of every metric in `names` over one log.
+fn load_one(
+    source: &Source,
+    names: &[String],
+    window: i64,
+) -> Result<BTreeMap<i64, Vec<(f64, usize)>>> {
+    let mut windows: BTreeMap<i64, Vec<(f64, usize)>> = BTreeMap::new();
+    for line in source.open()?.lines() {
+        let line = line.with_context(|| format!("read {}", source))?;
+        let Some(parsed) = parse_line(&line) else {
+            continue;
+        };
+        let start = parsed.timestamp.div_euclid(1000).div_euclid(window) * window;
+        for (key, value) in parsed.values {
+            for (i, name) in names.iter().enumerate() {
+                if matches(name, parsed.module, key) {
+                    let sums = windows
+                        .entry(start)
+                        .or_insert_with(|| vec![(0.0, 0); names.len()]);
+                    sums[i].0 += value;
+                    sums[i].1 += 1;
+                }
+            }
+        }
+    }
+    Ok(windows)
+}
+
+/// Mean over every metrics.log under `log_path` (one per host, compressed or in archives) of the
+/// metrics `names` per window of `window` seconds, next to the mean Sync P99 of the blocks
+/// generated in the window, and each metric's correlation with it over the windows that have
+/// both. `None` without any metrics.log.
+pub fn correlate_metrics(
+    log_path: &Path,
+    names: &[String],
+    window: i64,
+    data: &AnalysisData,
+) -> Result<Option<MetricCorrelation>> {
+    let sources = discover(log_path, &Pattern::Name("metrics.log".to_string()))?;
+    if sources.is_empty() {
+        return Ok(None);
+    }
+    let per_host = sources
+        .par_iter()
+        .map(|source| load_one(source, names, window))
+        .collect::<Result<Vec<_>>>()?;
+    let mut sums: BTreeMap<i64, Vec<(f64, usize)>> = BTreeMap::new();
+    for host in per_host {
+        for (start, host_sums) in host {
+            let total = sums
+                .entry(start)
+                .or_insert_with(|| vec![(0.0, 0); names.len()]);
+            for (t, h) in total.iter_mut().zip(host_sums) {
+                t.0 += h.0;
+                t.1 += h.1;
+            }
+        }
+    }
+
+    let mut sync: BTreeMap<i64, (f64, usize)> = BTreeMap::new();
+    for (h, block) in &data.blocks {
+        if let Some(agg) = data.block_dists.get(h).and_then(|d| d.get("Sync")) {
+            let w = sync
+                .entry(block.timestamp.div_euclid(window) * window)
+                .or_default();
+            w.0 += agg.value_for(NodePercentile::P99);
+            w.1 += 1;
+        }
+    }
+
+    let mut starts: Vec<i64> = sums.keys().chain(sync.keys()).copied().collect();
+    starts.sort_unstable();
+    starts.dedup();
+    let windows: Vec<MetricWindow> = starts
+        .into_iter()
+        .map(|start| {
+            let (sync_sum, blocks) = sync.get(&start).copied().unwrap_or_default();
+            MetricWindow {
+                window_start: start,
+                blocks,
+                sync_p99: (blocks > 0).then(|| sync_sum / blocks as f64),
+                metrics: match sums.get(&start) {
+                    Some(s) => s
+                        .iter()
+                        .map(|&(sum, n)| (n > 0).then(|| sum / n as f64))
+                        .collect(),
+                    None => vec![None; names.len()],
+                },
+            }
+        })
+        .collect();
+
+    let correlation = (0..names.len())
+        .map(|i| {
+            let pairs: Vec<(f64, f64)> = windows
+                .iter()
+                .filter_map(|w| Some((w.metrics[i]?, w.sync_p99?)))
+                .collect();
+            pearson(&pairs)
+        })
+        .collect();
+    Ok(Some(MetricCorrelation {
+        names: names.to_vec(),
+        hosts: sources.len(),
+        windows,
+        correlation,
+    }))
+}
+
+/// Pearson correlation coefficient, if there are two points and neither side is constant.
+fn pearson(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let (mx, my) = pairs
+        .iter()
+        .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x / n, sy + y / n));
+    let (mut cov, mut vx, mut vy) = (0.0, 0.0, 0.0);
+    for (x, y) in pairs {
+        cov += (x - mx) * (y - my);
+        vx += (x - mx) * (x - mx);
+        vy += (y - my) * (y - my);
+    }
+    (vx > 0.0 && vy > 0.0).then(|| cov / (vx * vy).sqrt())
+}
+
+pub fn print_metric_correlation(correlation: &MetricCorrelation, window: i64) {
+    let paired = correlation
+        .windows
+        .iter()
+        .filter(|w| w.sync_p99.is_some() && w.metrics.iter().any(Option::is_some))
+        .count();
+    println!(
+        "Metrics of {} hosts vs block Sync P99, per {}s window ({} windows with both)",
+        correlation.hosts, window, paired
+    );
+    for (name, r) in correlation.names.iter().zip(&correlation.correlation) {
+        match r {
+            Some(r) => println!("  {}: correlation {:.2}", name, r),
+            None => println!("  {}: correlation n/a (too few windows, or constant)", name),
+        }
+    }
+}
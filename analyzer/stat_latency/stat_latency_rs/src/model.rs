@@ -1,10 +1,13 @@
+use crate::block_latencies::BlockLatencies;
 use crate::quantile::QuantileAgg;
+use crate::tx_sketch::TimestampSketch;
+use crate::warnings::Warning;
 use ethereum_types::H256;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 
-fn parse_h256(s: &str) -> Result<H256, String> {
+pub fn parse_h256(s: &str) -> Result<H256, String> {
     match H256::from_str(s) {
         Ok(v) => Ok(v),
         Err(_) => {
@@ -40,8 +43,50 @@ where
     Ok(out)
 }
 
+fn deserialize_opt_h256<'de, D>(deserializer: D) -> Result<Option<H256>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw: Option<String> = Option::deserialize(deserializer)?;
+    raw.map(|s| parse_h256(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Instrumentation vintage of a blocks.log, see `HostBlocksLog::normalize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaVersion {
+    /// Builds logging `Relay`/`Insert` instead of `Receive`/`Sync`.
+    Legacy,
+    #[default]
+    Current,
+}
+
+impl SchemaVersion {
+    /// Latency keys of this version and the current key each stands for.
+    fn key_aliases(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            SchemaVersion::Legacy => &[("Relay", "Receive"), ("Insert", "Sync")],
+            SchemaVersion::Current => &[],
+        }
+    }
+
+    fn from_number(n: u32) -> Option<Self> {
+        match n {
+            1 => Some(SchemaVersion::Legacy),
+            2 => Some(SchemaVersion::Current),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Default)]
 pub struct HostBlocksLog {
+    /// 1 (legacy) or 2 (current); detected from the latency keys when absent.
+    #[serde(default)]
+    pub schema_version: Option<u32>,
+    /// Unit of the block latencies, `s` (the default) or `ms`.
+    #[serde(default)]
+    pub latency_unit: Option<String>,
     #[serde(default, deserialize_with = "deserialize_h256_map")]
     pub blocks: HashMap<H256, BlockJson>,
     #[serde(default, deserialize_with = "deserialize_h256_map")]
@@ -50,20 +95,112 @@ pub struct HostBlocksLog {
     pub sync_cons_gap_stats: Vec<HashMap<String, serde_json::Value>>,
     #[serde(default)]
     pub by_block_ratio: Vec<f64>,
+    /// SHA-256 of the log as read.
+    #[serde(skip)]
+    pub digest: [u8; 32],
+    /// Name of the host, see `host_processing::host_id`.
+    #[serde(skip)]
+    pub host: String,
+    /// Set by `normalize`.
+    #[serde(skip)]
+    pub schema: SchemaVersion,
+}
+
+impl HostBlocksLog {
+    /// Brings a log of any schema version to the current one: latency keys renamed to their
+    /// current names and latencies in seconds. Field names of older versions are accepted by
+    /// the serde aliases of `BlockJson` and `TxJson`.
+    pub fn normalize(&mut self) -> Result<(), String> {
+        self.schema = match self.schema_version {
+            Some(n) => SchemaVersion::from_number(n)
+                .ok_or_else(|| format!("unsupported schema_version {}", n))?,
+            None => {
+                let legacy_keys = SchemaVersion::Legacy.key_aliases();
+                let is_legacy = self.blocks.values().any(|b| {
+                    legacy_keys
+                        .iter()
+                        .any(|(old, _)| b.latencies.contains_key(old))
+                });
+                match is_legacy {
+                    true => SchemaVersion::Legacy,
+                    false => SchemaVersion::Current,
+                }
+            }
+        };
+        let scale = match self.latency_unit.as_deref() {
+            None | Some("s") => 1.0,
+            Some("ms") => 1e-3,
+            Some(unit) => return Err(format!("unsupported latency_unit {:?}", unit)),
+        };
+
+        for block in self.blocks.values_mut() {
+            block.latencies.rename(self.schema.key_aliases());
+            if scale != 1.0 {
+                block.latencies.scale(scale);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rough bytes the parsed log takes in memory, to bound the logs parsed but not yet merged
+    /// (`--inflight-memory`). Counts the tables at their capacity and every sample, but not the
+    /// allocator's overhead.
+    pub fn estimated_bytes(&self) -> usize {
+        fn table<K, V>(map: &HashMap<K, V>) -> usize {
+            map.capacity() * (size_of::<(K, V)>() + 1)
+        }
+        let blocks: usize = self
+            .blocks
+            .values()
+            .map(|b| b.referees.capacity() * size_of::<H256>() + b.latencies.heap_bytes())
+            .sum();
+        let txs: usize = self
+            .txs
+            .values()
+            .map(|t| {
+                t.received_timestamps.capacity() * size_of::<f64>()
+                    + (t.packed_timestamps.capacity() + t.ready_pool_timestamps.capacity())
+                        * size_of::<Option<f64>>()
+            })
+            .sum();
+        // Small maps of a few numbers each; a fixed guess per entry is close enough.
+        let gap_stats: usize = self
+            .sync_cons_gap_stats
+            .iter()
+            .map(|m| table(m) + m.len() * 16)
+            .sum();
+        size_of::<Self>()
+            + table(&self.blocks)
+            + blocks
+            + table(&self.txs)
+            + txs
+            + gap_stats
+            + self.by_block_ratio.capacity() * size_of::<f64>()
+    }
 }
 
 #[derive(Debug, Deserialize, Default)]
 pub struct BlockJson {
     #[serde(default)]
     pub timestamp: i64,
-    #[serde(default)]
+    #[serde(
+        default,
+        alias = "parent_hash",
+        deserialize_with = "deserialize_opt_h256"
+    )]
+    pub parent: Option<H256>,
+    #[serde(default, alias = "tx_count")]
     pub txs: i64,
-    #[serde(default)]
+    #[serde(default, alias = "block_size")]
     pub size: i64,
-    #[serde(default, deserialize_with = "deserialize_h256_vec")]
+    #[serde(
+        default,
+        alias = "referee_hashes",
+        deserialize_with = "deserialize_h256_vec"
+    )]
     pub referees: Vec<H256>,
-    #[serde(default)]
-    pub latencies: HashMap<String, Vec<f64>>,
+    #[serde(default, alias = "latency")]
+    pub latencies: BlockLatencies,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -135,14 +272,89 @@ pub struct BlockInfo {
     pub timestamp: i64,
     pub txs: i64,
     pub size: i64,
-    pub referee_count: i64,
+    pub parent: Option<H256>,
+    pub referees: Vec<H256>,
 }
 
+/// Timestamps of one tx over every node that logged it, in epoch seconds.
+///
+/// They are kept as f64, whose step at 1.7e9 s is about 0.24 us, so latencies taken as their
+/// differences are exact to well below a millisecond. An f32 steps by 128 s there, and even
+/// f32 deltas from a per-tx base would only save 4 bytes per reception: 300 nodes * 100k
+/// txs * 3 timestamps is 720 MB as f64 and 360 MB as f32 deltas. `--tx-streaming` bounds it
+/// instead, with a `TimestampSketch` per tx and kind.
 #[derive(Debug, Default)]
 pub struct TxAgg {
-    pub received: Vec<f64>,
-    pub packed: Vec<f64>,
-    pub ready: Vec<f64>,
+    pub received: TxTimestamps,
+    pub packed: TxTimestamps,
+    pub ready: TxTimestamps,
+}
+
+impl TxAgg {
+    pub fn new(streaming: bool) -> Self {
+        Self {
+            received: TxTimestamps::new(streaming),
+            packed: TxTimestamps::new(streaming),
+            ready: TxTimestamps::new(streaming),
+        }
+    }
+}
+
+/// One kind of timestamps of a tx: all of them, or their sketch.
+#[derive(Debug)]
+pub enum TxTimestamps {
+    Full(Vec<f64>),
+    Sketch(TimestampSketch),
+}
+
+impl Default for TxTimestamps {
+    fn default() -> Self {
+        TxTimestamps::Full(Vec::new())
+    }
+}
+
+impl TxTimestamps {
+    pub fn new(streaming: bool) -> Self {
+        match streaming {
+            true => TxTimestamps::Sketch(TimestampSketch::default()),
+            false => TxTimestamps::Full(Vec::new()),
+        }
+    }
+
+    pub fn push(&mut self, t: f64) {
+        match self {
+            TxTimestamps::Full(values) => values.push(t),
+            TxTimestamps::Sketch(sketch) => sketch.insert(t),
+        }
+    }
+
+    /// Number of timestamps, i.e. of nodes that logged the event.
+    pub fn len(&self) -> usize {
+        match self {
+            TxTimestamps::Full(values) => values.len(),
+            TxTimestamps::Sketch(sketch) => sketch.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// `f64::INFINITY` when empty.
+    pub fn min(&self) -> f64 {
+        match self {
+            TxTimestamps::Full(values) => values.iter().copied().fold(f64::INFINITY, f64::min),
+            TxTimestamps::Sketch(sketch) => sketch.min(),
+        }
+    }
+
+    /// `f64::NEG_INFINITY` when empty.
+    pub fn max(&self) -> f64 {
+        match self {
+            TxTimestamps::Full(values) => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            TxTimestamps::Sketch(sketch) => sketch.max(),
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -153,17 +365,174 @@ pub struct AnalysisData {
     pub sync_gap_p90: Vec<f64>,
     pub sync_gap_p99: Vec<f64>,
     pub sync_gap_max: Vec<f64>,
+    /// Per-host gap percentiles weighted by the host's sample count (`Cnt`), approximating the
+    /// gap distribution over all samples of all hosts.
+    pub sync_gap_pooled: Option<QuantileAgg>,
+    /// Sum of per-host `Avg * Cnt`, over the summed `Cnt` in `sync_gap_pooled`.
+    pub sync_gap_pooled_sum: f64,
     pub by_block_ratio: Vec<f64>,
     pub tx_wait_to_be_packed: Vec<f64>,
     pub blocks: HashMap<H256, BlockInfo>,
     pub block_dists: HashMap<H256, HashMap<String, QuantileAgg>>,
     pub txs: HashMap<H256, TxAgg>,
+    /// Sync samples of every block (before coverage filtering), bucketed by the start of the
+    /// heatmap window the block timestamp falls in. Only filled with a heatmap window.
+    pub sync_buckets: BTreeMap<i64, QuantileAgg>,
+    /// Name of every host, in load order, which the other per-host fields follow.
+    pub hosts: Vec<String>,
+    /// Hosts found under the log path; more than `hosts` if loading was interrupted.
+    pub hosts_expected: usize,
+    /// Hosts whose log has the legacy schema, read through its aliases.
+    pub legacy_hosts: usize,
+    /// Tx receptions of every host, in load order.
+    pub host_tx_receptions: Vec<HostTxReceptions>,
+    /// `HostBlocksLog::digest` of every host, in load order.
+    pub host_digests: Vec<[u8; 32]>,
+    /// Data-quality warnings, in the order found.
+    pub warnings: Vec<Warning>,
+    /// Exact copies of the (block, key) aggregates picked by `QuantilePolicy::verifies`.
+    pub quantile_shadows: HashMap<(H256, String), QuantileAgg>,
+}
+
+/// Per-tx node percentiles of the tx latency rows, one value per tx.
+#[derive(Debug, Default)]
+pub struct TxRowValues {
+    /// Received since the first node received the tx, of txs received by every node.
+    pub latency: HashMap<NodePercentile, Vec<f64>>,
+    /// Packed since the first node received the tx.
+    pub packed: HashMap<NodePercentile, Vec<f64>>,
+    /// Admitted to the ready pool since the first node received the tx.
+    pub ready: HashMap<NodePercentile, Vec<f64>>,
+}
+
+/// Nodes of one host log and the received timestamps they logged over all txs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostTxReceptions {
+    pub nodes: usize,
+    pub receptions: usize,
+}
+
+/// Per-block values of the block latency rows, keyed `"{key}::{percentile}"`.
+#[derive(Debug, Default)]
+pub struct BlockRowValues {
+    pub values: HashMap<String, Vec<f64>>,
+    /// Node samples behind each block's values, keyed by latency key, in the order of `values`.
+    pub samples: HashMap<String, Vec<u32>>,
+    /// Timestamp of each block behind the values, keyed by latency key, in the order of `values`.
+    pub timestamps: HashMap<String, Vec<i64>>,
+}
+
+/// The P90 (over nodes) of every event stage of a block, chained into the block's P90 time to
+/// `TxPoolUpdated`.
+#[derive(Debug, Default)]
+pub struct StageWaterfall {
+    /// Per block, the percentage of the chained P90 spent in each stage.
+    pub shares: HashMap<&'static str, Vec<f64>>,
+    /// Number of blocks whose longest P90 stage is each stage.
+    pub dominant: HashMap<&'static str, usize>,
+    pub blocks: usize,
+}
+
+/// One time window of `metrics_log::correlate_metrics`.
+#[derive(Debug, Clone, Default)]
+pub struct MetricWindow {
+    pub window_start: i64,
+    /// Blocks generated in the window.
+    pub blocks: usize,
+    /// Mean over those blocks of their Sync P99.
+    pub sync_p99: Option<f64>,
+    /// Mean of each metric over every sample of every host in the window.
+    pub metrics: Vec<Option<f64>>,
+}
+
+/// System metrics from the hosts' metrics.log next to block latency, per time window.
+#[derive(Debug, Default)]
+pub struct MetricCorrelation {
+    /// Metric names as given, `module::key` or a bare key.
+    pub names: Vec<String>,
+    /// Hosts with a metrics.log.
+    pub hosts: usize,
+    pub windows: Vec<MetricWindow>,
+    /// Pearson correlation of each metric with `sync_p99` over the windows with both.
+    pub correlation: Vec<Option<f64>>,
+}
+
+/// How blocks reference earlier ones, from the parent and referee hashes of the blocks kept.
+#[derive(Debug, Default)]
+pub struct DagMetrics {
+    /// Seconds from each referee's generation to the referencing block's.
+    pub referee_age: Vec<f64>,
+    /// Seconds from each parent's generation to its child's.
+    pub parent_age: Vec<f64>,
+    /// Referee references, and those to a referee older than `STALE_REFEREE_AGE`.
+    pub references: usize,
+    pub stale_references: usize,
+    /// Parent and referee references to blocks not kept (removed for coverage, or not logged).
+    pub unresolved: usize,
+    /// Blocks no other block names as parent or referee, but for those of the last
+    /// `STALE_REFEREE_AGE` seconds, which may not have been referenced yet.
+    pub unreferenced: usize,
+}
+
+/// A stretch without blocks, e.g. while the nodes restarted: the timestamps of the blocks either
+/// side of it.
+#[derive(Debug, Clone, Copy)]
+pub struct TimestampGap {
+    pub before: i64,
+    pub after: i64,
+}
+
+impl TimestampGap {
+    pub fn seconds(&self) -> i64 {
+        self.after - self.before
+    }
+}
+
+/// A block `validate_and_filter_blocks` excluded since not every node synced it.
+#[derive(Debug, Clone)]
+pub struct RemovedBlock {
+    pub hash: H256,
+    /// 0 if no host logged it.
+    pub timestamp: i64,
+    /// Nodes with a Sync sample of the block.
+    pub received: usize,
+    /// Nodes loaded.
+    pub total: usize,
+}
+
+/// Blocks generated in one `SIZE_SERIES_WINDOW` of block timestamps.
+#[derive(Debug, Clone, Default)]
+pub struct SizeBucket {
+    pub window_start: i64,
+    pub blocks: usize,
+    pub bytes: i64,
+    /// Bytes of all blocks up to the end of this window.
+    pub cumulative_bytes: i64,
+}
+
+/// How far txs propagated: per tx, over nodes, and per host, over txs.
+#[derive(Debug, Default)]
+pub struct TxReach {
+    /// Number of txs that reached each number of nodes.
+    pub histogram: BTreeMap<usize, usize>,
+    /// Nodes reached by each tx.
+    pub per_tx: Vec<f64>,
+    /// Fewest nodes reached first, with the number of nodes.
+    pub least_propagated: Vec<(H256, usize)>,
+    /// Per host, the percentage of (node, tx) receptions missing, most missing first.
+    pub host_missing_pct: Vec<(String, f64)>,
 }
 
 #[derive(Debug, Default)]
 pub struct TxAnalysis {
+    /// Txs not received by every node.
+    pub missing_tx: usize,
     pub min_tx_packed_to_block_latency: Vec<f64>,
     pub min_tx_to_ready_pool_latency: Vec<f64>,
+    /// Times each packed tx was packed, i.e. blocks it takes space in.
+    pub packings: Vec<f64>,
+    /// Last minus first packing of each tx packed more than once.
+    pub repack_spread: Vec<f64>,
     pub slowest_packed_hash: Option<H256>,
 }
 
@@ -23,21 +23,78 @@ fn exact_quantile(values: &[f64], q: f64) -> f64 {
     sorted[lo] + (sorted[hi] - sorted[lo]) * w
 }
 
+/// `exact_quantile` of the samples `points` stand for, `(x, w)` counting as `w` samples of `x`.
+fn weighted_quantile(points: &[(f64, f64)], q: f64) -> f64 {
+    if points.is_empty() {
+        return f64::NAN;
+    }
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+
+    let total: f64 = sorted.iter().map(|p| p.1).sum();
+    let h = ((total - 1.0) * q.clamp(0.0, 1.0)).max(0.0);
+    let at_rank = |rank: f64| {
+        let mut cumulative = 0.0;
+        for &(x, w) in &sorted {
+            cumulative += w;
+            if rank < cumulative {
+                return x;
+            }
+        }
+        sorted[sorted.len() - 1].0
+    };
+
+    let (lo, hi) = (at_rank(h.floor()), at_rank(h.ceil()));
+    lo + (hi - lo) * (h - h.floor())
+}
+
 #[derive(Debug)]
 pub struct BruteQuantileState {
     values: Vec<f64>,
+    /// Weight of each value, once any insert had a weight other than 1.
+    weights: Option<Vec<f64>>,
 }
 
 impl BruteQuantileState {
     pub fn new() -> Self {
-        Self { values: Vec::new() }
+        Self {
+            values: Vec::new(),
+            weights: None,
+        }
     }
 
     pub fn insert(&mut self, x: f64) {
         self.values.push(x);
+        if let Some(weights) = &mut self.weights {
+            weights.push(1.0);
+        }
+    }
+
+    /// Inserts `x` as `weight` samples of it, e.g. a per-host statistic over that many samples.
+    pub fn insert_weighted(&mut self, x: f64, weight: f64) {
+        if weight == 1.0 {
+            return self.insert(x);
+        }
+        let len = self.values.len();
+        self.weights
+            .get_or_insert_with(|| vec![1.0; len])
+            .push(weight);
+        self.values.push(x);
+    }
+
+    /// Every value with its weight, for merging into another state.
+    pub fn weighted_points(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let weight = |i: usize| self.weights.as_ref().map_or(1.0, |w| w[i]);
+        self.values
+            .iter()
+            .enumerate()
+            .map(move |(i, &x)| (x, weight(i)))
     }
 
     pub fn quantile(&self, q: f64) -> f64 {
-        exact_quantile(&self.values, q)
+        match &self.weights {
+            None => exact_quantile(&self.values, q),
+            Some(_) => weighted_quantile(&self.weighted_points().collect::<Vec<_>>(), q),
+        }
     }
 }
@@ -1,45 +1,253 @@
-use tdigests::TDigest;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::f64::consts::PI;
 
+/// Compression of the digest: it keeps at most about this many centroids.
+const COMPRESSION: f64 = 500.0;
+/// Inserts are buffered and merged into the centroids in sorted batches of this size.
+const BUFFER_SIZE: usize = 2000;
+
+/// `f64` ordered by `total_cmp`, for the tail heaps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sample(f64);
+
+impl Eq for Sample {}
+
+impl PartialOrd for Sample {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sample {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// Merging t-digest (Dunning & Ertl) with the arcsine scale function. Samples are buffered;
+/// a full buffer is sorted and merged with the centroids in one pass, so an insert is a push.
+///
+/// The smallest and largest samples, `low_tail` and `high_tail` of the expected count, are also
+/// kept exactly, so quantiles falling in either tail are exact. Weighted inserts give up that
+/// exactness, as a weighted point is not a sample.
 #[derive(Debug)]
 pub struct TDigestQuantileState {
-    digest: Option<TDigest>,
-    buffer: Vec<f64>,
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    total_weight: f64,
+    /// Whether every insert so far had weight 1.
+    unit_weights: bool,
+    min: f64,
+    max: f64,
+    low_capacity: usize,
+    high_capacity: usize,
+    /// Max-heap of the smallest samples.
+    low: BinaryHeap<Sample>,
+    /// Min-heap of the largest samples.
+    high: BinaryHeap<Reverse<Sample>>,
+    /// The tails in ascending order, as of the last merge.
+    sorted_low: Vec<f64>,
+    sorted_high: Vec<f64>,
 }
 
 impl TDigestQuantileState {
-    pub fn new(_expected_count: usize) -> Self {
+    pub fn new(expected_count: usize, low_tail: f64, high_tail: f64) -> Self {
+        // One sample more than the fraction, so the quantile at the tail boundary is exact too.
+        let capacity = |tail: f64| match tail > 0.0 {
+            true => (expected_count as f64 * tail).ceil() as usize + 1,
+            false => 0,
+        };
         Self {
-            digest: None,
-            buffer: vec![],
+            centroids: Vec::new(),
+            buffer: Vec::with_capacity(expected_count.min(BUFFER_SIZE)),
+            total_weight: 0.0,
+            unit_weights: true,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            low_capacity: capacity(low_tail),
+            high_capacity: capacity(high_tail),
+            low: BinaryHeap::new(),
+            high: BinaryHeap::new(),
+            sorted_low: Vec::new(),
+            sorted_high: Vec::new(),
         }
     }
 
     pub fn insert(&mut self, x: f64) {
-        self.buffer.push(x);
-        if self.buffer.len() >= 200 {
+        if self.low.len() < self.low_capacity {
+            self.low.push(Sample(x));
+        } else if self.low.peek().is_some_and(|top| x < top.0) {
+            self.low.pop();
+            self.low.push(Sample(x));
+        }
+        if self.high.len() < self.high_capacity {
+            self.high.push(Reverse(Sample(x)));
+        } else if self.high.peek().is_some_and(|top| x > top.0 .0) {
+            self.high.pop();
+            self.high.push(Reverse(Sample(x)));
+        }
+
+        self.push(Centroid {
+            mean: x,
+            weight: 1.0,
+        });
+    }
+
+    /// Inserts `x` as `weight` samples of it, e.g. a centroid of another digest.
+    pub fn insert_weighted(&mut self, x: f64, weight: f64) {
+        if weight == 1.0 {
+            return self.insert(x);
+        }
+        self.unit_weights = false;
+        self.push(Centroid { mean: x, weight });
+    }
+
+    fn push(&mut self, centroid: Centroid) {
+        self.buffer.push(centroid);
+        if self.buffer.len() >= BUFFER_SIZE {
             self.merge();
         }
     }
 
+    /// Every centroid and buffered point as `(mean, weight)`, for merging into another state.
+    pub fn weighted_points(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.centroids
+            .iter()
+            .chain(&self.buffer)
+            .map(|c| (c.mean, c.weight))
+    }
+
+    /// Folds the buffered samples into the centroids. Quantiles only see merged samples.
     pub fn merge(&mut self) {
         if self.buffer.is_empty() {
             return;
         }
+        self.buffer
+            .sort_unstable_by(|a, b| a.mean.total_cmp(&b.mean));
+        self.min = self.min.min(self.buffer[0].mean);
+        self.max = self.max.max(self.buffer[self.buffer.len() - 1].mean);
+        self.total_weight += self.buffer.iter().map(|c| c.weight).sum::<f64>();
 
-        let incoming =
-            TDigest::from_values(std::mem::replace(&mut self.buffer, Vec::with_capacity(300)));
-        let mut merged = match self.digest.take() {
-            Some(existing) => existing.merge(&incoming),
-            None => incoming,
-        };
-        merged.compress(2000);
-        self.digest = Some(merged);
+        let old = std::mem::take(&mut self.centroids);
+        let mut merged = Vec::with_capacity(old.len().min(COMPRESSION as usize) + 1);
+        let (mut i, mut j) = (0, 0);
+        let mut current: Option<Centroid> = None;
+        let mut weight_before = 0.0;
+        let mut weight_limit = 0.0;
+        while i < old.len() || j < self.buffer.len() {
+            let next = if j == self.buffer.len()
+                || (i < old.len() && old[i].mean <= self.buffer[j].mean)
+            {
+                i += 1;
+                old[i - 1]
+            } else {
+                j += 1;
+                self.buffer[j - 1]
+            };
+
+            match &mut current {
+                Some(c) if weight_before + c.weight + next.weight <= weight_limit => {
+                    c.weight += next.weight;
+                    c.mean += (next.mean - c.mean) * next.weight / c.weight;
+                }
+                _ => {
+                    if let Some(c) = current.take() {
+                        weight_before += c.weight;
+                        merged.push(c);
+                    }
+                    weight_limit =
+                        self.total_weight * next_quantile_limit(weight_before / self.total_weight);
+                    current = Some(next);
+                }
+            }
+        }
+        merged.extend(current);
+        self.centroids = merged;
+        self.buffer.clear();
+
+        self.sorted_low = self.low.iter().map(|s| s.0).collect();
+        self.sorted_low.sort_unstable_by(f64::total_cmp);
+        self.sorted_high = self.high.iter().map(|s| s.0 .0).collect();
+        self.sorted_high.sort_unstable_by(f64::total_cmp);
     }
 
     pub fn quantile(&self, q: f64) -> f64 {
-        self.digest
-            .as_ref()
-            .map(|d| d.estimate_quantile(q))
-            .unwrap_or(f64::NAN)
+        let Some(first) = self.centroids.first() else {
+            return f64::NAN;
+        };
+        if self.centroids.len() == 1 {
+            return first.mean;
+        }
+        // Until the digest compresses every centroid is a sample, and the quantile is exact.
+        if self.unit_weights && self.centroids.len() as f64 == self.total_weight {
+            let h = (self.centroids.len() - 1) as f64 * q.clamp(0.0, 1.0);
+            let (lo, hi) = (h.floor() as usize, h.ceil() as usize);
+            let w = h - lo as f64;
+            return self.centroids[lo].mean
+                + (self.centroids[hi].mean - self.centroids[lo].mean) * w;
+        }
+
+        if let Some(v) = self.tail_quantile(q) {
+            return v;
+        }
+
+        // Each centroid sits at the middle of its weight; interpolate between neighbours, and
+        // against the exact min and max at the ends.
+        let target = q.clamp(0.0, 1.0) * self.total_weight;
+        let mut prev_center = 0.0;
+        let mut prev_mean = self.min;
+        let mut cumulative = 0.0;
+        for c in &self.centroids {
+            let center = cumulative + c.weight / 2.0;
+            if target < center {
+                let w = (target - prev_center) / (center - prev_center);
+                return prev_mean + (c.mean - prev_mean) * w;
+            }
+            cumulative += c.weight;
+            prev_center = center;
+            prev_mean = c.mean;
+        }
+        let w = (target - prev_center) / (self.total_weight - prev_center);
+        prev_mean + (self.max - prev_mean) * w.min(1.0)
+    }
+
+    /// The exact quantile if both samples it interpolates between are in a retained tail.
+    fn tail_quantile(&self, q: f64) -> Option<f64> {
+        if !self.unit_weights {
+            return None;
+        }
+        let n = self.total_weight as usize;
+        let h = (n - 1) as f64 * q.clamp(0.0, 1.0);
+        let (lo, hi) = (h.floor() as usize, h.ceil() as usize);
+        let w = h - lo as f64;
+        let interpolate = |a: f64, b: f64| a + (b - a) * w;
+
+        if hi < self.sorted_low.len() {
+            return Some(interpolate(self.sorted_low[lo], self.sorted_low[hi]));
+        }
+        // Index `i` of all samples is index `i - skipped` of the high tail.
+        let skipped = n - self.sorted_high.len();
+        if lo >= skipped {
+            return Some(interpolate(
+                self.sorted_high[lo - skipped],
+                self.sorted_high[hi - skipped],
+            ));
+        }
+        None
     }
 }
+
+/// The highest quantile a centroid starting at quantile `q` may reach: one unit further on the
+/// scale `k(q) = COMPRESSION / (2 pi) * asin(2q - 1)`, which keeps tail centroids small.
+fn next_quantile_limit(q: f64) -> f64 {
+    let k = COMPRESSION / (2.0 * PI) * (2.0 * q - 1.0).asin();
+    let k_next = (k + 1.0).min(COMPRESSION / 4.0);
+    ((k_next * 2.0 * PI / COMPRESSION).sin() + 1.0) / 2.0
+}
@@ -0,0 +1,43 @@
+use crate::model::{AnalysisData, NodePercentile};
+use crate::stats::statistics_from_vec;
+
+/// Prints, per node percentile, the distribution over the shadowed (block, key) aggregates of the
+/// relative error of the streaming estimate against the exact value.
+pub fn print_quantile_errors(data: &AnalysisData) {
+    if data.quantile_shadows.is_empty() {
+        println!("No streaming quantile aggregates sampled for verification");
+        return;
+    }
+    let mut errors: Vec<(NodePercentile, Vec<f64>)> = NodePercentile::all_in_order()
+        .iter()
+        .map(|p| (*p, Vec::new()))
+        .collect();
+    for ((block, key), exact) in &data.quantile_shadows {
+        let Some(streaming) = data.block_dists.get(block).and_then(|d| d.get(key)) else {
+            continue;
+        };
+        for (p, errors) in &mut errors {
+            let (estimate, truth) = (streaming.value_for(*p), exact.value_for(*p));
+            if truth != 0.0 {
+                errors.push(((estimate - truth) / truth).abs());
+            }
+        }
+    }
+
+    println!(
+        "Relative streaming quantile error over {} exactly aggregated (block, key) pairs \
+         (exact values of 0 skipped):",
+        data.quantile_shadows.len()
+    );
+    for (p, errors) in errors {
+        let s = statistics_from_vec(errors);
+        println!(
+            "  {:<5} P50 {:.4}%  P90 {:.4}%  P99 {:.4}%  Max {:.4}%",
+            p.name(),
+            s.p50 * 100.0,
+            s.p90 * 100.0,
+            s.p99 * 100.0,
+            s.max * 100.0
+        );
+    }
+}
@@ -1,12 +1,21 @@
 use prettytable::{Cell, Row, Table};
-use std::collections::{BTreeSet, HashMap};
+use std::collections::BTreeSet;
 
-use crate::model::{AnalysisData, BlockScalars, NodePercentile, TxAnalysis};
-use crate::stats::{statistics_from_vec, Statistics};
+use crate::config::EVENT_STAGES;
+use crate::format::formatter;
+use crate::model::{
+    AnalysisData, BlockRowValues, BlockScalars, DagMetrics, MetricCorrelation, NodePercentile,
+    SizeBucket, StageWaterfall, TxAnalysis, TxReach, TxRowValues,
+};
+use crate::sparkline::{sparkline, sparklines_enabled, SPARKLINE_WIDTH};
+use crate::stats::{
+    confidence_intervals_enabled, robust_statistics_enabled, sample_counts, statistics_from_vec,
+    SampleCounts, Statistics,
+};
 
 pub fn build_table_title() -> Table {
     let mut table = Table::new();
-    table.set_titles(Row::new(vec![
+    let mut titles = Row::new(vec![
         Cell::new("name_tmp"),
         Cell::new("Avg"),
         Cell::new("P10"),
@@ -19,52 +28,85 @@ pub fn build_table_title() -> Table {
         Cell::new("P999"),
         Cell::new("Max"),
         Cell::new("Cnt"),
-    ]));
+        Cell::new("SmpAvg"),
+        Cell::new("SmpMin"),
+    ]);
+    if robust_statistics_enabled() {
+        titles.add_cell(Cell::new("MAD"));
+        titles.add_cell(Cell::new("TrimAvg"));
+    }
+    if confidence_intervals_enabled() {
+        for name in ["Avg CI95", "P50 CI95", "P90 CI95"] {
+            titles.add_cell(Cell::new(name));
+        }
+    }
+    if sparklines_enabled() {
+        titles.add_cell(Cell::new("Trend"));
+    }
+    table.set_titles(titles);
     table
 }
 
-pub fn add_block_rows(table: &mut Table, row_values: &mut HashMap<String, Vec<f64>>) {
+/// ` [label]` appended to the metric names of a subset of blocks.
+fn label_suffix(label: Option<&str>) -> String {
+    label.map(|l| format!(" [{}]", l)).unwrap_or_default()
+}
+
+pub fn add_block_rows(table: &mut Table, row_values: &mut BlockRowValues, label: Option<&str>) {
+    let suffix = label_suffix(label);
     for t in ["Receive", "Sync", "Cons"] {
         for p in NodePercentile::all_in_order() {
-            let metric = format!("block broadcast latency ({}/{})", t, p.name());
-            let key = format!("{}::{}", t, p.name());
-            let stats = statistics_from_vec(row_values.remove(&key).unwrap_or_default());
-            table.add_row(row_from_stats(metric, stats, Some("%.2f")));
+            let metric = format!("block broadcast latency ({}/{}){}", t, p.name(), suffix);
+            table.add_row(block_row(metric, row_values, t, *p));
+        }
+        table.add_empty_row();
+    }
+
+    for t in EVENT_STAGES {
+        for p in NodePercentile::all_in_order() {
+            let metric = format!("block event elapsed ({}/{}){}", t, p.name(), suffix);
+            table.add_row(block_row(metric, row_values, t, *p));
         }
         table.add_empty_row();
     }
+}
 
-    for t in [
-        "HeaderReady",
-        "BodyReady",
-        "SyncGraph",
-        "ConsensusGraphStart",
-        "ConsensusGraphReady",
-        "ComputeEpoch",
-        "NotifyTxPool",
-        "TxPoolUpdated",
-    ] {
+/// Rows of `build_earliest_receive_row_values`, which hide the delay between generation and the
+/// first node seeing the block.
+pub fn add_earliest_receive_rows(table: &mut Table, row_values: &mut BlockRowValues) {
+    for t in ["Receive", "Sync", "Cons"] {
         for p in NodePercentile::all_in_order() {
-            let metric = format!("block event elapsed ({}/{})", t, p.name());
-            let key = format!("{}::{}", t, p.name());
-            let stats = statistics_from_vec(row_values.remove(&key).unwrap_or_default());
-            table.add_row(row_from_stats(metric, stats, Some("%.2f")));
+            let metric = format!(
+                "block broadcast latency since earliest receive ({}/{})",
+                t,
+                p.name()
+            );
+            table.add_row(block_row(metric, row_values, t, *p));
         }
         table.add_empty_row();
     }
 }
 
+pub fn add_stage_waterfall_rows(table: &mut Table, waterfall: &mut StageWaterfall) {
+    for t in EVENT_STAGES {
+        let metric = format!("block stage share of P90 % ({})", t);
+        let stats = statistics_from_vec(waterfall.shares.remove(t).unwrap_or_default());
+        table.add_row(row_from_stats(metric, stats, Some("%.2f")));
+    }
+    table.add_empty_row();
+}
+
 pub fn add_custom_block_rows(
     table: &mut Table,
-    row_values: &mut HashMap<String, Vec<f64>>,
+    row_values: &mut BlockRowValues,
     custom_keys: &BTreeSet<String>,
+    label: Option<&str>,
 ) {
+    let suffix = label_suffix(label);
     for t in custom_keys {
         for p in NodePercentile::all_in_order() {
-            let metric = format!("custom block event elapsed ({}/{})", t, p.name());
-            let key = format!("{}::{}", t, p.name());
-            let stats = statistics_from_vec(row_values.remove(&key).unwrap_or_default());
-            table.add_row(row_from_stats(metric, stats, Some("%.2f")));
+            let metric = format!("custom block event elapsed ({}/{}){}", t, p.name(), suffix);
+            table.add_row(block_row(metric, row_values, t, *p));
         }
         table.add_empty_row();
     }
@@ -72,12 +114,12 @@ pub fn add_custom_block_rows(
 
 pub fn add_tx_rows(
     table: &mut Table,
-    tx_latency_rows: &mut HashMap<NodePercentile, Vec<f64>>,
-    tx_packed_rows: &mut HashMap<NodePercentile, Vec<f64>>,
+    tx_rows: &mut TxRowValues,
     tx_analysis: &TxAnalysis,
     data: &AnalysisData,
 ) {
-    if !tx_latency_rows
+    if !tx_rows
+        .latency
         .get(&NodePercentile::Avg)
         .map(|v| !v.is_empty())
         .unwrap_or(false)
@@ -87,14 +129,21 @@ pub fn add_tx_rows(
 
     for p in NodePercentile::all_in_order() {
         let metric = format!("tx broadcast latency ({})", p.name());
-        let stats = statistics_from_vec(tx_latency_rows.remove(p).unwrap_or_default());
+        let stats = statistics_from_vec(tx_rows.latency.remove(p).unwrap_or_default());
         table.add_row(row_from_stats(metric, stats, Some("%.2f")));
     }
     table.add_empty_row();
 
     for p in NodePercentile::all_in_order() {
         let metric = format!("tx packed to block latency ({})", p.name());
-        let stats = statistics_from_vec(tx_packed_rows.remove(p).unwrap_or_default());
+        let stats = statistics_from_vec(tx_rows.packed.remove(p).unwrap_or_default());
+        table.add_row(row_from_stats(metric, stats, Some("%.2f")));
+    }
+    table.add_empty_row();
+
+    for p in NodePercentile::all_in_order() {
+        let metric = format!("tx ready pool latency ({})", p.name());
+        let stats = statistics_from_vec(tx_rows.ready.remove(p).unwrap_or_default());
         table.add_row(row_from_stats(metric, stats, Some("%.2f")));
     }
     table.add_empty_row();
@@ -109,6 +158,16 @@ pub fn add_tx_rows(
         statistics_from_vec(tx_analysis.min_tx_to_ready_pool_latency.clone()),
         Some("%.2f"),
     ));
+    table.add_row(row_from_stats(
+        "tx packings".to_string(),
+        statistics_from_vec(tx_analysis.packings.clone()),
+        None,
+    ));
+    table.add_row(row_from_stats(
+        "tx re-pack spread (first to last packing)".to_string(),
+        statistics_from_vec(tx_analysis.repack_spread.clone()),
+        Some("%.2f"),
+    ));
     table.add_row(row_from_stats(
         "by_block_ratio".to_string(),
         statistics_from_vec(data.by_block_ratio.clone()),
@@ -121,6 +180,47 @@ pub fn add_tx_rows(
     ));
 }
 
+/// Per-window means of every metric of `correlate_metrics`, and of block Sync P99 alongside.
+pub fn add_metric_rows(table: &mut Table, correlation: &MetricCorrelation) {
+    table.add_row(row_from_stats(
+        "block Sync P99 per metrics window".to_string(),
+        statistics_from_vec(
+            correlation
+                .windows
+                .iter()
+                .filter_map(|w| w.sync_p99)
+                .collect(),
+        ),
+        Some("%.2f"),
+    ));
+    for (i, name) in correlation.names.iter().enumerate() {
+        table.add_row(row_from_stats(
+            format!("metric {} per window", name),
+            statistics_from_vec(
+                correlation
+                    .windows
+                    .iter()
+                    .filter_map(|w| w.metrics[i])
+                    .collect(),
+            ),
+            Some("%.2f"),
+        ));
+    }
+}
+
+pub fn add_tx_reach_rows(table: &mut Table, reach: &TxReach) {
+    table.add_row(row_from_stats(
+        "tx nodes reached".to_string(),
+        statistics_from_vec(reach.per_tx.clone()),
+        None,
+    ));
+    table.add_row(row_from_stats(
+        "host tx receptions missed (%)".to_string(),
+        statistics_from_vec(reach.host_missing_pct.iter().map(|(_, p)| *p).collect()),
+        Some("%.2f"),
+    ));
+}
+
 pub fn add_block_scalar_rows(table: &mut Table, scalars: &BlockScalars) {
     table.add_row(row_from_stats(
         "block txs".to_string(),
@@ -144,6 +244,27 @@ pub fn add_block_scalar_rows(table: &mut Table, scalars: &BlockScalars) {
     ));
 }
 
+pub fn add_dag_rows(table: &mut Table, dag: &DagMetrics) {
+    table.add_row(row_from_stats(
+        "block referee age".to_string(),
+        statistics_from_vec(dag.referee_age.clone()),
+        Some("%.2f"),
+    ));
+    table.add_row(row_from_stats(
+        "block parent age".to_string(),
+        statistics_from_vec(dag.parent_age.clone()),
+        Some("%.2f"),
+    ));
+}
+
+pub fn add_size_series_rows(table: &mut Table, series: &[SizeBucket]) {
+    table.add_row(row_from_stats(
+        "block bytes per minute".to_string(),
+        statistics_from_vec(series.iter().map(|b| b.bytes as f64).collect()),
+        None,
+    ));
+}
+
 pub fn add_sync_gap_rows(table: &mut Table, data: &AnalysisData) {
     table.add_row(row_from_stats(
         "node sync/cons gap (Avg)".to_string(),
@@ -170,26 +291,80 @@ pub fn add_sync_gap_rows(table: &mut Table, data: &AnalysisData) {
         statistics_from_vec(data.sync_gap_max.clone()),
         None,
     ));
+    if let Some(pooled) = &data.sync_gap_pooled {
+        let stats = Statistics {
+            avg: data.sync_gap_pooled_sum / pooled.weight(),
+            p10: pooled.value_for(NodePercentile::P10),
+            p30: pooled.value_for(NodePercentile::P30),
+            p50: pooled.value_for(NodePercentile::P50),
+            p80: pooled.value_for(NodePercentile::P80),
+            p90: pooled.value_for(NodePercentile::P90),
+            p95: pooled.value_for(NodePercentile::P95),
+            p99: pooled.value_for(NodePercentile::P99),
+            p999: pooled.value_for(NodePercentile::P999),
+            max: pooled.value_for(NodePercentile::Max),
+            cnt: pooled.weight().round() as usize,
+            ci: None,
+            robust: None,
+        };
+        table.add_row(row_from_stats(
+            "node sync/cons gap (pooled over samples)".to_string(),
+            stats,
+            None,
+        ));
+    }
+}
+
+pub fn add_confirmation_rows(table: &mut Table, rows: Vec<(String, Vec<f64>)>) {
+    for (metric, times) in rows {
+        table.add_row(row_from_stats(metric, statistics_from_vec(times), None));
+    }
+}
+
+/// Row of latency key `key` at node percentile `p`, with the node sample counts of its blocks.
+fn block_row(metric: String, row_values: &mut BlockRowValues, key: &str, p: NodePercentile) -> Row {
+    let values = row_values
+        .values
+        .remove(&format!("{}::{}", key, p.name()))
+        .unwrap_or_default();
+    let samples = row_values.samples.get(key).and_then(|c| sample_counts(c));
+    let trend = match sparklines_enabled() {
+        true => row_values
+            .timestamps
+            .get(key)
+            .map(|t| sparkline(t, &values, SPARKLINE_WIDTH)),
+        false => None,
+    };
+    row_with_samples(
+        metric,
+        statistics_from_vec(values),
+        Some("%.2f"),
+        samples,
+        trend,
+    )
 }
 
 fn row_from_stats(name: String, s: Statistics, fmt: Option<&str>) -> Row {
+    row_with_samples(name, s, fmt, None, None)
+}
+
+/// `trend` is the row's sparkline, for `--sparklines`.
+fn row_with_samples(
+    name: String,
+    s: Statistics,
+    fmt: Option<&str>,
+    samples: Option<SampleCounts>,
+    trend: Option<String>,
+) -> Row {
+    let formatter = formatter();
     let f = |v: f64| -> String {
-        if v.is_nan() {
-            return "nan".to_string();
-        }
         match fmt {
-            Some("%.2f") => format!("{:.2}", v),
-            _ => {
-                if (v - v.round()).abs() < 1e-9 {
-                    format!("{}", v as i64)
-                } else {
-                    format!("{:.2}", v)
-                }
-            }
+            Some("%.2f") => formatter.number(v),
+            _ => formatter.integral_or_number(v),
         }
     };
 
-    Row::new(vec![
+    let mut row = Row::new(vec![
         Cell::new(&name),
         Cell::new(&f(s.avg)),
         Cell::new(&f(s.p10)),
@@ -202,5 +377,26 @@ fn row_from_stats(name: String, s: Statistics, fmt: Option<&str>) -> Row {
         Cell::new(&f(s.p999)),
         Cell::new(&f(s.max)),
         Cell::new(&format!("{}", s.cnt)),
-    ])
+        Cell::new(&samples.map_or(String::new(), |c| formatter.number(c.avg))),
+        Cell::new(&samples.map_or(String::new(), |c| c.min.to_string())),
+    ]);
+    if robust_statistics_enabled() {
+        let robust = s.robust.map(|r| [r.mad, r.trimmed_avg]);
+        for i in 0..2 {
+            row.add_cell(Cell::new(&robust.map_or(String::new(), |r| f(r[i]))));
+        }
+    }
+    if confidence_intervals_enabled() {
+        let ci = s.ci.map(|ci| [ci.avg, ci.p50, ci.p90]);
+        for i in 0..3 {
+            let text = ci.map_or(String::new(), |ci| {
+                format!("{}..{}", f(ci[i].0), f(ci[i].1))
+            });
+            row.add_cell(Cell::new(&text));
+        }
+    }
+    if sparklines_enabled() {
+        row.add_cell(Cell::new(&trend.unwrap_or_default()));
+    }
+    row
 }
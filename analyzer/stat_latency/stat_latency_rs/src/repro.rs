@@ -0,0 +1,57 @@
+use sha2::{Digest, Sha256};
+
+use crate::model::AnalysisData;
+
+/// What a report was computed from: rerunning the same version with the same args and seed
+/// over inputs of the same digest gives the same report.
+#[derive(Debug, Clone)]
+pub struct ReproInfo {
+    pub args: Vec<String>,
+    pub seed: u64,
+    pub version: &'static str,
+    /// SHA-256 over the sorted digests of the host logs, so the load order does not matter.
+    pub input_digest: String,
+    pub hosts: usize,
+}
+
+impl ReproInfo {
+    pub fn new(seed: u64, data: &AnalysisData) -> Self {
+        let mut digests = data.host_digests.clone();
+        digests.sort_unstable();
+        let mut hasher = Sha256::new();
+        for digest in &digests {
+            hasher.update(digest);
+        }
+        Self {
+            args: std::env::args().collect(),
+            seed,
+            version: env!("CARGO_PKG_VERSION"),
+            input_digest: hex(&hasher.finalize()),
+            hosts: digests.len(),
+        }
+    }
+
+    /// `(name, value)` pairs, in the order every output lists them.
+    pub fn fields(&self) -> Vec<(String, String)> {
+        vec![
+            ("args".into(), self.args.join(" ")),
+            ("seed".into(), self.seed.to_string()),
+            ("version".into(), self.version.to_string()),
+            (
+                "input_digest".into(),
+                format!("sha256:{} ({} hosts)", self.input_digest, self.hosts),
+            ),
+        ]
+    }
+
+    pub fn print(&self) {
+        println!("ReproInfo");
+        for (name, value) in self.fields() {
+            println!("  {}: {}", name, value);
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
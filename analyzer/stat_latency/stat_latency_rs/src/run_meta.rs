@@ -0,0 +1,62 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Description of the test run, from `--run-meta`, e.g.
+/// `{"test_name": "tps-3000", "conflux_commit": "ab12cd", "node_count": 300, "target_tps": 3000,
+/// "topology": "random-8"}`. Every field is optional; other fields are kept as they are.
+#[derive(Debug, Default, Deserialize)]
+pub struct RunMeta {
+    pub test_name: Option<String>,
+    pub conflux_commit: Option<String>,
+    pub node_count: Option<usize>,
+    pub target_tps: Option<f64>,
+    /// A name, or any JSON describing the topology.
+    pub topology: Option<serde_json::Value>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl RunMeta {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("parse run metadata from {}", path.display()))
+    }
+
+    /// `(name, value)` pairs of the fields present, known fields first.
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        let mut push = |name: &str, value: Option<String>| {
+            if let Some(value) = value {
+                fields.push((name.to_string(), value));
+            }
+        };
+        push("test_name", self.test_name.clone());
+        push("conflux_commit", self.conflux_commit.clone());
+        push("node_count", self.node_count.map(|n| n.to_string()));
+        push("target_tps", self.target_tps.map(|t| t.to_string()));
+        push("topology", self.topology.as_ref().map(json_text));
+        for (name, value) in &self.extra {
+            push(name, Some(json_text(value)));
+        }
+        fields
+    }
+
+    pub fn print(&self) {
+        println!("RunMeta");
+        for (name, value) in self.fields() {
+            println!("  {}: {}", name, value);
+        }
+    }
+}
+
+/// Strings without their quotes, anything else as compact JSON.
+fn json_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
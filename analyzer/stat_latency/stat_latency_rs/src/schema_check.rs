@@ -0,0 +1,218 @@
+use anyhow::{anyhow, Error};
+use serde_json::Value;
+
+use crate::model::parse_h256;
+
+/// At most this many problems are listed for one log.
+const MAX_PROBLEMS: usize = 10;
+
+/// What a field must hold, as `HostBlocksLog` and its parts deserialize it.
+#[derive(Clone, Copy)]
+enum Expect {
+    Integer,
+    OptionalInteger,
+    Number,
+    OptionalNumber,
+    String,
+    Hash,
+    Array(&'static Expect),
+    Object,
+    /// An object keyed by block or tx hash.
+    HashMap(&'static [(&'static str, Expect)]),
+    /// An object of arrays of numbers, e.g. `latencies`.
+    NumberArrays,
+}
+
+const BLOCK_FIELDS: &[(&str, Expect)] = &[
+    ("timestamp", Expect::Integer),
+    ("parent", Expect::Hash),
+    ("parent_hash", Expect::Hash),
+    ("txs", Expect::Integer),
+    ("tx_count", Expect::Integer),
+    ("size", Expect::Integer),
+    ("block_size", Expect::Integer),
+    ("referees", Expect::Array(&Expect::Hash)),
+    ("referee_hashes", Expect::Array(&Expect::Hash)),
+    ("latencies", Expect::NumberArrays),
+    ("latency", Expect::NumberArrays),
+];
+
+const TX_FIELDS: &[(&str, Expect)] = &[
+    ("received_timestamps", Expect::Array(&Expect::Number)),
+    ("packed_timestamps", Expect::Array(&Expect::OptionalNumber)),
+    (
+        "ready_pool_timestamps",
+        Expect::Array(&Expect::OptionalNumber),
+    ),
+];
+
+const LOG_FIELDS: &[(&str, Expect)] = &[
+    ("schema_version", Expect::OptionalInteger),
+    ("latency_unit", Expect::String),
+    ("blocks", Expect::HashMap(BLOCK_FIELDS)),
+    ("txs", Expect::HashMap(TX_FIELDS)),
+    ("sync_cons_gap_stats", Expect::Array(&Expect::Object)),
+    ("by_block_ratio", Expect::Array(&Expect::Number)),
+];
+
+/// Turns the serde error of a blocks.log that failed to parse into one naming the malformed
+/// fields, e.g. `blocks.0x12..ab.latencies.Sync[3]: expected a number, found "0.25"`, by probing
+/// the log as a plain JSON value. Invalid JSON is reported with the text around the error.
+pub fn explain_parse_error(data: &[u8], err: serde_json::Error) -> Error {
+    let value: Value = match serde_json::from_slice(data) {
+        Ok(value) => value,
+        Err(syntax) => {
+            return anyhow!(
+                "invalid JSON at line {} column {}: {} (near {:?})",
+                syntax.line(),
+                syntax.column(),
+                syntax,
+                context_at(data, syntax.line(), syntax.column())
+            )
+        }
+    };
+
+    let mut problems = Vec::new();
+    match &value {
+        Value::Object(fields) => check_fields(fields, LOG_FIELDS, "", &mut problems),
+        other => problems.push(format!(
+            "top level: expected an object, found {}",
+            brief(other)
+        )),
+    }
+    if problems.is_empty() {
+        return anyhow!("{}", err);
+    }
+    let total = problems.len();
+    problems.truncate(MAX_PROBLEMS);
+    let more = match total > MAX_PROBLEMS {
+        true => "\n  ... and more",
+        false => "",
+    };
+    anyhow!("malformed blocks.log:\n  {}{}", problems.join("\n  "), more)
+}
+
+fn check_fields(
+    fields: &serde_json::Map<String, Value>,
+    expected: &[(&str, Expect)],
+    path: &str,
+    problems: &mut Vec<String>,
+) {
+    for (name, expect) in expected {
+        if let Some(v) = fields.get(*name) {
+            check(v, *expect, &join(path, name), problems);
+        }
+    }
+}
+
+fn check(v: &Value, expect: Expect, path: &str, problems: &mut Vec<String>) {
+    if problems.len() > MAX_PROBLEMS {
+        return;
+    }
+    let mismatch = |what: &str| format!("{}: expected {}, found {}", path, what, brief(v));
+    match expect {
+        Expect::Integer => {
+            if v.as_i64().is_none() {
+                problems.push(mismatch("an integer"))
+            }
+        }
+        Expect::OptionalInteger => {
+            if v.as_u64().is_none() && !v.is_null() {
+                problems.push(mismatch("a non-negative integer or null"))
+            }
+        }
+        Expect::Number => {
+            if !v.is_number() {
+                problems.push(mismatch("a number"))
+            }
+        }
+        Expect::OptionalNumber => {
+            if !v.is_number() && !v.is_null() {
+                problems.push(mismatch("a number or null"))
+            }
+        }
+        Expect::String => {
+            if !v.is_string() {
+                problems.push(mismatch("a string"))
+            }
+        }
+        Expect::Hash => match v.as_str() {
+            Some(s) if parse_h256(s).is_ok() => {}
+            _ => problems.push(mismatch("a 32-byte hex hash")),
+        },
+        Expect::Object => {
+            if !v.is_object() {
+                problems.push(mismatch("an object"))
+            }
+        }
+        Expect::Array(item) => match v.as_array() {
+            Some(items) => {
+                for (i, x) in items.iter().enumerate() {
+                    check(x, *item, &format!("{}[{}]", path, i), problems);
+                }
+            }
+            None => problems.push(mismatch("an array")),
+        },
+        Expect::NumberArrays => match v.as_object() {
+            Some(keys) => {
+                for (key, x) in keys {
+                    check(
+                        x,
+                        Expect::Array(&Expect::Number),
+                        &join(path, key),
+                        problems,
+                    );
+                }
+            }
+            None => problems.push(mismatch("an object of number arrays")),
+        },
+        Expect::HashMap(item_fields) => match v.as_object() {
+            Some(items) => {
+                for (hash, item) in items {
+                    let item_path = join(path, hash);
+                    if parse_h256(hash).is_err() {
+                        problems.push(format!("{}: key is not a 32-byte hex hash", item_path));
+                    }
+                    match item.as_object() {
+                        Some(fields) => check_fields(fields, item_fields, &item_path, problems),
+                        None => problems.push(format!(
+                            "{}: expected an object, found {}",
+                            item_path,
+                            brief(item)
+                        )),
+                    }
+                }
+            }
+            None => problems.push(mismatch("an object keyed by hash")),
+        },
+    }
+}
+
+fn join(path: &str, name: &str) -> String {
+    match path.is_empty() {
+        true => name.to_string(),
+        false => format!("{}.{}", path, name),
+    }
+}
+
+/// The value, cut short if long.
+fn brief(v: &Value) -> String {
+    let text = v.to_string();
+    match text.chars().count() > 40 {
+        true => format!("{}...", text.chars().take(40).collect::<String>()),
+        false => text,
+    }
+}
+
+/// Up to 40 bytes either side of a 1-based line and column.
+fn context_at(data: &[u8], line: usize, column: usize) -> String {
+    let line_start: usize = data
+        .split(|&b| b == b'\n')
+        .take(line.saturating_sub(1))
+        .map(|l| l.len() + 1)
+        .sum();
+    let at = (line_start + column.saturating_sub(1)).min(data.len());
+    let from = at.saturating_sub(40);
+    let to = (at + 40).min(data.len());
+    String::from_utf8_lossy(&data[from..to]).into_owned()
+}
@@ -0,0 +1,21 @@
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes the first Ctrl-C stop loading hosts, so the report covers the hosts merged so far; the
+/// second one exits at once.
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            eprintln!("interrupted again, exiting");
+            std::process::exit(130);
+        }
+        eprintln!("interrupted, reporting the hosts merged so far (Ctrl-C again to exit)");
+    })
+    .context("failed to install the Ctrl-C handler")
+}
+
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static SPARKLINES: AtomicBool = AtomicBool::new(false);
+
+/// Characters of a sparkline, lowest first.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Buckets (characters) of a sparkline over the whole run.
+pub const SPARKLINE_WIDTH: usize = 24;
+
+/// Makes the block rows of the table end with a `Trend` sparkline (`--sparklines`).
+pub fn enable_sparklines() {
+    SPARKLINES.store(true, Ordering::Relaxed);
+}
+
+pub fn sparklines_enabled() -> bool {
+    SPARKLINES.load(Ordering::Relaxed)
+}
+
+/// The mean of `values` in each of `width` equal spans of `timestamps` (block generation times,
+/// in the order of `values`), scaled between the lowest and highest span mean. Spans without
+/// values are blank, and every span is the lowest bar when the means are all equal. There are no
+/// more spans than seconds in the run. Empty without finite values.
+pub fn sparkline(timestamps: &[i64], values: &[f64], width: usize) -> String {
+    let points: Vec<(i64, f64)> = timestamps
+        .iter()
+        .copied()
+        .zip(values.iter().copied())
+        .filter(|(_, v)| v.is_finite())
+        .collect();
+    let (Some(first), Some(last)) = (
+        points.iter().map(|(t, _)| *t).min(),
+        points.iter().map(|(t, _)| *t).max(),
+    ) else {
+        return String::new();
+    };
+    let width = width.min((last - first + 1) as usize);
+    let span = (last - first + 1) as f64;
+    let mut sums = vec![(0.0, 0usize); width];
+    for (t, v) in points {
+        let i = (((t - first) as f64 / span) * width as f64) as usize;
+        let bucket = &mut sums[i.min(width - 1)];
+        bucket.0 += v;
+        bucket.1 += 1;
+    }
+    let means: Vec<Option<f64>> = sums
+        .iter()
+        .map(|&(sum, n)| (n > 0).then(|| sum / n as f64))
+        .collect();
+    let lo = means
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::INFINITY, f64::min);
+    let hi = means
+        .iter()
+        .flatten()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    means
+        .iter()
+        .map(|m| match m {
+            None => ' ',
+            Some(_) if hi <= lo => BARS[0],
+            Some(m) => {
+                let level = ((m - lo) / (hi - lo) * (BARS.len() - 1) as f64).round() as usize;
+                BARS[level.min(BARS.len() - 1)]
+            }
+        })
+        .collect()
+}
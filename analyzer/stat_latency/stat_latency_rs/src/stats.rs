@@ -1,5 +1,97 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::sync::atomic::{self, AtomicBool};
+
+static CONFIDENCE_INTERVALS: AtomicBool = AtomicBool::new(false);
+static ROBUST_STATISTICS: AtomicBool = AtomicBool::new(false);
+
+/// z of a two-sided 95% interval.
+const Z95: f64 = 1.96;
+
+/// Makes every `Statistics` computed from values carry the 95% confidence intervals of its Avg,
+/// P50 and P90, and every table row show them (`--ci`).
+pub fn enable_confidence_intervals() {
+    CONFIDENCE_INTERVALS.store(true, atomic::Ordering::Relaxed);
+}
+
+pub fn confidence_intervals_enabled() -> bool {
+    CONFIDENCE_INTERVALS.load(atomic::Ordering::Relaxed)
+}
+
+/// Makes every `Statistics` computed from values carry its MAD and trimmed mean, and every
+/// table row show them (`--robust`).
+pub fn enable_robust_statistics() {
+    ROBUST_STATISTICS.store(true, atomic::Ordering::Relaxed);
+}
+
+pub fn robust_statistics_enabled() -> bool {
+    ROBUST_STATISTICS.load(atomic::Ordering::Relaxed)
+}
+
+/// Share of values cut from each end for the trimmed mean.
+const TRIM: f64 = 0.05;
+
+/// Spread and center that a few corrupted values cannot move far, unlike Avg and Max.
+#[derive(Debug, Clone, Copy)]
+pub struct RobustStatistics {
+    /// Median absolute deviation from the median.
+    pub mad: f64,
+    /// Mean without the lowest and highest `TRIM` of the values.
+    pub trimmed_avg: f64,
+}
+
+impl RobustStatistics {
+    /// From sorted, non-empty values.
+    fn from_sorted(data: &[f64], median: f64) -> Self {
+        let mut deviations: Vec<f64> = data.iter().map(|v| (v - median).abs()).collect();
+        deviations.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let n = deviations.len();
+        let mad = match n % 2 {
+            1 => deviations[n / 2],
+            _ => (deviations[n / 2 - 1] + deviations[n / 2]) / 2.0,
+        };
+        let cut = (n as f64 * TRIM).floor() as usize;
+        let kept = &data[cut..n - cut];
+        Self {
+            mad,
+            trimmed_avg: kept.iter().sum::<f64>() / kept.len() as f64,
+        }
+    }
+}
+
+/// 95% confidence intervals, `(low, high)`, treating the values as independent samples: normal
+/// for the mean, and distribution-free (binomial order statistics) for the percentiles.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfidenceIntervals {
+    pub avg: (f64, f64),
+    pub p50: (f64, f64),
+    pub p90: (f64, f64),
+}
+
+impl ConfidenceIntervals {
+    /// From sorted values; `None` for fewer than two.
+    fn from_sorted(data: &[f64]) -> Option<Self> {
+        let n = data.len();
+        if n < 2 {
+            return None;
+        }
+        let nf = n as f64;
+        let mean = data.iter().sum::<f64>() / nf;
+        let var = data.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (nf - 1.0);
+        let half = Z95 * (var / nf).sqrt();
+        let quantile = |q: f64| {
+            let spread = Z95 * (nf * q * (1.0 - q)).sqrt();
+            let lo = (nf * q - spread).floor().max(0.0) as usize;
+            let hi = ((nf * q + spread).ceil() as usize).min(n - 1);
+            (data[lo.min(n - 1)], data[hi])
+        };
+        Some(Self {
+            avg: (mean - half, mean + half),
+            p50: quantile(0.5),
+            p90: quantile(0.9),
+        })
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Statistics {
@@ -14,6 +106,26 @@ pub struct Statistics {
     pub p999: f64,
     pub max: f64,
     pub cnt: usize,
+    /// Only with `enable_confidence_intervals`.
+    pub ci: Option<ConfidenceIntervals>,
+    /// Only with `enable_robust_statistics`.
+    pub robust: Option<RobustStatistics>,
+}
+
+/// How many node samples the blocks of a row were computed from.
+#[derive(Debug, Clone, Copy)]
+pub struct SampleCounts {
+    pub avg: f64,
+    pub min: u32,
+}
+
+pub fn sample_counts(counts: &[u32]) -> Option<SampleCounts> {
+    let min = *counts.iter().min()?;
+    let sum: f64 = counts.iter().map(|&c| c as f64).sum();
+    Some(SampleCounts {
+        avg: sum / (counts.len() as f64),
+        min,
+    })
 }
 
 pub fn statistics_from_sorted(data: &[f64]) -> Statistics {
@@ -30,12 +142,14 @@ pub fn statistics_from_sorted(data: &[f64]) -> Statistics {
             p999: f64::NAN,
             max: f64::NAN,
             cnt: 0,
+            ci: None,
+            robust: None,
         };
     }
 
     let cnt = data.len();
     let sum: f64 = data.iter().sum();
-    let avg = (sum / (cnt as f64) * 100.0).round() / 100.0;
+    let avg = sum / (cnt as f64);
     let pick = |q: f64| -> f64 {
         if cnt == 1 {
             return data[0];
@@ -51,11 +165,12 @@ pub fn statistics_from_sorted(data: &[f64]) -> Statistics {
         data[lo] + (data[hi] - data[lo]) * w
     };
 
+    let p50 = pick(0.5);
     Statistics {
         avg,
         p10: pick(0.1),
         p30: pick(0.3),
-        p50: pick(0.5),
+        p50,
         p80: pick(0.8),
         p90: pick(0.9),
         p95: pick(0.95),
@@ -63,6 +178,10 @@ pub fn statistics_from_sorted(data: &[f64]) -> Statistics {
         p999: pick(0.999),
         max: *data.last().unwrap(),
         cnt,
+        ci: confidence_intervals_enabled()
+            .then(|| ConfidenceIntervals::from_sorted(data))
+            .flatten(),
+        robust: robust_statistics_enabled().then(|| RobustStatistics::from_sorted(data, p50)),
     }
 }
 
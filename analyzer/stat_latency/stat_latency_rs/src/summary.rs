@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use crate::format::formatter;
+use crate::model::{AnalysisData, BlockRowValues, BlockScalars, NodePercentile, TxRowValues};
+
+/// Every key the summary line can show. `{receive,sync,cons}_pNN` are the mean over blocks of
+/// each block's node percentile (the Avg column of the block broadcast latency rows), and
+/// `tx_pNN` the same over txs received by every node.
+pub const SUMMARY_KEYS: &[&str] = &[
+    "nodes",
+    "hosts",
+    "blocks",
+    "txs",
+    "duration",
+    "tps",
+    "receive_p50",
+    "receive_p90",
+    "receive_p99",
+    "sync_p50",
+    "sync_p90",
+    "sync_p99",
+    "cons_p50",
+    "cons_p90",
+    "cons_p99",
+    "tx_p50",
+    "tx_p90",
+    "tx_p99",
+    "warnings",
+];
+
+/// Values of the summary keys, formatted, taken before the table consumes the row values.
+#[derive(Debug, Default)]
+pub struct Summary {
+    values: HashMap<String, String>,
+}
+
+impl Summary {
+    pub fn new(
+        data: &AnalysisData,
+        scalars: &BlockScalars,
+        block_rows: &BlockRowValues,
+        tx_rows: &TxRowValues,
+    ) -> Self {
+        let mut values = HashMap::new();
+        values.insert("nodes".into(), data.node_count.to_string());
+        values.insert("hosts".into(), data.hosts.len().to_string());
+        values.insert("blocks".into(), data.blocks.len().to_string());
+        values.insert("txs".into(), scalars.tx_sum.to_string());
+        values.insert("duration".into(), scalars.duration.to_string());
+        let tps = match scalars.duration > 0 {
+            true => scalars.tx_sum as f64 / scalars.duration as f64,
+            false => f64::NAN,
+        };
+        values.insert("tps".into(), formatter().with_decimals(tps, 1));
+
+        let percentiles = [
+            ("p50", NodePercentile::P50),
+            ("p90", NodePercentile::P90),
+            ("p99", NodePercentile::P99),
+        ];
+        for (key, prefix) in [("Receive", "receive"), ("Sync", "sync"), ("Cons", "cons")] {
+            for (suffix, p) in percentiles {
+                let per_block = block_rows.values.get(&format!("{}::{}", key, p.name()));
+                values.insert(
+                    format!("{}_{}", prefix, suffix),
+                    formatter().number(mean(per_block)),
+                );
+            }
+        }
+        for (suffix, p) in percentiles {
+            values.insert(
+                format!("tx_{}", suffix),
+                formatter().number(mean(tx_rows.latency.get(&p))),
+            );
+        }
+        Self { values }
+    }
+
+    /// `SUMMARY key=value ...` of `keys`, after `report=label` for a group or segment.
+    pub fn line(&self, keys: &[String], label: Option<&str>, warnings: usize) -> String {
+        let mut line = "SUMMARY".to_string();
+        if let Some(label) = label {
+            line.push_str(&format!(" report={}", label));
+        }
+        for key in keys {
+            let value = match key.as_str() {
+                "warnings" => warnings.to_string(),
+                key => self
+                    .values
+                    .get(key)
+                    .cloned()
+                    .unwrap_or_else(|| "nan".into()),
+            };
+            line.push_str(&format!(" {}={}", key, value));
+        }
+        line
+    }
+}
+
+fn mean(values: Option<&Vec<f64>>) -> f64 {
+    match values {
+        Some(v) if !v.is_empty() => v.iter().sum::<f64>() / v.len() as f64,
+        _ => f64::NAN,
+    }
+}
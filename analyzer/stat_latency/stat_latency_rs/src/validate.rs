@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use prettytable::format::consts::FORMAT_NO_LINESEP_WITH_TITLE;
+use prettytable::{Cell, Row, Table};
+use rayon::prelude::*;
+use serde::de::{Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::host_processing::{collect_hosts, HostLog};
+use crate::schema_check::explain_parse_error;
+
+/// The parts of a blocks.log `validate` looks at. Latencies and tx timestamps, nearly all of a
+/// log, are skipped without being stored.
+#[derive(Deserialize)]
+struct LogHeader {
+    #[serde(default)]
+    schema_version: Option<u32>,
+    #[serde(default)]
+    blocks: HashMap<String, BlockHeader>,
+    #[serde(default)]
+    txs: Count,
+    #[serde(default)]
+    sync_cons_gap_stats: Count,
+}
+
+#[derive(Deserialize)]
+struct BlockHeader {
+    #[serde(default)]
+    timestamp: i64,
+}
+
+/// Number of entries of an array or object, whatever they hold.
+#[derive(Default)]
+struct Count(usize);
+
+impl<'de> Deserialize<'de> for Count {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(CountVisitor)
+    }
+}
+
+struct CountVisitor;
+
+impl<'de> Visitor<'de> for CountVisitor {
+    type Value = Count;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array or object")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Count, A::Error> {
+        let mut n = 0;
+        while seq.next_element::<IgnoredAny>()?.is_some() {
+            n += 1;
+        }
+        Ok(Count(n))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Count, A::Error> {
+        let mut n = 0;
+        while map.next_entry::<IgnoredAny, IgnoredAny>()?.is_some() {
+            n += 1;
+        }
+        Ok(Count(n))
+    }
+}
+
+/// What `validate` found for one host.
+struct HostCheck {
+    id: String,
+    /// Bytes on disk of the log, or of the archive holding it.
+    size: Option<u64>,
+    header: Result<LogHeader>,
+}
+
+fn check_host(id: &str, host: &HostLog) -> HostCheck {
+    let (HostLog::Whole(source) | HostLog::Member(source, _)) = host;
+    let header = match host {
+        HostLog::Whole(source) => source.read_to_vec(),
+        HostLog::Member(source, member) => source.read_member(member),
+    }
+    .and_then(|data| {
+        serde_json::from_slice(&data)
+            .map_err(|e| explain_parse_error(&data, e))
+            .with_context(|| format!("parse JSON from {}", source))
+    });
+    HostCheck {
+        id: id.to_string(),
+        size: host.size(),
+        header,
+    }
+}
+
+/// Directories right below `log_path` without any blocks.log, e.g. of a host whose logs were
+/// never collected.
+fn hosts_without_log(log_path: &Path, ids: &[&str]) -> Result<Vec<String>> {
+    if !log_path.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut missing = Vec::new();
+    for entry in
+        fs::read_dir(log_path).with_context(|| format!("failed to list {}", log_path.display()))?
+    {
+        let entry = entry?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let prefix = format!("{}/", name);
+        if !ids.iter().any(|id| *id == name || id.starts_with(&prefix)) {
+            missing.push(name);
+        }
+    }
+    missing.sort();
+    Ok(missing)
+}
+
+fn size_cell(bytes: Option<u64>) -> String {
+    match bytes {
+        None => "-".to_string(),
+        Some(b) if b < 1 << 20 => format!("{:.1} KB", b as f64 / 1024.0),
+        Some(b) => format!("{:.1} MB", b as f64 / (1 << 20) as f64),
+    }
+}
+
+/// Checks that the hosts under `log_path` left usable logs, without analyzing them: prints, per
+/// host, the size of its blocks.log, whether it parses, its blocks, txs and nodes and the time
+/// range of its blocks, then the directories without a log. Returns whether every host passed.
+pub fn validate(log_path: &Path, merge_archive_outputs: bool) -> Result<bool> {
+    let t0 = Instant::now();
+    let hosts = collect_hosts(log_path, merge_archive_outputs)?;
+    let checks: Vec<HostCheck> = hosts
+        .par_iter()
+        .map(|(id, host)| check_host(id, host))
+        .collect();
+    let ids: Vec<&str> = hosts.iter().map(|(id, _)| id.as_str()).collect();
+    let missing = hosts_without_log(log_path, &ids)?;
+
+    let mut table = Table::new();
+    table.set_titles(Row::new(
+        [
+            "Host", "Size", "Status", "Schema", "Blocks", "Txs", "Nodes", "First", "Last", "Span",
+        ]
+        .iter()
+        .map(|t| Cell::new(t))
+        .collect(),
+    ));
+    let mut failures = Vec::new();
+    for check in &checks {
+        let mut cells = vec![check.id.clone(), size_cell(check.size)];
+        match &check.header {
+            Err(e) => {
+                cells.push("FAILED".to_string());
+                failures.push(format!("{}: {:#}", check.id, e));
+            }
+            Ok(header) => {
+                let timestamps = header
+                    .blocks
+                    .values()
+                    .map(|b| b.timestamp)
+                    .filter(|&t| t != 0);
+                let (first, last) = (timestamps.clone().min(), timestamps.max());
+                let status = match (header.blocks.is_empty(), header.sync_cons_gap_stats.0) {
+                    (true, _) => "no blocks",
+                    (_, 0) => "no nodes",
+                    _ => "ok",
+                };
+                if status != "ok" {
+                    failures.push(format!("{}: {}", check.id, status));
+                }
+                cells.extend([
+                    status.to_string(),
+                    header
+                        .schema_version
+                        .map_or_else(|| "-".to_string(), |v| v.to_string()),
+                    header.blocks.len().to_string(),
+                    header.txs.0.to_string(),
+                    header.sync_cons_gap_stats.0.to_string(),
+                    first.map_or_else(|| "-".to_string(), |t| t.to_string()),
+                    last.map_or_else(|| "-".to_string(), |t| t.to_string()),
+                    match (first, last) {
+                        (Some(first), Some(last)) => format!("{}s", last - first),
+                        _ => "-".to_string(),
+                    },
+                ]);
+            }
+        }
+        table.add_row(Row::new(cells.iter().map(|c| Cell::new(c)).collect()));
+    }
+    for name in &missing {
+        table.add_row(Row::new(vec![
+            Cell::new(name),
+            Cell::new("-"),
+            Cell::new("missing"),
+        ]));
+        failures.push(format!("{}: no blocks.log", name));
+    }
+    table.set_format(*FORMAT_NO_LINESEP_WITH_TITLE);
+    table.printstd();
+
+    let nodes: usize = checks
+        .iter()
+        .filter_map(|c| c.header.as_ref().ok())
+        .map(|h| h.sync_cons_gap_stats.0)
+        .sum();
+    println!(
+        "{} hosts, {} nodes, {} with problems, checked in {:.1}s",
+        checks.len() + missing.len(),
+        nodes,
+        failures.len(),
+        t0.elapsed().as_secs_f64()
+    );
+    for failure in &failures {
+        println!("  {}", failure);
+    }
+    Ok(failures.is_empty())
+}
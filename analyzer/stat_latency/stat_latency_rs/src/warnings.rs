@@ -0,0 +1,59 @@
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// A data-quality problem: what kind, the message printed for it, and its details as JSON.
+#[derive(Debug, Clone)]
+pub struct Warning {
+    /// e.g. `removed_block`, `clock_skew`, `tx_coverage`, `host_coverage`.
+    pub kind: &'static str,
+    pub message: String,
+    /// A JSON object whose fields are added to the record.
+    pub details: Value,
+}
+
+impl Warning {
+    pub fn new(kind: &'static str, message: String, details: Value) -> Self {
+        Self {
+            kind,
+            message,
+            details,
+        }
+    }
+
+    fn to_record(&self) -> Map<String, Value> {
+        let mut record = Map::new();
+        record.insert("kind".into(), self.kind.into());
+        record.insert("message".into(), self.message.clone().into());
+        if let Value::Object(details) = &self.details {
+            record.extend(details.clone());
+        }
+        record
+    }
+}
+
+/// Writes one JSON object per line: the report header (`"kind": "header"`), then every warning
+/// as `{"kind", "message", ..details}`.
+pub fn write_warnings(
+    path: &Path,
+    header: &[(String, String)],
+    warnings: &[Warning],
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+
+    let mut record = Map::new();
+    record.insert("kind".into(), "header".into());
+    for (name, value) in header {
+        record.insert(name.clone(), value.clone().into());
+    }
+    writeln!(out, "{}", Value::Object(record))?;
+    for warning in warnings {
+        writeln!(out, "{}", Value::Object(warning.to_record()))?;
+    }
+    out.flush()
+        .with_context(|| format!("failed to write {}", path.display()))
+}
@@ -0,0 +1,117 @@
+//! End-to-end runs of the binary over the logs in `tests/fixtures`, compared with the reports in
+//! `tests/expected`. After an intended change of the report, rerun with `UPDATE_EXPECTED=1` to
+//! rewrite them, and review the diff.
+//!
+//! - `plain`: two hosts, one naming blocks and txs by 0x-prefixed hashes, the other by bare ones.
+//! - `sparse`: one host without `txs` and `by_block_ratio`, one with an empty `txs`.
+//! - `archives`: two `blocks.log.7z`, each with `output0/blocks.log` and `output1/blocks.log`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// The report printed for `args`, run from the fixtures directory, without the command line
+/// (it holds the binary's path).
+fn report(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_stat_latency_rs"))
+        .args(args)
+        .current_dir(tests_dir().join("fixtures"))
+        .output()
+        .expect("failed to run stat_latency_rs");
+    assert!(
+        output.status.success(),
+        "stat_latency_rs {:?} failed:\n{}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout)
+        .expect("report is not UTF-8")
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("args:"))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+fn check(name: &str, args: &[&str]) {
+    let actual = report(args);
+    let path = tests_dir().join("expected").join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_EXPECTED").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e));
+    assert!(
+        actual == expected,
+        "report of {} differs from {}; rerun with UPDATE_EXPECTED=1 if intended\n{}",
+        name,
+        path.display(),
+        actual
+    );
+}
+
+#[test]
+fn test_plain_tree_with_bare_hashes() {
+    check("plain", &["-l", "plain"]);
+}
+
+#[test]
+fn test_missing_and_empty_sections() {
+    check("sparse", &["-l", "sparse"]);
+}
+
+#[test]
+fn test_archive_shallowest_output() {
+    check("archives", &["-l", "archives"]);
+}
+
+#[test]
+fn test_archive_every_output() {
+    check(
+        "archives_merged",
+        &["-l", "archives", "--merge-archive-outputs"],
+    );
+}
+
+#[test]
+fn test_validate() {
+    let validate = |dir: &str| {
+        Command::new(env!("CARGO_BIN_EXE_stat_latency_rs"))
+            .args(["validate", "-l", dir])
+            .current_dir(tests_dir().join("fixtures"))
+            .output()
+            .expect("failed to run stat_latency_rs")
+    };
+    let output = validate("archives");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("2 hosts, 2 nodes, 0 with problems"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("1735689602 | 1735689608 | 6s"),
+        "{}",
+        stdout
+    );
+
+    // A host directory whose log was never collected, next to a good one.
+    let dir = std::env::temp_dir().join(format!("stat_latency_validate_{}", std::process::id()));
+    fs::create_dir_all(dir.join("h0")).unwrap();
+    fs::create_dir_all(dir.join("h2")).unwrap();
+    fs::copy(
+        tests_dir().join("fixtures/plain/h0/blocks.log"),
+        dir.join("h0/blocks.log"),
+    )
+    .unwrap();
+    let output = validate(dir.to_str().unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.contains("h2: no blocks.log"), "{}", stdout);
+}
@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::Pattern;
+
+pub(crate) fn member_matches(member: &str, pattern: &Pattern) -> bool {
+    pattern.matches(member.rsplit('/').next().unwrap_or(member))
+}
+
+fn sevenz_reader(path: &Path) -> Result<sevenz_rust::SevenZReader<File>> {
+    let mut file =
+        File::open(path).with_context(|| format!("failed to open archive {}", path.display()))?;
+
+    let pos = file
+        .stream_position()
+        .with_context(|| format!("failed to get stream position for {}", path.display()))?;
+    let len = file
+        .seek(SeekFrom::End(0))
+        .with_context(|| format!("failed to seek to end for {}", path.display()))?;
+    file.seek(SeekFrom::Start(pos))
+        .with_context(|| format!("failed to seek to start for {}", path.display()))?;
+
+    let password = sevenz_rust::Password::empty();
+    sevenz_rust::SevenZReader::new(file, len, password)
+        .with_context(|| format!("failed to create 7z reader for {}", path.display()))
+}
+
+pub(crate) fn sevenz_members(archive: &Path, pattern: &Pattern) -> Result<Vec<String>> {
+    let mut members = Vec::new();
+    sevenz_reader(archive)?
+        .for_each_entries(|entry, _| {
+            if !entry.is_directory() && member_matches(entry.name(), pattern) {
+                members.push(entry.name().to_string());
+            }
+            Ok(true)
+        })
+        .with_context(|| format!("failed to iterate entries in {}", archive.display()))?;
+    Ok(members)
+}
+
+pub(crate) fn read_sevenz_member(archive: &Path, member: &str) -> Result<Vec<u8>> {
+    let mut result: Option<Vec<u8>> = None;
+    sevenz_reader(archive)?
+        .for_each_entries(|entry, reader| {
+            if entry.name() == member {
+                let mut out = Vec::new();
+                reader.read_to_end(&mut out)?;
+                result = Some(out);
+            }
+            Ok(true)
+        })
+        .with_context(|| {
+            format!(
+                "failed to read content of {} from {}",
+                member,
+                archive.display()
+            )
+        })?;
+
+    result.ok_or_else(|| {
+        anyhow!(
+            "member {} not found in archive {}",
+            member,
+            archive.display()
+        )
+    })
+}
+
+/// Every entry of the 7z archive in `reader` (`len` bytes) that `wanted` accepts, with its
+/// content, in one pass.
+pub(crate) fn sevenz_entries<R: Read + Seek>(
+    reader: R,
+    len: u64,
+    wanted: &mut dyn FnMut(&str) -> bool,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    sevenz_rust::SevenZReader::new(reader, len, sevenz_rust::Password::empty())
+        .context("failed to create 7z reader")?
+        .for_each_entries(|entry, reader| {
+            if !entry.is_directory() && wanted(entry.name()) {
+                let mut out = Vec::new();
+                reader.read_to_end(&mut out)?;
+                entries.push((entry.name().to_string(), out));
+            }
+            Ok(true)
+        })
+        .context("failed to iterate entries")?;
+    Ok(entries)
+}
+
+/// `sevenz_entries` of a gzipped tarball.
+pub(crate) fn tar_gz_entries(
+    reader: impl Read,
+    wanted: &mut dyn FnMut(&str) -> bool,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(reader));
+    let mut entries = Vec::new();
+    for entry in archive.entries().context("failed to iterate entries")? {
+        let mut entry = entry.context("failed to iterate entries")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if wanted(&name) {
+            let mut out = Vec::new();
+            entry
+                .read_to_end(&mut out)
+                .with_context(|| format!("failed to read content of {}", name))?;
+            entries.push((name, out));
+        }
+    }
+    Ok(entries)
+}
+
+fn tar_gz_reader(path: &Path) -> Result<tar::Archive<flate2::read::GzDecoder<File>>> {
+    let file =
+        File::open(path).with_context(|| format!("failed to open archive {}", path.display()))?;
+    Ok(tar::Archive::new(flate2::read::GzDecoder::new(file)))
+}
+
+pub(crate) fn tar_gz_members(archive: &Path, pattern: &Pattern) -> Result<Vec<String>> {
+    let mut members = Vec::new();
+    let mut reader = tar_gz_reader(archive)?;
+    let entries = reader
+        .entries()
+        .with_context(|| format!("failed to iterate entries in {}", archive.display()))?;
+    for entry in entries {
+        let entry =
+            entry.with_context(|| format!("failed to iterate entries in {}", archive.display()))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if member_matches(&name, pattern) {
+            members.push(name);
+        }
+    }
+    Ok(members)
+}
+
+pub(crate) fn read_tar_gz_member(archive: &Path, member: &str) -> Result<Vec<u8>> {
+    let mut reader = tar_gz_reader(archive)?;
+    let entries = reader
+        .entries()
+        .with_context(|| format!("failed to iterate entries in {}", archive.display()))?;
+    for entry in entries {
+        let mut entry =
+            entry.with_context(|| format!("failed to iterate entries in {}", archive.display()))?;
+        if entry.path()?.to_string_lossy() == member {
+            let mut out = Vec::new();
+            entry.read_to_end(&mut out).with_context(|| {
+                format!(
+                    "failed to read content of {} from {}",
+                    member,
+                    archive.display()
+                )
+            })?;
+            return Ok(out);
+        }
+    }
+    Err(anyhow!(
+        "member {} not found in archive {}",
+        member,
+        archive.display()
+    ))
+}
@@ -0,0 +1,305 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::Source;
+
+/// Lock file of the whole cache: shared while reading, adding or evicting entries, exclusive to
+/// remove entry locks.
+const DIR_LOCK: &str = ".lock";
+
+/// An archive as of one modification: path, length and modification time.
+type ArchiveVersion = (PathBuf, u64, SystemTime);
+
+/// Extracted archive members, kept in a directory shared by every process reading the same
+/// archives (e.g. the jobs of a CI matrix), so each member is extracted once.
+///
+/// An entry is named by the SHA-256 of the archive content and the member, so a changed archive
+/// never hits a stale entry. Processes coordinate through file locks: the one extracting an
+/// entry holds its lock and the others wait for it, and eviction (least recently read first,
+/// down to `max_bytes`) removes an entry only while holding its lock.
+#[derive(Debug, Clone)]
+pub struct MemberCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Archive digests, so each archive is hashed once.
+    digests: Arc<Mutex<HashMap<ArchiveVersion, String>>>,
+}
+
+impl MemberCache {
+    pub fn new(dir: &Path, max_bytes: u64) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create cache dir {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            digests: Arc::default(),
+        })
+    }
+
+    /// Content of `member` of `source`, or of the log `Source::open` reads without one. Sources
+    /// that are not archives are read directly.
+    pub fn read(&self, source: &Source, member: Option<&str>) -> Result<Vec<u8>> {
+        let (archive, pattern) = match source {
+            Source::SevenZ { archive, pattern } | Source::TarGz { archive, pattern } => {
+                (archive, pattern)
+            }
+            Source::File { .. } | Source::Stdin => return source.read_to_vec(),
+        };
+        let extract = || match member {
+            Some(member) => source.read_member(member),
+            None => source.read_to_vec(),
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.archive_digest(archive)?);
+        match member {
+            Some(member) => hasher.update(format!("member:{}", member)),
+            None => hasher.update(format!("shallowest:{:?}", pattern)),
+        }
+        let key = hex(&hasher.finalize());
+
+        let data = {
+            let dir_lock = self.lock_file(DIR_LOCK)?;
+            dir_lock.lock_shared()?;
+            let entry_lock = self.lock_file(&format!("{}.lock", key))?;
+            entry_lock.lock()?;
+
+            let entry = self.dir.join(&key);
+            match fs::read(&entry) {
+                Ok(data) => {
+                    // The modification time orders entries for eviction.
+                    File::options()
+                        .write(true)
+                        .open(&entry)
+                        .and_then(|f| f.set_modified(SystemTime::now()))
+                        .with_context(|| format!("failed to touch {}", entry.display()))?;
+                    data
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    let data = extract()?;
+                    let tmp = self.dir.join(format!("{}.tmp", key));
+                    fs::write(&tmp, &data)
+                        .and_then(|_| fs::rename(&tmp, &entry))
+                        .with_context(|| format!("failed to write {}", entry.display()))?;
+                    data
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to read {}", entry.display()))
+                }
+            }
+        };
+
+        self.evict()?;
+        Ok(data)
+    }
+
+    fn archive_digest(&self, archive: &Path) -> Result<String> {
+        let meta = fs::metadata(archive)
+            .with_context(|| format!("failed to stat {}", archive.display()))?;
+        let id = (
+            archive.to_path_buf(),
+            meta.len(),
+            meta.modified().unwrap_or(UNIX_EPOCH),
+        );
+        if let Some(digest) = self.digests.lock().unwrap().get(&id) {
+            return Ok(digest.clone());
+        }
+
+        let mut file = File::open(archive)
+            .with_context(|| format!("failed to open archive {}", archive.display()))?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 1 << 20];
+        loop {
+            let n = file
+                .read(&mut buf)
+                .with_context(|| format!("failed to read {}", archive.display()))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        let digest = hex(&hasher.finalize());
+        self.digests.lock().unwrap().insert(id, digest.clone());
+        Ok(digest)
+    }
+
+    /// Removes the least recently read entries until the cache fits `max_bytes`, and the
+    /// temporary files of extractions that crashed. Entries another process is reading or
+    /// extracting are skipped; a later read evicts them instead.
+    fn evict(&self) -> Result<()> {
+        let dir_lock = self.lock_file(DIR_LOCK)?;
+        dir_lock.lock_shared()?;
+
+        let mut entries = Vec::new();
+        let mut tmp_keys = Vec::new();
+        for name in self.file_names()? {
+            if name.starts_with('.') || name.ends_with(".lock") {
+                continue;
+            }
+            if let Some(key) = name.strip_suffix(".tmp") {
+                tmp_keys.push(key.to_string());
+                continue;
+            }
+            let meta = match fs::metadata(self.dir.join(&name)) {
+                Ok(meta) => meta,
+                // Evicted by another process since the listing.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("failed to stat {}", name)),
+            };
+            entries.push((meta.modified().unwrap_or(UNIX_EPOCH), meta.len(), name));
+        }
+
+        // An extraction holds its entry lock until the temporary file is renamed, so a temporary
+        // file with a free entry lock was left by a process that died.
+        for key in tmp_keys {
+            if let Some(_entry_lock) = self.try_lock_entry(&key)? {
+                let _ = fs::remove_file(self.dir.join(format!("{}.tmp", key)));
+            }
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+        for (_, len, name) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            let Some(_entry_lock) = self.try_lock_entry(&name)? else {
+                continue;
+            };
+            match fs::remove_file(self.dir.join(&name)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).with_context(|| format!("failed to evict {}", name)),
+            }
+            total -= len;
+        }
+
+        // A process may be waiting on the lock of an evicted entry, so entry locks are only
+        // removed while no other process is using the cache.
+        dir_lock.unlock()?;
+        if dir_lock.try_lock().is_ok() {
+            let names = self.file_names()?;
+            for name in &names {
+                let Some(key) = name.strip_suffix(".lock") else {
+                    continue;
+                };
+                if !key.is_empty() && !names.iter().any(|n| n == key) {
+                    let _ = fs::remove_file(self.dir.join(name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Lock of the entry `key` if no other process holds it.
+    fn try_lock_entry(&self, key: &str) -> Result<Option<File>> {
+        let entry_lock = self.lock_file(&format!("{}.lock", key))?;
+        Ok(entry_lock.try_lock().is_ok().then_some(entry_lock))
+    }
+
+    fn file_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.dir)
+            .with_context(|| format!("failed to list {}", self.dir.display()))?
+        {
+            names.push(entry?.file_name().to_string_lossy().into_owned());
+        }
+        Ok(names)
+    }
+
+    fn lock_file(&self, name: &str) -> Result<File> {
+        let path = self.dir.join(name);
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("failed to open lock file {}", path.display()))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pattern;
+
+    #[test]
+    fn test_member_cache_hit_and_eviction() {
+        let root = std::env::temp_dir().join(format!("testlog_io_cache_{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let archive = root.join("blocks.log.tar.gz");
+        let tar_gz = flate2::write::GzEncoder::new(
+            File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(tar_gz);
+        for (member, text) in [
+            ("output0/blocks.log", "zero\n"),
+            ("output1/blocks.log", "one\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(text.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, member, text.as_bytes())
+                .unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+        let source = Source::TarGz {
+            archive,
+            pattern: Pattern::Name("blocks.log".into()),
+        };
+
+        let cache_dir = root.join("cache");
+        let cache = MemberCache::new(&cache_dir, u64::MAX).unwrap();
+        let entries = || {
+            let mut names: Vec<_> = fs::read_dir(&cache_dir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .filter(|n| !n.starts_with('.') && !n.ends_with(".lock"))
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            cache.read(&source, Some("output1/blocks.log")).unwrap(),
+            b"one\n"
+        );
+        assert_eq!(cache.read(&source, None).unwrap(), b"zero\n");
+        assert_eq!(entries().len(), 2);
+
+        // A second reader, e.g. another process, is served from the cache.
+        for name in entries() {
+            fs::write(cache_dir.join(name), "cached").unwrap();
+        }
+        let other = MemberCache::new(&cache_dir, u64::MAX).unwrap();
+        assert_eq!(other.read(&source, None).unwrap(), b"cached");
+
+        // Eviction does not wait for other processes to stop using the cache, and removes the
+        // temporary file of an extraction that died.
+        let reader = File::open(cache_dir.join(DIR_LOCK)).unwrap();
+        reader.lock_shared().unwrap();
+        fs::write(cache_dir.join("crashed.tmp"), "partial").unwrap();
+        let small = MemberCache::new(&cache_dir, 0).unwrap();
+        assert_eq!(
+            small.read(&source, Some("output0/blocks.log")).unwrap(),
+            b"zero\n"
+        );
+        assert!(entries().is_empty());
+        drop(reader);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
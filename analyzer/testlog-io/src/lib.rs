@@ -0,0 +1,417 @@
+//! Discovery and reading of massive-test logs, shared by the analyzers.
+//!
+//! A log is looked up under a root directory by file name. Besides the plain file, it may be
+//! compressed on its own (`<name>.gz`, `<name>.zst`) or packed in an archive (`<name>.7z`,
+//! `<name>.tar.gz`, `<name>.tgz`), and the root `-` reads a single log from stdin. Every format
+//! is read through `Source`, so a new one only needs to be added here. Extracted archive
+//! members can be shared between processes through a `MemberCache`.
+//!
+//! Archives holding the logs of many nodes, whatever their names, are found by
+//! `discover_with_archives`; `read_archive_entries` opens an archive extracted from another one.
+
+mod archive;
+mod cache;
+
+pub use cache::MemberCache;
+
+use anyhow::{Context, Result};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Cursor, Read},
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Which file names are logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// The file name is exactly this, e.g. `blocks.log`.
+    Name(String),
+    /// The file name ends with this, e.g. `.log.new_blocks`.
+    Suffix(String),
+}
+
+impl Pattern {
+    pub fn matches(&self, file_name: &str) -> bool {
+        match self {
+            Pattern::Name(name) => file_name == name,
+            Pattern::Suffix(suffix) => file_name.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    SevenZ,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// The format of an archive named `name`, by its extension.
+    pub fn of(name: &str) -> Option<Self> {
+        if name.ends_with(".7z") {
+            Some(ArchiveFormat::SevenZ)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Every member of the `format` archive held in `data` that `wanted` accepts, with its content,
+/// read in one pass; e.g. an archive extracted from another one.
+pub fn read_archive_entries(
+    format: ArchiveFormat,
+    data: &[u8],
+    wanted: &mut dyn FnMut(&str) -> bool,
+) -> Result<Vec<(String, Vec<u8>)>> {
+    match format {
+        ArchiveFormat::SevenZ => {
+            archive::sevenz_entries(Cursor::new(data), data.len() as u64, wanted)
+        }
+        ArchiveFormat::TarGz => archive::tar_gz_entries(data, wanted),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    File {
+        path: PathBuf,
+        compression: Compression,
+    },
+    /// The log inside a 7z archive, see `Source::members`.
+    SevenZ {
+        archive: PathBuf,
+        pattern: Pattern,
+    },
+    /// The log inside a gzipped tarball, see `Source::members`.
+    TarGz {
+        archive: PathBuf,
+        pattern: Pattern,
+    },
+    Stdin,
+}
+
+impl Source {
+    /// The file or archive read; `None` for stdin.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Source::File { path, .. } => Some(path),
+            Source::SevenZ { archive, .. } | Source::TarGz { archive, .. } => Some(archive),
+            Source::Stdin => None,
+        }
+    }
+
+    /// Archive members matching the pattern, shallowest first (shortest name, then by name).
+    /// Empty for sources that are not archives.
+    pub fn members(&self) -> Result<Vec<String>> {
+        let mut members = match self {
+            Source::SevenZ { archive, pattern } => archive::sevenz_members(archive, pattern)?,
+            Source::TarGz { archive, pattern } => archive::tar_gz_members(archive, pattern)?,
+            Source::File { .. } | Source::Stdin => return Ok(vec![]),
+        };
+        members.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        Ok(members)
+    }
+
+    /// Content of one archive member, as listed by `members`.
+    pub fn read_member(&self, member: &str) -> Result<Vec<u8>> {
+        match self {
+            Source::SevenZ { archive, .. } => archive::read_sevenz_member(archive, member),
+            Source::TarGz { archive, .. } => archive::read_tar_gz_member(archive, member),
+            Source::File { .. } | Source::Stdin => {
+                Err(anyhow::anyhow!("{} is not an archive", self))
+            }
+        }
+    }
+
+    /// Every archive member `wanted` accepts, with its content, read in one pass instead of one
+    /// pass per `read_member`. Empty for sources that are not archives.
+    pub fn read_entries(
+        &self,
+        wanted: &mut dyn FnMut(&str) -> bool,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let (archive, format) = match self {
+            Source::SevenZ { archive, .. } => (archive, ArchiveFormat::SevenZ),
+            Source::TarGz { archive, .. } => (archive, ArchiveFormat::TarGz),
+            Source::File { .. } | Source::Stdin => return Ok(vec![]),
+        };
+        let file = File::open(archive)
+            .with_context(|| format!("failed to open archive {}", archive.display()))?;
+        match format {
+            ArchiveFormat::SevenZ => {
+                let len = file.metadata()?.len();
+                archive::sevenz_entries(file, len, wanted)
+            }
+            ArchiveFormat::TarGz => archive::tar_gz_entries(file, wanted),
+        }
+        .with_context(|| format!("failed to read {}", archive.display()))
+    }
+
+    /// Reader over the decompressed log. From an archive, the shallowest matching member is
+    /// read, extracted into memory first.
+    pub fn open(&self) -> Result<Box<dyn BufRead + Send>> {
+        Ok(match self {
+            Source::File { path, compression } => {
+                let file = File::open(path)
+                    .with_context(|| format!("failed to open {}", path.display()))?;
+                match compression {
+                    Compression::None => Box::new(BufReader::new(file)),
+                    Compression::Gzip => {
+                        Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(file)))
+                    }
+                    Compression::Zstd => Box::new(BufReader::new(
+                        zstd::Decoder::new(file)
+                            .with_context(|| format!("failed to open {}", path.display()))?,
+                    )),
+                }
+            }
+            Source::SevenZ { .. } | Source::TarGz { .. } => {
+                let member =
+                    self.members()?.into_iter().next().ok_or_else(|| {
+                        anyhow::anyhow!("no matching log found in archive {}", self)
+                    })?;
+                Box::new(Cursor::new(self.read_member(&member)?))
+            }
+            Source::Stdin => Box::new(BufReader::new(io::stdin())),
+        })
+    }
+
+    pub fn read_to_vec(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.read_into(&mut data)?;
+        Ok(data)
+    }
+
+    /// `read_to_vec` into `buf`, replacing its content but keeping its allocation, so a caller
+    /// reading many logs in turn can reuse one buffer.
+    pub fn read_into(&self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.clear();
+        self.open()?
+            .read_to_end(buf)
+            .with_context(|| format!("failed to read {}", self))?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path() {
+            Some(path) => write!(f, "{}", path.display()),
+            None => write!(f, "<stdin>"),
+        }
+    }
+}
+
+/// Every log under `root`, in path order. Symlinks are followed.
+///
+/// `root` may also be a single file, which is read whatever its name (its extension still picks
+/// the compression or archive format), or `-` for stdin.
+pub fn discover(root: &Path, pattern: &Pattern) -> Result<Vec<Source>> {
+    walk(root, pattern, false)
+}
+
+/// `discover`, also returning every `.7z`, `.tar.gz` and `.tgz` archive whatever its name, e.g.
+/// `10.0.3.17.7z` packing the logs of all nodes of a host. Whether such an archive holds any log
+/// is only known once its members are listed.
+pub fn discover_with_archives(root: &Path, pattern: &Pattern) -> Result<Vec<Source>> {
+    walk(root, pattern, true)
+}
+
+fn walk(root: &Path, pattern: &Pattern, any_archive: bool) -> Result<Vec<Source>> {
+    if root == Path::new("-") {
+        return Ok(vec![Source::Stdin]);
+    }
+    if root.is_file() {
+        return Ok(classify(root, pattern, true, any_archive)
+            .into_iter()
+            .collect());
+    }
+
+    let mut sources = Vec::new();
+    for entry in WalkDir::new(root).follow_links(true).sort_by_file_name() {
+        let entry = entry.with_context(|| format!("failed to walk {}", root.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        if let Some(source) = classify(entry.path(), pattern, false, any_archive) {
+            sources.push(source);
+        }
+    }
+    Ok(sources)
+}
+
+/// The source `path` is, if it holds a log. With `explicit`, the name in front of the format
+/// extension is not checked; with `any_archive`, neither is that of an archive.
+fn classify(path: &Path, pattern: &Pattern, explicit: bool, any_archive: bool) -> Option<Source> {
+    let name = path.file_name()?.to_str()?;
+    let holds_log = |stem: &str| explicit || pattern.matches(stem);
+    let archive_holds_log = |stem: &str| any_archive || holds_log(stem);
+    let path = path.to_path_buf();
+
+    if pattern.matches(name) {
+        return Some(Source::File {
+            path,
+            compression: Compression::None,
+        });
+    }
+    for ext in [".tar.gz", ".tgz"] {
+        if name.strip_suffix(ext).is_some_and(archive_holds_log) {
+            return Some(Source::TarGz {
+                archive: path,
+                pattern: pattern.clone(),
+            });
+        }
+    }
+    if name.strip_suffix(".7z").is_some_and(archive_holds_log) {
+        return Some(Source::SevenZ {
+            archive: path,
+            pattern: pattern.clone(),
+        });
+    }
+    for (ext, compression) in [(".gz", Compression::Gzip), (".zst", Compression::Zstd)] {
+        if name.strip_suffix(ext).is_some_and(holds_log) {
+            return Some(Source::File { path, compression });
+        }
+    }
+    explicit.then_some(Source::File {
+        path,
+        compression: Compression::None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::member_matches;
+    use std::{fs, io::Write};
+
+    #[test]
+    fn test_discover_and_read_every_format() {
+        let root = std::env::temp_dir().join(format!("testlog_io_{}", std::process::id()));
+        let host = |i: usize| {
+            let dir = root.join(format!("host{i}"));
+            fs::create_dir_all(&dir).unwrap();
+            dir
+        };
+        let content = |i: usize| format!("log of host {i}\n");
+
+        fs::write(host(0).join("blocks.log"), content(0)).unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(
+            File::create(host(1).join("blocks.log.gz")).unwrap(),
+            flate2::Compression::default(),
+        );
+        gz.write_all(content(1).as_bytes()).unwrap();
+        gz.finish().unwrap();
+
+        fs::write(
+            host(2).join("blocks.log.zst"),
+            zstd::encode_all(content(2).as_bytes(), 0).unwrap(),
+        )
+        .unwrap();
+
+        let tar_gz = flate2::write::GzEncoder::new(
+            File::create(host(3).join("blocks.log.tar.gz")).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(tar_gz);
+        for (member, text) in [("output1/blocks.log", "nested\n"), ("blocks.log", "top\n")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(text.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, member, text.as_bytes())
+                .unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+
+        fs::write(host(4).join("other.log"), "ignored").unwrap();
+
+        let pattern = Pattern::Name("blocks.log".into());
+        let sources = discover(&root, &pattern).unwrap();
+        let texts: Vec<_> = sources
+            .iter()
+            .map(|s| String::from_utf8(s.read_to_vec().unwrap()).unwrap())
+            .collect();
+        assert_eq!(texts, [content(0), content(1), content(2), "top\n".into()]);
+        assert_eq!(
+            sources[3].members().unwrap(),
+            ["blocks.log", "output1/blocks.log"]
+        );
+        assert_eq!(
+            sources[3].read_member("output1/blocks.log").unwrap(),
+            b"nested\n"
+        );
+
+        let explicit = discover(&host(4).join("other.log"), &pattern).unwrap();
+        assert_eq!(explicit[0].read_to_vec().unwrap(), b"ignored");
+        assert_eq!(discover(Path::new("-"), &pattern).unwrap(), [Source::Stdin]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_archives_of_any_name_and_nested() {
+        let root = std::env::temp_dir().join(format!("testlog_io_nested_{}", std::process::id()));
+        let packed = root.join("packed");
+        for i in 0..2 {
+            fs::create_dir_all(packed.join(format!("output{i}"))).unwrap();
+            fs::write(
+                packed.join(format!("output{i}/blocks.log")),
+                format!("node {i}\n"),
+            )
+            .unwrap();
+        }
+        fs::write(packed.join("notes.txt"), "ignored").unwrap();
+        let tar_gz = flate2::write::GzEncoder::new(
+            File::create(packed.join("inner.tar.gz")).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(tar_gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(7);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "node2/blocks.log", &b"node 2\n"[..])
+            .unwrap();
+        tar.into_inner().unwrap().finish().unwrap();
+        fs::create_dir_all(root.join("hosts")).unwrap();
+        sevenz_rust::compress_to_path(&packed, root.join("hosts/10.0.3.17.7z")).unwrap();
+
+        let pattern = Pattern::Name("blocks.log".into());
+        let hosts = root.join("hosts");
+        assert!(discover(&hosts, &pattern).unwrap().is_empty());
+        let sources = discover_with_archives(&hosts, &pattern).unwrap();
+        assert_eq!(sources.len(), 1);
+
+        let mut wanted =
+            |member: &str| member_matches(member, &pattern) || ArchiveFormat::of(member).is_some();
+        let mut entries = sources[0].read_entries(&mut wanted).unwrap();
+        entries.sort();
+        let names: Vec<_> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["inner.tar.gz", "output0/blocks.log", "output1/blocks.log"]
+        );
+        assert_eq!(entries[2].1, b"node 1\n");
+
+        let format = ArchiveFormat::of(&entries[0].0).unwrap();
+        let nested = read_archive_entries(format, &entries[0].1, &mut wanted).unwrap();
+        assert_eq!(
+            nested,
+            [("node2/blocks.log".to_string(), b"node 2\n".to_vec())]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,4 +1,7 @@
-from typing import Optional, Set, List, Tuple
+from typing import Any, Dict, Optional, Set, List, Tuple, Union
+
+import numpy as np
+from numpy.typing import NDArray
 
 class RustBlock:
     @property
@@ -28,6 +31,15 @@ class RustBlock:
     @property
     def block_size(self) -> int: ...
 
+    @property
+    def author(self) -> Optional[bytes]: ...
+
+    @property
+    def adaptive(self) -> bool: ...
+
+    @property
+    def heavy(self) -> bool: ...
+
     @property
     def children(self) -> List[bytes]: ...
 
@@ -50,13 +62,64 @@ class RustGraph:
     @staticmethod
     def load(path: str) -> RustGraph: ...
     
+    @staticmethod
+    def from_blocks(blocks: List[Dict[str, Any]]) -> RustGraph: ...
+    
+    @staticmethod
+    def load_bin(path: str) -> RustGraph: ...
+    
+    def save(self, path: str) -> None: ...
+    
+    @staticmethod
+    def from_bytes(data: bytes) -> RustGraph: ...
+    
+    def to_bytes(self) -> bytes: ...
+    
     @property
     def pivot_chain(self) -> List[RustBlock]: ...
     
+    @property
+    def block_count(self) -> int: ...
+    
+    def get_block(self, hash_or_prefix: Union[bytes, str]) -> Optional[RustBlock]: ...
+    
+    def blocks_at_height(self, height: int) -> List[RustBlock]: ...
+    
+    def epoch_blocks(self, pivot_hash: Union[bytes, str]) -> Set[bytes]: ...
+    
+    def anticone(self, hash: Union[bytes, str]) -> Set[bytes]: ...
+    
+    def epoch_stats(self) -> Dict[int, Dict[str, Any]]: ...
+    
+    def export_edges(self, path: str) -> None: ...
+    
+    def export_indices(self, path: str) -> None: ...
+    
+    def export_json(self, path: str, min_height: Optional[int] = None, max_height: Optional[int] = None, include_referees: bool = True) -> None: ...
+    
+    def export_dot(self, path: str, min_height: Optional[int] = None, max_height: Optional[int] = None, include_referees: bool = True) -> None: ...
+    
+    def export_graphml(self, path: str, min_height: Optional[int] = None, max_height: Optional[int] = None, include_referees: bool = True) -> None: ...
+    
+    def export_confirmation_audit(self, path: str, adv_percents: List[int] = [10, 15, 20, 25, 30], risk_thresholds: List[float] = [1e-4, 1e-5, 1e-6, 1e-7, 1e-8]) -> None: ...
+    
+    def pivot_heights(self) -> NDArray[np.uint64]: ...
+    
+    def block_table(self, pivot_only: bool = False) -> Dict[str, NDArray[Any]]: ...
+    
     def epoch_span(self, block: RustBlock) -> int: ...
     
     def avg_epoch_time(self, block: RustBlock) -> float: ...
     
     def confirmation_risk(self, block: RustBlock, adv_percent: int, risk_threshold: float) -> Tuple[int, int, int, float]: ...
     
-    def avg_confirm_time(self, adv_percent: int, risk_threshold: float) -> Tuple[float, int]: ...
\ No newline at end of file
+    def avg_confirm_time(self, adv_percent: int, risk_threshold: float) -> Tuple[float, int]: ...
+    
+    def author_block_counts(self) -> Dict[bytes, int]: ...
+    
+    def author_block_share(self) -> Dict[bytes, float]: ...
+    
+    def author_pivot_share(self) -> Dict[bytes, float]: ...
+    
+    @staticmethod
+    def analyze_many(paths: List[str], adv_percent: int, risk_threshold: float, workers: Optional[int] = None) -> List[Dict[str, Any]]: ...
\ No newline at end of file
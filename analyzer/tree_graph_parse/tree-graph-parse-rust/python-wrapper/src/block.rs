@@ -1,71 +1,113 @@
+use ethereum_types::H256;
 use pyo3::{
     prelude::*,
     types::{PyBytes, PyList, PySet},
 };
-use tree_graph_parse_rust::block::Block;
+use std::collections::BTreeSet;
+use tree_graph_parse_rust::{block::Block, frozen::FrozenGraph};
 
 use crate::to_py_obj::ToPyObj;
 
-#[pyclass]
+/// A block of a shared graph; edges are resolved from block ids to hashes on access.
+#[pyclass(module = "tg_parse_rpy")]
 #[derive(Clone)]
 pub(super) struct RustBlock {
-    pub block: Block,
+    graph: FrozenGraph,
+    id: usize,
 }
 
-impl From<&Block> for RustBlock {
-    fn from(block: &Block) -> Self {
-        Self {
-            block: block.clone(),
-        }
+impl RustBlock {
+    pub fn new(graph: FrozenGraph, id: usize) -> Self { Self { graph, id } }
+
+    pub fn block(&self) -> &Block { self.graph.block(self.id) }
+
+    fn hashes<'a>(&self, ids: impl IntoIterator<Item = &'a usize>) -> BTreeSet<H256> {
+        ids.into_iter().map(|&id| self.graph.hash_of(id)).collect()
     }
 }
 
 #[pymethods]
 impl RustBlock {
     #[getter]
-    pub fn id(&self) -> usize { self.block.id }
+    pub fn id(&self) -> usize { self.id }
 
     #[getter]
-    pub fn height(&self) -> u64 { self.block.height }
+    pub fn height(&self) -> u64 { self.block().height }
 
     #[getter]
-    pub fn hash(&self, py: Python) -> Py<PyBytes> { self.block.hash.to_py_obj(py) }
+    pub fn hash(&self, py: Python) -> Py<PyBytes> { self.block().hash.to_py_obj(py) }
 
     #[getter]
-    pub fn parent_hash(&self, py: Python) -> Py<PyAny> { self.block.parent_hash.to_py_obj(py) }
+    pub fn parent_hash(&self, py: Python) -> Py<PyAny> {
+        self.block()
+            .parent
+            .map(|id| self.graph.hash_of(id))
+            .to_py_obj(py)
+    }
 
     #[getter]
     pub fn referee_hashes(&self, py: Python) -> Py<PySet> {
-        self.block.referee_hashes.to_py_obj(py)
+        self.hashes(&self.block().referees).to_py_obj(py)
     }
 
     #[getter]
-    pub fn timestamp(&self) -> u64 { self.block.timestamp }
+    pub fn timestamp(&self) -> u64 { self.block().timestamp }
 
     #[getter]
-    pub fn log_timestamp(&self) -> u64 { self.block.log_timestamp }
+    pub fn log_timestamp(&self) -> u64 { self.block().log_timestamp }
 
     #[getter]
-    pub fn tx_count(&self) -> u64 { self.block.tx_count }
+    pub fn tx_count(&self) -> u64 { self.block().tx_count }
 
     #[getter]
-    pub fn block_size(&self) -> u64 { self.block.block_size }
+    pub fn block_size(&self) -> u64 { self.block().block_size }
 
+    /// 出块矿工地址，日志中没有记录时为 None
     #[getter]
-    pub fn children(&self, py: Python) -> Py<PyList> { self.block.children.to_py_obj(py) }
+    pub fn author(&self, py: Python) -> Py<PyAny> { self.block().author.to_py_obj(py) }
 
+    /// GHAST 自适应权重生效时出的块
     #[getter]
-    pub fn epoch_block(&self, py: Python) -> Py<PyAny> { self.block.epoch_block.to_py_obj(py) }
+    pub fn adaptive(&self) -> bool { self.block().adaptive }
 
+    /// 满足重块难度的块；自适应的块若是重块权重为 240，否则为 0
     #[getter]
-    pub fn epoch_set(&self, py: Python) -> Py<PySet> { self.block.epoch_set.to_py_obj(py) }
+    pub fn heavy(&self) -> bool { self.block().heavy }
+
+    #[getter]
+    pub fn children(&self, py: Python) -> Py<PyList> {
+        let children: Vec<H256> = self
+            .block()
+            .children
+            .iter()
+            .map(|&id| self.graph.hash_of(id))
+            .collect();
+        children.to_py_obj(py)
+    }
+
+    #[getter]
+    pub fn epoch_block(&self, py: Python) -> Py<PyAny> {
+        self.block()
+            .epoch_block
+            .map(|id| self.graph.hash_of(id))
+            .to_py_obj(py)
+    }
+
+    #[getter]
+    pub fn epoch_set(&self, py: Python) -> Py<PySet> {
+        self.block()
+            .epoch_set
+            .as_ref()
+            .map(|set| self.hashes(set))
+            .to_py_obj(py)
+    }
 
     #[getter]
-    pub fn past_set_size(&self) -> u64 { self.block.past_set_size }
+    pub fn past_set_size(&self) -> u64 { self.block().past_set_size }
 
     #[getter]
-    pub fn subtree_size(&self) -> u64 { self.block.subtree_size }
+    pub fn subtree_size(&self) -> u64 { self.block().subtree_size }
 
     #[getter]
-    pub fn epoch_size(&self) -> usize { self.block.epoch_size() }
+    pub fn epoch_size(&self) -> usize { self.block().epoch_size() }
 }
@@ -0,0 +1,65 @@
+use pyo3::{prelude::*, types::PyDict};
+use tree_graph_parse_rust::{block::Block, graph::Graph};
+
+use crate::utils::{bool_array, i64_array, u64_array};
+
+/// 区块字段按列存放，在释放 GIL 时构建，再一次性转成 numpy 数组
+#[derive(Default)]
+pub(crate) struct BlockColumns {
+    id: Vec<u64>,
+    height: Vec<u64>,
+    timestamp: Vec<u64>,
+    log_timestamp: Vec<u64>,
+    tx_count: Vec<u64>,
+    block_size: Vec<u64>,
+    subtree_size: Vec<u64>,
+    past_set_size: Vec<u64>,
+    epoch_size: Vec<u64>,
+    pivot: Vec<bool>,
+    /// 所属 epoch 的主链区块高度，不属于任何 epoch 时为 -1
+    epoch_height: Vec<i64>,
+}
+
+impl BlockColumns {
+    pub fn collect<'a>(graph: &'a Graph, blocks: impl Iterator<Item = &'a Block>) -> Self {
+        let mut columns = BlockColumns::default();
+        for block in blocks {
+            columns.id.push(block.id as u64);
+            columns.height.push(block.height);
+            columns.timestamp.push(block.timestamp);
+            columns.log_timestamp.push(block.log_timestamp);
+            columns.tx_count.push(block.tx_count);
+            columns.block_size.push(block.block_size);
+            columns.subtree_size.push(block.subtree_size);
+            columns.past_set_size.push(block.past_set_size);
+            columns.epoch_size.push(block.epoch_size() as u64);
+            columns.pivot.push(block.epoch_block == Some(block.id));
+            columns.epoch_height.push(
+                block
+                    .epoch_block
+                    .map_or(-1, |id| graph.block(id).height as i64),
+            );
+        }
+        columns
+    }
+
+    pub fn into_dict(self, py: Python) -> PyResult<Py<PyDict>> {
+        let dict = PyDict::new(py);
+        for (name, values) in [
+            ("id", &self.id),
+            ("height", &self.height),
+            ("timestamp", &self.timestamp),
+            ("log_timestamp", &self.log_timestamp),
+            ("tx_count", &self.tx_count),
+            ("block_size", &self.block_size),
+            ("subtree_size", &self.subtree_size),
+            ("past_set_size", &self.past_set_size),
+            ("epoch_size", &self.epoch_size),
+        ] {
+            dict.set_item(name, u64_array(py, values)?)?;
+        }
+        dict.set_item("pivot", bool_array(py, &self.pivot)?)?;
+        dict.set_item("epoch_height", i64_array(py, &self.epoch_height)?)?;
+        Ok(dict.into())
+    }
+}
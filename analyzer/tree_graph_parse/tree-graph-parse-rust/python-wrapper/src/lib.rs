@@ -1,13 +1,26 @@
 mod block;
+mod columns;
+mod raw_blocks;
 mod to_py_obj;
 mod utils;
 
 use block::RustBlock;
+use columns::BlockColumns;
+use ethereum_types::{Address, H256};
 use pyo3::{
+    exceptions::{PyKeyError, PyValueError},
     prelude::*,
-    types::{PyList, PyTuple},
+    types::{PyBytes, PyDict, PyList, PySet, PyString, PyTuple},
 };
-use tree_graph_parse_rust::graph::Graph;
+use raw_blocks::raw_blocks_from_dicts;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use to_py_obj::ToPyObj;
+use tree_graph_parse_rust::{
+    confirmation_audit::DEFAULT_RISK_THRESHOLDS, frozen::FrozenGraph, graph::Graph,
+    visualize::ExportOptions,
+};
+use utils::{parse_h256, u64_array};
 
 macro_rules! no_gil {
     ($py:ident, $expr:expr) => {
@@ -15,35 +28,232 @@ macro_rules! no_gil {
     };
 }
 
-#[pyclass]
+#[pyclass(module = "tg_parse_rpy")]
 struct RustGraph {
-    graph: Graph, // 裸指针字段
+    graph: FrozenGraph,
 }
 
 #[pymethods]
 impl RustGraph {
     #[staticmethod]
     fn load(path: &str, py: Python) -> PyResult<Self> {
-        let graph = no_gil!(py, Graph::load(path))
+        let graph = no_gil!(py, FrozenGraph::load(path))
             .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
         Ok(Self { graph })
     }
 
+    /// 读取 `save` 写出的二进制快照，不需要重新解析日志
+    #[staticmethod]
+    fn load_bin(path: &str, py: Python) -> PyResult<Self> {
+        let graph = no_gil!(py, Graph::load_bin(path))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+        Ok(Self {
+            graph: graph.freeze(),
+        })
+    }
+
+    fn save(&self, path: &str, py: Python) -> PyResult<()> {
+        no_gil!(py, self.graph.save(path))
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+    }
+
+    #[staticmethod]
+    fn from_bytes(data: &[u8], py: Python) -> PyResult<Self> {
+        let graph = no_gil!(py, Graph::from_bytes(data))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self {
+            graph: graph.freeze(),
+        })
+    }
+
+    fn to_bytes(&self, py: Python) -> Py<PyBytes> {
+        let bytes = no_gil!(py, self.graph.to_bytes());
+        PyBytes::new(py, &bytes).into()
+    }
+
+    /// pickle 支持：序列化为快照字节，反序列化时调用 `RustGraph.from_bytes`
+    fn __reduce__(&self, py: Python) -> PyResult<(PyObject, (Py<PyBytes>,))> {
+        let from_bytes = py.get_type::<RustGraph>().getattr("from_bytes")?;
+        Ok((from_bytes.into(), (self.to_bytes(py),)))
+    }
+
+    /// 由 Python 构造的区块列表建图，字段见 `raw_blocks_from_dicts`
+    #[staticmethod]
+    fn from_blocks(blocks: &PyList, py: Python) -> PyResult<Self> {
+        let raw_blocks = raw_blocks_from_dicts(blocks)?;
+        let graph = no_gil!(py, Graph::from_raw_blocks(raw_blocks))
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(Self {
+            graph: graph.freeze(),
+        })
+    }
+
     #[getter]
-    fn genesis_block(&self) -> RustBlock { self.graph.genesis_block().into() }
+    fn genesis_block(&self) -> RustBlock { RustBlock::new(self.graph.clone(), 0) }
 
     #[getter]
     fn pivot_chain(&self, py: Python) -> PyResult<Py<PyList>> {
-        let list = PyList::empty(py);
-        for block in self.graph.pivot_chain() {
-            list.append(PyCell::new(py, RustBlock::from(block))?)?;
+        let ids: Vec<_> = self.graph.pivot_chain().iter().map(|b| b.id).collect();
+        self.block_list(py, ids)
+    }
+
+    #[getter]
+    fn block_count(&self) -> usize { self.graph.block_count() }
+
+    /// 按完整哈希（32 字节或 64 位十六进制）或十六进制前缀查找区块，前缀有歧义时报错
+    fn get_block(&self, hash_or_prefix: &PyAny, py: Python) -> PyResult<Option<RustBlock>> {
+        let prefix = match hash_or_prefix.downcast::<PyString>() {
+            Ok(s) => Some(s.to_str()?.trim_start_matches("0x").to_ascii_lowercase()),
+            Err(_) => None,
+        };
+        let id = match prefix {
+            Some(prefix) if prefix.len() < 64 => {
+                if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(PyValueError::new_err("prefix must be non-empty hex"));
+                }
+                let matches: Vec<usize> = no_gil!(
+                    py,
+                    self.graph
+                        .blocks()
+                        .filter(|b| hex::encode(b.hash.as_bytes()).starts_with(&prefix))
+                        .map(|b| b.id)
+                        .take(2)
+                        .collect()
+                );
+                if matches.len() > 1 {
+                    return Err(PyValueError::new_err(format!(
+                        "prefix {prefix} matches more than one block"
+                    )));
+                }
+                matches.first().copied()
+            }
+            _ => self.graph.block_id(&parse_h256(hash_or_prefix)?),
+        };
+        Ok(id.map(|id| RustBlock::new(self.graph.clone(), id)))
+    }
+
+    fn blocks_at_height(&self, height: u64, py: Python) -> PyResult<Py<PyList>> {
+        let ids: Vec<_> = self.graph.blocks_at_height(height).map(|b| b.id).collect();
+        self.block_list(py, ids)
+    }
+
+    /// 以 `pivot_hash` 为主链区块的 epoch 中全部区块的哈希，不在主链上时为空集
+    fn epoch_blocks(&self, pivot_hash: &PyAny, py: Python) -> PyResult<Py<PySet>> {
+        let pivot = self.graph.block(self.lookup(pivot_hash)?);
+        let hashes: BTreeSet<H256> = self
+            .graph
+            .epoch_blocks(pivot)
+            .iter()
+            .map(|b| b.hash)
+            .collect();
+        Ok(hashes.to_py_obj(py))
+    }
+
+    /// 既不在 `hash` 的过去集也不在其未来集中的区块哈希
+    fn anticone(&self, hash: &PyAny, py: Python) -> PyResult<Py<PySet>> {
+        let block = self.graph.block(self.lookup(hash)?);
+        let hashes: BTreeSet<H256> = no_gil!(
+            py,
+            self.graph
+                .anticone(block)
+                .into_iter()
+                .map(|id| self.graph.hash_of(id))
+                .collect()
+        );
+        Ok(hashes.to_py_obj(py))
+    }
+
+    /// 高度到该 epoch 统计（`pivot`、`size`、`tx_count`、`span`、`avg_epoch_time`）的 dict
+    fn epoch_stats(&self, py: Python) -> PyResult<Py<PyDict>> {
+        let stats = no_gil!(py, self.graph.epoch_stats());
+        let result = PyDict::new(py);
+        for entry in stats {
+            let dict = PyDict::new(py);
+            dict.set_item("pivot", self.graph.hash_of(entry.pivot).to_py_obj(py))?;
+            dict.set_item("size", entry.size)?;
+            dict.set_item("tx_count", entry.tx_count)?;
+            dict.set_item("span", entry.span)?;
+            dict.set_item("avg_epoch_time", entry.avg_epoch_time)?;
+            result.set_item(entry.height, dict)?;
         }
-        Ok(list.into())
+        Ok(result.into())
+    }
+
+    fn export_edges(&self, path: &str, py: Python) -> PyResult<()> {
+        no_gil!(py, self.graph.export_edges(path)).map_err(io_error)
+    }
+
+    fn export_indices(&self, path: &str, py: Python) -> PyResult<()> {
+        no_gil!(py, self.graph.export_indices(path)).map_err(io_error)
+    }
+
+    /// 节点/边 JSON，可只导出 [min_height, max_height] 范围内的区块
+    #[pyo3(signature = (path, min_height = None, max_height = None, include_referees = true))]
+    fn export_json(
+        &self, path: &str, min_height: Option<u64>, max_height: Option<u64>,
+        include_referees: bool, py: Python,
+    ) -> PyResult<()> {
+        let options = export_options(min_height, max_height, include_referees);
+        no_gil!(py, self.graph.export_json(path, &options)).map_err(io_error)
+    }
+
+    #[pyo3(signature = (path, min_height = None, max_height = None, include_referees = true))]
+    fn export_dot(
+        &self, path: &str, min_height: Option<u64>, max_height: Option<u64>,
+        include_referees: bool, py: Python,
+    ) -> PyResult<()> {
+        let options = export_options(min_height, max_height, include_referees);
+        no_gil!(py, self.graph.export_dot(path, &options)).map_err(io_error)
+    }
+
+    #[pyo3(signature = (path, min_height = None, max_height = None, include_referees = true))]
+    fn export_graphml(
+        &self, path: &str, min_height: Option<u64>, max_height: Option<u64>,
+        include_referees: bool, py: Python,
+    ) -> PyResult<()> {
+        let options = export_options(min_height, max_height, include_referees);
+        no_gil!(py, self.graph.export_graphml(path, &options)).map_err(io_error)
     }
 
-    fn epoch_span(&self, block: &RustBlock) -> u64 { self.graph.epoch_span(&block.block) }
+    /// 每个主链区块在各攻击者算力、各风险阈值下的确认时间及 m、k、风险，写成 CSV
+    #[pyo3(signature = (path, adv_percents = vec![10, 15, 20, 25, 30], risk_thresholds = DEFAULT_RISK_THRESHOLDS.to_vec()))]
+    fn export_confirmation_audit(
+        &self, path: &str, adv_percents: Vec<usize>, risk_thresholds: Vec<f64>, py: Python,
+    ) -> PyResult<()> {
+        no_gil!(
+            py,
+            self.graph
+                .export_confirmation_audit_csv(&adv_percents, &risk_thresholds, path)
+        )
+        .map_err(io_error)
+    }
 
-    fn avg_epoch_time(&self, block: &RustBlock) -> f64 { self.graph.avg_epoch_time(&block.block) }
+    /// 主链区块高度，numpy uint64 数组
+    fn pivot_heights(&self, py: Python) -> PyResult<PyObject> {
+        let heights: Vec<u64> = no_gil!(
+            py,
+            self.graph.pivot_chain().iter().map(|b| b.height).collect()
+        );
+        u64_array(py, &heights)
+    }
+
+    /// 区块字段表，列名到 numpy 数组的 dict；全部区块按 id 排列，`pivot_only` 时按主链顺序
+    #[pyo3(signature = (pivot_only = false))]
+    fn block_table(&self, pivot_only: bool, py: Python) -> PyResult<Py<PyDict>> {
+        let graph = &self.graph;
+        let columns = no_gil!(py, {
+            if pivot_only {
+                BlockColumns::collect(graph, graph.pivot_chain().into_iter())
+            } else {
+                BlockColumns::collect(graph, graph.blocks())
+            }
+        });
+        columns.into_dict(py)
+    }
+
+    fn epoch_span(&self, block: &RustBlock) -> u64 { self.graph.epoch_span(block.block()) }
+
+    fn avg_epoch_time(&self, block: &RustBlock) -> f64 { self.graph.avg_epoch_time(block.block()) }
 
     fn confirmation_risk(
         &self, block: &RustBlock, adv_percent: usize, risk_threshold: f64, py: Python,
@@ -51,7 +261,7 @@ impl RustGraph {
         match no_gil!(
             py,
             self.graph
-                .confirmation_risk(&block.block, adv_percent, risk_threshold)
+                .confirmation_risk(block.block(), adv_percent, risk_threshold)
         ) {
             Some((a, b, c, d)) => PyTuple::new(
                 py,
@@ -65,6 +275,107 @@ impl RustGraph {
     fn avg_confirm_time(&self, adv_percent: usize, risk_threshold: f64, py: Python) -> (f64, u64) {
         no_gil!(py, self.graph.avg_confirm_time(adv_percent, risk_threshold))
     }
+
+    /// 矿工地址（20 字节 bytes）到出块数的 dict，不含创世区块和没有记录矿工的区块
+    fn author_block_counts(&self, py: Python) -> PyResult<Py<PyDict>> {
+        author_dict(py, self.graph.author_block_counts())
+    }
+
+    /// 矿工地址到其区块占全部区块比例的 dict
+    fn author_block_share(&self, py: Python) -> PyResult<Py<PyDict>> {
+        author_dict(py, self.graph.author_block_share())
+    }
+
+    /// 矿工地址到其区块占主链比例的 dict
+    fn author_pivot_share(&self, py: Python) -> PyResult<Py<PyDict>> {
+        author_dict(py, no_gil!(py, self.graph.author_pivot_share()))
+    }
+
+    /// 在 Rust 线程池中并行加载并分析多个节点日志，按 `paths` 顺序返回每个节点的结果 dict：
+    /// `path`、`block_count`、`avg_confirm_time`、`confirmed_blocks`，加载失败时只有 `path` 和
+    /// `error`。`workers` 为空时使用全部 CPU
+    #[staticmethod]
+    #[pyo3(signature = (paths, adv_percent, risk_threshold, workers = None))]
+    fn analyze_many(
+        paths: Vec<String>, adv_percent: usize, risk_threshold: f64, workers: Option<usize>,
+        py: Python,
+    ) -> PyResult<Py<PyList>> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(workers.unwrap_or(0))
+            .build()
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        let results: Vec<_> = no_gil!(
+            py,
+            pool.install(|| {
+                paths
+                    .par_iter()
+                    .map(|path| {
+                        let graph = Graph::load(path).map_err(|e| e.to_string())?;
+                        let (avg_time, confirmed) =
+                            graph.avg_confirm_time(adv_percent, risk_threshold);
+                        Ok((graph.block_count(), avg_time, confirmed))
+                    })
+                    .collect::<Vec<Result<_, String>>>()
+            })
+        );
+
+        let list = PyList::empty(py);
+        for (path, result) in paths.iter().zip(results) {
+            let dict = PyDict::new(py);
+            dict.set_item("path", path)?;
+            match result {
+                Ok((block_count, avg_time, confirmed)) => {
+                    dict.set_item("block_count", block_count)?;
+                    dict.set_item("avg_confirm_time", avg_time)?;
+                    dict.set_item("confirmed_blocks", confirmed)?;
+                }
+                Err(e) => dict.set_item("error", e)?,
+            }
+            list.append(dict)?;
+        }
+        Ok(list.into())
+    }
+}
+
+impl RustGraph {
+    fn lookup(&self, hash: &PyAny) -> PyResult<usize> {
+        let hash = parse_h256(hash)?;
+        self.graph
+            .block_id(&hash)
+            .ok_or_else(|| PyKeyError::new_err(format!("block {:?} not found", hash)))
+    }
+
+    fn block_list(&self, py: Python, ids: Vec<usize>) -> PyResult<Py<PyList>> {
+        let list = PyList::empty(py);
+        for id in ids {
+            list.append(PyCell::new(py, RustBlock::new(self.graph.clone(), id))?)?;
+        }
+        Ok(list.into())
+    }
+}
+
+fn export_options(
+    min_height: Option<u64>, max_height: Option<u64>, include_referees: bool,
+) -> ExportOptions {
+    let height_range = (min_height.is_some() || max_height.is_some())
+        .then(|| min_height.unwrap_or(0)..=max_height.unwrap_or(u64::MAX));
+    ExportOptions {
+        height_range,
+        include_referees,
+    }
+}
+
+fn author_dict<T: ToPyObject>(py: Python, values: BTreeMap<Address, T>) -> PyResult<Py<PyDict>> {
+    let dict = PyDict::new(py);
+    for (author, value) in values {
+        dict.set_item(PyBytes::new(py, author.as_bytes()), value)?;
+    }
+    Ok(dict.into())
+}
+
+fn io_error(e: impl std::fmt::Display) -> PyErr {
+    PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string())
 }
 
 #[pymodule]
@@ -0,0 +1,128 @@
+use ethereum_types::H256;
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyList},
+};
+use std::collections::HashMap;
+use tree_graph_parse_rust::block::RawBlock;
+
+use crate::utils::{parse_address, parse_h256};
+
+/// 把 Python 传入的区块 dict 列表转成 RawBlock，创世区块（没有 parent 的那个）放在最前
+///
+/// 每个 dict 必须有 `hash` 和 `timestamp`；`parent` 为空表示创世区块；`referees` 默认为空；
+/// `log_timestamp` 默认等于 `timestamp`；`height` 缺省时取父区块高度加一；`tx_count` 和
+/// `block_size` 默认为 0；`author` 可选；GHAST 标记 `adaptive` 和 `heavy` 默认为 False
+pub(crate) fn raw_blocks_from_dicts(blocks: &PyList) -> PyResult<Vec<RawBlock>> {
+    let mut genesis: Option<RawBlock> = None;
+    let mut raw_blocks = Vec::with_capacity(blocks.len());
+    let mut heights: HashMap<H256, Option<u64>> = HashMap::with_capacity(blocks.len());
+
+    for item in blocks {
+        let dict: &PyDict = item.downcast()?;
+        let hash = parse_h256(required(dict, "hash")?)?;
+        let timestamp: u64 = required(dict, "timestamp")?.extract()?;
+        let log_timestamp: u64 = optional(dict, "log_timestamp")?.unwrap_or(timestamp);
+        let tx_count: u64 = optional(dict, "tx_count")?.unwrap_or(0);
+        let block_size: u64 = optional(dict, "block_size")?.unwrap_or(0);
+        let adaptive: bool = optional(dict, "adaptive")?.unwrap_or(false);
+        let heavy: bool = optional(dict, "heavy")?.unwrap_or(false);
+        let author = match dict.get_item("author")? {
+            Some(author) if !author.is_none() => Some(parse_address(author)?),
+            _ => None,
+        };
+
+        let parent_hash = match dict.get_item("parent")? {
+            Some(parent) if !parent.is_none() => Some(parse_h256(parent)?),
+            _ => None,
+        };
+        let referee_hashes = match dict.get_item("referees")? {
+            Some(referees) if !referees.is_none() => referees
+                .iter()?
+                .map(|r| parse_h256(r?))
+                .collect::<PyResult<Vec<_>>>()?,
+            _ => vec![],
+        };
+
+        let Some(parent_hash) = parent_hash else {
+            if genesis.is_some() {
+                return Err(PyValueError::new_err("more than one block has no parent"));
+            }
+            heights.insert(hash, Some(0));
+            genesis = Some(RawBlock::genesis_block(hash));
+            continue;
+        };
+
+        heights.insert(hash, optional(dict, "height")?);
+        raw_blocks.push(RawBlock {
+            author,
+            adaptive,
+            heavy,
+            ..RawBlock::new(
+                0,
+                hash,
+                parent_hash,
+                referee_hashes,
+                timestamp,
+                log_timestamp,
+                tx_count,
+                block_size,
+            )
+        });
+    }
+
+    let Some(genesis) = genesis else {
+        return Err(PyValueError::new_err("no block without parent (genesis)"));
+    };
+    let parents: HashMap<H256, H256> = raw_blocks
+        .iter()
+        .filter_map(|b| Some((b.hash, b.parent_hash?)))
+        .collect();
+    for block in &mut raw_blocks {
+        block.height = resolve_height(&mut heights, &parents, block.hash)?;
+    }
+    raw_blocks.insert(0, genesis);
+    Ok(raw_blocks)
+}
+
+/// 沿父区块向上找到第一个已知高度，再逐个向下填充
+fn resolve_height(
+    heights: &mut HashMap<H256, Option<u64>>, parents: &HashMap<H256, H256>, hash: H256,
+) -> PyResult<u64> {
+    let mut unresolved = vec![];
+    let mut cursor = hash;
+    let mut height = loop {
+        match heights.get(&cursor) {
+            Some(Some(height)) => break *height,
+            Some(None) if unresolved.len() < parents.len() => {
+                unresolved.push(cursor);
+                cursor = parents[&cursor];
+            }
+            Some(None) => return Err(PyValueError::new_err("parent links form a cycle")),
+            None => {
+                return Err(PyValueError::new_err(format!(
+                    "parent {:?} is not in the block list",
+                    cursor
+                )))
+            }
+        }
+    };
+    for hash in unresolved.into_iter().rev() {
+        height += 1;
+        heights.insert(hash, Some(height));
+    }
+    Ok(heights[&hash].unwrap())
+}
+
+fn required<'a>(dict: &'a PyDict, key: &str) -> PyResult<&'a PyAny> {
+    dict.get_item(key)?
+        .ok_or_else(|| PyValueError::new_err(format!("block dict has no {key:?}")))
+}
+
+fn optional<'a, T: FromPyObject<'a>>(dict: &'a PyDict, key: &str) -> PyResult<Option<T>> {
+    match dict.get_item(key)? {
+        Some(value) if !value.is_none() => Ok(Some(value.extract()?)),
+        _ => Ok(None),
+    }
+}
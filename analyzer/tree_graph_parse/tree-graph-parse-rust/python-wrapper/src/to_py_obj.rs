@@ -1,4 +1,4 @@
-use ethereum_types::H256;
+use ethereum_types::{Address, H256};
 use pyo3::{
     prelude::*,
     types::{PyBytes, PyList, PySet},
@@ -30,6 +30,19 @@ impl ToPyObj for Option<H256> {
     }
 }
 
+impl ToPyObj for Option<Address> {
+    type Item = PyAny;
+
+    // 20 字节的 PyBytes 或 None
+
+    fn to_py_obj(&self, py: Python) -> Py<Self::Item> {
+        match self {
+            Some(author) => PyBytes::new(py, author.as_bytes()).into(),
+            None => py.None(),
+        }
+    }
+}
+
 impl ToPyObj for Vec<H256> {
     type Item = PyList;
 
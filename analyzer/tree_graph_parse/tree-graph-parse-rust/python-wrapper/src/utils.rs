@@ -1,10 +1,9 @@
-use ethereum_types::H256;
+use ethereum_types::{Address, H256};
 use pyo3::{
     prelude::*,
-    types::{PyBytes, PyString},
+    types::{PyByteArray, PyBytes, PyString},
 };
 
-#[allow(dead_code)]
 pub fn parse_h256(input: &PyAny) -> PyResult<H256> {
     // Try to extract as bytes first
     if let Ok(bytes) = input.extract::<&PyBytes>() {
@@ -48,3 +47,43 @@ pub fn parse_h256(input: &PyAny) -> PyResult<H256> {
         ))
     }
 }
+
+/// 矿工地址：20 字节的 bytes，或 40 个十六进制字符（可带 0x 前缀）
+pub fn parse_address(input: &PyAny) -> PyResult<Address> {
+    let bytes = match input.extract::<&PyBytes>() {
+        Ok(bytes) => bytes.as_bytes().to_vec(),
+        Err(_) => hex::decode(input.extract::<&str>()?.trim_start_matches("0x")).map_err(|e| {
+            PyErr::new::<pyo3::exceptions::PyValueError, _>(format!("Invalid hex string: {}", e))
+        })?,
+    };
+    if bytes.len() != 20 {
+        return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
+            "Address must be exactly 20 bytes long",
+        ));
+    }
+    Ok(Address::from_slice(&bytes))
+}
+
+/// 把列数据转成 numpy 数组：数据在 bytearray 中按小端排列，numpy 直接引用，不再复制
+fn numpy_array(py: Python, bytes: &[u8], dtype: &str) -> PyResult<PyObject> {
+    let buffer = PyByteArray::new(py, bytes);
+    let array = py
+        .import("numpy")?
+        .call_method1("frombuffer", (buffer, dtype))?;
+    Ok(array.into())
+}
+
+pub fn u64_array(py: Python, values: &[u64]) -> PyResult<PyObject> {
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    numpy_array(py, &bytes, "<u8")
+}
+
+pub fn i64_array(py: Python, values: &[i64]) -> PyResult<PyObject> {
+    let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    numpy_array(py, &bytes, "<i8")
+}
+
+pub fn bool_array(py: Python, values: &[bool]) -> PyResult<PyObject> {
+    let bytes: Vec<u8> = values.iter().map(|&v| v as u8).collect();
+    numpy_array(py, &bytes, "?")
+}
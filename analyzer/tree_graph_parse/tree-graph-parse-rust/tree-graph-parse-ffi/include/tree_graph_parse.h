@@ -0,0 +1,74 @@
+/* C interface of tree-graph-parse. Link against libtree_graph_parse (cdylib or staticlib). */
+
+#ifndef TREE_GRAPH_PARSE_H
+#define TREE_GRAPH_PARSE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define TG_OK 0
+#define TG_NOT_CONFIRMED 1
+#define TG_ERROR (-1)
+
+/* Opaque handle to a finalized graph; safe to query from several threads at once. */
+typedef struct TgGraph TgGraph;
+
+typedef struct TgBlockInfo {
+    uint8_t hash[32];
+    uint64_t height;
+    uint64_t timestamp;
+    uint64_t log_timestamp;
+    uint64_t subtree_size;
+    uint64_t past_set_size;
+    uint64_t epoch_size;
+} TgBlockInfo;
+
+typedef struct TgConfirmation {
+    /* Seconds after the block timestamp. */
+    uint64_t time_offset;
+    uint64_t m;
+    uint64_t k;
+    double risk;
+} TgConfirmation;
+
+/* Message of the last failure on the calling thread, or NULL. Panics inside the library are
+ * reported as failures. */
+const char *tg_last_error(void);
+
+/* Loads a conflux log file or directory. Returns NULL on failure. */
+TgGraph *tg_graph_load(const char *path);
+
+void tg_graph_free(TgGraph *graph);
+
+/* Number of blocks, genesis included. Returns 0 on failure. */
+size_t tg_graph_block_count(const TgGraph *graph);
+
+/* Number of pivot blocks, genesis included. Returns 0 on failure. */
+size_t tg_graph_pivot_chain_len(const TgGraph *graph);
+
+/* Writes up to `capacity` pivot blocks, genesis first, and returns the full chain length, or 0
+ * on failure. `out` may be NULL when `capacity` is 0. */
+size_t tg_graph_pivot_chain(const TgGraph *graph, TgBlockInfo *out, size_t capacity);
+
+/* `hash` points to 32 bytes. Returns TG_OK or TG_ERROR. */
+int tg_graph_block(const TgGraph *graph, const uint8_t *hash, TgBlockInfo *out);
+
+/* Confirmation of a pivot block, for `adv_percent` in 1..49 and `risk_threshold` in (0, 1).
+ * Returns TG_OK, TG_NOT_CONFIRMED or TG_ERROR. */
+int tg_confirmation_risk(const TgGraph *graph, const uint8_t *hash, uint32_t adv_percent,
+                         double risk_threshold, TgConfirmation *out);
+
+/* Average confirmation time over the pivot chain, with the arguments of tg_confirmation_risk.
+ * Returns TG_OK or TG_ERROR. */
+int tg_avg_confirm_time(const TgGraph *graph, uint32_t adv_percent, double risk_threshold,
+                        double *out_avg, uint64_t *out_blocks);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TREE_GRAPH_PARSE_H */
@@ -0,0 +1,392 @@
+//! C bindings for loading a graph and querying its pivot chain and confirmation risk.
+//!
+//! The matching declarations are in `include/tree_graph_parse.h`. A `TgGraph` is an opaque
+//! handle owned by the caller and released with `tg_graph_free`; it may be queried from several
+//! threads at once. Functions returning a status use `TG_OK`, `TG_NOT_CONFIRMED` or `TG_ERROR`;
+//! after `TG_ERROR` (or a null handle from `tg_graph_load`), `tg_last_error` describes the failure
+//! on the calling thread. A panic inside the library is reported the same way instead of
+//! unwinding into the caller.
+
+use ethereum_types::H256;
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+use tree_graph_parse_rust::{
+    block::Block, frozen::FrozenGraph, graph::Graph, math::risk::ADV_PERCENT_RANGE,
+};
+
+pub const TG_OK: c_int = 0;
+pub const TG_NOT_CONFIRMED: c_int = 1;
+pub const TG_ERROR: c_int = -1;
+
+pub struct TgGraph(FrozenGraph);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TgBlockInfo {
+    pub hash: [u8; 32],
+    pub height: u64,
+    pub timestamp: u64,
+    pub log_timestamp: u64,
+    pub subtree_size: u64,
+    pub past_set_size: u64,
+    pub epoch_size: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TgConfirmation {
+    /// Seconds after the block timestamp.
+    pub time_offset: u64,
+    pub m: u64,
+    pub k: u64,
+    pub risk: f64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, returning `on_panic` with the panic message as the last error if it panics.
+fn catch_panic<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".into());
+        set_last_error(format!("panic: {}", message));
+        on_panic
+    })
+}
+
+fn check_risk_args(adv_percent: u32, risk_threshold: f64) -> Result<(), String> {
+    if !ADV_PERCENT_RANGE.contains(&(adv_percent as usize)) {
+        return Err(format!(
+            "adv_percent must be in {}..={}, got {}",
+            ADV_PERCENT_RANGE.start(),
+            ADV_PERCENT_RANGE.end(),
+            adv_percent
+        ));
+    }
+    if !(risk_threshold > 0. && risk_threshold < 1.) {
+        return Err(format!(
+            "risk_threshold must be in (0, 1), got {}",
+            risk_threshold
+        ));
+    }
+    Ok(())
+}
+
+/// Message of the last failure on this thread, or null. Valid until the next failing call on
+/// this thread.
+#[no_mangle]
+pub extern "C" fn tg_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Loads a conflux log file or directory. Returns null on failure.
+///
+/// # Safety
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tg_graph_load(path: *const c_char) -> *mut TgGraph {
+    if path.is_null() {
+        set_last_error("path is null");
+        return ptr::null_mut();
+    }
+    let path = match CStr::from_ptr(path).to_str() {
+        Ok(path) => path,
+        Err(e) => {
+            set_last_error(e);
+            return ptr::null_mut();
+        }
+    };
+    catch_panic(ptr::null_mut(), || match Graph::load(path) {
+        Ok(graph) => Box::into_raw(Box::new(TgGraph(graph.freeze()))),
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    })
+}
+
+/// # Safety
+/// `graph` must be null or a handle from `tg_graph_load` not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn tg_graph_free(graph: *mut TgGraph) {
+    if !graph.is_null() {
+        drop(Box::from_raw(graph));
+    }
+}
+
+/// Number of blocks, genesis included. Returns 0 on failure.
+///
+/// # Safety
+/// `graph` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn tg_graph_block_count(graph: *const TgGraph) -> usize {
+    if graph.is_null() {
+        set_last_error("graph is null");
+        return 0;
+    }
+    catch_panic(0, || (*graph).0.block_count())
+}
+
+/// Number of pivot blocks, genesis included. Returns 0 on failure.
+///
+/// # Safety
+/// `graph` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn tg_graph_pivot_chain_len(graph: *const TgGraph) -> usize {
+    if graph.is_null() {
+        set_last_error("graph is null");
+        return 0;
+    }
+    catch_panic(0, || (*graph).0.pivot_chain().len())
+}
+
+/// Writes up to `capacity` pivot blocks, genesis first, into `out` and returns the full length
+/// of the pivot chain, or 0 on failure. `out` may be null when `capacity` is 0.
+///
+/// # Safety
+/// `graph` must be null or a live handle and `out` must point to `capacity` writable
+/// `TgBlockInfo`.
+#[no_mangle]
+pub unsafe extern "C" fn tg_graph_pivot_chain(
+    graph: *const TgGraph, out: *mut TgBlockInfo, capacity: usize,
+) -> usize {
+    if graph.is_null() || (out.is_null() && capacity > 0) {
+        set_last_error("graph or out is null");
+        return 0;
+    }
+    catch_panic(0, || {
+        let pivot_chain = (*graph).0.pivot_chain();
+        for (idx, block) in pivot_chain.iter().take(capacity).enumerate() {
+            *out.add(idx) = block_info(block);
+        }
+        pivot_chain.len()
+    })
+}
+
+/// Looks up a block by its 32-byte hash.
+///
+/// # Safety
+/// `graph` must be null or a live handle, `hash` must be null or point to 32 bytes and `out`
+/// must be null or point to a writable `TgBlockInfo`.
+#[no_mangle]
+pub unsafe extern "C" fn tg_graph_block(
+    graph: *const TgGraph, hash: *const u8, out: *mut TgBlockInfo,
+) -> c_int {
+    if graph.is_null() || out.is_null() {
+        set_last_error("graph or out is null");
+        return TG_ERROR;
+    }
+    catch_panic(TG_ERROR, || match find_block(&(*graph).0, hash) {
+        Some(block) => {
+            *out = block_info(block);
+            TG_OK
+        }
+        None => TG_ERROR,
+    })
+}
+
+/// Confirmation of the pivot block `hash` against an adversary with `adv_percent` (1 to 49) of
+/// the block generation power. Returns `TG_NOT_CONFIRMED` if the risk never drops below
+/// `risk_threshold`, which must be in (0, 1).
+///
+/// # Safety
+/// `graph` must be a live handle, `hash` must point to 32 bytes and `out` to a writable
+/// `TgConfirmation`.
+#[no_mangle]
+pub unsafe extern "C" fn tg_confirmation_risk(
+    graph: *const TgGraph, hash: *const u8, adv_percent: u32, risk_threshold: f64,
+    out: *mut TgConfirmation,
+) -> c_int {
+    if graph.is_null() || out.is_null() {
+        set_last_error("graph or out is null");
+        return TG_ERROR;
+    }
+    if let Err(e) = check_risk_args(adv_percent, risk_threshold) {
+        set_last_error(e);
+        return TG_ERROR;
+    }
+    let graph = &(*graph).0;
+    let Some(block) = find_block(graph, hash) else {
+        return TG_ERROR;
+    };
+    if block.height == 0 || block.epoch_block != Some(block.id) {
+        set_last_error("confirmation risk is only defined on the pivot chain");
+        return TG_ERROR;
+    }
+
+    catch_panic(TG_ERROR, || {
+        match graph.confirmation_risk(block, adv_percent as usize, risk_threshold) {
+            Some((time_offset, m, k, risk)) => {
+                *out = TgConfirmation {
+                    time_offset,
+                    m,
+                    k,
+                    risk,
+                };
+                TG_OK
+            }
+            None => TG_NOT_CONFIRMED,
+        }
+    })
+}
+
+/// Average confirmation time over the pivot chain, see `Graph::avg_confirm_time`. The arguments
+/// are those of `tg_confirmation_risk`.
+///
+/// # Safety
+/// `graph` must be a live handle; `out_avg` and `out_blocks` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn tg_avg_confirm_time(
+    graph: *const TgGraph, adv_percent: u32, risk_threshold: f64, out_avg: *mut f64,
+    out_blocks: *mut u64,
+) -> c_int {
+    if graph.is_null() || out_avg.is_null() || out_blocks.is_null() {
+        set_last_error("graph, out_avg or out_blocks is null");
+        return TG_ERROR;
+    }
+    if let Err(e) = check_risk_args(adv_percent, risk_threshold) {
+        set_last_error(e);
+        return TG_ERROR;
+    }
+    catch_panic(TG_ERROR, || {
+        let (avg, blocks) = (*graph)
+            .0
+            .avg_confirm_time(adv_percent as usize, risk_threshold);
+        *out_avg = avg;
+        *out_blocks = blocks;
+        TG_OK
+    })
+}
+
+unsafe fn find_block(graph: &Graph, hash: *const u8) -> Option<&Block> {
+    if hash.is_null() {
+        set_last_error("hash is null");
+        return None;
+    }
+    let hash = H256::from_slice(std::slice::from_raw_parts(hash, 32));
+    let block = graph.get_block(&hash);
+    if block.is_none() {
+        set_last_error(format!("block {:?} not found", hash));
+    }
+    block
+}
+
+fn block_info(block: &Block) -> TgBlockInfo {
+    TgBlockInfo {
+        hash: block.hash.0,
+        height: block.height,
+        timestamp: block.timestamp,
+        log_timestamp: block.log_timestamp,
+        subtree_size: block.subtree_size,
+        past_set_size: block.past_set_size,
+        epoch_size: block.epoch_size() as u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tree_graph_parse_rust::block::RawBlock;
+
+    fn write_log(blocks: u64) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "tg_ffi_{}.conflux.log.new_blocks",
+            std::process::id()
+        ));
+        let mut file = std::fs::File::create(&path).unwrap();
+        for i in 1..=blocks {
+            let timestamp = 1735689600 + i;
+            let hash = H256::from_low_u64_be;
+            let block = RawBlock::new(i, hash(i + 1), hash(i), vec![], timestamp, timestamp, 0, 0);
+            writeln!(file, "{}", block.to_log_line()).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_ffi_roundtrip() {
+        let path = write_log(200);
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        unsafe {
+            let graph = tg_graph_load(c_path.as_ptr());
+            std::fs::remove_file(&path).unwrap();
+            assert!(!graph.is_null());
+            assert_eq!(tg_graph_block_count(graph), 201);
+
+            let len = tg_graph_pivot_chain_len(graph);
+            let mut pivot = vec![TgBlockInfo::default(); len];
+            assert_eq!(tg_graph_pivot_chain(graph, pivot.as_mut_ptr(), len), len);
+            assert_eq!(pivot[len - 1].height, 200);
+
+            let mut info = TgBlockInfo::default();
+            assert_eq!(
+                tg_graph_block(graph, pivot[10].hash.as_ptr(), &mut info),
+                TG_OK
+            );
+            assert_eq!(info.height, 10);
+
+            let mut confirmation = TgConfirmation::default();
+            let status =
+                tg_confirmation_risk(graph, pivot[10].hash.as_ptr(), 20, 1e-6, &mut confirmation);
+            assert_eq!(status, TG_OK);
+            assert!(confirmation.risk < 1e-6);
+
+            for (adv_percent, risk_threshold) in [(0, 1e-6), (50, 1e-6), (20, 0.), (20, f64::NAN)] {
+                let status = tg_confirmation_risk(
+                    graph,
+                    pivot[10].hash.as_ptr(),
+                    adv_percent,
+                    risk_threshold,
+                    &mut confirmation,
+                );
+                assert_eq!(status, TG_ERROR);
+                let (mut avg, mut blocks) = (0., 0);
+                let status =
+                    tg_avg_confirm_time(graph, adv_percent, risk_threshold, &mut avg, &mut blocks);
+                assert_eq!(status, TG_ERROR);
+            }
+
+            let missing = [0xffu8; 32];
+            assert_eq!(tg_graph_block(graph, missing.as_ptr(), &mut info), TG_ERROR);
+            assert!(!tg_last_error().is_null());
+
+            assert_eq!(tg_graph_pivot_chain(graph, ptr::null_mut(), 0), len);
+            assert_eq!(tg_graph_pivot_chain(graph, ptr::null_mut(), 1), 0);
+            let hash = pivot[10].hash.as_ptr();
+            assert_eq!(tg_graph_block(graph, hash, ptr::null_mut()), TG_ERROR);
+            assert_eq!(tg_graph_block(ptr::null(), hash, &mut info), TG_ERROR);
+            assert_eq!(tg_graph_block_count(ptr::null()), 0);
+            assert_eq!(tg_graph_pivot_chain_len(ptr::null()), 0);
+            let message = CStr::from_ptr(tg_last_error());
+            assert_eq!(message.to_str().unwrap(), "graph is null");
+
+            tg_graph_free(graph);
+        }
+
+        let missing = CString::new("/nonexistent/conflux.log").unwrap();
+        assert!(unsafe { tg_graph_load(missing.as_ptr()) }.is_null());
+    }
+
+    #[test]
+    fn test_panic_is_an_error() {
+        assert_eq!(catch_panic(TG_ERROR, || panic!("out of range")), TG_ERROR);
+        let message = unsafe { CStr::from_ptr(tg_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "panic: out of range");
+        assert_eq!(catch_panic(TG_ERROR, || TG_OK), TG_OK);
+    }
+}
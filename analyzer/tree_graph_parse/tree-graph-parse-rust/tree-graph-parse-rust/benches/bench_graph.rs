@@ -0,0 +1,47 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tree_graph_parse_rust::{
+    generate::{GenerateParams, LatencyModel},
+    graph::Graph,
+};
+
+fn params(duration: u64) -> GenerateParams {
+    GenerateParams {
+        duration,
+        latency: LatencyModel::Exponential(1.),
+        ..Default::default()
+    }
+}
+
+fn bench_generate_and_finalize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate_and_finalize");
+    group.sample_size(10);
+
+    for duration in [300, 1200, 3600].iter() {
+        group.bench_with_input(
+            format!("duration={}s", duration),
+            duration,
+            |b, &duration| {
+                b.iter(|| Graph::generate(black_box(&params(duration))).unwrap());
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_avg_confirm_time(c: &mut Criterion) {
+    let mut group = c.benchmark_group("avg_confirm_time");
+    group.sample_size(10);
+
+    for duration in [300, 1200].iter() {
+        let graph = Graph::generate(&params(*duration)).unwrap();
+        group.bench_with_input(format!("duration={}s", duration), &graph, |b, graph| {
+            b.iter(|| graph.avg_confirm_time(black_box(20), black_box(1e-6)));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_generate_and_finalize, bench_avg_confirm_time);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rayon::prelude::*;
+use tree_graph_parse_rust::math::*;
+
+/// Confirmation risk of many pivot blocks, as `analyze_all_nodes` computes it over many graphs
+/// at once: every thread reads the same few cached PMFs.
+fn bench_cache_contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("math_cache_contention");
+    group.sample_size(20);
+
+    let queries: Vec<(usize, usize)> = (0..4096)
+        .map(|i| ([10, 20, 30][i % 3], 50 + i % 200))
+        .collect();
+    for threads in [1, 2, 4, 8, 16].iter() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(*threads)
+            .build()
+            .unwrap();
+        group.bench_with_input(format!("threads={}", threads), threads, |b, _| {
+            b.iter(|| {
+                pool.install(|| {
+                    queries
+                        .par_iter()
+                        .map(|&(adv_percent, m)| {
+                            normal_confirmation_risk(adv_percent, black_box(m), black_box(m / 4))
+                        })
+                        .sum::<f32>()
+                })
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_cache_contention);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+//! One line of a conflux log, as `Graph::load` parses each line holding a block insertion.
+//! Logs come from the nodes of a test run and may be cut off or corrupted, so a malformed line
+//! must come back as an error.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tree_graph_parse_rust::block::RawBlock;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data) {
+        let _ = RawBlock::parse_log_line(line);
+    }
+});
@@ -0,0 +1,183 @@
+//! GHOST rule audit: replay the max-child choice over time using the subtree weights of the
+//! pivot rule and report blocks whose final heaviest child was not the heaviest one at some
+//! earlier moment.
+
+use ethereum_types::H256;
+
+use crate::{
+    block::Block,
+    graph::Graph,
+    graph_computer::WeightSeries,
+    pivot_rule::{FirstLogged, PivotRule},
+    utils::time_series::TimeSeries,
+};
+
+#[derive(Debug, Clone)]
+pub struct GhostDeviation {
+    /// The block whose children were compared.
+    pub block: H256,
+    pub height: u64,
+    pub on_pivot_chain: bool,
+    /// The child chosen in the final ordering.
+    pub final_child: H256,
+    /// The child that was strictly heavier when the deviation was first observed.
+    pub early_leader: H256,
+    /// First log timestamp at which another child was strictly heavier than the final child.
+    pub first_deviation: u64,
+    /// Log timestamp at which the final child took the lead for good, `None` if another child
+    /// was still heavier at the end of the log.
+    pub settled_at: Option<u64>,
+    /// Largest observed weight lead of another child over the final child.
+    pub max_deficit: u32,
+}
+
+impl Graph {
+    pub fn ghost_rule_audit(&self) -> Vec<GhostDeviation> {
+        self.ghost_rule_audit_with(&FirstLogged)
+    }
+
+    /// `ghost_rule_audit` of a graph finalized with `rule`, comparing its subtree weights.
+    pub fn ghost_rule_audit_with(&self, rule: &dyn PivotRule) -> Vec<GhostDeviation> {
+        let pivot_ids: std::collections::HashSet<usize> =
+            self.pivot_chain().into_iter().map(|b| b.id).collect();
+        let weight_series = WeightSeries::new(&self.blocks, rule);
+
+        let mut deviations: Vec<_> = self
+            .blocks()
+            .filter(|b| b.children.len() > 1)
+            .filter_map(|b| self.audit_block(b, pivot_ids.contains(&b.id), &weight_series))
+            .collect();
+        deviations.sort_by_key(|d| (d.height, d.first_deviation));
+        deviations
+    }
+
+    fn audit_block(
+        &self, block: &Block, on_pivot_chain: bool, weight_series: &WeightSeries,
+    ) -> Option<GhostDeviation> {
+        let child_series: Vec<_> = block
+            .children
+            .iter()
+            .map(|&id| weight_series.get(id))
+            .collect();
+
+        // For every moment, the index of the heaviest child if it beats the final choice.
+        let leader_series = TimeSeries::array_cartesian_map(&child_series, |weights| {
+            let weight = |w: &Option<&u32>| w.copied().unwrap_or(0);
+            let final_weight = weight(&weights[0]);
+            let (idx, best) = weights
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(idx, w)| (idx, weight(w)))
+                .max_by_key(|&(idx, w)| (w, std::cmp::Reverse(idx)))?;
+            Some((best > final_weight).then(|| (idx, best - final_weight)))
+        });
+
+        let mut first_deviation = None;
+        let mut settled_at = None;
+        let mut max_deficit = 0;
+        let mut deviating = false;
+        for (ts, leader) in leader_series.iter() {
+            match leader {
+                Some((idx, deficit)) => {
+                    first_deviation.get_or_insert((ts, *idx));
+                    max_deficit = max_deficit.max(*deficit);
+                    settled_at = None;
+                    deviating = true;
+                }
+                None if deviating => {
+                    settled_at = Some(ts);
+                    deviating = false;
+                }
+                None => {}
+            }
+        }
+
+        let (first_ts, leader_idx) = first_deviation?;
+        Some(GhostDeviation {
+            block: block.hash,
+            height: block.height,
+            on_pivot_chain,
+            final_child: self.hash_of(block.children[0]),
+            early_leader: self.hash_of(block.children[leader_idx]),
+            first_deviation: first_ts,
+            settled_at,
+            max_deficit,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::RawBlock;
+
+    fn hash(i: u64) -> H256 { H256::from_low_u64_be(i) }
+
+    fn block(height: u64, i: u64, parent: u64) -> RawBlock {
+        RawBlock::new(height, hash(i), hash(parent), vec![], i, i, 0, 0)
+    }
+
+    #[test]
+    fn test_reorg_is_reported() {
+        // 0 <- 1 <- 2 leads until 0 <- 3 <- 4 <- 5 catches up at 4 and overtakes it at 5.
+        let graph = Graph::from_raw_blocks(vec![
+            RawBlock::genesis_block(hash(0)),
+            block(1, 1, 0),
+            block(2, 2, 1),
+            block(1, 3, 0),
+            block(2, 4, 3),
+            block(3, 5, 4),
+        ])
+        .unwrap();
+
+        let deviations = graph.ghost_rule_audit();
+        assert_eq!(deviations.len(), 1);
+        let deviation = &deviations[0];
+        assert_eq!(deviation.block, hash(0));
+        assert!(deviation.on_pivot_chain);
+        assert_eq!(deviation.final_child, hash(3));
+        assert_eq!(deviation.early_leader, hash(1));
+        assert_eq!(deviation.first_deviation, 1);
+        assert_eq!(deviation.settled_at, Some(4));
+        assert_eq!(deviation.max_deficit, 2);
+    }
+
+    #[test]
+    fn test_chain_has_no_deviation() {
+        let graph = Graph::from_raw_blocks(vec![
+            RawBlock::genesis_block(hash(0)),
+            block(1, 1, 0),
+            block(2, 2, 1),
+            block(2, 3, 1),
+        ])
+        .unwrap();
+        assert!(graph.ghost_rule_audit().is_empty());
+    }
+
+    #[test]
+    fn test_compares_adaptive_weights() {
+        let adaptive = |raw: RawBlock, heavy| RawBlock {
+            adaptive: true,
+            heavy,
+            ..raw
+        };
+        // 0 <- 1 <- 2 <- 3 keeps more blocks, but 2 and 3 weigh nothing and the heavy block 5
+        // outweighs 1 as soon as it is logged.
+        let graph = Graph::from_raw_blocks(vec![
+            RawBlock::genesis_block(hash(0)),
+            block(1, 1, 0),
+            adaptive(block(2, 2, 1), false),
+            adaptive(block(3, 3, 2), false),
+            block(1, 4, 0),
+            adaptive(block(2, 5, 4), true),
+        ])
+        .unwrap();
+
+        let deviations = graph.ghost_rule_audit();
+        assert_eq!(deviations.len(), 1);
+        assert_eq!(deviations[0].final_child, hash(4));
+        assert_eq!(deviations[0].settled_at, Some(4));
+        assert_eq!(deviations[0].max_deficit, 1);
+    }
+}
@@ -0,0 +1,113 @@
+//! Block production by author (miner), to check the mining-power split a test was configured
+//! with against the blocks that actually made it into the graph.
+//!
+//! Genesis is never counted. Blocks whose log line records no author are left out of the
+//! per-author entries but still count in the totals shares are taken of.
+
+use ethereum_types::Address;
+use std::collections::BTreeMap;
+
+use crate::graph::Graph;
+
+impl Graph {
+    /// Number of blocks produced by each author.
+    pub fn author_block_counts(&self) -> BTreeMap<Address, u64> {
+        let mut counts = BTreeMap::new();
+        for author in self.blocks().skip(1).filter_map(|b| b.author) {
+            *counts.entry(author).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Fraction of all blocks produced by each author.
+    pub fn author_block_share(&self) -> BTreeMap<Address, f64> {
+        let total = self.block_count().saturating_sub(1);
+        share(self.author_block_counts(), total)
+    }
+
+    /// Fraction of the pivot chain produced by each author.
+    pub fn author_pivot_share(&self) -> BTreeMap<Address, f64> {
+        let pivot_chain = self.pivot_chain();
+        let mut counts = BTreeMap::new();
+        for author in pivot_chain.iter().skip(1).filter_map(|b| b.author) {
+            *counts.entry(author).or_default() += 1;
+        }
+        share(counts, pivot_chain.len() - 1)
+    }
+}
+
+fn share(counts: BTreeMap<Address, u64>, total: usize) -> BTreeMap<Address, f64> {
+    counts
+        .into_iter()
+        .map(|(author, count)| (author, count as f64 / total.max(1) as f64))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::RawBlock, generate::GenerateParams};
+
+    #[test]
+    fn test_author_shares_follow_mining_power() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 1200,
+            mining_power: vec![3., 1.],
+            ..Default::default()
+        })
+        .unwrap();
+        let (large, small) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+
+        let counts = graph.author_block_counts();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(
+            counts.values().sum::<u64>() as usize,
+            graph.block_count() - 1
+        );
+
+        let block_share = graph.author_block_share();
+        assert!((block_share[&large] - 0.75).abs() < 0.05);
+        assert!((block_share[&large] + block_share[&small] - 1.).abs() < 1e-9);
+
+        let pivot_share = graph.author_pivot_share();
+        assert!(pivot_share[&large] > pivot_share[&small]);
+        assert!((pivot_share.values().sum::<f64>() - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_author_parsed_from_log() {
+        let author: Address = "0x1000000000000000000000000000000000000001"
+            .parse()
+            .unwrap();
+        let hash = ethereum_types::H256::from_low_u64_be;
+        let block = |height: u64| {
+            RawBlock::new(
+                height,
+                hash(height + 1),
+                hash(height),
+                vec![],
+                height,
+                height,
+                0,
+                0,
+            )
+        };
+        let log: String = [Some(author), Some(author), None]
+            .into_iter()
+            .zip(1..)
+            .map(|(block_author, height)| {
+                let block = RawBlock {
+                    author: block_author,
+                    ..block(height)
+                };
+                block.to_log_line() + "\n"
+            })
+            .collect();
+        let graph = Graph::from_log(log.as_bytes()).unwrap();
+
+        let counts = graph.author_block_counts();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[&author], 2);
+        assert_eq!(graph.block(3).author, None);
+    }
+}
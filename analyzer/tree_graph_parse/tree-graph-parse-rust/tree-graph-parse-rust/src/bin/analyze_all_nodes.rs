@@ -1,62 +1,502 @@
 extern crate tree_graph_parse_rust;
 
+use ethereum_types::H256;
 use rayon::prelude::*;
-use std::error::Error;
-use walkdir::WalkDir;
+use serde_json::{json, Value};
+use std::{error::Error, io::Cursor, path::Path};
+use testlog_io::{
+    discover_with_archives, read_archive_entries, ArchiveFormat, Compression, Pattern, Source,
+};
 
-use tree_graph_parse_rust::graph::Graph;
+use tree_graph_parse_rust::{
+    block_set::BlockSet,
+    confirmation_spread::ConfirmationSpread,
+    frozen::FrozenGraph,
+    graph::Graph,
+    math::{cache_stats, risk::ADV_PERCENT_RANGE, set_cache_limits, CacheLimits, DiskCache},
+    progress::stderr_reporter,
+};
 
-// 查找所有匹配pattern的文件
-fn find_files(root_path: &str, pattern: &str) -> Vec<String> {
-    let mut matching_files = Vec::new();
+// 一个节点的日志：磁盘上的文件或整个归档，或归档中的一个成员
+enum Input {
+    Source(Source),
+    // 归档，以及从外到内的成员路径：最后一个是日志，之前的都是嵌套的归档。加载该节点时才解压
+    Member(Source, Vec<String>),
+    // 无法列出成员的归档，加载时按该节点的错误报告
+    Failed(anyhow::Error),
+}
 
-    for entry in WalkDir::new(root_path)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-    {
-        let path = entry.path();
-        if path.is_file()
-            && path
-                .file_name()
-                .and_then(|name| name.to_str())
-                .map(|name| name == pattern)
-                .unwrap_or(false)
-        {
-            matching_files.push(path.to_path_buf().to_str().unwrap().to_string());
+// 收集 root 下所有节点的日志。任意名字的 .7z/.tar.gz 归档里每个匹配的成员都是一个节点，
+// 归档中的归档递归展开；各归档并行列出成员。这里只记下成员路径，不保留解压的内容，
+// 只有嵌套的归档要临时解压才能列出。有 glob 时只保留相对 root 的名字匹配它的日志
+fn collect_inputs(
+    root: &Path, pattern: &Pattern, glob: Option<&glob::Pattern>,
+) -> anyhow::Result<Vec<(String, Input)>> {
+    let root_name = root.to_string_lossy();
+    let selected = |name: &str| {
+        let relative = name.strip_prefix(&*root_name).unwrap_or(name);
+        glob.is_none_or(|glob| glob.matches(relative.trim_start_matches('/')))
+    };
+    let per_source: Vec<Vec<(String, Input)>> = discover_with_archives(root, pattern)?
+        .into_par_iter()
+        .map(|source| {
+            let name = source.to_string();
+            let mut inputs = vec![];
+            match &source {
+                Source::SevenZ { .. } | Source::TarGz { .. } => {
+                    let mut logs = vec![];
+                    let listed = source.read_entries(&mut |member| {
+                        list_member(&name, member, pattern, &selected, &mut logs)
+                    });
+                    match listed {
+                        Ok(nested) => {
+                            for log in logs {
+                                let input = Input::Member(source.clone(), vec![log.clone()]);
+                                inputs.push((member_name(&name, &log), input));
+                            }
+                            for (member, data) in nested {
+                                let nested_name = member_name(&name, &member);
+                                list_nested(
+                                    &source,
+                                    nested_name,
+                                    vec![member],
+                                    data,
+                                    pattern,
+                                    &selected,
+                                    &mut inputs,
+                                );
+                            }
+                        }
+                        Err(err) => inputs.push((name, Input::Failed(err))),
+                    }
+                }
+                _ if selected(&name) => inputs.push((name, Input::Source(source))),
+                _ => {}
+            }
+            inputs
+        })
+        .collect();
+    Ok(per_source.into_iter().flatten().collect())
+}
+
+// 归档成员的名字：归档路径/成员路径
+fn member_name(archive: &str, member: &str) -> String {
+    format!("{archive}/{}", member.trim_start_matches("./"))
+}
+
+// 列出归档 archive 的一个成员：名字匹配的日志记入 logs，不解压；返回是否要解压，
+// 只有嵌套的归档需要
+fn list_member(
+    archive: &str, member: &str, pattern: &Pattern, selected: &dyn Fn(&str) -> bool,
+    logs: &mut Vec<String>,
+) -> bool {
+    let name = member_name(archive, member);
+    let file_name = name.rsplit('/').next().unwrap_or(&name);
+    if ArchiveFormat::of(file_name).is_some() {
+        return true;
+    }
+    if pattern.matches(file_name) && selected(&name) {
+        logs.push(member.to_string());
+    }
+    false
+}
+
+// 列出嵌套归档 name 中的日志，chain 是从最外层归档 source 到它的成员路径，data 是它解压后的内容
+fn list_nested(
+    source: &Source, name: String, chain: Vec<String>, data: Vec<u8>, pattern: &Pattern,
+    selected: &dyn Fn(&str) -> bool, inputs: &mut Vec<(String, Input)>,
+) {
+    let format = ArchiveFormat::of(&name).unwrap();
+    let mut logs = vec![];
+    let listed = read_archive_entries(format, &data, &mut |member| {
+        list_member(&name, member, pattern, selected, &mut logs)
+    });
+    let nested = match listed {
+        Ok(nested) => nested,
+        Err(err) => {
+            inputs.push((name, Input::Failed(err)));
+            return;
         }
+    };
+    drop(data);
+    for log in logs {
+        let input = Input::Member(source.clone(), [chain.clone(), vec![log.clone()]].concat());
+        inputs.push((member_name(&name, &log), input));
+    }
+    for (member, data) in nested {
+        let nested_name = member_name(&name, &member);
+        let chain = [chain.clone(), vec![member]].concat();
+        list_nested(source, nested_name, chain, data, pattern, selected, inputs);
     }
+}
 
-    matching_files
+// 解压归档 source 中按 chain 逐层找到的成员
+fn read_member(source: &Source, chain: &[String]) -> anyhow::Result<Vec<u8>> {
+    let take = |entries: Vec<(String, Vec<u8>)>, member: &str| {
+        entries
+            .into_iter()
+            .next()
+            .map(|(_, data)| data)
+            .ok_or_else(|| anyhow::anyhow!("member {member} disappeared from {source}"))
+    };
+    let mut data = take(
+        source.read_entries(&mut |member| member == chain[0])?,
+        &chain[0],
+    )?;
+    for pair in chain.windows(2) {
+        let format = ArchiveFormat::of(&pair[0]).unwrap();
+        let entries = read_archive_entries(format, &data, &mut |member| member == pair[1])?;
+        data = take(entries, &pair[1])?;
+    }
+    Ok(data)
 }
 
-// 多线程加载所有图
-fn load_all_graphs(file_paths: Vec<String>) -> Vec<Graph> {
+// 多线程加载所有图；未压缩的日志按路径加载（支持进度和单遍加载），其余格式先解压再解析，
+// 归档成员在这里才解压，同时只有正在加载的节点持有解压的内容。
+// 每个节点的加载结果单独返回，一个节点的日志或归档损坏不影响其他节点
+fn load_all_graphs(
+    inputs: Vec<(String, Input)>, progress: bool, single_pass: bool,
+) -> Vec<(String, anyhow::Result<FrozenGraph>)> {
     // 使用rayon并行处理所有文件
-    file_paths
-        .par_iter()
-        .map(|path| Graph::load(&path).unwrap())
+    inputs
+        .into_par_iter()
+        .map(|(name, input)| {
+            let mut reporter: Box<dyn FnMut(_)> = if progress {
+                Box::new(stderr_reporter(&name))
+            } else {
+                Box::new(|_| {})
+            };
+            let graph = match input {
+                Input::Source(Source::File {
+                    compression: Compression::None,
+                    ..
+                }) if single_pass => Graph::load_single_pass(&name, &mut reporter),
+                Input::Source(Source::File {
+                    compression: Compression::None,
+                    ..
+                }) => Graph::load_with_progress(&name, &mut reporter),
+                Input::Source(source) => source.open().and_then(Graph::from_log),
+                Input::Member(source, chain) => {
+                    read_member(&source, &chain).and_then(|data| Graph::from_log(Cursor::new(data)))
+                }
+                Input::Failed(err) => Err(err),
+            };
+            drop(reporter);
+            (name, graph.map(Graph::freeze))
+        })
         .collect()
 }
 
+// 打印每个图的内存占用，以及全部图同时加载时的总量
+fn print_memory_stats(graphs: &[FrozenGraph]) {
+    let mut total_bytes = 0;
+    let mut bitmap_bytes = 0;
+    for (idx, graph) in graphs.iter().enumerate() {
+        let stats = graph.memory_stats();
+        println!("Graph {idx}:\n{stats}");
+        total_bytes += stats.total_bytes();
+        bitmap_bytes += stats.past_set_bitmap_bytes;
+    }
+    println!(
+        "All {} graphs: {:.2} MiB resident, up to {:.2} MiB more while loading in parallel",
+        graphs.len(),
+        total_bytes as f64 / (1 << 20) as f64,
+        bitmap_bytes as f64 / (1 << 20) as f64
+    );
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    // 要搜索的根路径
-    let root_path = "/data/liuyuan/perftest/0422/2000_rand";
+    let _math_cache = DiskCache::from_env();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mem_stats = args.iter().any(|arg| arg == "--mem-stats");
+    let progress = args.iter().any(|arg| arg == "--progress");
+    // 边解析边建图，不保留整份解析结果；内存仍随整个日志增长
+    let single_pass = args.iter().any(|arg| arg == "--single-pass");
+    // 跳过日志或归档无法加载的节点，继续分析其余节点，最后报告各节点的错误
+    let skip_bad_nodes = args.iter().any(|arg| arg == "--skip-bad-nodes");
+    // 只统计该区块集合（如 stat_latency_rs --block-set-out 的输出）中的区块
+    let only_blocks = match args.iter().position(|arg| arg == "--only-blocks") {
+        Some(i) => {
+            let path = args.get(i + 1).ok_or("missing value for --only-blocks")?;
+            let set = BlockSet::read(path)?;
+            println!("Only blocks of {path}: {} blocks", set.blocks.len());
+            for (name, value) in &set.provenance {
+                println!("  {name}: {value}");
+            }
+            Some(set.hashes())
+        }
+        None => None,
+    };
+    // 节点平均确认时间偏离中位数超过该秒数时报告
+    let divergence: f64 = match args.iter().position(|arg| arg == "--divergence") {
+        Some(i) => args
+            .get(i + 1)
+            .ok_or("missing value for --divergence")?
+            .parse()?,
+        None => 5.,
+    };
+
+    // 攻击者算力（百分比）和风险阈值，每组组合各算一遍确认时间；图和数学缓存共用
+    let adv_percents: Vec<usize> = match args.iter().position(|arg| arg == "--adv-percents") {
+        Some(i) => parse_adv_percents(args.get(i + 1).ok_or("missing value for --adv-percents")?)?,
+        None => vec![10],
+    };
+    let risks: Vec<f64> = match args.iter().position(|arg| arg == "--risks") {
+        Some(i) => parse_risks(args.get(i + 1).ok_or("missing value for --risks")?)?,
+        None => vec![1e-6],
+    };
+
+    // 数学缓存的内存上限（MiB），扫描多组算力时防止缓存无限增长；超出后淘汰最久未用的条目
+    if let Some(i) = args.iter().position(|arg| arg == "--math-cache-mb") {
+        let mb: usize = args
+            .get(i + 1)
+            .ok_or("missing value for --math-cache-mb")?
+            .parse()?;
+        set_cache_limits(CacheLimits {
+            max_bytes: Some(mb << 20),
+            ..Default::default()
+        });
+    }
+
+    // 要搜索的根路径，可用 --root 指定
+    let root_path = match args.iter().position(|arg| arg == "--root") {
+        Some(i) => args.get(i + 1).ok_or("missing value for --root")?.as_str(),
+        None => "/data/liuyuan/perftest/0422/2000_rand",
+    };
+    // 只加载相对根路径的名字匹配该模式的日志，如 "host1*/**"；归档成员的名字是 归档路径/成员
+    let glob = match args.iter().position(|arg| arg == "--glob") {
+        Some(i) => Some(glob::Pattern::new(
+            args.get(i + 1).ok_or("missing value for --glob")?,
+        )?),
+        None => None,
+    };
 
-    // 固定的文件名模式
-    let file_pattern = "conflux.log.new_blocks";
+    // 固定的文件名模式，也匹配 .gz/.zst 压缩，以及任意名字的 .7z/.tar.gz 归档中的成员
+    let file_pattern = Pattern::Name("conflux.log.new_blocks".into());
 
-    // 查找所有匹配的文件
-    let matching_files = find_files(root_path, file_pattern);
-    println!("Found {} matching files", matching_files.len());
+    // 查找所有匹配的文件，并行解压归档
+    let inputs = collect_inputs(Path::new(root_path), &file_pattern, glob.as_ref())?;
+    println!("Found {} matching files", inputs.len());
 
     // 多线程加载所有文件
-    let graphs = load_all_graphs(matching_files);
+    let mut names = vec![];
+    let mut graphs = vec![];
+    let mut failed = vec![];
+    for (name, graph) in load_all_graphs(inputs, progress, single_pass) {
+        match graph {
+            Ok(graph) => {
+                names.push(name);
+                graphs.push(graph);
+            }
+            Err(err) if skip_bad_nodes => failed.push((name, format!("{err:#}"))),
+            Err(err) => return Err(format!("failed to load {name}: {err:#}").into()),
+        }
+    }
     println!("Successfully loaded {} graphs", graphs.len());
+    if !failed.is_empty() {
+        println!("Skipped {} nodes that failed to load", failed.len());
+    }
 
-    graphs.par_iter().for_each(|x| {
-        x.avg_confirm_time(10, 1e-6);
-    });
+    if mem_stats {
+        print_memory_stats(&graphs);
+    }
+
+    // 每个节点、每个攻击者算力下一次算出所有风险阈值的确认时间：times[节点][算力][阈值]
+    let times: Vec<Vec<Vec<Vec<(H256, u64)>>>> = graphs
+        .par_iter()
+        .map(|graph| {
+            adv_percents
+                .iter()
+                .map(|&adv_percent| {
+                    graph.confirm_times_by_block(adv_percent, &risks, &|block| {
+                        only_blocks
+                            .as_ref()
+                            .is_none_or(|set| set.contains(&block.hash))
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    if mem_stats {
+        let stats = cache_stats();
+        println!(
+            "Math cache: {} entries, {:.2} MiB, {} hits, {} misses, {} evictions",
+            stats.entries,
+            stats.bytes as f64 / (1 << 20) as f64,
+            stats.hits,
+            stats.misses,
+            stats.evictions
+        );
+    }
+
+    let mut nodes = vec![];
+    let mut aggregates = vec![];
+    for (a, &adv_percent) in adv_percents.iter().enumerate() {
+        for (r, &risk) in risks.iter().enumerate() {
+            println!("\nAdversary power {adv_percent}%, risk {risk:e}");
+            let times: Vec<_> = times.iter().map(|node| node[a][r].clone()).collect();
+            let avg_confirm_times: Vec<f64> = times
+                .iter()
+                .map(|times| {
+                    times.iter().map(|(_, time)| *time).sum::<u64>() as f64 / times.len() as f64
+                })
+                .collect();
+            for (idx, name) in names.iter().enumerate() {
+                println!(
+                    "Graph {idx} ({name}): avg confirmation time {:.2} from {} blocks",
+                    avg_confirm_times[idx],
+                    times[idx].len()
+                );
+                nodes.push(json!({
+                    "path": name,
+                    "block_count": graphs[idx].block_count(),
+                    "pivot_length": graphs[idx].pivot_chain().len(),
+                    "avg_confirm_time": avg_confirm_times[idx],
+                    "confirm_block_count": times[idx].len(),
+                    "risk": risk,
+                    "adv_percent": adv_percent,
+                }));
+            }
+
+            let spread = ConfirmationSpread::new(&times);
+            print_spread(&spread, &names, divergence);
+            let mut aggregate = aggregate(&avg_confirm_times, &times, &spread, &names, divergence);
+            aggregate["risk"] = json!(risk);
+            aggregate["adv_percent"] = json!(adv_percent);
+            aggregates.push(aggregate);
+        }
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--output") {
+        let path = args.get(i + 1).ok_or("missing value for --output")?;
+        // nodes 每个节点、算力和阈值一项；aggregate 每组算力和阈值一项
+        let results = json!({
+            "nodes": nodes,
+            "aggregate": aggregates,
+            "failed_nodes": failed
+                .iter()
+                .map(|(path, error)| json!({"path": path, "error": error}))
+                .collect::<Vec<_>>(),
+        });
+        std::fs::write(path, serde_json::to_string_pretty(&results)?)?;
+        println!("Results of {} graphs written to {path}", graphs.len());
+    }
+
+    if !failed.is_empty() {
+        println!("\n{} nodes failed to load and were skipped:", failed.len());
+        for (name, error) in &failed {
+            println!("  {name}: {error}");
+        }
+    }
 
     Ok(())
 }
+
+// 逗号分隔的列表，如 "10,15,20,30"
+fn parse_list<T: std::str::FromStr>(value: &str) -> Result<Vec<T>, T::Err> {
+    value.split(',').map(|item| item.trim().parse()).collect()
+}
+
+// 攻击者算力百分比：逗号分隔的列表，每个都须在风险模型接受的 1..=49 之内
+fn parse_adv_percents(value: &str) -> Result<Vec<usize>, Box<dyn Error>> {
+    let adv_percents: Vec<usize> = parse_list(value)?;
+    if let Some(adv_percent) = adv_percents
+        .iter()
+        .find(|adv_percent| !ADV_PERCENT_RANGE.contains(adv_percent))
+    {
+        return Err(format!("adversary power {adv_percent}% is not in 1..=49").into());
+    }
+    Ok(adv_percents)
+}
+
+// 风险阈值：逗号分隔的列表，或 "1e-4..1e-8" 表示其间每个 10 的幂；都须在 (0, 1) 之内
+fn parse_risks(value: &str) -> Result<Vec<f64>, Box<dyn Error>> {
+    let risks: Vec<f64> = match value.split_once("..") {
+        None => parse_list(value)?,
+        Some((from, to)) => {
+            let (from, to): (f64, f64) = (from.parse()?, to.parse()?);
+            if from <= 0. || to <= 0. {
+                return Err(format!("risk range {value} is not positive").into());
+            }
+            let (from, to) = (from.log10().round() as i32, to.log10().round() as i32);
+            let step = if from <= to { 1 } else { -1 };
+            (0..=(to - from).abs())
+                .map(|i| 10f64.powi(from + step * i))
+                .collect()
+        }
+    };
+    if let Some(risk) = risks.iter().find(|&&risk| !(risk > 0. && risk < 1.)) {
+        return Err(format!("risk {risk:e} is not in (0, 1)").into());
+    }
+    Ok(risks)
+}
+
+// --output 的汇总部分：各节点平均确认时间的分布，以及节点间的确认时间差异
+fn aggregate(
+    avg_confirm_times: &[f64], times: &[Vec<(H256, u64)>], spread: &ConfirmationSpread,
+    names: &[String], divergence: f64,
+) -> Value {
+    let avgs: Vec<f64> = avg_confirm_times
+        .iter()
+        .copied()
+        .filter(|avg| avg.is_finite())
+        .collect();
+    let mean = avgs.iter().sum::<f64>() / avgs.len() as f64;
+    json!({
+        "node_count": avg_confirm_times.len(),
+        "confirm_block_count": times.iter().map(Vec::len).sum::<usize>(),
+        "avg_confirm_time": {
+            "mean": mean,
+            "min": avgs.iter().copied().reduce(f64::min),
+            "max": avgs.iter().copied().reduce(f64::max),
+        },
+        "spread": {
+            "common_blocks": spread.spreads.len(),
+            "partial_blocks": spread.partial_blocks,
+            "p50": spread.percentile(50),
+            "p90": spread.percentile(90),
+            "p99": spread.percentile(99),
+            "max": spread.spreads.last(),
+        },
+        "divergence": divergence,
+        "divergent_nodes": spread
+            .divergent(divergence)
+            .map(|offset| json!({
+                "path": names[offset.node],
+                "mean_offset": offset.mean_offset,
+                "max_offset": offset.max_offset,
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+// 各节点对同一区块的确认时间差异；平均偏离中位数超过 `divergence` 秒的节点视图可能不一致
+fn print_spread(spread: &ConfirmationSpread, names: &[String], divergence: f64) {
+    println!(
+        "\nConfirmation time spread across {} nodes, {} blocks confirmed on every node, {} on some only",
+        spread.nodes,
+        spread.spreads.len(),
+        spread.partial_blocks
+    );
+    println!(
+        "  p50 {}s, p90 {}s, p99 {}s, max {}s; {} blocks within {}s",
+        spread.percentile(50),
+        spread.percentile(90),
+        spread.percentile(99),
+        spread.spreads.last().copied().unwrap_or(0),
+        spread.within(divergence as u64),
+        divergence
+    );
+    let divergent: Vec<_> = spread.divergent(divergence).collect();
+    if divergent.is_empty() {
+        println!("  No node diverges from the median by more than {divergence}s on average");
+    }
+    for offset in divergent {
+        println!(
+            "  Graph {} ({}) diverges: {:+.1}s on average, up to {:+.1}s",
+            offset.node, names[offset.node], offset.mean_offset, offset.max_offset
+        );
+    }
+}
@@ -1,41 +1,52 @@
 extern crate tree_graph_parse_rust;
 
-use std::time::Instant;
+use std::{collections::HashSet, time::Instant};
 
-use tree_graph_parse_rust::graph::Graph;
+use ethereum_types::H256;
+use tree_graph_parse_rust::{
+    block_set::BlockSet, graph::Graph, math::DiskCache, progress::stderr_reporter,
+};
 
-fn avg_confirmation_time(graph: &Graph, adv_percent: usize, risk_threshold: f64) {
-    let mut total_confirm_time = 0.;
-    let mut block_cnt = 0;
-    for block in graph.pivot_chain() {
-        if block.height == 0 {
-            continue;
+// --only-blocks <file>：只输出和统计该区块集合中的区块
+fn parse_args() -> Option<HashSet<H256>> {
+    let mut only_blocks = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .unwrap_or_else(|| panic!("missing value for {flag}"));
+        match flag.as_str() {
+            "--only-blocks" => {
+                let set = BlockSet::read(&value).unwrap();
+                println!("Only blocks of {value}: {} blocks", set.blocks.len());
+                for (name, value) in &set.provenance {
+                    println!("  {name}: {value}");
+                }
+                only_blocks = Some(set.hashes());
+            }
+            _ => panic!("unknown argument {flag}"),
         }
-
-        let Some((time_elapsed, ..)) = graph.confirmation_risk(block, adv_percent, risk_threshold)
-        else {
-            continue;
-        };
-
-        total_confirm_time +=
-            (time_elapsed as f64 + graph.avg_epoch_time(block)) * block.epoch_size() as f64;
-        block_cnt += block.epoch_size();
     }
-    println!(
-        "Average confirmation time for {adv_percent}: {:.2} from {} blocks",
-        total_confirm_time / block_cnt as f64,
-        block_cnt
-    );
+    only_blocks
 }
 
 fn main() {
+    let _math_cache = DiskCache::from_env();
+
+    let only_blocks = parse_args();
+    let keep = |hash: &H256| only_blocks.as_ref().is_none_or(|set| set.contains(hash));
+
     let instant = Instant::now();
 
-    let graph = Graph::load("/data/liuyuan/perftest/0324/10000_15000/").unwrap();
+    let graph = Graph::load_with_progress(
+        "/data/liuyuan/perftest/0324/10000_15000/",
+        &mut stderr_reporter("load"),
+    )
+    .unwrap();
 
     // dbg!(&graph.genesis_block().subtree_size_series);
     for block in graph.pivot_chain() {
-        if block.height == 0 {
+        if block.height == 0 || !keep(&block.hash) {
             continue;
         }
 
@@ -67,12 +78,15 @@ fn main() {
         println!("\n");
     }
 
-    for &risk in [1e-4, 1e-5, 1e-6, 1e-7, 1e-8].iter() {
-        println!("\n confirmation risk {risk}");
-        avg_confirmation_time(&graph, 10, risk);
-        avg_confirmation_time(&graph, 15, risk);
-        avg_confirmation_time(&graph, 20, risk);
-        avg_confirmation_time(&graph, 30, risk);
+    let risks = [1e-4, 1e-5, 1e-6, 1e-7, 1e-8];
+    for adv_percent in [10, 15, 20, 30] {
+        println!("\nAdversary power {adv_percent}%");
+        for stats in graph.confirm_time_vs_risk_among(adv_percent, &risks, &|b| keep(&b.hash)) {
+            println!(
+                " confirmation risk {:e}: avg {:.2}, p50 {}, p90 {}, p99 {} from {} blocks",
+                stats.risk_threshold, stats.avg, stats.p50, stats.p90, stats.p99, stats.block_cnt
+            );
+        }
     }
 
     println!("\nTotal time elapsed: {:?}", instant.elapsed());
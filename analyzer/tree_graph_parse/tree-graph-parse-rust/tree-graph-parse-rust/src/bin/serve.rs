@@ -0,0 +1,69 @@
+extern crate tree_graph_parse_rust;
+
+use std::{
+    error::Error,
+    panic::{catch_unwind, AssertUnwindSafe},
+    thread,
+};
+
+use tiny_http::{Header, Method, Response, Server};
+use tree_graph_parse_rust::{
+    frozen::FrozenGraph, graph::Graph, inspect, math::DiskCache, progress::stderr_reporter,
+};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+const WORKERS: usize = 4;
+
+// 用法: serve <日志路径> [监听地址]
+fn main() -> Result<(), Box<dyn Error>> {
+    let _math_cache = DiskCache::from_env();
+
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("usage: serve <conflux log path> [addr, default {DEFAULT_ADDR}]");
+        std::process::exit(2);
+    };
+    let addr = args.next().unwrap_or_else(|| DEFAULT_ADDR.to_string());
+
+    let graph: FrozenGraph = Graph::load_with_progress(&path, &mut stderr_reporter(&path))?.into();
+    let server = std::sync::Arc::new(Server::http(&addr).map_err(|e| e.to_string())?);
+    eprintln!("Serving {} blocks on http://{addr}", graph.block_count());
+
+    // 只读查询，多个线程共享同一个图
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let graph = graph.clone();
+            let server = server.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let response = if *request.method() == Method::Get {
+                        // 单个请求出错时返回500，工作线程继续服务
+                        catch_unwind(AssertUnwindSafe(|| inspect::handle(&graph, request.url())))
+                            .unwrap_or_else(|_| inspect::InspectResponse {
+                                status: 500,
+                                body: "{\"error\": \"internal error\"}".to_string(),
+                            })
+                    } else {
+                        inspect::InspectResponse {
+                            status: 405,
+                            body: "{\"error\": \"only GET is supported\"}".to_string(),
+                        }
+                    };
+                    let content_type =
+                        Header::from_bytes("Content-Type", "application/json").unwrap();
+                    let response = Response::from_string(response.body)
+                        .with_status_code(response.status)
+                        .with_header(content_type);
+                    if let Err(e) = request.respond(response) {
+                        eprintln!("Failed to respond: {e}");
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+extern crate tree_graph_parse_rust;
+
+use std::time::Instant;
+
+use tree_graph_parse_rust::math::simulation::{simulate_reversal, SimulationParams};
+
+fn parse_args() -> SimulationParams {
+    let mut params = SimulationParams::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .unwrap_or_else(|| panic!("missing value for {flag}"));
+        match flag.as_str() {
+            "--adv-percent" => params.adv_percent = value.parse().unwrap(),
+            "--trials" => params.trials = value.parse().unwrap(),
+            "--block-rate" => params.block_rate = value.parse().unwrap(),
+            "--delay" => params.network_delay = value.parse().unwrap(),
+            "--seed" => params.seed = value.parse().unwrap(),
+            _ => panic!("unknown argument {flag}"),
+        }
+    }
+    params
+}
+
+fn main() {
+    let instant = Instant::now();
+    let base = parse_args();
+
+    println!(
+        "adv {}%, {} trials, block rate {}/s, network delay {}s",
+        base.adv_percent, base.trials, base.block_rate, base.network_delay
+    );
+    println!("m\tk\tanalytical\tempirical\t95% CI\t\tbounded");
+    for m in [10, 50, 100, 500] {
+        let expected_adv = (m as f64 * (1. - 2. * base.adv_percent as f64 / 100.)) as usize;
+        for adv in [expected_adv / 4, expected_adv / 2, expected_adv] {
+            let result = simulate_reversal(&SimulationParams {
+                m,
+                adv,
+                ..base.clone()
+            });
+            println!(
+                "{}\t{}\t{:.3e}\t{:.3e}\t[{:.2e}, {:.2e}]\t{}",
+                m,
+                adv,
+                result.analytical_risk,
+                result.empirical_risk,
+                result.ci_low,
+                result.ci_high,
+                result.analytical_bounds_empirical()
+            );
+        }
+    }
+
+    println!("\nTotal time elapsed: {:?}", instant.elapsed());
+}
@@ -1,8 +1,13 @@
-use chrono::{DateTime, Utc};
-use ethereum_types::H256;
-use std::{collections::BTreeSet, str::FromStr};
+use anyhow::Context;
+use chrono::{DateTime, SecondsFormat, Utc};
+use ethereum_types::{Address, H256};
+use regex::Regex;
+use std::str::FromStr;
 
-use crate::{graph::Graph, utils::time_series::TimeSeries};
+use crate::{
+    graph::{Graph, NEW_BLOCK_MARKER},
+    utils::time_series::TimeSeries,
+};
 
 macro_rules! regex {
     ($pattern:expr) => {{
@@ -14,39 +19,65 @@ macro_rules! regex {
     }};
 }
 
+/// A block as read from the log. Edges are still given by hash; they are resolved to block ids
+/// when the graph is built.
+#[derive(Debug, Default, Clone)]
+pub struct RawBlock {
+    pub height: u64,
+    pub hash: H256,
+    pub parent_hash: Option<H256>,
+    pub referee_hashes: Vec<H256>,
+    pub timestamp: u64,
+    pub log_timestamp: u64,
+    pub tx_count: u64,
+    pub block_size: u64,
+    /// Miner of the block, if the log line records it.
+    pub author: Option<Address>,
+    /// GHAST flags, false if the log line does not record them: the block was mined while
+    /// adaptive weights were in force, and its proof of work met the heavy block target.
+    pub adaptive: bool,
+    pub heavy: bool,
+}
+
+/// A block of a finalized graph. Edges and sets hold block ids, the index of a block in the
+/// graph (genesis is 0, the others follow log order); `Graph::hash_of` maps them back to hashes.
 #[derive(Debug, Default, Clone)]
 #[allow(dead_code)]
 pub struct Block {
     pub id: usize,
     pub height: u64,
     pub hash: H256,
-    pub parent_hash: Option<H256>,
-    pub referee_hashes: BTreeSet<H256>,
+    pub parent: Option<usize>,
+    /// Sorted.
+    pub referees: Vec<usize>,
     pub timestamp: u64,
     pub log_timestamp: u64,
     pub tx_count: u64,
     pub block_size: u64,
+    pub author: Option<Address>,
+    pub adaptive: bool,
+    pub heavy: bool,
 
     // Lazy computed fields
-    pub children: Vec<H256>,
+    pub children: Vec<usize>,
 
-    pub epoch_block: Option<H256>,
-    pub epoch_set: Option<BTreeSet<H256>>,
+    pub epoch_block: Option<usize>,
+    /// Sorted.
+    pub epoch_set: Option<Vec<usize>>,
 
     pub past_set_size: u64,
 
     pub subtree_size: u64,
-    pub subtree_size_series: Option<TimeSeries<u16>>,
+    pub subtree_size_series: Option<TimeSeries<u32>>,
     pub subtree_adv_series: Option<TimeSeries<i16>>,
 }
 
-impl Block {
-    pub(super) fn new(
-        height: u64, hash: H256, parent_hash: H256, referee_hashes: BTreeSet<H256>, timestamp: u64,
-        log_timestamp: u64, tx_count: u64, block_size: u64, id: usize,
+impl RawBlock {
+    pub fn new(
+        height: u64, hash: H256, parent_hash: H256, referee_hashes: Vec<H256>, timestamp: u64,
+        log_timestamp: u64, tx_count: u64, block_size: u64,
     ) -> Self {
-        Block {
-            id,
+        RawBlock {
             height,
             hash,
             parent_hash: Some(parent_hash),
@@ -55,101 +86,264 @@ impl Block {
             log_timestamp,
             tx_count,
             block_size,
-            subtree_size: 0,
-            subtree_size_series: None,
-            epoch_block: None,
-            children: Vec::new(),
-            epoch_set: None,
-            past_set_size: 0,
-            subtree_adv_series: None,
+            author: None,
+            adaptive: false,
+            heavy: false,
         }
     }
 
-    pub(super) fn genesis_block(hash: H256) -> Self {
-        Block {
-            id: 0,
+    pub fn genesis_block(hash: H256) -> Self {
+        RawBlock {
             hash,
             ..Default::default()
         }
     }
 
-    pub(super) fn parse_log_line(line: &str, id: usize) -> Self {
-        let log_time_caps =
+    /// Parses a block insertion line of a conflux log. Logs are untrusted and may be cut off
+    /// mid-line, so a line lacking a field or holding a malformed one is an error, not a panic.
+    pub fn parse_log_line(line: &str) -> Result<Self, anyhow::Error> {
+        let log_time_str =
             regex!(r"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:[+-]\d{2}:\d{2}|Z)")
-                .captures(line)
-                .unwrap();
-        let log_time_str = &log_time_caps[0];
+                .find(line)
+                .context("missing log time")?
+                .as_str();
         let log_timestamp = DateTime::parse_from_rfc3339(log_time_str)
-            .unwrap()
+            .with_context(|| format!("invalid log time {:?}", log_time_str))?
             .with_timezone(&Utc)
             .timestamp() as u64;
 
-        // Parse height
-        let height_caps = regex!(r"height: (\d+)").captures(line).unwrap();
-        let height = height_caps[1].parse::<u64>().unwrap();
+        let height = parse_field(line, regex!(r"height: (\d+)"), "height")?;
 
-        // Parse hash
-        let hash_caps = regex!(r"hash: Some\((0x[a-f0-9]+)\)")
-            .captures(line)
-            .unwrap();
-        let block_hash = H256::from_str(hash_caps[1].as_ref()).unwrap();
+        let hash_str = capture(line, regex!(r"hash: Some\((0x[a-f0-9]+)\)"), "hash")?;
+        let block_hash = parse_hash(hash_str)?;
 
-        // Parse parent hash
-        let parent_caps = regex!(r"parent_hash: (0x[a-f0-9]+)")
-            .captures(line)
-            .unwrap();
-        let parent_hash = H256::from_str(parent_caps[1].as_ref()).unwrap();
+        let parent_str = capture(line, regex!(r"parent_hash: (0x[a-f0-9]+)"), "parent_hash")?;
+        let parent_hash = parse_hash(parent_str)?;
 
-        // Parse referee hashes
-        let referee_caps = regex!(r"referee_hashes: \[(.*?)\]").captures(line).unwrap();
-        let referee_str = &referee_caps[1];
-        let referee_hashes: BTreeSet<H256> = if !referee_str.is_empty() {
+        let referee_str = capture(line, regex!(r"referee_hashes: \[(.*?)\]"), "referee_hashes")?;
+        let referee_hashes: Vec<H256> = if !referee_str.is_empty() {
             referee_str
                 .split(',')
-                .map(|h| H256::from_str(h.trim()).unwrap())
-                .collect()
+                .map(|h| parse_hash(h.trim()))
+                .collect::<Result<_, _>>()?
         } else {
             Default::default()
         };
 
-        // Parse timestamp
-        let timestamp_caps = regex!(r"timestamp: (\d+)").captures(line).unwrap();
-        let timestamp = timestamp_caps[1].parse::<u64>().unwrap();
+        let timestamp = parse_field(line, regex!(r"timestamp: (\d+)"), "timestamp")?;
+        let tx_count = parse_field(line, regex!(r"tx_count=(\d+)"), "tx_count")?;
+        let block_size = parse_field(line, regex!(r"block_size=(\d+)"), "block_size")?;
 
-        // Parse tx_count and block_size
-        let tx_count_caps = regex!(r"tx_count=(\d+)").captures(line).unwrap();
-        let tx_count = tx_count_caps[1].parse::<u64>().unwrap();
+        // Parse author, missing from older logs
+        let author = regex!(r"author: (0x[a-f0-9]{40})\b")
+            .captures(line)
+            .and_then(|caps| Address::from_str(&caps[1]).ok());
 
-        let block_size_caps = regex!(r"block_size=(\d+)").captures(line).unwrap();
-        let block_size = block_size_caps[1].parse::<u64>().unwrap();
+        // Parse GHAST flags, missing from older logs
+        let adaptive = regex!(r"adaptive: (true|false)")
+            .captures(line)
+            .is_some_and(|caps| &caps[1] == "true");
+        let heavy = regex!(r"is_heavy=(true|false)")
+            .captures(line)
+            .is_some_and(|caps| &caps[1] == "true");
 
-        Block::new(
-            height,
-            block_hash,
-            parent_hash,
-            referee_hashes,
-            timestamp,
-            log_timestamp,
-            tx_count,
-            block_size,
-            id,
+        Ok(RawBlock {
+            author,
+            adaptive,
+            heavy,
+            ..RawBlock::new(
+                height,
+                block_hash,
+                parent_hash,
+                referee_hashes,
+                timestamp,
+                log_timestamp,
+                tx_count,
+                block_size,
+            )
+        })
+    }
+
+    /// A block insertion line that `parse_log_line` reads back into this block. Only the fields
+    /// the parser reads are written; a block without parent gets a zero parent hash.
+    pub fn to_log_line(&self) -> String {
+        let log_time = DateTime::<Utc>::from_timestamp(self.log_timestamp as i64, 0)
+            .unwrap_or_default()
+            .to_rfc3339_opts(SecondsFormat::Secs, true);
+        let author = self
+            .author
+            .map_or(String::new(), |author| format!("author: {:?}, ", author));
+        let referees: Vec<_> = self
+            .referee_hashes
+            .iter()
+            .map(|hash| format!("{:?}", hash))
+            .collect();
+        format!(
+            "{} INFO {}: block_header=BlockHeader {{ height: {}, hash: Some({:?}), parent_hash: \
+             {:?}, timestamp: {}, {}adaptive: {}, referee_hashes: [{}] }}, tx_count={}, \
+             block_size={}, is_heavy={}",
+            log_time,
+            NEW_BLOCK_MARKER,
+            self.height,
+            self.hash,
+            self.parent_hash.unwrap_or_default(),
+            self.timestamp,
+            author,
+            self.adaptive,
+            referees.join(", "),
+            self.tx_count,
+            self.block_size,
+            self.heavy
         )
     }
+}
+
+/// The first group of `pattern` in `line`.
+fn capture<'a>(line: &'a str, pattern: &Regex, field: &str) -> Result<&'a str, anyhow::Error> {
+    pattern
+        .captures(line)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str())
+        .with_context(|| format!("missing {}", field))
+}
+
+fn parse_field(line: &str, pattern: &Regex, field: &str) -> Result<u64, anyhow::Error> {
+    let value = capture(line, pattern, field)?;
+    value
+        .parse()
+        .with_context(|| format!("invalid {} {:?}", field, value))
+}
+
+fn parse_hash(s: &str) -> Result<H256, anyhow::Error> {
+    H256::from_str(s).with_context(|| format!("invalid hash {:?}", s))
+}
+
+impl Block {
+    /// `parent` and `referees` are the ids of the raw block's parent and referees.
+    pub(super) fn from_raw(
+        raw: RawBlock, id: usize, parent: Option<usize>, mut referees: Vec<usize>,
+    ) -> Self {
+        referees.sort_unstable();
+        referees.dedup();
+        Block {
+            id,
+            height: raw.height,
+            hash: raw.hash,
+            parent,
+            referees,
+            timestamp: raw.timestamp,
+            log_timestamp: raw.log_timestamp,
+            tx_count: raw.tx_count,
+            block_size: raw.block_size,
+            author: raw.author,
+            adaptive: raw.adaptive,
+            heavy: raw.heavy,
+            ..Default::default()
+        }
+    }
 
     pub fn sib_subtree_size(&self, graph: &Graph) -> u64 {
         self.children
             .get(1)
-            .map_or(0, |h| graph.get_block(h).unwrap().subtree_size)
+            .map_or(0, |&id| graph.block(id).subtree_size)
     }
 
     pub fn all_sib_subtree_size(&self, graph: &Graph) -> u64 {
         self.children[1..]
             .iter()
-            .map(|h| graph.get_block(h).unwrap().subtree_size)
+            .map(|&id| graph.block(id).subtree_size)
             .sum()
     }
 
-    pub fn max_child(&self) -> Option<H256> { self.children.first().cloned() }
+    /// Weight of the block in a subtree under GHAST: 1, unless it is adaptive, then `heavy_ratio`
+    /// if it is heavy and 0 otherwise.
+    pub fn ghast_weight(&self, heavy_ratio: u64) -> u64 {
+        match (self.adaptive, self.heavy) {
+            (false, _) => 1,
+            (true, true) => heavy_ratio,
+            (true, false) => 0,
+        }
+    }
+
+    pub fn max_child(&self) -> Option<usize> { self.children.first().copied() }
 
     pub fn epoch_size(&self) -> usize { 1 + self.epoch_set.as_ref().map_or(0, |x| x.len()) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "2025-01-01T00:00:07Z INFO new block inserted into graph: \
+        block_header=BlockHeader { height: 2, hash: Some(0x0000000000000000000000000000000000000000000000000000000000000003), \
+        parent_hash: 0x0000000000000000000000000000000000000000000000000000000000000002, \
+        timestamp: 7, referee_hashes: [0x0000000000000000000000000000000000000000000000000000000000000001] }, \
+        tx_count=5, block_size=100";
+
+    #[test]
+    fn test_parse_log_line() {
+        let block = RawBlock::parse_log_line(LINE).unwrap();
+        assert_eq!(block.height, 2);
+        assert_eq!(block.hash, H256::from_low_u64_be(3));
+        assert_eq!(block.parent_hash, Some(H256::from_low_u64_be(2)));
+        assert_eq!(block.referee_hashes, vec![H256::from_low_u64_be(1)]);
+        assert_eq!((block.timestamp, block.log_timestamp), (7, 1735689607));
+        assert_eq!((block.tx_count, block.block_size), (5, 100));
+        // Lines of older logs carry neither the author nor the GHAST flags.
+        assert_eq!(
+            (block.author, block.adaptive, block.heavy),
+            (None, false, false)
+        );
+    }
+
+    #[test]
+    fn test_log_line_roundtrip() {
+        let block = RawBlock::parse_log_line(LINE).unwrap();
+        let line = block.to_log_line();
+        assert!(line.starts_with("2025-01-01T00:00:07Z INFO new block inserted into graph: "));
+        assert_eq!(RawBlock::parse_log_line(&line).unwrap().to_log_line(), line);
+
+        let block = RawBlock {
+            author: Some(Address::from_low_u64_be(7)),
+            adaptive: true,
+            heavy: true,
+            referee_hashes: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(4)],
+            ..block
+        };
+        let parsed = RawBlock::parse_log_line(&block.to_log_line()).unwrap();
+        assert_eq!(parsed.author, block.author);
+        assert_eq!((parsed.adaptive, parsed.heavy), (true, true));
+        assert_eq!(parsed.referee_hashes, block.referee_hashes);
+        assert_eq!(parsed.log_timestamp, block.log_timestamp);
+    }
+
+    #[test]
+    fn test_malformed_log_line_is_error() {
+        // A line cut off anywhere before the last value lacks a field.
+        for end in 0..LINE.len() - "=100".len() {
+            assert!(
+                RawBlock::parse_log_line(&LINE[..end]).is_err(),
+                "{}",
+                &LINE[..end]
+            );
+        }
+        for (from, to) in [
+            (
+                "0x0000000000000000000000000000000000000000000000000000000000000003",
+                "0x03",
+            ),
+            ("height: 2", "height: 99999999999999999999"),
+            ("2025-01-01T00:00:07Z", "2025-13-01T00:00:07Z"),
+            (
+                "[0x0000000000000000000000000000000000000000000000000000000000000001]",
+                "[0x01,]",
+            ),
+        ] {
+            assert!(
+                RawBlock::parse_log_line(&LINE.replace(from, to)).is_err(),
+                "{}",
+                to
+            );
+        }
+    }
+}
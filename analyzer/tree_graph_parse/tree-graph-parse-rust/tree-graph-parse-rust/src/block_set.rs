@@ -0,0 +1,220 @@
+//! A set of blocks chosen by one analysis, to restrict another one to the same blocks.
+//!
+//! `stat_latency_rs --block-set-out` writes the blocks every node synced; the binaries here read
+//! such a file with `--only-blocks`. The file is a JSON object:
+//!
+//! ```json
+//! {
+//!   "format": "conflux-block-set",
+//!   "version": 1,
+//!   "provenance": {"tool": "stat_latency_rs", "filter": "synced by every node", "nodes": "300"},
+//!   "blocks": [
+//!     {"hash": "0x1f…", "timestamp": 1735689602, "synced": 300}
+//!   ]
+//! }
+//! ```
+//!
+//! `provenance` holds strings only and says how the set was chosen, and from which run. Only
+//! `hash` is required of a block; fields this version does not know are ignored.
+
+use anyhow::{bail, Context};
+use ethereum_types::H256;
+use serde_json::Value;
+use std::{collections::HashSet, fs, path::Path, str::FromStr};
+
+use crate::load_options::GraphLoadOptions;
+
+pub const BLOCK_SET_FORMAT: &str = "conflux-block-set";
+pub const BLOCK_SET_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSetEntry {
+    pub hash: H256,
+    /// Timestamp in the block header, in seconds.
+    pub timestamp: Option<i64>,
+    /// Nodes that synced the block.
+    pub synced: Option<usize>,
+}
+
+impl BlockSetEntry {
+    pub fn new(hash: H256) -> Self {
+        BlockSetEntry {
+            hash,
+            timestamp: None,
+            synced: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockSet {
+    /// `(name, value)` pairs, written in this order.
+    pub provenance: Vec<(String, String)>,
+    pub blocks: Vec<BlockSetEntry>,
+}
+
+impl BlockSet {
+    pub fn hashes(&self) -> HashSet<H256> { self.blocks.iter().map(|b| b.hash).collect() }
+
+    /// Options loading a graph of these blocks only.
+    pub fn load_options(&self) -> GraphLoadOptions {
+        GraphLoadOptions {
+            include_only: Some(self.hashes()),
+            ..Default::default()
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let provenance = self
+            .provenance
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{}: {}",
+                    Value::from(name.as_str()),
+                    Value::from(value.as_str())
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let mut fields = vec![format!("\"hash\": \"{:?}\"", block.hash)];
+                if let Some(timestamp) = block.timestamp {
+                    fields.push(format!("\"timestamp\": {}", timestamp));
+                }
+                if let Some(synced) = block.synced {
+                    fields.push(format!("\"synced\": {}", synced));
+                }
+                format!("\n    {{{}}}", fields.join(", "))
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let end = if self.blocks.is_empty() { "" } else { "\n  " };
+        format!(
+            "{{\n  \"format\": \"{}\",\n  \"version\": {},\n  \"provenance\": {{{}}},\n  \"blocks\": [{}{}]\n}}\n",
+            BLOCK_SET_FORMAT, BLOCK_SET_VERSION, provenance, blocks, end
+        )
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, anyhow::Error> {
+        let root: Value = serde_json::from_str(text).context("block set is not JSON")?;
+        let format = root.get("format").and_then(Value::as_str);
+        if format != Some(BLOCK_SET_FORMAT) {
+            bail!(
+                "not a block set: format is {:?}, expected {:?}",
+                format,
+                BLOCK_SET_FORMAT
+            );
+        }
+        match root.get("version").and_then(Value::as_u64) {
+            Some(version) if version <= BLOCK_SET_VERSION => {}
+            version => bail!(
+                "unsupported block set version {:?}, up to {} is known",
+                version,
+                BLOCK_SET_VERSION
+            ),
+        }
+
+        let mut provenance = Vec::new();
+        if let Some(fields) = root.get("provenance").and_then(Value::as_object) {
+            for (name, value) in fields {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                provenance.push((name.clone(), value));
+            }
+        }
+
+        let Some(entries) = root.get("blocks").and_then(Value::as_array) else {
+            bail!("block set has no \"blocks\" array");
+        };
+        let mut blocks = Vec::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            let hash = entry
+                .get("hash")
+                .and_then(Value::as_str)
+                .with_context(|| format!("block {} has no hash", i))?;
+            let hash = H256::from_str(hash)
+                .with_context(|| format!("block {}: invalid hash {:?}", i, hash))?;
+            blocks.push(BlockSetEntry {
+                hash,
+                timestamp: entry.get("timestamp").and_then(Value::as_i64),
+                synced: entry
+                    .get("synced")
+                    .and_then(Value::as_u64)
+                    .map(|n| n as usize),
+            });
+        }
+        Ok(BlockSet { provenance, blocks })
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::from_json(&text).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json())
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut with_metadata = BlockSetEntry::new(H256::repeat_byte(0x1f));
+        with_metadata.timestamp = Some(1735689602);
+        with_metadata.synced = Some(300);
+        let set = BlockSet {
+            provenance: vec![
+                ("tool".to_string(), "stat_latency_rs".to_string()),
+                ("filter".to_string(), "synced by \"every\" node".to_string()),
+            ],
+            blocks: vec![with_metadata, BlockSetEntry::new(H256::repeat_byte(0xa0))],
+        };
+        let parsed = BlockSet::from_json(&set.to_json()).unwrap();
+        assert!(BlockSet::from_json(&BlockSet::default().to_json())
+            .unwrap()
+            .blocks
+            .is_empty());
+        assert_eq!(parsed.blocks, set.blocks);
+        // serde_json keeps object keys sorted.
+        let mut provenance = set.provenance.clone();
+        provenance.sort();
+        assert_eq!(parsed.provenance, provenance);
+    }
+
+    #[test]
+    fn test_rejects_other_formats_and_newer_versions() {
+        let blocks = r#""blocks": [{"hash": "0x01", "future_field": 1}]"#;
+        assert!(
+            BlockSet::from_json(&format!(r#"{{"format": "csv", "version": 1, {}}}"#, blocks))
+                .is_err()
+        );
+        assert!(BlockSet::from_json(&format!(
+            r#"{{"format": "conflux-block-set", "version": 2, {}}}"#,
+            blocks
+        ))
+        .is_err());
+        let hash = format!("{:?}", H256::from_low_u64_be(1));
+        let set = BlockSet::from_json(&format!(
+            r#"{{"format": "conflux-block-set", "version": 1, "blocks": [{{"hash": "{}", "future_field": 1}}]}}"#,
+            hash
+        ))
+        .unwrap();
+        assert_eq!(
+            set.blocks,
+            vec![BlockSetEntry::new(H256::from_low_u64_be(1))]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Block timestamps against log arrival times.
+//!
+//! A block's `timestamp` comes from its miner's clock and its `log_timestamp` from the logging
+//! node's, so `log_timestamp - timestamp` is the propagation delay plus the offset between the
+//! two clocks. Blocks logged before their declared creation time, or long after it, point at
+//! miners with bad clocks, which distort `epoch_span` and `avg_epoch_time`. Genesis is skipped.
+
+use ethereum_types::{Address, H256};
+use std::collections::BTreeMap;
+
+use crate::graph::Graph;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkewedBlock {
+    pub id: usize,
+    pub hash: H256,
+    pub author: Option<Address>,
+    /// `log_timestamp - timestamp`, in seconds.
+    pub delay: i64,
+}
+
+/// Arrival delays of the blocks of one author.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthorDrift {
+    /// `None` groups the blocks whose log line records no author.
+    pub author: Option<Address>,
+    pub blocks: usize,
+    pub mean_delay: f64,
+    pub median_delay: i64,
+    pub min_delay: i64,
+    pub max_delay: i64,
+    /// Median delay of this author minus the median delay over all blocks. Positive when the
+    /// author's clock lags behind the others.
+    pub offset: i64,
+    /// Blocks logged before their timestamp.
+    pub early_blocks: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockReport {
+    pub max_skew: u64,
+    pub median_delay: i64,
+    /// Blocks logged before their timestamp, by id.
+    pub early: Vec<SkewedBlock>,
+    /// Blocks logged more than `max_skew` seconds after their timestamp, by id.
+    pub late: Vec<SkewedBlock>,
+    /// By author, blocks without author first.
+    pub per_author: Vec<AuthorDrift>,
+}
+
+impl Graph {
+    /// `log_timestamp - timestamp` of every block, by id; 0 for genesis.
+    pub fn arrival_delays(&self) -> Vec<i64> {
+        self.blocks()
+            .map(|b| match b.height {
+                0 => 0,
+                _ => b.log_timestamp as i64 - b.timestamp as i64,
+            })
+            .collect()
+    }
+
+    pub fn clock_report(&self, max_skew: u64) -> ClockReport {
+        let delays = self.arrival_delays();
+        let skewed = |id: usize| SkewedBlock {
+            id,
+            hash: self.hash_of(id),
+            author: self.block(id).author,
+            delay: delays[id],
+        };
+
+        let mut early = vec![];
+        let mut late = vec![];
+        let mut by_author: BTreeMap<Option<Address>, Vec<i64>> = BTreeMap::new();
+        for block in self.blocks().skip(1) {
+            let delay = delays[block.id];
+            if delay < 0 {
+                early.push(skewed(block.id));
+            } else if delay as u64 > max_skew {
+                late.push(skewed(block.id));
+            }
+            by_author.entry(block.author).or_default().push(delay);
+        }
+
+        let median_delay = median(delays[1..].to_vec());
+        let per_author = by_author
+            .into_iter()
+            .map(|(author, delays)| {
+                let author_median = median(delays.clone());
+                AuthorDrift {
+                    author,
+                    blocks: delays.len(),
+                    mean_delay: delays.iter().sum::<i64>() as f64 / delays.len() as f64,
+                    median_delay: author_median,
+                    min_delay: *delays.iter().min().unwrap(),
+                    max_delay: *delays.iter().max().unwrap(),
+                    offset: author_median - median_delay,
+                    early_blocks: delays.iter().filter(|&&d| d < 0).count(),
+                }
+            })
+            .collect();
+
+        ClockReport {
+            max_skew,
+            median_delay,
+            early,
+            late,
+            per_author,
+        }
+    }
+}
+
+/// Lower median; 0 when empty.
+fn median(mut values: Vec<i64>) -> i64 {
+    if values.is_empty() {
+        return 0;
+    }
+    let mid = (values.len() - 1) / 2;
+    *values.select_nth_unstable(mid).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::RawBlock;
+
+    #[test]
+    fn test_clock_report_flags_skewed_author() {
+        let hash = H256::from_low_u64_be;
+        let (honest, fast) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+
+        // Honest blocks arrive 1s after their timestamp; the fast clock runs 5s ahead.
+        let mut raw_blocks = vec![RawBlock::genesis_block(hash(0))];
+        for i in 1..=9u64 {
+            let (author, timestamp) = match i % 3 {
+                0 => (fast, 100 + i + 5),
+                _ => (honest, 100 + i),
+            };
+            raw_blocks.push(RawBlock {
+                author: Some(author),
+                ..RawBlock::new(
+                    i,
+                    hash(i),
+                    hash(i - 1),
+                    vec![],
+                    timestamp,
+                    100 + i + 1,
+                    0,
+                    0,
+                )
+            });
+        }
+        raw_blocks[4].log_timestamp += 30;
+        let graph = Graph::from_raw_blocks(raw_blocks).unwrap();
+
+        let report = graph.clock_report(10);
+        assert_eq!(report.median_delay, 1);
+        assert_eq!(
+            report.early.iter().map(|b| b.id).collect::<Vec<_>>(),
+            vec![3, 6, 9]
+        );
+        assert_eq!(report.late.len(), 1);
+        assert_eq!(report.late[0].id, 4);
+        assert_eq!(report.late[0].delay, 31);
+
+        let drift: BTreeMap<_, _> = report
+            .per_author
+            .iter()
+            .map(|d| (d.author.unwrap(), d))
+            .collect();
+        assert_eq!(drift[&fast].offset, -5);
+        assert_eq!(drift[&fast].early_blocks, 3);
+        assert_eq!(drift[&honest].offset, 0);
+        assert_eq!(drift[&honest].max_delay, 31);
+    }
+}
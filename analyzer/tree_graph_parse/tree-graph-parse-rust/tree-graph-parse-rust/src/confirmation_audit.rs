@@ -0,0 +1,158 @@
+//! Inputs of the confirmation risk model for every pivot block, so they can be audited outside
+//! the analyzer.
+//!
+//! For each pivot block (genesis excluded), adversary power and risk threshold, the audit records
+//! the first moment the risk drops below the threshold, with the `m` (honest blocks since the
+//! parent) and `k` (lead over the heaviest sibling) the model was evaluated at.
+
+use ethereum_types::H256;
+use std::{fs::File, io::Write};
+
+use crate::graph::Graph;
+
+/// Column schema of `export_confirmation_audit_csv`. New columns are only ever appended.
+pub const CONFIRMATION_AUDIT_HEADER: &str =
+    "height,hash,timestamp,adv_percent,risk_threshold,confirm_time_offset,m,k,risk";
+
+/// The thresholds `compute_confirmation` reports.
+pub const DEFAULT_RISK_THRESHOLDS: [f64; 5] = [1e-4, 1e-5, 1e-6, 1e-7, 1e-8];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfirmationAuditRow {
+    pub height: u64,
+    pub hash: H256,
+    pub timestamp: u64,
+    pub adv_percent: usize,
+    pub risk_threshold: f64,
+    /// `None` if the risk never drops below the threshold within the log.
+    pub confirmation: Option<AuditedConfirmation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AuditedConfirmation {
+    /// Seconds after the block timestamp.
+    pub confirm_time_offset: u64,
+    pub m: u64,
+    pub k: u64,
+    pub risk: f64,
+}
+
+impl Graph {
+    /// One row per pivot block, adversary power and threshold, in that nesting order.
+    pub fn confirmation_audit(
+        &self, adv_percents: &[usize], risk_thresholds: &[f64],
+    ) -> Vec<ConfirmationAuditRow> {
+        let mut rows = vec![];
+        for block in self.pivot_chain().into_iter().skip(1) {
+            for &adv_percent in adv_percents {
+                let confirmations = self.confirmation_risks(block, adv_percent, risk_thresholds);
+                for (&risk_threshold, confirmation) in risk_thresholds.iter().zip(confirmations) {
+                    let confirmation =
+                        confirmation.map(|(confirm_time_offset, m, k, risk)| AuditedConfirmation {
+                            confirm_time_offset,
+                            m,
+                            k,
+                            risk,
+                        });
+                    rows.push(ConfirmationAuditRow {
+                        height: block.height,
+                        hash: block.hash,
+                        timestamp: block.timestamp,
+                        adv_percent,
+                        risk_threshold,
+                        confirmation,
+                    });
+                }
+            }
+        }
+        rows
+    }
+
+    /// CSV with header `CONFIRMATION_AUDIT_HEADER`. Thresholds and risks are written in `{:e}`
+    /// notation; the last four cells are empty for unconfirmed rows.
+    pub fn export_confirmation_audit_csv(
+        &self, adv_percents: &[usize], risk_thresholds: &[f64], filename: &str,
+    ) -> Result<(), anyhow::Error> {
+        let mut file = File::create(filename)?;
+        writeln!(file, "{}", CONFIRMATION_AUDIT_HEADER)?;
+        for row in self.confirmation_audit(adv_percents, risk_thresholds) {
+            let confirmation = row.confirmation.map_or(",,,".to_string(), |c| {
+                format!("{},{},{},{:e}", c.confirm_time_offset, c.m, c.k, c.risk)
+            });
+            writeln!(
+                file,
+                "{},{:?},{},{},{:e},{}",
+                row.height,
+                row.hash,
+                row.timestamp,
+                row.adv_percent,
+                row.risk_threshold,
+                confirmation
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::GenerateParams;
+
+    #[test]
+    fn test_confirmation_audit_csv() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 300,
+            ..Default::default()
+        })
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("tg_audit_{}.csv", std::process::id()));
+        graph
+            .export_confirmation_audit_csv(
+                &[10, 20],
+                &DEFAULT_RISK_THRESHOLDS,
+                path.to_str().unwrap(),
+            )
+            .unwrap();
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CONFIRMATION_AUDIT_HEADER));
+        let rows: Vec<Vec<&str>> = lines.map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), (graph.pivot_chain().len() - 1) * 2 * 5);
+        assert!(rows.iter().all(|row| row.len() == 9));
+
+        let block = graph.pivot_chain()[5];
+        let (offset, m, k, _) = graph.confirmation_risk(block, 20, 1e-6).unwrap();
+        let row = &rows[4 * 10 + 5 + 2];
+        assert_eq!(row[0], block.height.to_string());
+        assert_eq!((row[3], row[4]), ("20", "1e-6"));
+        assert_eq!(
+            row[5..8],
+            [offset.to_string(), m.to_string(), k.to_string()]
+        );
+    }
+
+    #[test]
+    fn test_thresholds_share_one_series() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 120,
+            ..Default::default()
+        })
+        .unwrap();
+        // Both sides of the switch to the log-domain series.
+        let thresholds = [1e-3, 1e-8, 1e-14, 1e-20];
+        let rows = graph.confirmation_audit(&[20], &thresholds);
+        for (i, row) in rows.iter().enumerate() {
+            let block = graph.pivot_chain()[1 + i / thresholds.len()];
+            let expected = graph.confirmation_risk(block, 20, row.risk_threshold);
+            assert_eq!(
+                row.confirmation
+                    .map(|c| (c.confirm_time_offset, c.m, c.k, c.risk)),
+                expected
+            );
+        }
+        assert!(rows.iter().any(|row| row.confirmation.is_some()));
+    }
+}
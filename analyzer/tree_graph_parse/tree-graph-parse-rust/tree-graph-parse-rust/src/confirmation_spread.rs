@@ -0,0 +1,141 @@
+//! How far apart the nodes of one run confirm the same blocks.
+//!
+//! Every node logs its own view of the DAG, so the same block gets a confirmation time per node.
+//! Views that agree confirm a block within a few seconds of each other; a node confirming
+//! consistently later or earlier than the others had a diverging view worth investigating.
+
+use ethereum_types::H256;
+use std::collections::HashMap;
+
+/// How one node's confirmation times compare with the median over all nodes, on the blocks every
+/// node confirmed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeOffset {
+    /// Index of the node in the input.
+    pub node: usize,
+    /// Mean seconds later than the median; negative if earlier.
+    pub mean_offset: f64,
+    /// Offset of largest magnitude.
+    pub max_offset: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfirmationSpread {
+    pub nodes: usize,
+    /// Latest minus earliest confirmation time of every block confirmed on every node, ascending.
+    pub spreads: Vec<u64>,
+    /// Blocks confirmed on some nodes but not all.
+    pub partial_blocks: usize,
+    /// One entry per node, in input order.
+    pub offsets: Vec<NodeOffset>,
+}
+
+impl ConfirmationSpread {
+    /// Compares the confirmation times of the blocks in `per_node`, one `(hash, seconds)` list
+    /// per node as given by `Graph::confirm_times_by_block`.
+    pub fn new(per_node: &[Vec<(H256, u64)>]) -> Self {
+        let mut by_block: HashMap<H256, Vec<u64>> = HashMap::new();
+        for times in per_node {
+            for &(hash, time) in times {
+                by_block.entry(hash).or_default().push(time);
+            }
+        }
+        let medians: HashMap<H256, f64> = by_block
+            .iter()
+            .filter(|(_, times)| times.len() == per_node.len())
+            .map(|(hash, times)| (*hash, median(times)))
+            .collect();
+
+        let mut spreads: Vec<u64> = by_block
+            .values()
+            .filter(|times| times.len() == per_node.len())
+            .map(|times| times.iter().max().unwrap() - times.iter().min().unwrap())
+            .collect();
+        spreads.sort_unstable();
+
+        let offsets = per_node
+            .iter()
+            .enumerate()
+            .map(|(node, times)| {
+                let offsets: Vec<f64> = times
+                    .iter()
+                    .filter_map(|(hash, time)| Some(*time as f64 - medians.get(hash)?))
+                    .collect();
+                NodeOffset {
+                    node,
+                    mean_offset: match offsets.len() {
+                        0 => 0.,
+                        n => offsets.iter().sum::<f64>() / n as f64,
+                    },
+                    max_offset: offsets
+                        .iter()
+                        .copied()
+                        .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                        .unwrap_or(0.),
+                }
+            })
+            .collect();
+
+        ConfirmationSpread {
+            nodes: per_node.len(),
+            partial_blocks: by_block.len() - spreads.len(),
+            spreads,
+            offsets,
+        }
+    }
+
+    /// The `p`-th percentile of `spreads`, 0 without any block.
+    pub fn percentile(&self, p: usize) -> u64 {
+        match self.spreads.len() {
+            0 => 0,
+            len => self.spreads[(len * p / 100).min(len - 1)],
+        }
+    }
+
+    /// Blocks every node confirmed within `seconds` of each other.
+    pub fn within(&self, seconds: u64) -> usize { self.spreads.partition_point(|&s| s <= seconds) }
+
+    /// Nodes confirming more than `seconds` later or earlier than the median on average.
+    pub fn divergent(&self, seconds: f64) -> impl Iterator<Item = &NodeOffset> + '_ {
+        self.offsets
+            .iter()
+            .filter(move |offset| offset.mean_offset.abs() > seconds)
+    }
+}
+
+fn median(times: &[u64]) -> f64 {
+    let mut sorted = times.to_vec();
+    sorted.sort_unstable();
+    let len = sorted.len();
+    (sorted[(len - 1) / 2] + sorted[len / 2]) as f64 / 2.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u64) -> H256 { H256::from_low_u64_be(n) }
+
+    #[test]
+    fn test_spread_and_divergent_node() {
+        let node = |late: u64| vec![(hash(1), 10 + late), (hash(2), 20 + late), (hash(3), 30)];
+        let mut per_node = vec![node(0), node(1), node(0), node(9)];
+        // Block 4 only made it to one node.
+        per_node[0].push((hash(4), 40));
+
+        let spread = ConfirmationSpread::new(&per_node);
+        assert_eq!(spread.nodes, 4);
+        assert_eq!(spread.spreads, vec![0, 9, 9]);
+        assert_eq!(spread.partial_blocks, 1);
+        assert_eq!(spread.percentile(50), 9);
+        assert_eq!(spread.within(5), 1);
+
+        // Medians are 10.5 and 20.5: node 3 is 8.5s late on two of the three blocks.
+        let divergent: Vec<_> = spread.divergent(5.).collect();
+        assert_eq!(divergent.len(), 1);
+        assert_eq!(divergent[0].node, 3);
+        assert!((divergent[0].mean_offset - 17. / 3.).abs() < 1e-9);
+        assert_eq!(divergent[0].max_offset, 8.5);
+        assert_eq!(spread.offsets[0].max_offset, -0.5);
+    }
+}
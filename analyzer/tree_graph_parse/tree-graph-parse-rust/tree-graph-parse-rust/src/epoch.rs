@@ -0,0 +1,244 @@
+//! Epoch membership and past/future/anticone queries.
+//!
+//! The past of a block is everything reachable through parent and referee edges, its future is
+//! everything that reaches it, and its anticone is the rest of the graph. Set queries walk the
+//! graph on demand and return sorted block ids.
+
+use ethereum_types::H256;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::Write,
+};
+
+use crate::{block::Block, graph::Graph};
+
+/// Column schema of `export_execution_order`.
+pub const EXECUTION_ORDER_HEADER: &str = "epoch_height,position,hash";
+
+/// Summary of the epoch led by one pivot block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochStats {
+    pub height: u64,
+    /// Id of the pivot block.
+    pub pivot: usize,
+    /// Blocks in the epoch, the pivot block included.
+    pub size: usize,
+    pub tx_count: u64,
+    /// See `Graph::epoch_span`.
+    pub span: u64,
+    /// See `Graph::avg_epoch_time`.
+    pub avg_epoch_time: f64,
+}
+
+impl Graph {
+    /// Blocks of the epoch led by `pivot`, the pivot block last. Empty if `pivot` is not on the
+    /// pivot chain.
+    pub fn epoch_blocks(&self, pivot: &Block) -> Vec<&Block> {
+        let Some(epoch_set) = &pivot.epoch_set else {
+            return vec![];
+        };
+        epoch_set
+            .iter()
+            .map(|&id| self.block(id))
+            .chain(std::iter::once(self.block(pivot.id)))
+            .collect()
+    }
+
+    /// Blocks of the epoch led by the pivot block `pivot_hash` in the order Conflux executes
+    /// them: every block after its parent and referees in the epoch, ties broken by ascending
+    /// hash. The pivot block, which has the whole epoch in its past, comes last. `None` if
+    /// `pivot_hash` is not on the pivot chain.
+    pub fn epoch_execution_order(&self, pivot_hash: &H256) -> Option<Vec<&Block>> {
+        let pivot = self.get_block(pivot_hash)?;
+        if pivot.epoch_block != Some(pivot.id) {
+            return None;
+        }
+        let members = self.epoch_blocks(pivot);
+        // Edges to other blocks of the epoch not executed yet, and the reverse edges.
+        let mut pending: HashMap<usize, usize> = HashMap::new();
+        let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+        for block in &members {
+            let edges = block.parent.iter().chain(&block.referees);
+            for &edge in edges.filter(|&&e| self.block(e).epoch_block == Some(pivot.id)) {
+                *pending.entry(block.id).or_default() += 1;
+                dependents.entry(edge).or_default().push(block.id);
+            }
+        }
+
+        let mut ready: BTreeSet<(H256, usize)> = members
+            .iter()
+            .filter(|b| !pending.contains_key(&b.id))
+            .map(|b| (b.hash, b.id))
+            .collect();
+        let mut order = Vec::with_capacity(members.len());
+        while let Some((_, id)) = ready.pop_first() {
+            order.push(self.block(id));
+            for &dependent in dependents.get(&id).into_iter().flatten() {
+                let count = pending.get_mut(&dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert((self.hash_of(dependent), dependent));
+                }
+            }
+        }
+        Some(order)
+    }
+
+    /// Every block in an epoch as a CSV of `EXECUTION_ORDER_HEADER`: epochs by height, blocks in
+    /// execution order. Blocks in no epoch yet are left out.
+    pub fn export_execution_order(&self, filename: &str) -> Result<(), anyhow::Error> {
+        let mut file = File::create(filename)?;
+        writeln!(file, "{}", EXECUTION_ORDER_HEADER)?;
+        for pivot in self.pivot_chain() {
+            let order = self.epoch_execution_order(&pivot.hash).unwrap_or_default();
+            for (position, block) in order.into_iter().enumerate() {
+                writeln!(file, "{},{},{:?}", pivot.height, position, block.hash)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// One entry per pivot block except genesis, by height.
+    pub fn epoch_stats(&self) -> Vec<EpochStats> {
+        self.pivot_chain()
+            .into_iter()
+            .filter(|block| block.height > 0)
+            .map(|pivot| EpochStats {
+                height: pivot.height,
+                pivot: pivot.id,
+                size: pivot.epoch_size(),
+                tx_count: self.epoch_blocks(pivot).iter().map(|b| b.tx_count).sum(),
+                span: self.epoch_span(pivot),
+                avg_epoch_time: self.avg_epoch_time(pivot),
+            })
+            .collect()
+    }
+
+    /// Ids of the blocks `block` reaches through parent and referee edges, itself excluded.
+    pub fn past_set(&self, block: &Block) -> Vec<usize> {
+        self.reachable(block.id, |b| {
+            b.referees.iter().chain(b.parent.iter()).copied().collect()
+        })
+    }
+
+    /// Ids of the blocks that reach `block` through parent and referee edges, itself excluded.
+    pub fn future_set(&self, block: &Block) -> Vec<usize> {
+        let mut referrers: Vec<Vec<usize>> = vec![vec![]; self.block_count()];
+        for b in self.blocks() {
+            for &referee in &b.referees {
+                referrers[referee].push(b.id);
+            }
+        }
+        self.reachable(block.id, |b| {
+            b.children.iter().chain(&referrers[b.id]).copied().collect()
+        })
+    }
+
+    /// Ids of the blocks in neither the past nor the future of `block`, itself excluded.
+    pub fn anticone(&self, block: &Block) -> Vec<usize> {
+        let mut related = vec![false; self.block_count()];
+        related[block.id] = true;
+        for id in self
+            .past_set(block)
+            .into_iter()
+            .chain(self.future_set(block))
+        {
+            related[id] = true;
+        }
+        (0..self.block_count()).filter(|&id| !related[id]).collect()
+    }
+
+    fn reachable(&self, start: usize, next: impl Fn(&Block) -> Vec<usize>) -> Vec<usize> {
+        let mut visited = vec![false; self.block_count()];
+        visited[start] = true;
+        let mut stack = vec![start];
+        let mut found = vec![];
+        while let Some(id) = stack.pop() {
+            for next_id in next(self.block(id)) {
+                if !visited[next_id] {
+                    visited[next_id] = true;
+                    found.push(next_id);
+                    stack.push(next_id);
+                }
+            }
+        }
+        found.sort_unstable();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::GenerateParams;
+
+    #[test]
+    fn test_past_future_anticone_partition() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 60,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for block in graph.blocks().step_by(7) {
+            let past = graph.past_set(block);
+            let future = graph.future_set(block);
+            let anticone = graph.anticone(block);
+            assert_eq!(
+                past.len() + future.len() + anticone.len() + 1,
+                graph.block_count()
+            );
+            assert_eq!(past.len() as u64 + 1, block.past_set_size);
+            for &id in &future {
+                assert!(graph.past_set(graph.block(id)).contains(&block.id));
+            }
+        }
+    }
+
+    #[test]
+    fn test_epoch_stats_cover_epochs() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 60,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let stats = graph.epoch_stats();
+        assert_eq!(stats.len(), graph.pivot_chain().len() - 1);
+        for entry in &stats {
+            let pivot = graph.block(entry.pivot);
+            let members = graph.epoch_blocks(pivot);
+            assert_eq!(members.len(), entry.size);
+            assert!(members.iter().all(|b| b.epoch_block == Some(pivot.id)));
+        }
+    }
+
+    #[test]
+    fn test_epoch_execution_order() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 120,
+            ..Default::default()
+        })
+        .unwrap();
+
+        for pivot in graph.pivot_chain() {
+            let order = graph.epoch_execution_order(&pivot.hash).unwrap();
+            assert_eq!(order.len(), pivot.epoch_size());
+            assert_eq!(order.last().unwrap().id, pivot.id);
+            let position: HashMap<usize, usize> =
+                order.iter().enumerate().map(|(i, b)| (b.id, i)).collect();
+            for (i, block) in order.iter().enumerate() {
+                for edge in block.parent.iter().chain(&block.referees) {
+                    assert!(position.get(edge).is_none_or(|&p| p < i));
+                }
+            }
+        }
+
+        let off_pivot = graph
+            .blocks()
+            .find(|b| b.epoch_block != Some(b.id))
+            .unwrap();
+        assert!(graph.epoch_execution_order(&off_pivot.hash).is_none());
+    }
+}
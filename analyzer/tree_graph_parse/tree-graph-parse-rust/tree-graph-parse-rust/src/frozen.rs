@@ -0,0 +1,65 @@
+//! Immutable, shareable handle to a finalized graph.
+
+use std::{ops::Deref, sync::Arc};
+
+#[cfg(feature = "native")]
+use rayon::prelude::*;
+
+#[cfg(feature = "native")]
+use crate::block::Block;
+use crate::graph::Graph;
+
+/// A finalized `Graph` behind an `Arc`.
+///
+/// Every lazily computed field of `Block` (`subtree_size_series`, `epoch_set`, ...) is filled in
+/// by `GraphComputer::finalize` before a `Graph` is handed out, and a `FrozenGraph` gives no
+/// mutable access, so clones can be queried from any number of threads. All `&self` query APIs
+/// of `Graph` are available through `Deref`.
+#[derive(Clone)]
+pub struct FrozenGraph(Arc<Graph>);
+
+// Sharing across threads relies on `Graph` holding no interior mutability.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Graph>();
+};
+
+#[cfg(feature = "native")]
+impl FrozenGraph {
+    pub fn load(file_or_path: &str) -> Result<Self, anyhow::Error> {
+        Ok(Graph::load(file_or_path)?.freeze())
+    }
+
+    /// Applies `f` to every pivot block except genesis in parallel, keeping pivot chain order.
+    pub fn par_map_pivot_chain<T: Send>(&self, f: impl Fn(&Graph, &Block) -> T + Sync) -> Vec<T> {
+        self.pivot_chain()
+            .into_par_iter()
+            .filter(|block| block.height > 0)
+            .map(|block| f(&self.0, block))
+            .collect()
+    }
+
+    /// Applies `f` to every block in parallel, returning the results in block id order.
+    pub fn par_map_blocks<T: Send>(&self, f: impl Fn(&Graph, &Block) -> T + Sync) -> Vec<T> {
+        self.0
+            .blocks()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map(|block| f(&self.0, block))
+            .collect()
+    }
+}
+
+impl Deref for FrozenGraph {
+    type Target = Graph;
+
+    fn deref(&self) -> &Graph { &self.0 }
+}
+
+impl From<Graph> for FrozenGraph {
+    fn from(graph: Graph) -> Self { FrozenGraph(Arc::new(graph)) }
+}
+
+impl Graph {
+    pub fn freeze(self) -> FrozenGraph { self.into() }
+}
@@ -0,0 +1,382 @@
+//! Synthetic tree-graph generator.
+//!
+//! Blocks are produced by a Poisson process and each block becomes visible to every miner after
+//! a propagation delay drawn from a [`LatencyModel`]. A new block picks its parent by the GHOST
+//! rule over the blocks visible at its generation time and references visible tips according to
+//! the [`RefereePolicy`]. The same seed always yields the same graph.
+
+use ethereum_types::{Address, H256};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::BTreeSet;
+
+use crate::{block::RawBlock, graph::Graph};
+
+#[derive(Debug, Clone, Copy)]
+pub enum LatencyModel {
+    Fixed(f64),
+    Uniform(f64, f64),
+    Exponential(f64),
+}
+
+impl LatencyModel {
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        match *self {
+            LatencyModel::Fixed(latency) => latency,
+            LatencyModel::Uniform(low, high) => rng.gen_range(low..=high),
+            LatencyModel::Exponential(mean) => -mean * (1. - rng.gen::<f64>()).ln(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RefereePolicy {
+    NoReferees,
+    AllTips,
+    /// Reference at most this many tips, preferring the most recent ones.
+    MaxTips(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct GenerateParams {
+    /// Blocks per second.
+    pub block_rate: f64,
+    pub latency: LatencyModel,
+    pub referee_policy: RefereePolicy,
+    /// Seconds of block generation.
+    pub duration: u64,
+    pub start_timestamp: u64,
+    pub seed: u64,
+    /// Relative mining power of each miner; miner `i` authors blocks as
+    /// `Address::from_low_u64_be(i + 1)`. Empty leaves blocks without author.
+    pub mining_power: Vec<f64>,
+}
+
+impl Default for GenerateParams {
+    fn default() -> Self {
+        GenerateParams {
+            block_rate: 2.,
+            latency: LatencyModel::Uniform(0.5, 2.),
+            referee_policy: RefereePolicy::MaxTips(200),
+            duration: 600,
+            start_timestamp: 1_700_000_000,
+            seed: 0,
+            mining_power: vec![],
+        }
+    }
+}
+
+struct GenBlock {
+    parent: usize,
+    height: u64,
+    generated_at: f64,
+    visible_at: f64,
+    author: Option<Address>,
+    children: Vec<usize>,
+    /// Visible blocks in the subtree, including itself.
+    visible_subtree: u64,
+    referenced: bool,
+}
+
+impl Graph {
+    pub fn generate(params: &GenerateParams) -> Result<Self, anyhow::Error> {
+        let mut rng = StdRng::seed_from_u64(params.seed);
+
+        let mut blocks = vec![GenBlock {
+            parent: 0,
+            height: 0,
+            generated_at: 0.,
+            visible_at: 0.,
+            author: None,
+            children: vec![],
+            visible_subtree: 1,
+            referenced: true,
+        }];
+        let mut referees: Vec<Vec<usize>> = vec![vec![]];
+        // Generated blocks not visible yet, and visible blocks nobody builds on or references.
+        let mut in_flight: Vec<usize> = vec![];
+        let mut tips: BTreeSet<usize> = BTreeSet::new();
+
+        let total_power: f64 = params.mining_power.iter().sum();
+        let mut now = 0.;
+        loop {
+            now += -(1. - rng.gen::<f64>()).ln() / params.block_rate;
+            if now >= params.duration as f64 {
+                break;
+            }
+
+            in_flight.sort_by(|a, b| blocks[*a].visible_at.total_cmp(&blocks[*b].visible_at));
+            let arrived = in_flight.partition_point(|idx| blocks[*idx].visible_at <= now);
+            for idx in in_flight.drain(..arrived).collect::<Vec<_>>() {
+                make_visible(&mut blocks, &referees[idx], &mut tips, idx);
+            }
+
+            let parent = ghost_tip(&blocks);
+            let mut block_referees: Vec<usize> =
+                tips.iter().copied().filter(|&t| t != parent).collect();
+            match params.referee_policy {
+                RefereePolicy::NoReferees => block_referees.clear(),
+                RefereePolicy::AllTips => {}
+                RefereePolicy::MaxTips(max) => {
+                    let skip = block_referees.len().saturating_sub(max);
+                    block_referees.drain(..skip);
+                }
+            }
+
+            let author = (total_power > 0.).then(|| {
+                let mut draw = rng.gen::<f64>() * total_power;
+                let miner = params
+                    .mining_power
+                    .iter()
+                    .position(|&power| {
+                        draw -= power;
+                        draw < 0.
+                    })
+                    .unwrap_or(params.mining_power.len() - 1);
+                Address::from_low_u64_be(miner as u64 + 1)
+            });
+
+            let idx = blocks.len();
+            blocks.push(GenBlock {
+                parent,
+                height: blocks[parent].height + 1,
+                generated_at: now,
+                visible_at: now + params.latency.sample(&mut rng).max(0.),
+                author,
+                children: vec![],
+                visible_subtree: 0,
+                referenced: false,
+            });
+            referees.push(block_referees);
+            in_flight.push(idx);
+        }
+
+        let hash_of = |idx: usize| H256::from_low_u64_be(idx as u64 + 1);
+        let mut raw_blocks = Vec::with_capacity(blocks.len());
+        raw_blocks.push(RawBlock::genesis_block(hash_of(0)));
+
+        // Ids follow the order in which an observer would log the blocks.
+        let mut log_order: Vec<usize> = (1..blocks.len()).collect();
+        log_order.sort_by(|a, b| blocks[*a].visible_at.total_cmp(&blocks[*b].visible_at));
+        for idx in log_order {
+            let block = &blocks[idx];
+            raw_blocks.push(RawBlock {
+                author: block.author,
+                ..RawBlock::new(
+                    block.height,
+                    hash_of(idx),
+                    hash_of(block.parent),
+                    referees[idx].iter().map(|&r| hash_of(r)).collect(),
+                    params.start_timestamp + block.generated_at as u64,
+                    params.start_timestamp + block.visible_at.ceil() as u64,
+                    0,
+                    0,
+                )
+            });
+        }
+
+        Self::from_raw_blocks(raw_blocks)
+    }
+}
+
+fn make_visible(
+    blocks: &mut [GenBlock], referees: &[usize], tips: &mut BTreeSet<usize>, idx: usize,
+) {
+    let parent = blocks[idx].parent;
+    blocks[parent].children.push(idx);
+    tips.remove(&parent);
+    for &r in referees {
+        blocks[r].referenced = true;
+        tips.remove(&r);
+    }
+    if blocks[idx].children.is_empty() && !blocks[idx].referenced {
+        tips.insert(idx);
+    }
+
+    let mut cursor = idx;
+    loop {
+        blocks[cursor].visible_subtree += 1;
+        if cursor == 0 {
+            break;
+        }
+        cursor = blocks[cursor].parent;
+    }
+}
+
+fn ghost_tip(blocks: &[GenBlock]) -> usize {
+    let mut cursor = 0;
+    loop {
+        let heaviest = blocks[cursor]
+            .children
+            .iter()
+            .copied()
+            .max_by_key(|&c| (blocks[c].visible_subtree, std::cmp::Reverse(c)));
+        match heaviest {
+            Some(child) => cursor = child,
+            None => return cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_params(seed: u64) -> GenerateParams {
+        GenerateParams {
+            duration: 300,
+            seed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_generate_is_deterministic() {
+        let a = Graph::generate(&small_params(7)).unwrap();
+        let b = Graph::generate(&small_params(7)).unwrap();
+        assert_eq!(a.blocks().count(), b.blocks().count());
+        for block in a.blocks() {
+            let other = b.get_block(&block.hash).unwrap();
+            assert_eq!(block.id, other.id);
+            assert_eq!(block.parent, other.parent);
+            assert_eq!(block.referees, other.referees);
+            assert_eq!(block.log_timestamp, other.log_timestamp);
+        }
+    }
+
+    #[test]
+    fn test_finalize_invariants() {
+        let graph = Graph::generate(&small_params(1)).unwrap();
+        let block_count = graph.blocks().count() as u64;
+        assert_eq!(graph.genesis_block().subtree_size, block_count);
+
+        for block in graph.blocks() {
+            let children_sum: u64 = block
+                .children
+                .iter()
+                .map(|&id| graph.block(id).subtree_size)
+                .sum();
+            assert_eq!(block.subtree_size, children_sum + 1);
+            assert!(block.past_set_size >= block.height);
+        }
+
+        let pivot = graph.pivot_chain();
+        let epoch_total: usize = pivot.iter().skip(1).map(|b| b.epoch_size()).sum();
+        assert!(epoch_total as u64 <= block_count - 1);
+        for pair in pivot.windows(2) {
+            assert_eq!(pair[0].max_child(), Some(pair[1].id));
+        }
+    }
+
+    #[test]
+    fn test_confirmation_on_generated_graph() {
+        let graph = Graph::generate(&GenerateParams {
+            latency: LatencyModel::Fixed(0.2),
+            ..small_params(3)
+        })
+        .unwrap();
+        let (avg_time, blocks) = graph.avg_confirm_time(20, 1e-6);
+        assert!(blocks > 0);
+        assert!(avg_time > 0.);
+
+        let slow = Graph::generate(&GenerateParams {
+            latency: LatencyModel::Fixed(0.2),
+            ..small_params(3)
+        })
+        .unwrap()
+        .avg_confirm_time(30, 1e-6);
+        assert!(slow.0 > avg_time);
+    }
+
+    #[test]
+    fn test_confirm_time_vs_risk_matches_avg() {
+        let graph = Graph::generate(&GenerateParams {
+            latency: LatencyModel::Fixed(0.2),
+            ..small_params(4)
+        })
+        .unwrap();
+        let risks = [1e-4, 1e-6, 1e-8];
+        let stats = graph.confirm_time_vs_risk(20, &risks);
+        for (stat, &risk) in stats.iter().zip(&risks) {
+            let (avg_time, blocks) = graph.avg_confirm_time(20, risk);
+            assert_eq!(stat.block_cnt, blocks);
+            assert!((stat.avg - avg_time).abs() < 1e-6 * avg_time);
+            assert!(stat.p50 <= stat.p90 && stat.p90 <= stat.p99);
+        }
+        assert!(stats[0].avg < stats[1].avg && stats[1].avg < stats[2].avg);
+    }
+
+    #[test]
+    fn test_confirm_times_among_keeps_only_accepted_blocks() {
+        let graph = Graph::generate(&small_params(4)).unwrap();
+        let risks = [1e-6];
+        let all = graph.confirm_times(20, &risks);
+        assert_eq!(graph.confirm_times_among(20, &risks, &|_| true), all);
+        assert!(graph.confirm_times_among(20, &risks, &|_| false)[0].is_empty());
+
+        let even = graph.confirm_times_among(20, &risks, &|b| b.id % 2 == 0);
+        let odd = graph.confirm_times_among(20, &risks, &|b| b.id % 2 == 1);
+        let mut split = [even[0].clone(), odd[0].clone()].concat();
+        let mut all = all[0].clone();
+        split.sort_unstable();
+        all.sort_unstable();
+        assert_eq!(split, all);
+        assert!(!even[0].is_empty() && !odd[0].is_empty());
+    }
+
+    #[test]
+    fn test_series_accessors_match_confirmation() {
+        let graph = Graph::generate(&small_params(5)).unwrap();
+        let block = graph.pivot_chain()[3];
+        let (offset, m, k, _) = graph.confirmation_risk(block, 20, 1e-6).unwrap();
+        let confirm_time = block.timestamp + offset;
+        let parent = graph.get_parent(block).unwrap();
+
+        let total = graph.total_blocks_at(confirm_time).unwrap();
+        assert_eq!(total + 1 - parent.past_set_size, m);
+        assert_eq!(
+            graph.adversary_advantage_at(block, confirm_time),
+            Some(k as i64)
+        );
+
+        let series = graph.block_series(block);
+        assert!(series.windows(2).all(|w| w[0].timestamp < w[1].timestamp));
+        let last = series.last().unwrap();
+        assert_eq!(last.subtree_size, Some(block.subtree_size));
+        assert_eq!(
+            last.total_blocks,
+            Some(graph.genesis_block().subtree_size - 1)
+        );
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let graph = Graph::generate(&small_params(6)).unwrap();
+        let stats = graph.memory_stats();
+        assert_eq!(stats.block_count, graph.blocks().count());
+        assert!(stats.time_series_points >= stats.block_count - 1);
+        assert!(stats.past_set_bitmap_bytes >= stats.block_count / 8);
+        assert!(stats.peak_bytes() > stats.total_bytes());
+        assert!(stats.to_string().contains("MiB"));
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_frozen_graph_parallel_queries() {
+        let graph = Graph::generate(&small_params(7)).unwrap().freeze();
+        let expected: Vec<_> = graph
+            .pivot_chain()
+            .into_iter()
+            .filter(|b| b.height > 0)
+            .map(|b| graph.confirmation_risk(b, 20, 1e-6))
+            .collect();
+
+        let parallel = graph.par_map_pivot_chain(|g, b| g.confirmation_risk(b, 20, 1e-6));
+        assert_eq!(parallel, expected);
+
+        let shared = graph.clone();
+        let count = std::thread::spawn(move || shared.par_map_blocks(|_, b| b.id).len())
+            .join()
+            .unwrap();
+        assert_eq!(count, graph.blocks().count());
+    }
+}
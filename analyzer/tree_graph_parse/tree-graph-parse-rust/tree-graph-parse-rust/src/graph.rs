@@ -1,87 +1,295 @@
-use anyhow::bail;
+use anyhow::{bail, Context};
 use ethereum_types::H256;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs::File,
     io::{BufRead, Write},
+    ops::RangeBounds,
 };
 
 use crate::{
-    block::Block, graph_computer::GraphComputer, load, math::normal_confirmation_risk,
+    block::{Block, RawBlock},
+    graph_computer::GraphComputer,
+    honest_rate::HonestRate,
+    load_options::GraphLoadOptions,
+    math::{HiddenMaliciousRandomWalk, RiskModel, RiskTable},
+    pivot_rule::{FirstLogged, PivotRule},
+    progress::{LoadProgress, Stopwatch, PARSE_REPORT_INTERVAL},
     utils::time_series::TimeSeries,
 };
 
+pub(crate) const NEW_BLOCK_MARKER: &str = "new block inserted into graph";
+
+enum LogEntry {
+    Genesis(H256),
+    Block(RawBlock),
+}
+
 #[allow(dead_code)]
 pub struct Graph {
-    pub(super) block_map: HashMap<H256, Block>,
-    pub(super) root_hash: H256,
+    /// Indexed by block id; genesis is at 0.
+    pub(super) blocks: Vec<Block>,
+    pub(super) id_map: HashMap<H256, usize>,
+    pub(super) height_index: BTreeMap<u64, Vec<usize>>,
+    pub(super) timestamp_index: BTreeMap<u64, Vec<usize>>,
+    /// Peak bytes held by the past-set bitmaps during finalize; they are dropped afterwards.
+    pub(super) past_set_bitmap_bytes: usize,
 }
 
+#[cfg(feature = "native")]
+use crate::{graph_computer::SinglePassInterner, load};
+
 impl Graph {
+    #[cfg(feature = "native")]
     pub fn load(file_or_path: &str) -> Result<Self, anyhow::Error> {
+        Self::load_with_progress(file_or_path, &mut |_| {})
+    }
+
+    /// `load`, picking the pivot chain by `rule` instead of `FirstLogged`.
+    #[cfg(feature = "native")]
+    pub fn load_with_rule(file_or_path: &str, rule: &dyn PivotRule) -> Result<Self, anyhow::Error> {
+        let start = Stopwatch::start();
         let reader = load::open_conflux_log(file_or_path)?;
+        let raw_blocks = Self::parse_blocks(reader, &mut |_| {})?;
+        Self::finish_load(raw_blocks, start, rule, &mut |_| {})
+    }
 
-        let mut root_hash: Option<H256> = None;
-        let mut block_map: HashMap<H256, Block> = Default::default();
+    /// `load`, reporting parsing progress and the duration of every finalize pass.
+    #[cfg(feature = "native")]
+    pub fn load_with_progress(
+        file_or_path: &str, progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<Self, anyhow::Error> {
+        Self::load_with_options(file_or_path, &GraphLoadOptions::default(), progress)
+    }
 
-        let mut next_id = 1;
+    /// `load_with_progress`, leaving out the blocks `options` does not accept.
+    #[cfg(feature = "native")]
+    pub fn load_with_options(
+        file_or_path: &str, options: &GraphLoadOptions, progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<Self, anyhow::Error> {
+        let start = Stopwatch::start();
+        let reader = load::open_conflux_log(file_or_path)?;
+        let raw_blocks = options.apply(Self::parse_blocks(reader, progress)?);
+        Self::finish_load(raw_blocks, start, &FirstLogged, progress)
+    }
 
-        for line in reader.lines() {
-            let line = line?;
-            if !line.contains("new block inserted into graph") {
-                continue;
-            }
-            let block = Block::parse_log_line(&line, next_id);
-            next_id += 1;
+    /// `load` in a single pass that interns every block as soon as it is parsed, instead of
+    /// parsing the whole log first. The parsed form of the blocks, with their referee hashes, is
+    /// never held beside the interned graph, except for blocks that name a block logged after
+    /// them. Finalizing still needs every block, so peak memory is that of the finished graph and
+    /// grows with the whole log, not with its live frontier.
+    #[cfg(feature = "native")]
+    pub fn load_single_pass(
+        file_or_path: &str, progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<Self, anyhow::Error> {
+        let start = Stopwatch::start();
+        let reader = load::open_conflux_log(file_or_path)?;
+        let mut interner = SinglePassInterner::new();
+        let blocks = Self::parse_log(reader, progress, &mut |entry| match entry {
+            LogEntry::Genesis(root_hash) => interner.set_genesis(root_hash),
+            LogEntry::Block(block) => interner.push(block),
+        })?;
+        progress(LoadProgress::Parsed {
+            blocks,
+            elapsed: start.elapsed(),
+        });
 
-            if block.height != 1 {
-                block_map.insert(block.hash, block);
+        let graph = interner.finish()?.finalize_with(&FirstLogged, progress)?;
+        progress(LoadProgress::Done {
+            blocks,
+            elapsed: start.elapsed(),
+        });
+        Ok(graph)
+    }
+
+    /// `load` from log content already in memory, e.g. a log uploaded to a browser. Lines other
+    /// than block insertions are skipped, so a full conflux log works as well.
+    pub fn from_log(reader: impl BufRead) -> Result<Self, anyhow::Error> {
+        Self::from_log_with_options(reader, &GraphLoadOptions::default())
+    }
+
+    /// `from_log`, leaving out the blocks `options` does not accept.
+    pub fn from_log_with_options(
+        reader: impl BufRead, options: &GraphLoadOptions,
+    ) -> Result<Self, anyhow::Error> {
+        let start = Stopwatch::start();
+        let raw_blocks = options.apply(Self::parse_blocks(reader, &mut |_| {})?);
+        Self::finish_load(raw_blocks, start, &FirstLogged, &mut |_| {})
+    }
+
+    /// Blocks in log order, preceded by genesis.
+    fn parse_blocks(
+        reader: impl BufRead, progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<Vec<RawBlock>, anyhow::Error> {
+        // Placeholder for genesis, filled once the first height 1 block names it.
+        let mut raw_blocks = vec![RawBlock::default()];
+        Self::parse_log(reader, progress, &mut |entry| match entry {
+            LogEntry::Genesis(root_hash) => raw_blocks[0] = RawBlock::genesis_block(root_hash),
+            LogEntry::Block(block) => raw_blocks.push(block),
+        })?;
+        Ok(raw_blocks)
+    }
+
+    /// Passes the blocks of the log to `visit` in log order. Genesis is passed when the first
+    /// height 1 block names it, before that block. Returns the number of blocks, genesis
+    /// included.
+    fn parse_log(
+        mut reader: impl BufRead, progress: &mut dyn FnMut(LoadProgress),
+        visit: &mut dyn FnMut(LogEntry),
+    ) -> Result<u64, anyhow::Error> {
+        let mut root_hash: Option<H256> = None;
+        let mut blocks = 1; // genesis
+        let mut lines = 0;
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            lines += 1;
+            if lines % PARSE_REPORT_INTERVAL == 0 {
+                progress(LoadProgress::Parsing { lines, blocks });
+            }
+            if !line.contains(NEW_BLOCK_MARKER) {
                 continue;
             }
+            let block = RawBlock::parse_log_line(&line)
+                .with_context(|| format!("malformed block at line {}", lines))?;
 
-            let Some(parent_hash) = block.parent_hash else {
-                bail!("block {:?} has no parent hash", block.hash)
-            };
+            if block.height == 1 {
+                let Some(parent_hash) = block.parent_hash else {
+                    bail!("block {:?} has no parent hash", block.hash)
+                };
 
-            match root_hash.as_ref() {
-                Some(&h) if h != parent_hash => {
-                    bail!("Inconsistent genesis hash");
-                }
-                None => {
-                    root_hash = Some(parent_hash);
-                    block_map.insert(parent_hash, Block::genesis_block(parent_hash));
+                match root_hash.as_ref() {
+                    Some(&h) if h != parent_hash => {
+                        bail!("Inconsistent genesis hash");
+                    }
+                    None => {
+                        root_hash = Some(parent_hash);
+                        visit(LogEntry::Genesis(parent_hash));
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
 
-            block_map.insert(block.hash, block);
+            blocks += 1;
+            visit(LogEntry::Block(block));
         }
 
-        let Some(root_hash) = root_hash else {
+        if root_hash.is_none() {
             bail!("No root hash");
-        };
+        }
+
+        progress(LoadProgress::Parsing { lines, blocks });
+        Ok(blocks)
+    }
 
-        let unready_graph = GraphComputer::new(Self {
-            block_map,
-            root_hash,
+    fn finish_load(
+        raw_blocks: Vec<RawBlock>, start: Stopwatch, rule: &dyn PivotRule,
+        progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<Self, anyhow::Error> {
+        let blocks = raw_blocks.len() as u64;
+        progress(LoadProgress::Parsed {
+            blocks,
+            elapsed: start.elapsed(),
         });
-        unready_graph.finalize()
+
+        let graph = GraphComputer::intern(raw_blocks)?.finalize_with(rule, progress)?;
+        progress(LoadProgress::Done {
+            blocks,
+            elapsed: start.elapsed(),
+        });
+        Ok(graph)
+    }
+
+    /// Builds and finalizes a graph from blocks in log order, genesis first. Block ids follow
+    /// this order; later duplicates of a hash are ignored.
+    pub fn from_raw_blocks(raw_blocks: Vec<RawBlock>) -> Result<Self, anyhow::Error> {
+        Self::from_raw_blocks_with_rule(raw_blocks, &FirstLogged)
+    }
+
+    /// `from_raw_blocks`, picking the pivot chain by `rule`.
+    pub fn from_raw_blocks_with_rule(
+        raw_blocks: Vec<RawBlock>, rule: &dyn PivotRule,
+    ) -> Result<Self, anyhow::Error> {
+        GraphComputer::intern(raw_blocks)?.finalize_with(rule, &mut |_| {})
+    }
+
+    pub(super) fn build_indices(&mut self) {
+        for block in &self.blocks {
+            self.height_index
+                .entry(block.height)
+                .or_default()
+                .push(block.id);
+            self.timestamp_index
+                .entry(block.timestamp)
+                .or_default()
+                .push(block.id);
+        }
     }
 
-    pub fn blocks(&self) -> impl Iterator<Item = &Block> + '_ { self.block_map.values() }
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> + '_ { self.blocks.iter() }
+
+    pub fn block_count(&self) -> usize { self.blocks.len() }
 
-    pub fn genesis_block(&self) -> &Block { self.block_map.get(&self.root_hash).unwrap() }
+    pub fn genesis_block(&self) -> &Block { &self.blocks[0] }
 
-    pub fn root_hash(&self) -> H256 { self.root_hash }
+    pub fn root_hash(&self) -> H256 { self.blocks[0].hash }
 
-    pub fn get_block(&self, hash: &H256) -> Option<&Block> { self.block_map.get(hash) }
+    pub fn get_block(&self, hash: &H256) -> Option<&Block> {
+        self.id_map.get(hash).map(|&id| &self.blocks[id])
+    }
 
     pub fn get_block_mut(&mut self, hash: &H256) -> Option<&mut Block> {
-        self.block_map.get_mut(hash)
+        self.id_map.get(hash).map(|&id| &mut self.blocks[id])
+    }
+
+    /// Panics if `id` is not a block of this graph.
+    pub fn block(&self, id: usize) -> &Block { &self.blocks[id] }
+
+    pub fn get_block_by_id(&self, id: usize) -> Option<&Block> { self.blocks.get(id) }
+
+    pub fn block_id(&self, hash: &H256) -> Option<usize> { self.id_map.get(hash).copied() }
+
+    /// Panics if `id` is not a block of this graph.
+    pub fn hash_of(&self, id: usize) -> H256 { self.blocks[id].hash }
+
+    pub fn blocks_at_height(&self, height: u64) -> impl Iterator<Item = &Block> + '_ {
+        self.height_index
+            .get(&height)
+            .into_iter()
+            .flatten()
+            .map(|&id| self.block(id))
+    }
+
+    pub fn blocks_in_height_range(
+        &self, range: impl RangeBounds<u64>,
+    ) -> impl Iterator<Item = &Block> + '_ {
+        self.height_index
+            .range(range)
+            .flat_map(|(_, ids)| ids)
+            .map(|&id| self.block(id))
+    }
+
+    pub fn blocks_in_time_range(
+        &self, range: impl RangeBounds<u64>,
+    ) -> impl Iterator<Item = &Block> + '_ {
+        self.timestamp_index
+            .range(range)
+            .flat_map(|(_, ids)| ids)
+            .map(|&id| self.block(id))
+    }
+
+    pub fn max_height(&self) -> u64 {
+        self.height_index
+            .last_key_value()
+            .map_or(0, |(height, _)| *height)
     }
 
     pub fn get_parent(&self, block: &Block) -> Option<&Block> {
-        block.parent_hash.map(|h| self.get_block(&h).unwrap())
+        block.parent.map(|id| self.block(id))
     }
 
     pub fn pivot_chain(&self) -> Vec<&Block> {
@@ -90,21 +298,17 @@ impl Graph {
 
         loop {
             chain.push(current);
-            let Some(child_hash) = current.max_child() else {
+            let Some(child_id) = current.max_child() else {
                 break;
             };
-            current = self.block_map.get(&child_hash).unwrap();
+            current = self.block(child_id);
         }
 
         chain
     }
 
     pub fn get_referees(&self, block: &Block) -> Vec<&Block> {
-        block
-            .referee_hashes
-            .iter()
-            .map(|hash| self.get_block(hash).unwrap())
-            .collect()
+        block.referees.iter().map(|&id| self.block(id)).collect()
     }
 
     pub fn epoch_span(&self, block: &Block) -> u64 {
@@ -122,6 +326,13 @@ impl Graph {
     }
 
     pub fn avg_confirm_time(&self, adv_percent: usize, risk_threshold: f64) -> (f64, u64) {
+        self.avg_confirm_time_with(&HiddenMaliciousRandomWalk, adv_percent, risk_threshold)
+    }
+
+    /// `avg_confirm_time` under the given adversary model.
+    pub fn avg_confirm_time_with(
+        &self, model: &dyn RiskModel, adv_percent: usize, risk_threshold: f64,
+    ) -> (f64, u64) {
         let mut total_confirm_time = 0.;
         let mut block_cnt = 0;
         for block in self.pivot_chain() {
@@ -130,7 +341,7 @@ impl Graph {
             }
 
             let Some((time_elapsed, ..)) =
-                self.confirmation_risk(block, adv_percent, risk_threshold)
+                self.confirmation_risk_with(model, block, adv_percent, risk_threshold)
             else {
                 continue;
             };
@@ -145,8 +356,8 @@ impl Graph {
     fn iter_epochs(&self, block: &Block, mut visitor: impl FnMut(&Block)) {
         assert!(block.epoch_block.is_some());
         if let Some(set) = block.epoch_set.as_ref() {
-            for h in set.iter() {
-                visitor(self.get_block(h).unwrap());
+            for &id in set.iter() {
+                visitor(self.block(id));
             }
         }
         visitor(block)
@@ -154,9 +365,9 @@ impl Graph {
 
     pub fn export_edges(&self, filename: &str) -> Result<(), anyhow::Error> {
         let mut edges = Vec::new();
-        for (_, block) in &self.block_map {
-            if let Some(parent_hash) = &block.parent_hash {
-                edges.push((parent_hash.clone(), block.hash.clone()));
+        for block in &self.blocks {
+            if let Some(parent) = block.parent {
+                edges.push((self.hash_of(parent), block.hash));
             }
         }
 
@@ -169,60 +380,351 @@ impl Graph {
 
     pub fn export_indices(&self, filename: &str) -> Result<(), anyhow::Error> {
         let mut file = File::create(filename)?;
-        for (idx, hash) in self.block_map.keys().enumerate() {
-            writeln!(file, "{},{}", hash, idx)?;
+        for block in &self.blocks {
+            writeln!(file, "{},{}", block.hash, block.id)?;
         }
         Ok(())
     }
 }
 
+pub use confirmation::ConfirmTimeStats;
+
 mod confirmation {
     use super::*;
 
+    /// Confirmation times of every block under one risk threshold, see
+    /// `Graph::confirm_time_vs_risk`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ConfirmTimeStats {
+        pub risk_threshold: f64,
+        /// Same value as `Graph::avg_confirm_time`.
+        pub avg: f64,
+        pub p50: u64,
+        pub p90: u64,
+        pub p99: u64,
+        pub block_cnt: u64,
+    }
+
+    impl ConfirmTimeStats {
+        fn from_times(risk_threshold: f64, mut times: Vec<u64>) -> Self {
+            times.sort_unstable();
+            let percentile = |p: usize| match times.len() {
+                0 => 0,
+                len => times[(len * p / 100).min(len - 1)],
+            };
+            ConfirmTimeStats {
+                risk_threshold,
+                avg: times.iter().sum::<u64>() as f64 / times.len() as f64,
+                p50: percentile(50),
+                p90: percentile(90),
+                p99: percentile(99),
+                block_cnt: times.len() as u64,
+            }
+        }
+    }
+
+    /// The `f32` risk series is clamped here; lower thresholds switch to the log-domain path.
+    const LINEAR_RISK_FLOOR: f64 = 1e-12;
+    const PRECISE_RISK_FLOOR: f64 = 1e-30;
+
     impl Graph {
         pub fn confirmation_risk(
             &self, block: &Block, adv_percent: usize, risk_threshold: f64,
         ) -> Option<(u64, u64, u64, f64)> {
-            let &(confirm_time_offset, risk) = self
-                .confirmation_risk_series(block, adv_percent)
-                .iter()
-                .find(|(_, risk)| *risk < risk_threshold as f32)?;
+            self.confirmation_risk_with(
+                &HiddenMaliciousRandomWalk,
+                block,
+                adv_percent,
+                risk_threshold,
+            )
+        }
+
+        /// `confirmation_risk` under the given adversary model.
+        pub fn confirmation_risk_with(
+            &self, model: &dyn RiskModel, block: &Block, adv_percent: usize, risk_threshold: f64,
+        ) -> Option<(u64, u64, u64, f64)> {
+            self.confirmation_risks_with(model, block, adv_percent, &[risk_threshold])
+                .pop()
+                .unwrap()
+        }
 
-            let confirm_time = block.timestamp + confirm_time_offset;
+        /// `confirmation_risk` for every threshold of `risk_thresholds`, in that order, computing
+        /// the risk series of the block once.
+        pub fn confirmation_risks(
+            &self, block: &Block, adv_percent: usize, risk_thresholds: &[f64],
+        ) -> Vec<Option<(u64, u64, u64, f64)>> {
+            self.confirmation_risks_with(
+                &HiddenMaliciousRandomWalk,
+                block,
+                adv_percent,
+                risk_thresholds,
+            )
+        }
 
-            let parent = self.get_parent(block).unwrap();
+        /// `confirmation_risks` under the given adversary model.
+        pub fn confirmation_risks_with(
+            &self, model: &dyn RiskModel, block: &Block, adv_percent: usize,
+            risk_thresholds: &[f64],
+        ) -> Vec<Option<(u64, u64, u64, f64)>> {
+            let needs_precise = risk_thresholds.iter().any(|&risk| risk < LINEAR_RISK_FLOOR);
+            let needs_linear = risk_thresholds
+                .iter()
+                .any(|&risk| risk >= LINEAR_RISK_FLOOR);
+            let series =
+                needs_linear.then(|| self.confirmation_risk_series_with(model, block, adv_percent));
+            let precise_series = needs_precise
+                .then(|| self.confirmation_risk_series_precise_with(model, block, adv_percent));
 
+            let parent = self.get_parent(block).unwrap();
             let total_blocks = self.genesis_block().subtree_size_series.as_ref().unwrap();
             let sib_adv_blocks = parent.subtree_adv_series.as_ref().unwrap();
 
-            let total_block = *total_blocks.at(confirm_time).unwrap() as u64;
-            let m = total_block + 1 - parent.past_set_size as u64;
-            let k = *sib_adv_blocks.at(confirm_time).unwrap() as u64;
-            Some((confirm_time_offset, m, k, risk as f64))
+            risk_thresholds
+                .iter()
+                .map(|&risk_threshold| {
+                    let (confirm_time_offset, risk) = if risk_threshold < LINEAR_RISK_FLOOR {
+                        *precise_series
+                            .as_ref()
+                            .unwrap()
+                            .iter()
+                            .find(|(_, risk)| *risk < risk_threshold)?
+                    } else {
+                        series
+                            .as_ref()
+                            .unwrap()
+                            .iter()
+                            .find(|(_, risk)| *risk < risk_threshold as f32)
+                            .map(|&(offset, risk)| (offset, risk as f64))?
+                    };
+
+                    let confirm_time = block.timestamp + confirm_time_offset;
+                    let total_block = *total_blocks.at(confirm_time).unwrap() as u64;
+                    let m = total_block + 1 - parent.past_set_size as u64;
+                    let k = *sib_adv_blocks.at(confirm_time).unwrap() as u64;
+                    Some((confirm_time_offset, m, k, risk))
+                })
+                .collect()
         }
 
         pub fn confirmation_risk_series(
             &self, block: &Block, adv_percent: usize,
         ) -> Vec<(u64, f32)> {
+            self.confirmation_risk_series_with(&HiddenMaliciousRandomWalk, block, adv_percent)
+        }
+
+        pub fn confirmation_risk_series_with(
+            &self, model: &dyn RiskModel, block: &Block, adv_percent: usize,
+        ) -> Vec<(u64, f32)> {
+            let risk_inputs = self.risk_inputs(block);
+            let risk_table = RiskTable::build_with(
+                model,
+                adv_percent,
+                risk_inputs.iter().filter_map(|(_, x)| *x),
+            );
+            let confirmation_series = risk_inputs.map(|input| match input {
+                None => 1.,
+                Some((m, n)) => risk_table.get(m, n).max(LINEAR_RISK_FLOOR as f32),
+            });
+            Self::trim_risk_series(block, confirmation_series)
+        }
+
+        /// Same as `confirmation_risk_series`, evaluated in the log domain so that risks down
+        /// to `1e-30` are resolved.
+        pub fn confirmation_risk_series_precise(
+            &self, block: &Block, adv_percent: usize,
+        ) -> Vec<(u64, f64)> {
+            self.confirmation_risk_series_precise_with(
+                &HiddenMaliciousRandomWalk,
+                block,
+                adv_percent,
+            )
+        }
+
+        pub fn confirmation_risk_series_precise_with(
+            &self, model: &dyn RiskModel, block: &Block, adv_percent: usize,
+        ) -> Vec<(u64, f64)> {
+            let confirmation_series = self.risk_inputs(block).map(|input| match input {
+                None => 1.,
+                Some((m, n)) => model
+                    .precise_confirmation_risk(adv_percent, m, n)
+                    .max(PRECISE_RISK_FLOOR),
+            });
+            Self::trim_risk_series(block, confirmation_series)
+        }
+
+        /// Same as `confirmation_risk_series_precise`, with the adversary's share following the
+        /// honest block rate instead of staying at `adv_percent`, see
+        /// `HonestRate::adv_percent_at`.
+        pub fn confirmation_risk_series_varying(
+            &self, block: &Block, adv_percent: usize, rate: &HonestRate,
+        ) -> Vec<(u64, f64)> {
+            self.confirmation_risk_series_varying_with(
+                &HiddenMaliciousRandomWalk,
+                block,
+                adv_percent,
+                rate,
+            )
+        }
+
+        pub fn confirmation_risk_series_varying_with(
+            &self, model: &dyn RiskModel, block: &Block, adv_percent: usize, rate: &HonestRate,
+        ) -> Vec<(u64, f64)> {
+            let confirmation_series = TimeSeries::tuple_cartesian_map(
+                &self.risk_inputs(block),
+                &rate.adv_percent_series(adv_percent),
+                |input, adv_percent| {
+                    Some(match *input? {
+                        None => 1.,
+                        Some((m, n)) => model
+                            .precise_confirmation_risk(*adv_percent?, m, n)
+                            .max(PRECISE_RISK_FLOOR),
+                    })
+                },
+            );
+            Self::trim_risk_series(block, confirmation_series)
+        }
+
+        /// Seconds after the timestamp of `block` until its risk under a varying honest block
+        /// rate drops below `risk_threshold`, and the risk then.
+        pub fn confirmation_risk_varying(
+            &self, block: &Block, adv_percent: usize, risk_threshold: f64, rate: &HonestRate,
+        ) -> Option<(u64, f64)> {
+            self.confirmation_risk_series_varying(block, adv_percent, rate)
+                .into_iter()
+                .find(|(_, risk)| *risk < risk_threshold)
+        }
+
+        /// Confirmation time statistics for every threshold in `risks`.
+        ///
+        /// The risk series of each pivot block is computed once and shared by all thresholds,
+        /// instead of once per `avg_confirm_time` call. The confirmation time of a block is the
+        /// time for its epoch's pivot block to be confirmed, plus how much earlier it was
+        /// generated than the pivot block.
+        pub fn confirm_time_vs_risk(
+            &self, adv_percent: usize, risks: &[f64],
+        ) -> Vec<ConfirmTimeStats> {
+            self.confirm_time_vs_risk_among(adv_percent, risks, &|_| true)
+        }
+
+        /// `confirm_time_vs_risk` over the blocks `keep` accepts only, e.g. those of a
+        /// `BlockSet`.
+        pub fn confirm_time_vs_risk_among(
+            &self, adv_percent: usize, risks: &[f64], keep: &dyn Fn(&Block) -> bool,
+        ) -> Vec<ConfirmTimeStats> {
+            risks
+                .iter()
+                .zip(self.confirm_times_among(adv_percent, risks, keep))
+                .map(|(&risk_threshold, times)| ConfirmTimeStats::from_times(risk_threshold, times))
+                .collect()
+        }
+
+        /// Confirmation time of every block confirmed under each threshold in `risks`, the
+        /// samples behind `confirm_time_vs_risk`.
+        pub fn confirm_times(&self, adv_percent: usize, risks: &[f64]) -> Vec<Vec<u64>> {
+            self.confirm_times_among(adv_percent, risks, &|_| true)
+        }
+
+        /// `confirm_times` of the blocks `keep` accepts only. Epochs without any such block are
+        /// skipped before their risk series is computed.
+        pub fn confirm_times_among(
+            &self, adv_percent: usize, risks: &[f64], keep: &dyn Fn(&Block) -> bool,
+        ) -> Vec<Vec<u64>> {
+            let mut times: Vec<Vec<u64>> = vec![vec![]; risks.len()];
+            self.visit_confirm_times(adv_percent, risks, keep, &mut |i, _, time| {
+                times[i].push(time)
+            });
+            times
+        }
+
+        /// Hash and confirmation time of every block `keep` accepts that is confirmed under each
+        /// threshold in `risks`, e.g. to match the same blocks across the graphs of several nodes.
+        pub fn confirm_times_by_block(
+            &self, adv_percent: usize, risks: &[f64], keep: &dyn Fn(&Block) -> bool,
+        ) -> Vec<Vec<(H256, u64)>> {
+            let mut times = vec![vec![]; risks.len()];
+            self.visit_confirm_times(adv_percent, risks, keep, &mut |i, b, time| {
+                times[i].push((b.hash, time))
+            });
+            times
+        }
+
+        /// Calls `visit` with the index in `risks`, the block and its confirmation time for
+        /// every confirmed block `keep` accepts.
+        fn visit_confirm_times(
+            &self, adv_percent: usize, risks: &[f64], keep: &dyn Fn(&Block) -> bool,
+            visit: &mut dyn FnMut(usize, &Block, u64),
+        ) {
+            let needs_precise = risks.iter().any(|&risk| risk < LINEAR_RISK_FLOOR);
+            let needs_linear = risks.iter().any(|&risk| risk >= LINEAR_RISK_FLOOR);
+
+            for block in self.pivot_chain() {
+                if block.height == 0 {
+                    continue;
+                }
+                let mut kept = 0;
+                self.iter_epochs(block, |b| kept += keep(b) as usize);
+                if kept == 0 {
+                    continue;
+                }
+
+                let series =
+                    needs_linear.then(|| self.confirmation_risk_series(block, adv_percent));
+                let precise_series = needs_precise
+                    .then(|| self.confirmation_risk_series_precise(block, adv_percent));
+
+                for (i, &risk_threshold) in risks.iter().enumerate() {
+                    let confirm_time_offset = if risk_threshold < LINEAR_RISK_FLOOR {
+                        precise_series
+                            .as_ref()
+                            .unwrap()
+                            .iter()
+                            .find(|(_, risk)| *risk < risk_threshold)
+                            .map(|(offset, _)| *offset)
+                    } else {
+                        series
+                            .as_ref()
+                            .unwrap()
+                            .iter()
+                            .find(|(_, risk)| *risk < risk_threshold as f32)
+                            .map(|(offset, _)| *offset)
+                    };
+                    let Some(confirm_time_offset) = confirm_time_offset else {
+                        continue;
+                    };
+
+                    self.iter_epochs(block, |b| {
+                        if keep(b) {
+                            visit(i, b, confirm_time_offset + block.timestamp - b.timestamp)
+                        }
+                    });
+                }
+            }
+        }
+
+        /// (m, n) at every moment, or None if the block has no advantage.
+        fn risk_inputs(&self, block: &Block) -> TimeSeries<Option<(usize, usize)>> {
             let parent = self.get_parent(block).unwrap();
             let total_blocks = self.genesis_block().subtree_size_series.as_ref().unwrap();
             let sib_adv_blocks = parent.subtree_adv_series.as_ref().unwrap();
-            let mut confirmation_series =
-                TimeSeries::tuple_cartesian_map(total_blocks, sib_adv_blocks, |total, sib_adv| {
-                    if *sib_adv? <= 0 {
-                        return Some(1.);
-                    }
-                    let m = *total? as usize + 1 - parent.past_set_size as usize;
-                    let n = *sib_adv? as usize;
-                    Some(normal_confirmation_risk(adv_percent, m, n).max(1e-12))
-                });
+            TimeSeries::tuple_cartesian_map(total_blocks, sib_adv_blocks, |total, sib_adv| {
+                if *sib_adv? <= 0 {
+                    return Some(None);
+                }
+                let m = *total? as usize + 1 - parent.past_set_size as usize;
+                let n = *sib_adv? as usize;
+                Some(Some((m, n)))
+            })
+        }
 
+        fn trim_risk_series<T: Clone + PartialEq + PartialOrd + From<f32>>(
+            block: &Block, mut confirmation_series: TimeSeries<T>,
+        ) -> Vec<(u64, T)> {
             confirmation_series.reduce();
 
             confirmation_series
                 .iter()
-                .skip_while(|(_, risk)| **risk >= 0.5)
-                .map(|(ts, risk)| (ts - block.timestamp, *risk))
+                .skip_while(|(_, risk)| **risk >= T::from(0.5))
+                .map(|(ts, risk)| (ts - block.timestamp, risk.clone()))
                 .collect()
         }
     }
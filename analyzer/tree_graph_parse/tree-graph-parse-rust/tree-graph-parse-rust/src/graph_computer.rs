@@ -1,102 +1,151 @@
-use std::collections::{BTreeSet, HashMap};
+use std::{borrow::Cow, collections::HashMap};
 
-use anyhow::bail;
+use anyhow::anyhow;
 use ethereum_types::H256;
 
 use crate::{
-    block::Block,
+    block::{Block, RawBlock},
     graph::Graph,
+    pivot_rule::{FirstLogged, PivotRule},
+    progress::{LoadProgress, Stopwatch},
     utils::{bitmap::Bitmap, time_series::TimeSeries},
 };
 
 pub struct GraphComputer(Graph);
 
 impl GraphComputer {
-    pub fn new(graph: Graph) -> Self { Self(graph) }
+    /// Assigns block ids in the order of `raw_blocks` (genesis first) and resolves every parent
+    /// and referee hash to an id.
+    pub fn intern(raw_blocks: Vec<RawBlock>) -> anyhow::Result<Self> {
+        let mut id_map: HashMap<H256, usize> = HashMap::with_capacity(raw_blocks.len());
+        let mut unique_blocks = Vec::with_capacity(raw_blocks.len());
+        for raw in raw_blocks {
+            if id_map.contains_key(&raw.hash) {
+                continue;
+            }
+            id_map.insert(raw.hash, unique_blocks.len());
+            unique_blocks.push(raw);
+        }
 
-    pub fn finalize(mut self) -> anyhow::Result<Graph> {
-        self.check_block_hash()?;
+        let resolve = |hash: &H256| {
+            id_map
+                .get(hash)
+                .copied()
+                .ok_or_else(|| anyhow!("block hash {:?} has no block", hash))
+        };
+        let blocks = unique_blocks
+            .into_iter()
+            .enumerate()
+            .map(|(id, raw)| {
+                let parent = raw.parent_hash.as_ref().map(resolve).transpose()?;
+                let referees = raw
+                    .referee_hashes
+                    .iter()
+                    .map(resolve)
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(Block::from_raw(raw, id, parent, referees))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self(Graph {
+            blocks,
+            id_map,
+            height_index: Default::default(),
+            timestamp_index: Default::default(),
+            past_set_bitmap_bytes: 0,
+        }))
+    }
 
-        let root_hash = self.0.root_hash();
+    pub fn finalize(self) -> anyhow::Result<Graph> { self.finalize_with_progress(&mut |_| {}) }
 
-        self.set_parent();
+    /// `finalize`, reporting the duration of every pass.
+    pub fn finalize_with_progress(
+        self, progress: &mut dyn FnMut(LoadProgress),
+    ) -> anyhow::Result<Graph> {
+        self.finalize_with(&FirstLogged, progress)
+    }
 
-        self.apply_block(&root_hash, |g, b| {
-            g.calculate_subtree_size(b);
-        });
+    /// `finalize_with_progress`, ordering children (and so picking the pivot chain) by `rule`.
+    pub fn finalize_with(
+        mut self, rule: &dyn PivotRule, progress: &mut dyn FnMut(LoadProgress),
+    ) -> anyhow::Result<Graph> {
+        let mut phase_start = Stopwatch::start();
+        let mut phase_done = |name| {
+            progress(LoadProgress::Phase {
+                name,
+                elapsed: phase_start.elapsed(),
+            });
+            phase_start = Stopwatch::start();
+        };
 
-        self.apply_block(&root_hash, |g, b| g.sort_children(b));
+        self.set_parent();
+        phase_done("set_parent");
 
-        let pivot_hashes: Vec<_> = self.0.pivot_chain().into_iter().map(|b| b.hash).collect();
-        for pivot_hash in pivot_hashes {
-            self.apply_block(&pivot_hash, |g, b| {
-                g.mark_epoch(b, pivot_hash);
-            });
+        let pre_order = tree_pre_order(&self.0.blocks, 0);
+
+        for &id in pre_order.iter().rev() {
+            self.calculate_subtree_size(id);
         }
+        phase_done("subtree_size");
 
-        self.set_block_by_map(self.compute_past_set_bitmap(), |block, bitmap| {
-            block.past_set_size = bitmap.count() as u64;
-        });
+        let mut weights = vec![0; self.0.blocks.len()];
+        for &id in pre_order.iter().rev() {
+            let block = self.get_block(id);
+            weights[id] =
+                rule.block_weight(block) + block.children.iter().map(|&c| weights[c]).sum::<u64>();
+        }
+        for &id in &pre_order {
+            self.sort_children(id, &weights, rule);
+        }
+        phase_done("sort_children");
 
-        self.set_block_by_map(self.compute_subtree_adv(), |block, adv_series| {
-            block.subtree_adv_series = Some(adv_series);
-        });
+        let pivot_ids: Vec<_> = self.0.pivot_chain().into_iter().map(|b| b.id).collect();
+        for pivot_id in pivot_ids {
+            self.mark_epoch(pivot_id);
+        }
+        phase_done("mark_epoch");
 
-        Ok(self.0)
-    }
+        let (past_set_size, bitmap_bytes) = self.compute_past_set_size();
+        self.0.past_set_bitmap_bytes = bitmap_bytes;
+        for (block, size) in self.0.blocks.iter_mut().zip(past_set_size) {
+            block.past_set_size = size;
+        }
+        phase_done("past_set");
 
-    fn check_block_hash(&self) -> anyhow::Result<()> {
-        let graph = &self.0;
-        for block in graph.block_map.values() {
-            if let Some(h) = block.parent_hash {
-                if h != graph.root_hash() && !graph.block_map.contains_key(&h) {
-                    bail!("block hash {:?} has no block", h)
-                }
-            }
+        for (id, adv_series) in self.compute_subtree_adv(rule) {
+            self.0.blocks[id].subtree_adv_series = Some(adv_series);
         }
-        Ok(())
+        phase_done("subtree_adv");
+
+        self.0.build_indices();
+        phase_done("build_indices");
+
+        Ok(self.0)
     }
 
     fn set_parent(&mut self) {
-        let pairs: Vec<(H256, H256)> = self
-            .0
-            .block_map
-            .iter()
-            .filter_map(|(hash, block)| block.parent_hash.map(|p| (*hash, p)))
-            .collect();
-
-        for (hash, parent_hash) in pairs {
-            self.0
-                .block_map
-                .get_mut(&parent_hash)
-                .unwrap()
-                .children
-                .push(hash);
+        for id in 0..self.0.blocks.len() {
+            if let Some(parent) = self.0.blocks[id].parent {
+                self.0.blocks[parent].children.push(id);
+            }
         }
     }
 
-    fn calculate_subtree_size<'a>(&mut self, block: &mut Block) -> (u64, TimeSeries<u16>) {
-        if block.subtree_size > 0 {
-            return (
-                block.subtree_size,
-                block.subtree_size_series.clone().unwrap(),
-            );
-        }
+    /// Requires the subtree sizes of all children.
+    fn calculate_subtree_size(&mut self, id: usize) {
+        let block = self.get_block(id);
 
-        // Calculate subtree_size for all children first
         let mut children_sum = 1;
         let mut subtree_timeseries = if block.log_timestamp > 0 {
-            vec![TimeSeries::new(block.log_timestamp, 1u16)]
+            vec![Cow::Owned(TimeSeries::new(block.log_timestamp, 1u32))]
         } else {
             vec![]
         };
 
-        for child_hash in &block.children {
-            self.apply_block(child_hash, |graph, child| {
-                let (child_size, child_series) = graph.calculate_subtree_size(child);
-                subtree_timeseries.push(child_series);
-                children_sum += child_size;
-            });
+        for &child_id in &block.children {
+            let child = self.get_block(child_id);
+            subtree_timeseries.push(Cow::Borrowed(child.subtree_size_series.as_ref().unwrap()));
+            children_sum += child.subtree_size;
         }
 
         let mut subtree_size_series =
@@ -105,179 +154,349 @@ impl GraphComputer {
                     children_series
                         .iter()
                         .filter_map(|x| x.copied())
-                        .sum::<u16>(),
+                        .sum::<u32>(),
                 )
             });
         subtree_size_series.reduce();
 
         // Current node's subtree_size = 1 + sum of all children's subtree_size
+        let block = &mut self.0.blocks[id];
         block.subtree_size = children_sum;
-
         block.subtree_size_series = Some(subtree_size_series);
-
-        (
-            block.subtree_size,
-            block.subtree_size_series.clone().unwrap(),
-        )
     }
 
-    fn sort_children(&mut self, block: &mut Block) {
-        block.children.sort_by(|a, b| {
-            let a_size = self.get_block(a).subtree_size;
-            let b_size = self.get_block(b).subtree_size;
-            b_size.cmp(&a_size)
+    /// Heaviest subtree first, by the subtree `weights` of `rule`.
+    fn sort_children(&mut self, id: usize, weights: &[u64], rule: &dyn PivotRule) {
+        let mut children = std::mem::take(&mut self.0.blocks[id].children);
+        children.sort_by(|&a, &b| {
+            weights[b]
+                .cmp(&weights[a])
+                .then_with(|| rule.tie_break(self.get_block(a), self.get_block(b)))
         });
-
-        for child_hash in &block.children {
-            self.apply_block(child_hash, |graph, child| {
-                graph.sort_children(child);
-            });
-        }
+        self.0.blocks[id].children = children;
     }
 
-    fn mark_epoch(&mut self, block: &mut Block, epoch_hash: H256) -> BTreeSet<H256> {
-        if block.epoch_block.is_some() {
-            return Default::default();
+    /// Assigns to the epoch of `epoch_id` every block reachable through referee edges that is
+    /// not in an earlier epoch yet.
+    fn mark_epoch(&mut self, epoch_id: usize) {
+        if self.get_block(epoch_id).epoch_block.is_some() {
+            return;
         }
 
-        block.epoch_block = Some(epoch_hash);
+        let mut epoch_set: Vec<usize> = vec![];
+        let mut stack = vec![epoch_id];
+        while let Some(id) = stack.pop() {
+            let block = &mut self.0.blocks[id];
+            if block.epoch_block.is_some() {
+                continue;
+            }
+            block.epoch_block = Some(epoch_id);
+            stack.extend(block.referees.iter().copied());
+            if id != epoch_id {
+                epoch_set.push(id);
+            }
+        }
 
-        let mut epoch_set: BTreeSet<H256> = Default::default();
+        epoch_set.sort_unstable();
+        self.0.blocks[epoch_id].epoch_set = Some(epoch_set);
+    }
 
-        for referee_hash in &block.referee_hashes {
-            self.apply_block(referee_hash, |g, b| {
-                epoch_set.extend(g.mark_epoch(b, epoch_hash));
-            });
+    /// Past-set size of every block (indexed by id), and the peak bytes held by bitmaps while
+    /// computing them.
+    ///
+    /// Blocks are visited in log order, and a block's bitmap is dropped as soon as every block
+    /// naming it as parent or referee has been computed, so only the bitmaps of the live frontier
+    /// are held at any time.
+    fn compute_past_set_size(&self) -> (Vec<u64>, usize) {
+        let blocks = &self.0.blocks;
+        fn dependencies(block: &Block) -> impl Iterator<Item = &usize> {
+            block.referees.iter().chain(block.parent.iter())
         }
 
-        if block.hash == epoch_hash {
-            block.epoch_set = Some(epoch_set);
-            Default::default()
-        } else {
-            epoch_set.insert(block.hash);
-            epoch_set
+        let mut pending_dependents: Vec<usize> = vec![0; blocks.len()];
+        for block in blocks {
+            for &id in dependencies(block) {
+                pending_dependents[id] += 1;
+            }
         }
-    }
 
-    fn compute_past_set_bitmap(&self) -> HashMap<H256, Bitmap> {
-        let mut graph_bitmaps: HashMap<H256, Bitmap> = HashMap::new();
-        let mut working_stack: Vec<H256> = Vec::new();
-        let mut keys_iter = self.0.block_map.keys();
+        let mut past_set_size: Vec<Option<u64>> = vec![None; blocks.len()];
+        let mut graph_bitmaps: Vec<Option<Bitmap>> = vec![None; blocks.len()];
+        let mut live_bytes = 0;
+        let mut peak_bytes = 0;
+        let mut working_stack: Vec<usize> = Vec::new();
+        let mut ids_iter = 0..blocks.len();
 
         loop {
-            let hash = if let Some(hash) = working_stack.pop() {
-                hash
-            } else if let Some(hash) = keys_iter.next() {
-                *hash
+            let id = if let Some(id) = working_stack.pop() {
+                id
+            } else if let Some(id) = ids_iter.next() {
+                id
             } else {
-                return graph_bitmaps;
+                let past_set_size = past_set_size.into_iter().map(Option::unwrap).collect();
+                return (past_set_size, peak_bytes);
             };
 
-            if graph_bitmaps.contains_key(&hash) {
+            if past_set_size[id].is_some() {
                 continue;
             }
 
-            let block = self.get_block(&hash);
+            let block = &blocks[id];
             let mut bitmap_collector = PastsetCollector::new();
-            for hash in block.referee_hashes.iter() {
-                bitmap_collector.insert(*hash, &graph_bitmaps);
-            }
-            if let Some(parent_hash) = block.parent_hash {
-                bitmap_collector.insert(parent_hash, &graph_bitmaps)
+            for &dep_id in dependencies(block) {
+                bitmap_collector.insert(dep_id, &graph_bitmaps);
             }
 
-            match bitmap_collector.into_result() {
-                PastsetCollectResult::Ready(mut bitmap) => {
-                    bitmap.set(block.id);
-                    graph_bitmaps.insert(hash, bitmap);
-                }
-                PastsetCollectResult::Pending(hashes) => {
-                    working_stack.push(hash);
-                    working_stack.extend(hashes);
+            let mut bitmap = match bitmap_collector.into_result() {
+                PastsetCollectResult::Ready(bitmap) => bitmap,
+                PastsetCollectResult::Pending(ids) => {
+                    working_stack.push(id);
+                    working_stack.extend(ids);
                     continue;
                 }
+            };
+            bitmap.set(id);
+            past_set_size[id] = Some(bitmap.count() as u64);
+
+            live_bytes += bitmap.len_bytes();
+            peak_bytes = peak_bytes.max(live_bytes);
+
+            for &dep_id in dependencies(block) {
+                pending_dependents[dep_id] -= 1;
+                if pending_dependents[dep_id] == 0 {
+                    if let Some(released) = graph_bitmaps[dep_id].take() {
+                        live_bytes -= released.len_bytes();
+                    }
+                }
+            }
+
+            if pending_dependents[id] > 0 {
+                graph_bitmaps[id] = Some(bitmap);
+            } else {
+                live_bytes -= bitmap.len_bytes();
             }
         }
     }
 
-    fn compute_subtree_adv(&self) -> HashMap<H256, TimeSeries<i16>> {
-        let mut answer: HashMap<H256, TimeSeries<i16>> = Default::default();
+    /// Lead of the pivot child over its heaviest sibling, in the subtree weights of `rule`.
+    fn compute_subtree_adv(&self, rule: &dyn PivotRule) -> Vec<(usize, TimeSeries<i16>)> {
+        let weight_series = WeightSeries::new(&self.0.blocks, rule);
+        let mut answer = vec![];
         for block in self.0.pivot_chain() {
             if block.children.is_empty() {
                 continue;
             }
 
-            let child_subtree_size_series: Vec<_> = block
+            let child_weight_series: Vec<_> = block
                 .children
                 .iter()
-                .map(|hash| self.get_block(hash).subtree_size_series.as_ref().unwrap())
+                .map(|&id| weight_series.get(id))
                 .collect();
 
             let subtree_adv_series =
-                TimeSeries::array_cartesian_map(&child_subtree_size_series, |weights| {
-                    let best_child_weight = *weights[0]? as i16;
+                TimeSeries::array_cartesian_map(&child_weight_series, |weights| {
+                    let best_child_weight = *weights[0]? as i64;
 
                     let max_sib_weight = weights[1..]
                         .iter()
                         .filter_map(|x| x.copied())
                         .max()
-                        .unwrap_or(0) as i16;
+                        .unwrap_or(0) as i64;
 
-                    Some(best_child_weight - max_sib_weight)
+                    let adv = best_child_weight - max_sib_weight;
+                    Some(adv.clamp(i16::MIN as i64, i16::MAX as i64) as i16)
                 });
 
-            answer.insert(block.hash, subtree_adv_series);
+            answer.push((block.id, subtree_adv_series));
         }
         answer
     }
 
-    fn apply_block(&mut self, hash: &H256, mut f: impl FnMut(&mut Self, &mut Block)) {
-        let Some(mut block) = self.0.block_map.remove(hash) else {
+    fn get_block(&self, id: usize) -> &Block { &self.0.blocks[id] }
+}
+
+/// `GraphComputer::intern` for blocks handed over one at a time while a log is parsed, so that
+/// their parsed form is dropped as soon as they are interned. Ids and the handling of duplicate
+/// hashes are those of `intern`. Every block stays in memory: this saves the parsed copy of the
+/// log, it does not bound memory to a window of it.
+#[cfg(feature = "native")]
+pub(crate) struct SinglePassInterner {
+    blocks: Vec<Block>,
+    id_map: HashMap<H256, usize>,
+    /// Blocks that name a block logged after them, by id, interned once all blocks are known.
+    unresolved: Vec<(usize, RawBlock)>,
+    /// Blocks logged before genesis is known: an id is only assigned once genesis has id 0.
+    before_genesis: Option<Vec<RawBlock>>,
+}
+
+#[cfg(feature = "native")]
+impl SinglePassInterner {
+    pub(crate) fn new() -> Self {
+        Self {
+            // Placeholder for genesis, replaced by `set_genesis`.
+            blocks: vec![Block::default()],
+            id_map: HashMap::new(),
+            unresolved: vec![],
+            before_genesis: Some(vec![]),
+        }
+    }
+
+    pub(crate) fn set_genesis(&mut self, root_hash: H256) {
+        self.blocks[0] = Block::from_raw(RawBlock::genesis_block(root_hash), 0, None, vec![]);
+        self.id_map.insert(root_hash, 0);
+        for raw in self.before_genesis.take().unwrap_or_default() {
+            self.push(raw);
+        }
+    }
+
+    pub(crate) fn push(&mut self, raw: RawBlock) {
+        if let Some(before_genesis) = &mut self.before_genesis {
+            before_genesis.push(raw);
             return;
+        }
+        if self.id_map.contains_key(&raw.hash) {
+            return;
+        }
+        let id = self.blocks.len();
+        self.id_map.insert(raw.hash, id);
+
+        let parent = raw.parent_hash.map(|hash| self.id_map.get(&hash).copied());
+        let referees: Option<Vec<usize>> = raw
+            .referee_hashes
+            .iter()
+            .map(|hash| self.id_map.get(hash).copied())
+            .collect();
+        match (parent, referees) {
+            (Some(None), _) | (_, None) => {
+                // Placeholder, replaced in `finish`.
+                self.blocks.push(Block::default());
+                self.unresolved.push((id, raw));
+            }
+            (parent, Some(referees)) => {
+                self.blocks
+                    .push(Block::from_raw(raw, id, parent.flatten(), referees));
+            }
+        }
+    }
+
+    pub(crate) fn finish(mut self) -> anyhow::Result<GraphComputer> {
+        let resolve = |hash: &H256| {
+            self.id_map
+                .get(hash)
+                .copied()
+                .ok_or_else(|| anyhow!("block hash {:?} has no block", hash))
         };
-        f(self, &mut block);
-        self.0.block_map.insert(*hash, block);
+        for (id, raw) in std::mem::take(&mut self.unresolved) {
+            let parent = raw.parent_hash.as_ref().map(resolve).transpose()?;
+            let referees = raw
+                .referee_hashes
+                .iter()
+                .map(resolve)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            self.blocks[id] = Block::from_raw(raw, id, parent, referees);
+        }
+
+        Ok(GraphComputer(Graph {
+            blocks: self.blocks,
+            id_map: self.id_map,
+            height_index: Default::default(),
+            timestamp_index: Default::default(),
+            past_set_bitmap_bytes: 0,
+        }))
     }
+}
 
-    fn set_block_by_map<T>(
-        &mut self, mut map: HashMap<H256, T>, set_block: impl Fn(&mut Block, T),
-    ) {
-        for (hash, block) in self.0.block_map.iter_mut() {
-            if let Some(val) = map.remove(hash) {
-                set_block(block, val);
-            }
+/// Blocks of the parent tree under `root`, every block before its children. Reversed, every block
+/// comes after its children.
+fn tree_pre_order(blocks: &[Block], root: usize) -> Vec<usize> {
+    let mut order = Vec::with_capacity(blocks.len());
+    let mut stack = vec![root];
+    while let Some(id) = stack.pop() {
+        order.push(id);
+        stack.extend(blocks[id].children.iter().copied());
+    }
+    order
+}
+
+/// Subtree weights of a `PivotRule` over time, the weighted `subtree_size_series`. Where every
+/// block weighs 1 they are the subtree sizes, and nothing is computed until asked for.
+pub(crate) struct WeightSeries<'a> {
+    blocks: &'a [Block],
+    /// By block id, `None` outside the tree under genesis.
+    computed: Option<Vec<Option<TimeSeries<u32>>>>,
+}
+
+impl<'a> WeightSeries<'a> {
+    /// Requires the children of every block.
+    pub(crate) fn new(blocks: &'a [Block], rule: &dyn PivotRule) -> Self {
+        if blocks.iter().all(|block| rule.block_weight(block) == 1) {
+            return WeightSeries {
+                blocks,
+                computed: None,
+            };
+        }
+
+        let mut computed: Vec<Option<TimeSeries<u32>>> = vec![None; blocks.len()];
+        for &id in tree_pre_order(blocks, 0).iter().rev() {
+            let block = &blocks[id];
+            let own = (block.log_timestamp > 0)
+                .then(|| TimeSeries::new(block.log_timestamp, rule.block_weight(block) as u32));
+            let series: Vec<&TimeSeries<u32>> = own
+                .iter()
+                .chain(
+                    block
+                        .children
+                        .iter()
+                        .map(|&child| computed[child].as_ref().unwrap()),
+                )
+                .collect();
+            let mut weight_series = TimeSeries::array_cartesian_map(&series, |weights| {
+                Some(weights.iter().filter_map(|x| x.copied()).sum::<u32>())
+            });
+            weight_series.reduce();
+            computed[id] = Some(weight_series);
+        }
+        WeightSeries {
+            blocks,
+            computed: Some(computed),
         }
     }
 
-    fn get_block(&self, hash: &H256) -> &Block { self.0.block_map.get(hash).unwrap() }
+    /// Panics if `id` is not in the tree under genesis.
+    pub(crate) fn get(&self, id: usize) -> Cow<'_, TimeSeries<u32>> {
+        match &self.computed {
+            Some(computed) => Cow::Borrowed(computed[id].as_ref().unwrap()),
+            None => Cow::Borrowed(self.blocks[id].subtree_size_series.as_ref().unwrap()),
+        }
+    }
 }
 
 enum PastsetCollector<'a> {
     ReadyBitmaps(Vec<&'a Bitmap>),
-    PendingHashes(Vec<H256>),
+    PendingIds(Vec<usize>),
 }
 
 enum PastsetCollectResult {
     Ready(Bitmap),
-    Pending(Vec<H256>),
+    Pending(Vec<usize>),
 }
 
 impl<'a> PastsetCollector<'a> {
     pub fn new() -> Self { Self::ReadyBitmaps(vec![]) }
 
-    pub fn insert(&mut self, hash: H256, graph_bitmaps: &'a HashMap<H256, Bitmap>) {
+    pub fn insert(&mut self, id: usize, graph_bitmaps: &'a [Option<Bitmap>]) {
         use PastsetCollector::*;
-        match (&mut *self, graph_bitmaps.get(&hash)) {
+        match (&mut *self, graph_bitmaps[id].as_ref()) {
             (ReadyBitmaps(ref mut bitmaps), Some(bitmap)) => {
                 bitmaps.push(bitmap);
             }
             (ReadyBitmaps(_), None) => {
-                *self = PendingHashes(vec![hash]);
+                *self = PendingIds(vec![id]);
             }
-            (PendingHashes(ref mut hashes), None) => {
-                hashes.push(hash);
+            (PendingIds(ref mut ids), None) => {
+                ids.push(id);
             }
-            (PendingHashes(_), Some(_)) => {}
+            (PendingIds(_), Some(_)) => {}
         }
     }
 
@@ -291,7 +510,55 @@ impl<'a> PastsetCollector<'a> {
                     acc
                 }))
             }
-            PastsetCollector::PendingHashes(hashes) => Pending(hashes),
+            PastsetCollector::PendingIds(ids) => Pending(ids),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_finalize_deep_chain() {
+        // Deep enough to overflow the default test thread stack with recursive passes, and more
+        // blocks than a `u16` subtree size can count.
+        const DEPTH: u64 = 70_000;
+        let hash = |i: u64| H256::from_low_u64_be(i + 1);
+
+        let mut raw_blocks = vec![RawBlock::genesis_block(hash(0))];
+        for i in 1..=DEPTH {
+            let referees = if i >= 2 { vec![hash(i - 2)] } else { vec![] };
+            raw_blocks.push(RawBlock::new(i, hash(i), hash(i - 1), referees, i, 1, 0, 0));
+        }
+
+        let graph = Graph::from_raw_blocks(raw_blocks).unwrap();
+        assert_eq!(graph.genesis_block().subtree_size, DEPTH + 1);
+        assert_eq!(graph.pivot_chain().len() as u64, DEPTH + 1);
+
+        let tip = graph.get_block(&hash(DEPTH)).unwrap();
+        assert_eq!(tip.past_set_size, DEPTH + 1);
+        assert_eq!(tip.epoch_size(), 1);
+        assert_eq!(
+            *graph
+                .genesis_block()
+                .subtree_size_series
+                .as_ref()
+                .unwrap()
+                .at(1)
+                .unwrap() as u64,
+            DEPTH
+        );
+    }
+
+    #[test]
+    fn test_intern_rejects_unknown_parent() {
+        let hash = |i: u64| H256::from_low_u64_be(i + 1);
+        let raw_blocks = vec![
+            RawBlock::genesis_block(hash(0)),
+            RawBlock::new(1, hash(1), hash(0), vec![], 1, 1, 0, 0),
+            RawBlock::new(2, hash(2), hash(5), vec![], 2, 2, 0, 0),
+        ];
+        assert!(Graph::from_raw_blocks(raw_blocks).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+//! Honest block rate over time, estimated from the DAG, and the adversary share it implies.
+//!
+//! The risk models take the adversary's share of the mining power as fixed. A massive test that
+//! ramps its load up and down changes the honest block rate, while an adversary's hashrate would
+//! not follow: sized against the whole-run average rate, it is a larger share of the mining power
+//! while the honest rate dips and a smaller one while it peaks. As in the risk model, every block
+//! in the graph counts as honest. Timestamps are log timestamps, the time base of the risk
+//! series.
+
+use anyhow::ensure;
+
+use crate::{graph::Graph, utils::time_series::TimeSeries};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HonestRate {
+    /// Seconds of the trailing window the rate is measured over.
+    pub window: u64,
+    /// Blocks per second over the whole run.
+    pub average: f64,
+    start: u64,
+    /// Blocks per second at every second from `start`.
+    rates: Vec<f64>,
+}
+
+impl HonestRate {
+    /// Blocks per second received in the `window` seconds up to `timestamp`, or since the first
+    /// block early in the run. Outside the run, the rate at its nearest end.
+    pub fn at(&self, timestamp: u64) -> f64 {
+        match self.rates.len() {
+            0 => self.average,
+            len => self.rates[(timestamp.saturating_sub(self.start) as usize).min(len - 1)],
+        }
+    }
+
+    /// Share at `timestamp`, in whole percent, of an adversary holding `adv_percent`% of the
+    /// mining power at the average rate. Clamped to 1..=49, the range the risk models accept.
+    pub fn adv_percent_at(&self, adv_percent: usize, timestamp: u64) -> usize {
+        if adv_percent == 0 {
+            return 0;
+        }
+        let share = adv_percent as f64 / 100.;
+        let adversary = self.average * share / (1. - share);
+        let total = adversary + self.at(timestamp);
+        if total <= 0. {
+            return adv_percent;
+        }
+        ((adversary / total * 100.).round() as usize).clamp(1, 49)
+    }
+
+    /// `adv_percent_at` with a point wherever it changes.
+    pub fn adv_percent_series(&self, adv_percent: usize) -> TimeSeries<usize> {
+        if self.rates.is_empty() {
+            return TimeSeries::new(self.start, adv_percent);
+        }
+        let points = (0..self.rates.len() as u64)
+            .map(|offset| {
+                let timestamp = self.start + offset;
+                (timestamp, self.adv_percent_at(adv_percent, timestamp))
+            })
+            .collect();
+        let mut series = TimeSeries::new_list(points, |values| *values[0]);
+        series.reduce();
+        series
+    }
+}
+
+impl Graph {
+    /// Honest block rate at every second of the run, over a trailing window of `window` seconds.
+    pub fn honest_rate(&self, window: u64) -> Result<HonestRate, anyhow::Error> {
+        ensure!(window > 0, "rate window must be positive");
+        let points: Vec<(u64, u64)> = self.total_blocks_points().collect();
+        let (Some(&(start, _)), Some(&(end, total))) = (points.first(), points.last()) else {
+            return Ok(HonestRate {
+                window,
+                average: 0.,
+                start: 0,
+                rates: vec![],
+            });
+        };
+
+        let totals: Vec<u64> = (start..=end)
+            .map(|timestamp| self.total_blocks_at(timestamp).unwrap_or(0))
+            .collect();
+        let rates = (0..totals.len())
+            .map(|i| {
+                let before = i.checked_sub(window as usize).map_or(0, |j| totals[j]);
+                (totals[i] - before) as f64 / (window as usize).min(i + 1) as f64
+            })
+            .collect();
+        Ok(HonestRate {
+            window,
+            average: total as f64 / totals.len() as f64,
+            start,
+            rates,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::GenerateParams;
+
+    #[test]
+    fn test_adversary_share_follows_honest_rate() {
+        let rate = HonestRate {
+            window: 10,
+            average: 2.,
+            start: 100,
+            rates: vec![2., 1., 4.],
+        };
+        // The adversary mines 0.5 blocks per second throughout.
+        assert_eq!(rate.adv_percent_at(20, 100), 20);
+        assert_eq!(rate.adv_percent_at(20, 101), 33);
+        assert_eq!(rate.adv_percent_at(20, 102), 11);
+        assert_eq!(rate.adv_percent_at(20, 500), 11);
+        assert_eq!(rate.adv_percent_at(0, 101), 0);
+
+        let series = rate.adv_percent_series(20);
+        assert_eq!(series.iter().count(), 3);
+        assert_eq!(series.at(150), Some(&11));
+    }
+
+    #[test]
+    fn test_constant_rate_keeps_adversary_share() {
+        let graph = Graph::generate(&GenerateParams {
+            block_rate: 2.,
+            duration: 600,
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(graph.honest_rate(0).is_err());
+        let rate = graph.honest_rate(60).unwrap();
+        assert!((rate.average - 2.).abs() < 0.2);
+
+        let pivot_chain = graph.pivot_chain();
+        let block = pivot_chain[pivot_chain.len() / 2];
+        assert!((rate.at(block.timestamp) - 2.).abs() < 0.5);
+        assert!(rate.adv_percent_at(20, block.timestamp).abs_diff(20) <= 3);
+
+        let (offset, risk) = graph
+            .confirmation_risk_varying(block, 20, 1e-6, &rate)
+            .unwrap();
+        assert!(risk < 1e-6);
+        let (fixed_offset, ..) = graph.confirmation_risk(block, 20, 1e-6).unwrap();
+        assert!(offset.abs_diff(fixed_offset) <= 10);
+    }
+}
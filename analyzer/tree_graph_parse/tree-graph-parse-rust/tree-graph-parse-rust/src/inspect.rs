@@ -0,0 +1,249 @@
+//! Read-only JSON queries on a loaded graph, served over HTTP by the `serve` binary.
+//!
+//! | path                               | result                                       |
+//! |------------------------------------|----------------------------------------------|
+//! | `/block/{hash}`                    | fields and edges of one block                |
+//! | `/pivot`                           | the pivot chain, genesis first               |
+//! | `/epoch/{height}`                  | the pivot block at `height` and its epoch    |
+//! | `/risk/{hash}?adv=20&risk=1e-6`    | confirmation of a pivot block                |
+//!
+//! `adv` is the adversary power in percent (default 20) and `risk` the threshold (default 1e-6).
+
+use ethereum_types::H256;
+use std::str::FromStr;
+
+use crate::{block::Block, graph::Graph, math::risk::ADV_PERCENT_RANGE};
+
+const DEFAULT_ADV_PERCENT: usize = 20;
+const DEFAULT_RISK_THRESHOLD: f64 = 1e-6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectResponse {
+    pub status: u16,
+    /// JSON document; errors are `{"error": "..."}`.
+    pub body: String,
+}
+
+impl InspectResponse {
+    fn ok(body: String) -> Self { InspectResponse { status: 200, body } }
+
+    fn error(status: u16, message: &str) -> Self {
+        InspectResponse {
+            status,
+            body: format!("{{\"error\": {}}}", quote(message)),
+        }
+    }
+}
+
+/// Answers a request for `url` (path plus optional query string).
+pub fn handle(graph: &Graph, url: &str) -> InspectResponse {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    match segments.as_slice() {
+        ["block", hash] => with_block(graph, hash, |block| {
+            InspectResponse::ok(block_json(graph, block))
+        }),
+        ["pivot"] => InspectResponse::ok(pivot_json(graph)),
+        ["epoch", height] => match height.parse::<u64>() {
+            Ok(height) => epoch_json(graph, height).map_or_else(
+                || InspectResponse::error(404, "no pivot block at this height"),
+                InspectResponse::ok,
+            ),
+            Err(_) => InspectResponse::error(400, "height must be an integer"),
+        },
+        ["risk", hash] => with_block(graph, hash, |block| risk_json(graph, block, query)),
+        _ => InspectResponse::error(404, "unknown path"),
+    }
+}
+
+fn with_block(
+    graph: &Graph, hash: &str, f: impl FnOnce(&Block) -> InspectResponse,
+) -> InspectResponse {
+    let Ok(hash) = H256::from_str(hash.trim_start_matches("0x")) else {
+        return InspectResponse::error(400, "invalid block hash");
+    };
+    match graph.get_block(&hash) {
+        Some(block) => f(block),
+        None => InspectResponse::error(404, "block not found"),
+    }
+}
+
+fn is_pivot(block: &Block) -> bool { block.epoch_block == Some(block.id) }
+
+fn block_json(graph: &Graph, block: &Block) -> String {
+    let hashes = |ids: &[usize]| {
+        let hashes: Vec<_> = ids
+            .iter()
+            .map(|&id| quote_hash(graph.hash_of(id)))
+            .collect();
+        format!("[{}]", hashes.join(", "))
+    };
+    let optional_hash =
+        |id: Option<usize>| id.map_or("null".to_string(), |id| quote_hash(graph.hash_of(id)));
+
+    format!(
+        "{{\"hash\": {}, \"height\": {}, \"timestamp\": {}, \"log_timestamp\": {}, \
+         \"tx_count\": {}, \"block_size\": {}, \"parent\": {}, \"referees\": {}, \
+         \"children\": {}, \"pivot\": {}, \"epoch_block\": {}, \"epoch_size\": {}, \
+         \"subtree_size\": {}, \"past_set_size\": {}}}",
+        quote_hash(block.hash),
+        block.height,
+        block.timestamp,
+        block.log_timestamp,
+        block.tx_count,
+        block.block_size,
+        optional_hash(block.parent),
+        hashes(&block.referees),
+        hashes(&block.children),
+        is_pivot(block),
+        optional_hash(block.epoch_block),
+        block.epoch_size(),
+        block.subtree_size,
+        block.past_set_size,
+    )
+}
+
+fn pivot_json(graph: &Graph) -> String {
+    let entries: Vec<_> = graph
+        .pivot_chain()
+        .into_iter()
+        .map(|block| {
+            format!(
+                "{{\"hash\": {}, \"height\": {}, \"timestamp\": {}, \"epoch_size\": {}, \
+                 \"subtree_size\": {}}}",
+                quote_hash(block.hash),
+                block.height,
+                block.timestamp,
+                block.epoch_size(),
+                block.subtree_size
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(", "))
+}
+
+fn epoch_json(graph: &Graph, height: u64) -> Option<String> {
+    let pivot = graph.blocks_at_height(height).find(|b| is_pivot(b))?;
+    let mut members: Vec<_> = pivot
+        .epoch_set
+        .iter()
+        .flatten()
+        .map(|&id| quote_hash(graph.hash_of(id)))
+        .collect();
+    members.push(quote_hash(pivot.hash));
+
+    Some(format!(
+        "{{\"height\": {}, \"pivot\": {}, \"blocks\": [{}], \"epoch_span\": {}, \
+         \"avg_epoch_time\": {}}}",
+        height,
+        quote_hash(pivot.hash),
+        members.join(", "),
+        graph.epoch_span(pivot),
+        json_f64(graph.avg_epoch_time(pivot))
+    ))
+}
+
+fn risk_json(graph: &Graph, block: &Block, query: &str) -> InspectResponse {
+    let mut adv_percent = DEFAULT_ADV_PERCENT;
+    let mut risk_threshold = DEFAULT_RISK_THRESHOLD;
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        match key {
+            "adv" => match value.parse() {
+                Ok(v) if ADV_PERCENT_RANGE.contains(&v) => adv_percent = v,
+                _ => return InspectResponse::error(400, "adv must be an integer in 1..=49"),
+            },
+            "risk" => match value.parse() {
+                Ok(v) if v > 0. && v < 1. => risk_threshold = v,
+                _ => return InspectResponse::error(400, "risk must be in (0, 1)"),
+            },
+            _ => {}
+        }
+    }
+
+    if block.height == 0 || !is_pivot(block) {
+        return InspectResponse::error(400, "confirmation risk is only defined on the pivot chain");
+    }
+
+    let confirmation = graph.confirmation_risk(block, adv_percent, risk_threshold);
+    let fields = match confirmation {
+        Some((time_offset, m, k, risk)) => format!(
+            "\"confirmed\": true, \"time_offset\": {}, \"m\": {}, \"k\": {}, \"risk\": {}",
+            time_offset,
+            m,
+            k,
+            json_f64(risk)
+        ),
+        None => "\"confirmed\": false".to_string(),
+    };
+    InspectResponse::ok(format!(
+        "{{\"hash\": {}, \"adv_percent\": {}, \"risk_threshold\": {}, {}}}",
+        quote_hash(block.hash),
+        adv_percent,
+        json_f64(risk_threshold),
+        fields
+    ))
+}
+
+fn quote(s: &str) -> String { format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")) }
+
+fn quote_hash(hash: H256) -> String { format!("\"{:?}\"", hash) }
+
+/// JSON has no NaN or infinity.
+fn json_f64(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:e}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::GenerateParams;
+
+    #[test]
+    fn test_handle_routes() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 120,
+            ..Default::default()
+        })
+        .unwrap();
+        let pivot = graph.pivot_chain();
+        let mid_pivot = pivot[pivot.len() / 2];
+
+        let response = handle(&graph, &format!("/block/{:?}", mid_pivot.hash));
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains("\"pivot\": true"));
+
+        let response = handle(&graph, "/pivot");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body.matches("\"hash\":").count(), pivot.len());
+
+        let response = handle(&graph, &format!("/epoch/{}", mid_pivot.height));
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.body.matches("\"0x").count(),
+            1 + mid_pivot.epoch_size()
+        );
+
+        let response = handle(&graph, &format!("/risk/{:?}?adv=10", pivot[1].hash));
+        assert_eq!(response.status, 200);
+        assert!(response.body.contains("\"adv_percent\": 10"));
+
+        assert_eq!(handle(&graph, "/block/0x1234").status, 400);
+        assert_eq!(
+            handle(&graph, &format!("/block/{:?}", H256::zero())).status,
+            404
+        );
+        assert_eq!(handle(&graph, "/epoch/abc").status, 400);
+        assert_eq!(handle(&graph, "/nothing").status, 404);
+        let genesis_risk = format!("/risk/{:?}", graph.root_hash());
+        assert_eq!(handle(&graph, &genesis_risk).status, 400);
+        for adv in ["0", "50", "x"] {
+            let risk = format!("/risk/{:?}?adv={adv}", pivot[1].hash);
+            assert_eq!(handle(&graph, &risk).status, 400);
+        }
+    }
+}
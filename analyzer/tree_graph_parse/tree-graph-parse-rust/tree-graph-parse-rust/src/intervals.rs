@@ -0,0 +1,241 @@
+//! Block generation and arrival intervals, to check from the DAG alone that a test ran at the
+//! block rate it was configured with.
+//!
+//! Generation intervals are the gaps between consecutive block `timestamp`s and arrival
+//! intervals the gaps between consecutive `log_timestamp`s, both taken over all blocks in time
+//! order, whoever mined them. Both timestamps have a resolution of one second, so at a few blocks
+//! per second most gaps are 0 and the rate is the figure to compare with the configuration.
+//! Genesis is skipped.
+
+use anyhow::ensure;
+use std::collections::BTreeMap;
+
+use crate::graph::Graph;
+
+/// Distribution of the gaps between consecutive times, in seconds.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IntervalDistribution {
+    pub count: usize,
+    /// Last time minus first time.
+    pub span: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl IntervalDistribution {
+    fn of(mut times: Vec<u64>) -> Self {
+        times.sort_unstable();
+        let mut intervals: Vec<u64> = times.windows(2).map(|w| w[1] - w[0]).collect();
+        intervals.sort_unstable();
+        let percentile = |p: usize| match intervals.len() {
+            0 => 0,
+            len => intervals[(len * p / 100).min(len - 1)],
+        };
+        let span = match (times.first(), times.last()) {
+            (Some(first), Some(last)) => last - first,
+            _ => 0,
+        };
+        IntervalDistribution {
+            count: intervals.len(),
+            span,
+            mean: match intervals.len() {
+                0 => 0.,
+                n => span as f64 / n as f64,
+            },
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: intervals.last().copied().unwrap_or(0),
+        }
+    }
+
+    /// Blocks per second, 0 without any interval and infinite if they all share one second.
+    pub fn rate(&self) -> f64 {
+        match self.count {
+            0 => 0.,
+            n => n as f64 / self.span as f64,
+        }
+    }
+}
+
+/// Intervals of the blocks generated, and of those logged, in `[start, end)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalWindow {
+    pub start: u64,
+    pub end: u64,
+    /// Blocks whose `timestamp` is in the window.
+    pub generated: usize,
+    /// Blocks whose `log_timestamp` is in the window.
+    pub arrived: usize,
+    pub generation: IntervalDistribution,
+    pub arrival: IntervalDistribution,
+}
+
+impl IntervalWindow {
+    /// Blocks generated per second of the window.
+    pub fn generation_rate(&self) -> f64 { self.generated as f64 / (self.end - self.start) as f64 }
+
+    /// Blocks logged per second of the window.
+    pub fn arrival_rate(&self) -> f64 { self.arrived as f64 / (self.end - self.start) as f64 }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalStats {
+    pub generation: IntervalDistribution,
+    pub arrival: IntervalDistribution,
+    /// Windows of `window` seconds from the earliest `timestamp` or `log_timestamp`, in time
+    /// order. Windows without any block are left out, so a skewed timestamp adds one window
+    /// rather than every window up to it.
+    pub windows: Vec<IntervalWindow>,
+}
+
+impl Graph {
+    /// Generation and arrival intervals over the whole graph and per window of `window` seconds.
+    pub fn interval_stats(&self, window: u64) -> Result<IntervalStats, anyhow::Error> {
+        ensure!(window > 0, "interval window must be positive");
+        let timestamps: Vec<u64> = self.blocks().skip(1).map(|b| b.timestamp).collect();
+        let log_timestamps: Vec<u64> = self.blocks().skip(1).map(|b| b.log_timestamp).collect();
+
+        let mut windows = vec![];
+        if let Some(first) = timestamps.iter().chain(&log_timestamps).min().copied() {
+            // Generated and arrived times of every window holding a block, by window index.
+            let mut buckets: BTreeMap<u64, (Vec<u64>, Vec<u64>)> = BTreeMap::new();
+            for &time in &timestamps {
+                buckets
+                    .entry((time - first) / window)
+                    .or_default()
+                    .0
+                    .push(time);
+            }
+            for &time in &log_timestamps {
+                buckets
+                    .entry((time - first) / window)
+                    .or_default()
+                    .1
+                    .push(time);
+            }
+            for (index, (generated, arrived)) in buckets {
+                let start = first + index * window;
+                windows.push(IntervalWindow {
+                    start,
+                    end: start + window,
+                    generated: generated.len(),
+                    arrived: arrived.len(),
+                    generation: IntervalDistribution::of(generated),
+                    arrival: IntervalDistribution::of(arrived),
+                });
+            }
+        }
+
+        Ok(IntervalStats {
+            generation: IntervalDistribution::of(timestamps),
+            arrival: IntervalDistribution::of(log_timestamps),
+            windows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::RawBlock, generate::GenerateParams};
+    use ethereum_types::H256;
+
+    #[test]
+    fn test_intervals_of_a_chain() {
+        let hash = |n: u64| H256::from_low_u64_be(n);
+        // Generated at 10, 10, 13, 20 and logged one second later, except the last one.
+        let mut raw_blocks = vec![RawBlock::genesis_block(hash(1))];
+        for (i, (timestamp, log_timestamp)) in [(10, 11), (10, 11), (13, 14), (20, 25)]
+            .into_iter()
+            .enumerate()
+        {
+            let i = i as u64 + 2;
+            raw_blocks.push(RawBlock::new(
+                i - 1,
+                hash(i),
+                hash(i - 1),
+                vec![],
+                timestamp,
+                log_timestamp,
+                0,
+                0,
+            ));
+        }
+        let stats = Graph::from_raw_blocks(raw_blocks)
+            .unwrap()
+            .interval_stats(10)
+            .unwrap();
+
+        let generation = &stats.generation;
+        assert_eq!((generation.count, generation.span), (3, 10));
+        assert_eq!((generation.p50, generation.max), (3, 7));
+        assert!((generation.rate() - 0.3).abs() < 1e-9);
+        assert_eq!(stats.arrival.max, 11);
+
+        // Windows [10, 20) and [20, 30).
+        assert_eq!(stats.windows.len(), 2);
+        assert_eq!(
+            (stats.windows[0].generated, stats.windows[0].arrived),
+            (3, 3)
+        );
+        assert_eq!(stats.windows[0].generation.span, 3);
+        assert_eq!(
+            (stats.windows[1].generated, stats.windows[1].arrived),
+            (1, 1)
+        );
+        assert_eq!(stats.windows[1].generation.count, 0);
+        assert!((stats.windows[0].generation_rate() - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_skewed_timestamp_adds_one_window() {
+        let hash = |n: u64| H256::from_low_u64_be(n);
+        let block = |n: u64, timestamp| {
+            RawBlock::new(
+                n - 1,
+                hash(n),
+                hash(n - 1),
+                vec![],
+                timestamp,
+                timestamp,
+                0,
+                0,
+            )
+        };
+        let graph = Graph::from_raw_blocks(vec![
+            RawBlock::genesis_block(hash(1)),
+            block(2, 10),
+            block(3, 12),
+            block(4, 4_000_000_000),
+        ])
+        .unwrap();
+
+        assert!(graph.interval_stats(0).is_err());
+        let stats = graph.interval_stats(1).unwrap();
+        let starts: Vec<u64> = stats.windows.iter().map(|w| w.start).collect();
+        assert_eq!(starts, vec![10, 12, 4_000_000_000]);
+        assert_eq!(stats.generation.max, 4_000_000_000 - 12);
+    }
+
+    #[test]
+    fn test_generated_graph_runs_at_configured_rate() {
+        let graph = Graph::generate(&GenerateParams {
+            block_rate: 2.,
+            duration: 1200,
+            ..Default::default()
+        })
+        .unwrap();
+        let stats = graph.interval_stats(120).unwrap();
+        assert!((stats.generation.rate() - 2.).abs() < 0.1);
+        assert!((stats.arrival.rate() - 2.).abs() < 0.1);
+        // Ten full windows, plus one for the blocks still arriving after generation stopped.
+        assert!(stats.windows.len() >= 10);
+        for window in &stats.windows[..10] {
+            assert!((window.generation_rate() - 2.).abs() < 0.4);
+        }
+    }
+}
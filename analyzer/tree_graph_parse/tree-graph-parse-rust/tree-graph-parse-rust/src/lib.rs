@@ -1,6 +1,32 @@
+pub mod audit;
+pub mod authors;
 pub mod block;
+#[cfg(feature = "native")]
+pub mod block_set;
+pub mod clock;
+pub mod confirmation_audit;
+pub mod confirmation_spread;
+pub mod epoch;
+pub mod frozen;
+pub mod generate;
 pub mod graph;
 pub mod graph_computer;
+pub mod honest_rate;
+pub mod inspect;
+pub mod intervals;
+#[cfg(feature = "native")]
 pub mod load;
+pub mod load_options;
 pub mod math;
+pub mod memory;
+pub mod metrics;
+pub mod pivot_rule;
+pub mod progress;
+pub mod referee_audit;
+pub mod replay;
+pub mod sensitivity;
+pub mod series;
+pub mod snapshot;
+pub mod stale;
 pub mod utils;
+pub mod visualize;
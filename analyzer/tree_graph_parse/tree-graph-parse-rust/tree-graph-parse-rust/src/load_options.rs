@@ -0,0 +1,173 @@
+use ethereum_types::H256;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use crate::block::RawBlock;
+
+/// Blocks to leave out while loading a graph, e.g. the warm-up of a run or the blocks the
+/// latency analyzer dropped for lack of propagation data. Genesis is always kept.
+///
+/// A dropped block is contracted out of the DAG instead of taking its descendants with it: a
+/// kept block whose parent was dropped hangs from its nearest kept ancestor, and a reference to
+/// a dropped block becomes one to the referee's nearest kept ancestor. Heights stay as logged, so
+/// after a dropped warm-up the first kept blocks hang from genesis at their original heights.
+#[derive(Debug, Clone, Default)]
+pub struct GraphLoadOptions {
+    /// Keep only these blocks, e.g. `BlockSet::hashes`.
+    pub include_only: Option<HashSet<H256>>,
+    pub exclude: HashSet<H256>,
+    /// Drop blocks above this height.
+    pub max_height: Option<u64>,
+    /// Keep blocks whose header timestamp is in this range.
+    pub time_range: Option<Range<u64>>,
+}
+
+impl GraphLoadOptions {
+    /// Whether every block is kept.
+    pub fn keeps_all(&self) -> bool {
+        self.include_only.is_none()
+            && self.exclude.is_empty()
+            && self.max_height.is_none()
+            && self.time_range.is_none()
+    }
+
+    pub fn accepts(&self, block: &RawBlock) -> bool {
+        self.include_only
+            .as_ref()
+            .is_none_or(|set| set.contains(&block.hash))
+            && !self.exclude.contains(&block.hash)
+            && self.max_height.is_none_or(|max| block.height <= max)
+            && self
+                .time_range
+                .as_ref()
+                .is_none_or(|range| range.contains(&block.timestamp))
+    }
+
+    /// Drops the blocks not accepted from `raw_blocks` (in log order, genesis first) and
+    /// rewires the edges of the others around them.
+    pub fn apply(&self, raw_blocks: Vec<RawBlock>) -> Vec<RawBlock> {
+        if self.keeps_all() || raw_blocks.is_empty() {
+            return raw_blocks;
+        }
+        let genesis = raw_blocks[0].hash;
+        let dropped: HashMap<H256, H256> = raw_blocks[1..]
+            .iter()
+            .filter(|block| !self.accepts(block))
+            .map(|block| (block.hash, block.parent_hash.unwrap_or(genesis)))
+            .collect();
+
+        // Nearest kept ancestor of every dropped block looked up so far.
+        let mut resolved: HashMap<H256, H256> = HashMap::new();
+        let mut kept_ancestor = |hash: H256| {
+            let mut path = vec![];
+            let mut cursor = hash;
+            let ancestor = loop {
+                if let Some(&ancestor) = resolved.get(&cursor) {
+                    break ancestor;
+                }
+                match dropped.get(&cursor) {
+                    Some(&parent) => {
+                        path.push(cursor);
+                        cursor = parent;
+                    }
+                    None => break cursor,
+                }
+            };
+            for hash in path {
+                resolved.insert(hash, ancestor);
+            }
+            ancestor
+        };
+
+        let mut kept = Vec::with_capacity(raw_blocks.len() - dropped.len());
+        for (i, mut block) in raw_blocks.into_iter().enumerate() {
+            if i > 0 && dropped.contains_key(&block.hash) {
+                continue;
+            }
+            block.parent_hash = block.parent_hash.map(&mut kept_ancestor);
+            let mut referees = Vec::with_capacity(block.referee_hashes.len());
+            for referee in block.referee_hashes.drain(..) {
+                let referee = kept_ancestor(referee);
+                if Some(referee) != block.parent_hash && !referees.contains(&referee) {
+                    referees.push(referee);
+                }
+            }
+            block.referee_hashes = referees;
+            kept.push(block);
+        }
+        kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+
+    fn hash(n: u64) -> H256 { H256::from_low_u64_be(n) }
+
+    /// Genesis 1, the chain 2 <- 3 <- 4 at timestamps 10, 20, 30, and 5 on 3 referencing 4.
+    fn raw_blocks() -> Vec<RawBlock> {
+        vec![
+            RawBlock::genesis_block(hash(1)),
+            RawBlock::new(1, hash(2), hash(1), vec![], 10, 10, 0, 0),
+            RawBlock::new(2, hash(3), hash(2), vec![], 20, 20, 0, 0),
+            RawBlock::new(3, hash(4), hash(3), vec![], 30, 30, 0, 0),
+            RawBlock::new(3, hash(5), hash(3), vec![hash(4)], 30, 30, 0, 0),
+        ]
+    }
+
+    fn edges(blocks: &[RawBlock]) -> Vec<(H256, Option<H256>, Vec<H256>)> {
+        blocks
+            .iter()
+            .map(|b| (b.hash, b.parent_hash, b.referee_hashes.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_default_keeps_everything() {
+        let options = GraphLoadOptions::default();
+        assert!(options.keeps_all());
+        assert_eq!(edges(&options.apply(raw_blocks())), edges(&raw_blocks()));
+    }
+
+    #[test]
+    fn test_dropped_blocks_are_contracted() {
+        let options = GraphLoadOptions {
+            exclude: [hash(3), hash(4)].into_iter().collect(),
+            ..Default::default()
+        };
+        assert_eq!(
+            edges(&options.apply(raw_blocks())),
+            vec![
+                (hash(1), None, vec![]),
+                (hash(2), Some(hash(1)), vec![]),
+                // Its referee 4 hangs from 2 as well, which is already its parent.
+                (hash(5), Some(hash(2)), vec![]),
+            ]
+        );
+
+        // The warm-up before timestamp 20 goes; genesis stays.
+        let options = GraphLoadOptions {
+            time_range: Some(20..100),
+            ..Default::default()
+        };
+        let graph = Graph::from_raw_blocks(options.apply(raw_blocks())).unwrap();
+        assert_eq!(graph.block_count(), 4);
+        assert_eq!(graph.get_block(&hash(3)).unwrap().parent, Some(0));
+        assert_eq!(graph.genesis_block().subtree_size, 4);
+    }
+
+    #[test]
+    fn test_filters_combine() {
+        let options = GraphLoadOptions {
+            include_only: Some([hash(2), hash(3), hash(5)].into_iter().collect()),
+            max_height: Some(2),
+            ..Default::default()
+        };
+        let kept: Vec<H256> = options.apply(raw_blocks()).iter().map(|b| b.hash).collect();
+        assert_eq!(kept, vec![hash(1), hash(2), hash(3)]);
+    }
+}
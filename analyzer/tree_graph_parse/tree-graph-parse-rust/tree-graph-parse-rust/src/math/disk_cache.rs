@@ -0,0 +1,217 @@
+//! Optional on-disk persistence for the `compute_range` caches.
+//!
+//! All entries live in one versioned file. It is read lazily the first time a `CacheID` is
+//! requested, and written back when the [`DiskCache`] guard is dropped (or on
+//! [`DiskCache::flush`]). Cached vectors are deterministic prefixes, so when several processes
+//! share the file the longest vector for each key wins.
+
+use anyhow::{bail, Context};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufReader, BufWriter, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::utils::{snapshot, CacheID};
+
+/// Bump whenever the math behind any cached value changes.
+const CACHE_FILE_VERSION: u32 = 1;
+const CACHE_FILE_MAGIC: &[u8; 4] = b"TGRC";
+
+struct DiskState {
+    path: PathBuf,
+    /// Loaded on first lookup.
+    entries: Option<HashMap<CacheID, Vec<f64>>>,
+}
+
+static DISK_STATE: Mutex<Option<DiskState>> = Mutex::new(None);
+
+/// Guard enabling the disk cache; flushes to disk when dropped.
+pub struct DiskCache {
+    _private: (),
+}
+
+impl DiskCache {
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        *DISK_STATE.lock().unwrap() = Some(DiskState {
+            path: path.as_ref().to_path_buf(),
+            entries: None,
+        });
+        DiskCache { _private: () }
+    }
+
+    /// Enable the disk cache if the `TREE_GRAPH_MATH_CACHE` environment variable names a file.
+    pub fn from_env() -> Option<Self> { std::env::var_os("TREE_GRAPH_MATH_CACHE").map(Self::open) }
+
+    pub fn flush(&self) -> anyhow::Result<()> {
+        // Take the snapshot before locking: `preload` runs under the in-memory cache lock.
+        let in_memory = snapshot();
+
+        let mut guard = DISK_STATE.lock().unwrap();
+        let Some(state) = guard.as_mut() else {
+            return Ok(());
+        };
+
+        // Another process may have extended the file since we loaded it.
+        let mut merged = read_cache_file(&state.path).unwrap_or_default();
+        for (cache_id, values) in in_memory {
+            let entry = merged.entry(cache_id).or_default();
+            if values.len() > entry.len() {
+                *entry = values;
+            }
+        }
+
+        write_cache_file(&state.path, &merged)?;
+        state.entries = Some(merged);
+        Ok(())
+    }
+}
+
+impl Drop for DiskCache {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            eprintln!("failed to flush math cache: {:#}", e);
+        }
+        *DISK_STATE.lock().unwrap() = None;
+    }
+}
+
+/// Values persisted for `cache_id`, or an empty vector if the disk cache is disabled.
+pub(super) fn preload(cache_id: CacheID) -> Vec<f64> {
+    let mut guard = DISK_STATE.lock().unwrap();
+    let Some(state) = guard.as_mut() else {
+        return vec![];
+    };
+
+    let path = &state.path;
+    let entries = state.entries.get_or_insert_with(|| {
+        read_cache_file(path).unwrap_or_else(|e| {
+            if path.exists() {
+                eprintln!("ignore math cache {}: {:#}", path.display(), e);
+            }
+            Default::default()
+        })
+    });
+    entries.get(&cache_id).cloned().unwrap_or_default()
+}
+
+fn read_cache_file(path: &Path) -> anyhow::Result<HashMap<CacheID, Vec<f64>>> {
+    let file = fs::File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != CACHE_FILE_MAGIC {
+        bail!("not a math cache file");
+    }
+    let version = read_u64(&mut reader)?;
+    if version != CACHE_FILE_VERSION as u64 {
+        bail!("cache version {} != {}", version, CACHE_FILE_VERSION);
+    }
+
+    let mut entries = HashMap::new();
+    let entry_count = read_u64(&mut reader)?;
+    for _ in 0..entry_count {
+        let tag = read_u64(&mut reader)?;
+        let a = read_u64(&mut reader)? as usize;
+        let b = read_u64(&mut reader)? as usize;
+        let cache_id = match tag {
+            0 => CacheID::HiddenMalicious(a, b),
+            1 => CacheID::RandomWalk(a),
+            2 => CacheID::LnRandomWalk(a),
+            _ => bail!("unknown cache tag {}", tag),
+        };
+
+        // A corrupt length must fail like a truncated file, not allocate.
+        let len = read_u64(&mut reader)?;
+        let remaining = file_len.saturating_sub(reader.stream_position()?);
+        if len.checked_mul(8).is_none_or(|bytes| bytes > remaining) {
+            bail!("cache entry of {} values past the end of the file", len);
+        }
+        let len = len as usize;
+        let mut values = Vec::with_capacity(len);
+        for _ in 0..len {
+            values.push(f64::from_bits(read_u64(&mut reader)?));
+        }
+        entries.insert(cache_id, values);
+    }
+    Ok(entries)
+}
+
+fn write_cache_file(path: &Path, entries: &HashMap<CacheID, Vec<f64>>) -> anyhow::Result<()> {
+    // Write to a sibling file and rename, so concurrent readers never see a partial file.
+    let tmp_path = path.with_extension(format!("tmp.{}", std::process::id()));
+    {
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+        writer.write_all(CACHE_FILE_MAGIC)?;
+        writer.write_all(&(CACHE_FILE_VERSION as u64).to_le_bytes())?;
+        writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+        for (cache_id, values) in entries {
+            let (tag, a, b) = match *cache_id {
+                CacheID::HiddenMalicious(m, adv_percent) => (0, m, adv_percent),
+                CacheID::RandomWalk(adv_percent) => (1, adv_percent, 0),
+                CacheID::LnRandomWalk(adv_percent) => (2, adv_percent, 0),
+            };
+            for word in [tag, a as u64, b as u64, values.len() as u64] {
+                writer.write_all(&word.to_le_bytes())?;
+            }
+            for v in values {
+                writer.write_all(&v.to_bits().to_le_bytes())?;
+            }
+        }
+        writer.flush()?;
+    }
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("rename {} to {}", tmp_path.display(), path.display()))?;
+    Ok(())
+}
+
+fn read_u64(reader: &mut impl Read) -> std::io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_file_roundtrip() {
+        let path = std::env::temp_dir().join(format!("tg_math_cache_{}.bin", std::process::id()));
+        let mut entries = HashMap::new();
+        entries.insert(CacheID::HiddenMalicious(100, 20), vec![0.5, 0.25, 1e-300]);
+        entries.insert(CacheID::RandomWalk(30), vec![]);
+
+        write_cache_file(&path, &entries).unwrap();
+        let loaded = read_cache_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, entries);
+    }
+
+    #[test]
+    fn test_corrupt_length_is_an_error() {
+        let path =
+            std::env::temp_dir().join(format!("tg_math_cache_len_{}.bin", std::process::id()));
+        let mut entries = HashMap::new();
+        entries.insert(CacheID::RandomWalk(30), vec![0.5; 4]);
+        write_cache_file(&path, &entries).unwrap();
+
+        // The length field of the only entry follows magic, version, count, tag, a and b.
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[44..52].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let corrupt = read_cache_file(&path);
+        bytes[44..52].copy_from_slice(&5u64.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let truncated = read_cache_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(corrupt.is_err());
+        assert!(truncated.is_err());
+    }
+}
@@ -1,35 +1,37 @@
-use std::sync::atomic::AtomicUsize;
-
 use statrs::{
     distribution::{Discrete, NegativeBinomial},
     function::beta::beta_reg,
 };
 
-use super::utils::BATCH_SIZE;
-
-pub fn compute_hidden_malicious_blocks_batch(
-    start_k: usize, m: usize, adv_percent: usize,
-) -> [f64; BATCH_SIZE] {
-    static CNT: AtomicUsize = AtomicUsize::new(0);
-    let cnt = CNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-    dbg!(cnt);
-
+pub fn compute_hidden_malicious_blocks(k: usize, m: usize, adv_percent: usize) -> f64 {
     let prob = 1. - adv_percent as f64 / 100.0;
     let nb_dist = NegativeBinomial::new(m as f64 + 1., prob).unwrap();
-
-    let mut answer = [0.; BATCH_SIZE];
-
-    for i in 0..BATCH_SIZE {
-        answer[i] = nb_dist.pmf((start_k + i) as u64);
-    }
-
-    return answer;
+    nb_dist.pmf(k as u64)
 }
 
-pub fn compute_hidden_malicious_blocks(k: usize, m: usize, adv_percent: usize) -> f64 {
+/// Extends `pmf`, where `pmf[k] = compute_hidden_malicious_blocks(k, m, adv_percent)`, to
+/// `length` entries.
+///
+/// The distribution is built once for the first missing entry; the following ones come from
+/// the recurrence `pmf(k + 1) = pmf(k) * q * (k + r) / (k + 1)` with `r = m + 1`, stepped in the
+/// log domain so that it still holds where `pmf(k)` underflows for large `m`.
+pub fn extend_hidden_malicious_blocks(
+    pmf: &mut Vec<f64>, length: usize, m: usize, adv_percent: usize,
+) {
+    let start = pmf.len();
+    if start >= length {
+        return;
+    }
     let prob = 1. - adv_percent as f64 / 100.0;
     let nb_dist = NegativeBinomial::new(m as f64 + 1., prob).unwrap();
-    nb_dist.pmf(k as u64)
+    let (r, ln_q) = ((m + 1) as f64, (1. - prob).ln());
+
+    let mut ln_pmf = nb_dist.ln_pmf(start as u64);
+    pmf.reserve(length - start);
+    for k in start..length {
+        pmf.push(ln_pmf.exp());
+        ln_pmf += ln_q + ((k as f64 + r) / (k + 1) as f64).ln();
+    }
 }
 
 /// Given 恶意节点算力占比 b, m 个诚实区块，不诚实区块 >= k 的概率。
@@ -54,6 +56,30 @@ mod tests {
     use super::*;
     use statrs::distribution::DiscreteCDF;
 
+    #[test]
+    fn test_extend_matches_pointwise() {
+        for (m, adv_percent) in [(0, 10), (50, 20), (7000, 30)] {
+            let mut pmf = vec![];
+            extend_hidden_malicious_blocks(&mut pmf, 2000, m, adv_percent);
+            // Continuing a cached prefix gives the same values.
+            let mut continued = pmf[..700].to_vec();
+            extend_hidden_malicious_blocks(&mut continued, 4000, m, adv_percent);
+            assert_eq!(continued.len(), 4000);
+
+            for k in (0..4000).step_by(7) {
+                let expected = compute_hidden_malicious_blocks(k, m, adv_percent);
+                assert!(
+                    (continued[k] - expected).abs() <= expected * 1e-9,
+                    "m={m} adv={adv_percent}% k={k}: {} vs {expected}",
+                    continued[k]
+                );
+                if k < 700 {
+                    assert_eq!(pmf[k], continued[k]);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_hidden_malicious_blocks() {
         use statrs::prec::almost_eq;
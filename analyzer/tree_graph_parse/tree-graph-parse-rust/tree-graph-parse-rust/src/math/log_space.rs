@@ -0,0 +1,137 @@
+//! Log-domain evaluation of the confirmation risk.
+//!
+//! `normal_confirmation_risk` works in `f64`/`f32` and its callers clamp the result at `1e-12`.
+//! This path keeps every intermediate value as a natural logarithm, so risks far below the
+//! smallest `f32` (down to and beyond `1e-20`) stay meaningful for the security reports.
+
+use statrs::distribution::{Discrete, DiscreteCDF, NegativeBinomial};
+
+use super::{
+    random_walk::{geometric_ratio, log_prob, min_s},
+    utils::{compute_range, CacheID},
+};
+
+/// Below this, `NegativeBinomial::sf` is no longer trusted and the tail is summed explicitly.
+const MIN_DIRECT_SF: f64 = 1e-250;
+
+/// ln(e^a + e^b)
+pub fn ln_add(a: f64, b: f64) -> f64 {
+    if a == f64::NEG_INFINITY {
+        return b;
+    }
+    if b == f64::NEG_INFINITY {
+        return a;
+    }
+    let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+    hi + (lo - hi).exp().ln_1p()
+}
+
+/// ln of `compute_random_walk_prob`, without the underflow cut-off of the linear version.
+pub fn ln_random_walk_prob(k: usize, adv_percent: usize) -> f64 {
+    let b = adv_percent as f64 / 100.;
+    assert!((0.0..0.5).contains(&b), "b 必须在 (0, 0.5) 之间");
+    if k == 0 {
+        return f64::NEG_INFINITY;
+    }
+
+    // Relative contribution below which the remaining terms are summarized by a geometric tail.
+    const LN_RELATIVE_ERROR_LIMIT: f64 = -27.6; // ln(1e-12)
+
+    let k = k as i64;
+    let r = geometric_ratio(b);
+    let ln_tail_factor = (r / (1. - r)).ln();
+
+    let mut sum = f64::NEG_INFINITY;
+    let mut n = k + 1;
+    loop {
+        let term = log_prob(n, k, b, min_s(n, k, b)).min(0.);
+        sum = ln_add(sum, term);
+        if sum >= 0. {
+            return 0.;
+        }
+
+        n += 1;
+        if n % 10 == 0 && term + ln_tail_factor < sum + LN_RELATIVE_ERROR_LIMIT {
+            return ln_add(sum, term + ln_tail_factor).min(0.);
+        }
+    }
+}
+
+/// ln P(at least `adv + 1` hidden blocks while the honest nodes produce `m + 1` blocks).
+fn ln_hidden_sf(nb_dist: &NegativeBinomial, adv: usize) -> f64 {
+    let sf = nb_dist.sf(adv as u64);
+    if sf > MIN_DIRECT_SF {
+        return sf.ln();
+    }
+
+    // Deep in the tail the terms shrink geometrically, so the sum converges quickly.
+    let mut sum = f64::NEG_INFINITY;
+    let mut k = adv as u64 + 1;
+    loop {
+        let term = nb_dist.ln_pmf(k);
+        sum = ln_add(sum, term);
+        if term < sum - 40. {
+            return sum;
+        }
+        k += 1;
+    }
+}
+
+/// ln of `normal_confirmation_risk`.
+pub fn ln_normal_confirmation_risk(adv_percent: usize, m: usize, adv: usize) -> f64 {
+    let prob = 1. - adv_percent as f64 / 100.0;
+    let nb_dist = NegativeBinomial::new(m as f64 + 1., prob).unwrap();
+
+    let ln_random_walk_prob = compute_range(adv + 1, CacheID::LnRandomWalk(adv_percent), |k| {
+        ln_random_walk_prob(k, adv_percent)
+    });
+
+    let mut sum = ln_hidden_sf(&nb_dist, adv);
+    for k in 0..adv {
+        sum = ln_add(sum, nb_dist.ln_pmf(k as u64) + ln_random_walk_prob[adv - k]);
+    }
+    sum.min(0.)
+}
+
+/// `normal_confirmation_risk` in `f64`, accurate for risks far below `1e-12`.
+pub fn precise_confirmation_risk(adv_percent: usize, m: usize, adv: usize) -> f64 {
+    ln_normal_confirmation_risk(adv_percent, m, adv).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{normal_confirmation_risk, random_walk::compute_random_walk_prob};
+
+    #[test]
+    fn test_matches_linear_domain() {
+        for (adv_percent, m, adv) in [(10, 100, 20), (20, 100, 40), (30, 200, 60)] {
+            let linear = normal_confirmation_risk(adv_percent, m, adv) as f64;
+            let precise = precise_confirmation_risk(adv_percent, m, adv);
+            assert!(
+                (linear - precise).abs() < linear * 1e-4,
+                "{linear} vs {precise}"
+            );
+        }
+
+        for (k, adv_percent) in [(10, 20), (100, 30), (300, 40)] {
+            let linear = compute_random_walk_prob(k, adv_percent);
+            let precise = ln_random_walk_prob(k, adv_percent).exp();
+            assert!(
+                (linear - precise).abs() < linear * 1e-6,
+                "{linear} vs {precise}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_extreme_tail() {
+        // Far below the f32 range: the linear path returns 0 here.
+        let ln_risk = ln_normal_confirmation_risk(20, 1000, 600);
+        assert!(ln_risk.is_finite());
+        assert!(ln_risk < (1e-50f64).ln());
+
+        let risk = precise_confirmation_risk(10, 100, 60);
+        assert!(risk > 1e-21 && risk < 1e-18, "{risk}");
+    }
+}
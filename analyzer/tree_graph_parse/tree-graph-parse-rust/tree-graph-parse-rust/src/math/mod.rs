@@ -1,15 +1,27 @@
+#[cfg(feature = "native")]
+mod disk_cache;
 pub mod hidden_malicious_blocks;
+pub mod log_space;
 pub mod random_walk;
+pub mod risk;
+pub mod risk_model;
+pub mod simulation;
 mod utils;
 
 use statrs::distribution::{DiscreteCDF, NegativeBinomial};
+use std::{collections::HashMap, ops::Range};
 
 use self::{
-    hidden_malicious_blocks::compute_hidden_malicious_blocks,
-    random_walk::compute_random_walk_prob, utils::compute_range,
+    hidden_malicious_blocks::{compute_hidden_malicious_blocks, extend_hidden_malicious_blocks},
+    random_walk::compute_random_walk_prob,
+    utils::{compute_range, compute_range_with},
 };
 
+#[cfg(feature = "native")]
+pub use disk_cache::DiskCache;
+pub use risk_model::{HiddenMaliciousRandomWalk, Nakamoto, RiskModel};
 use utils::CacheID;
+pub use utils::{cache_limits, cache_stats, set_cache_limits, CacheLimits, CacheStats};
 
 pub fn normal_confirmation_risk(adv_percent: usize, m: usize, adv: usize) -> f32 {
     let prob = 1. - adv_percent as f64 / 100.0;
@@ -18,8 +30,8 @@ pub fn normal_confirmation_risk(adv_percent: usize, m: usize, adv: usize) -> f32
     let random_walk_prob = compute_range(adv + 1, CacheID::RandomWalk(adv_percent), |k| {
         compute_random_walk_prob(k, adv_percent)
     });
-    let pmf_list = compute_range(adv, CacheID::HiddenMalicious(m, adv_percent), |k| {
-        compute_hidden_malicious_blocks(k, m, adv_percent)
+    let pmf_list = compute_range_with(adv, CacheID::HiddenMalicious(m, adv_percent), |pmf, len| {
+        extend_hidden_malicious_blocks(pmf, len, m, adv_percent)
     });
 
     let mut sum = 0.0;
@@ -30,3 +42,142 @@ pub fn normal_confirmation_risk(adv_percent: usize, m: usize, adv: usize) -> f32
     sum += nb_dist.sf(adv as u64);
     sum as f32
 }
+
+/// Random-walk terms below this are dropped from the batched sum; they are far below any risk
+/// threshold in use.
+const NEGLIGIBLE_WALK_PROB: f64 = 1e-40;
+
+/// `normal_confirmation_risk` for every `m` in `m_range` with a fixed `adv`.
+///
+/// The distribution is only built for the first point; the following ones are derived by
+/// recurrences in `r = m + 1` of the negative binomial distribution:
+/// `pmf_{r+1}(k) = pmf_r(k) * p * (k + r) / r` and
+/// `sf_{r+1}(a) = sf_r(a) + q * pmf_r(a) * (a + r) / r`.
+/// Only hidden-block counts `k` whose random-walk term is not negligible are tracked, so the cost
+/// per point no longer grows with `adv`.
+pub fn normal_confirmation_risk_batch(
+    adv_percent: usize, m_range: Range<usize>, adv: usize,
+) -> Vec<f32> {
+    if m_range.is_empty() {
+        return vec![];
+    }
+
+    let prob = 1. - adv_percent as f64 / 100.0;
+    let m_start = m_range.start;
+    let nb_dist = NegativeBinomial::new(m_start as f64 + 1., prob).unwrap();
+
+    let random_walk_prob = compute_range(adv + 1, CacheID::RandomWalk(adv_percent), |k| {
+        compute_random_walk_prob(k, adv_percent)
+    });
+    let window = random_walk_prob
+        .iter()
+        .skip(1)
+        .position(|&p| p < NEGLIGIBLE_WALK_PROB)
+        .map_or(adv, |j| j + 1)
+        .min(adv);
+    let k_start = adv - window;
+
+    // pmf_list[i] = pmf(k_start + i), for k in [k_start, adv].
+    let r = (m_start + 1) as f64;
+    let mut pmf_list = Vec::with_capacity(window + 1);
+    pmf_list.push(compute_hidden_malicious_blocks(
+        k_start,
+        m_start,
+        adv_percent,
+    ));
+    for k in k_start..adv {
+        let last = *pmf_list.last().unwrap();
+        pmf_list.push(last * (k as f64 + r) / (k + 1) as f64 * (1. - prob));
+    }
+    let mut sf = nb_dist.sf(adv as u64);
+
+    let mut answer = Vec::with_capacity(m_range.len());
+    for m in m_range {
+        let mut sum = sf;
+        for (i, pmf) in pmf_list[..window].iter().enumerate() {
+            sum += pmf * random_walk_prob[window - i];
+        }
+        answer.push(sum as f32);
+
+        let r = (m + 1) as f64;
+        sf += (1. - prob) * pmf_list[window] * (adv as f64 + r) / r;
+        for (i, pmf) in pmf_list.iter_mut().enumerate() {
+            *pmf *= prob * ((k_start + i) as f64 + r) / r;
+        }
+    }
+    answer
+}
+
+/// Precomputed confirmation risks for a known set of `(m, adv)` points.
+///
+/// Points sharing `adv` are evaluated in increasing `m`: close points are reached through the
+/// model's batched evaluation (the recurrences of `normal_confirmation_risk_batch` for the
+/// default model), distant ones restart from the caches.
+pub struct RiskTable {
+    risks: HashMap<(usize, usize), f32>,
+}
+
+impl RiskTable {
+    /// Beyond this gap in `m`, restarting from the caches is cheaper than stepping.
+    const MAX_STEP: usize = 16;
+
+    pub fn build(adv_percent: usize, points: impl IntoIterator<Item = (usize, usize)>) -> Self {
+        Self::build_with(&HiddenMaliciousRandomWalk, adv_percent, points)
+    }
+
+    pub fn build_with(
+        model: &dyn RiskModel, adv_percent: usize, points: impl IntoIterator<Item = (usize, usize)>,
+    ) -> Self {
+        let mut m_by_adv: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (m, adv) in points {
+            m_by_adv.entry(adv).or_default().push(m);
+        }
+
+        let mut risks = HashMap::new();
+        for (adv, mut ms) in m_by_adv {
+            ms.sort_unstable();
+            ms.dedup();
+
+            let mut chunk_start = 0;
+            for i in 1..=ms.len() {
+                if i < ms.len() && ms[i] - ms[i - 1] <= Self::MAX_STEP {
+                    continue;
+                }
+                let chunk = &ms[chunk_start..i];
+                let first = chunk[0];
+                let batch = model.confirmation_risk_batch(
+                    adv_percent,
+                    first..chunk[chunk.len() - 1] + 1,
+                    adv,
+                );
+                for &m in chunk {
+                    risks.insert((m, adv), batch[m - first]);
+                }
+                chunk_start = i;
+            }
+        }
+        RiskTable { risks }
+    }
+
+    /// Panics if `(m, adv)` was not among the points the table was built from.
+    pub fn get(&self, m: usize, adv: usize) -> f32 { self.risks[&(m, adv)] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_matches_pointwise() {
+        for (adv_percent, adv) in [(10, 1), (20, 15), (30, 40), (20, 600)] {
+            let batch = normal_confirmation_risk_batch(adv_percent, 50..400, adv);
+            for (m, risk) in (50..400).zip(batch) {
+                let expected = normal_confirmation_risk(adv_percent, m, adv);
+                assert!(
+                    (risk - expected).abs() <= expected * 1e-4 + 1e-30,
+                    "adv={adv_percent}% m={m} k={adv}: {risk} vs {expected}"
+                );
+            }
+        }
+    }
+}
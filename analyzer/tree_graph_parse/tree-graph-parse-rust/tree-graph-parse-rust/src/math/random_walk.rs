@@ -77,14 +77,14 @@ fn g(s: f64, b: f64) -> f64 { (b * s.exp() + (1.0 - b) * (-s).exp()).ln() }
 
 // 对应 MMA 中的 logProb[n_,k_,b_,s_] := g[s,b]*n - s*k
 /// 计算对数概率函数：n*g(s,b) - s*k
-fn log_prob(n: i64, k: i64, b: f64, s: f64) -> f64 {
+pub(super) fn log_prob(n: i64, k: i64, b: f64, s: f64) -> f64 {
     let g_value = g(s, b);
     (n as f64) * g_value - (k as f64) * s
 }
 
 // 对应 MMA 的公式：1/2 Log[(-k + b k - n + b n)/(b (k - n))]
 /// 计算 s 的最优解：0.5 * ln[ ((1-b)(k+n)) / (b(n-k)) ]
-fn min_s(n: i64, k: i64, b: f64) -> f64 {
+pub(super) fn min_s(n: i64, k: i64, b: f64) -> f64 {
     let numerator = (1.0 - b) * (k + n) as f64;
     let denominator = b * (n - k) as f64;
     0.5 * (numerator / denominator).ln()
@@ -108,7 +108,7 @@ fn term_inf_approx(n: i64, k: i64, b: f64, s_inf: f64) -> f64 {
 
 // 对应 MMA 中发现的等比数列性质：r = Exp[g(min_s_inf(b), b)] = 2*sqrt(b(1-b))
 /// 计算无穷级数的公比：r = 2√[b(1-b)]
-fn geometric_ratio(b: f64) -> f64 { 2.0 * (b * (1.0 - b)).sqrt() }
+pub(super) fn geometric_ratio(b: f64) -> f64 { 2.0 * (b * (1.0 - b)).sqrt() }
 
 #[cfg(test)]
 mod tests {
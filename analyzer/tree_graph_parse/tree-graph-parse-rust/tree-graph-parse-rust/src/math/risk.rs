@@ -0,0 +1,178 @@
+//! The confirmation risk math, for tools that use it without a graph.
+//!
+//! The functions of this module check their inputs and return an error where the ones they wrap
+//! panic or return NaN. The adversary power `adv_percent` must be in `1..=49`: at 50% and above
+//! the adversary eventually overtakes any lead and the bounds below do not converge, and without
+//! an adversary there is nothing to bound.
+//!
+//! The model is the one of `HiddenMaliciousRandomWalk`: while the honest nodes produce `m` blocks
+//! since the parent of a block, the adversary mines in private a number of blocks that follows
+//! a negative binomial distribution. If those are fewer than the `adv` blocks by which the
+//! block's subtree leads its heaviest sibling, the adversary then races the remaining deficit as
+//! a random walk. Values computed here fill the caches shared with the graph analyses.
+
+use anyhow::{ensure, Result};
+
+use super::{
+    hidden_malicious_blocks::{
+        compute_hidden_malicious_blocks_prob, extend_hidden_malicious_blocks,
+    },
+    normal_confirmation_risk_batch,
+    random_walk::compute_random_walk_prob,
+    utils::{compute_range, compute_range_with, CacheID},
+};
+
+/// Adversary powers, in percent of the mining power, the risk math accepts.
+pub const ADV_PERCENT_RANGE: std::ops::RangeInclusive<usize> = 1..=49;
+
+fn check_adv_percent(adv_percent: usize) -> Result<()> {
+    ensure!(
+        ADV_PERCENT_RANGE.contains(&adv_percent),
+        "adversary power must be in 1..=49 percent, got {adv_percent}"
+    );
+    Ok(())
+}
+
+/// Probability that the block is reverted. Takes time and memory linear in `adv`.
+pub fn confirmation_risk(adv_percent: usize, m: usize, adv: usize) -> Result<f32> {
+    check_adv_percent(adv_percent)?;
+    Ok(super::normal_confirmation_risk(adv_percent, m, adv))
+}
+
+/// `confirmation_risk` for every `m` in `m_range` with a fixed `adv`, faster than one call per
+/// point.
+pub fn confirmation_risk_curve(
+    adv_percent: usize, m_range: std::ops::Range<usize>, adv: usize,
+) -> Result<Vec<f32>> {
+    check_adv_percent(adv_percent)?;
+    Ok(normal_confirmation_risk_batch(adv_percent, m_range, adv))
+}
+
+/// Upper bound on the probability that the adversary ever catches up a deficit of `k` blocks
+/// in the random walk, 0 for `k = 0`. Always at least the gambler's ruin probability
+/// `(q / p)^k` of the walk.
+pub fn random_walk_bound(k: usize, adv_percent: usize) -> Result<f64> {
+    check_adv_percent(adv_percent)?;
+    let bounds = compute_range(k + 1, CacheID::RandomWalk(adv_percent), |k| {
+        compute_random_walk_prob(k, adv_percent)
+    });
+    Ok(bounds[k])
+}
+
+/// Probabilities that the adversary mined exactly `0..len` blocks in private while the honest
+/// nodes produced `m`.
+pub fn hidden_blocks_pmf(len: usize, m: usize, adv_percent: usize) -> Result<Vec<f64>> {
+    check_adv_percent(adv_percent)?;
+    Ok(compute_range_with(
+        len,
+        CacheID::HiddenMalicious(m, adv_percent),
+        |pmf, len| extend_hidden_malicious_blocks(pmf, len, m, adv_percent),
+    ))
+}
+
+/// Probability that the adversary mined at least `k` blocks in private while the honest nodes
+/// produced `m`.
+pub fn hidden_blocks_at_least(k: usize, m: usize, adv_percent: usize) -> Result<f64> {
+    check_adv_percent(adv_percent)?;
+    Ok(compute_hidden_malicious_blocks_prob(
+        adv_percent as f64 / 100.,
+        m,
+        k,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(value: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (value - expected).abs() <= expected.abs() * tolerance,
+            "{value} vs {expected}"
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_adversary_power() {
+        for adv_percent in [0, 50, 51, 100] {
+            assert!(confirmation_risk(adv_percent, 10, 3).is_err());
+            assert!(confirmation_risk_curve(adv_percent, 10..20, 3).is_err());
+            assert!(random_walk_bound(3, adv_percent).is_err());
+            assert!(hidden_blocks_pmf(3, 10, adv_percent).is_err());
+            assert!(hidden_blocks_at_least(3, 10, adv_percent).is_err());
+        }
+        assert!(confirmation_risk(49, 10, 3).is_ok());
+    }
+
+    #[test]
+    fn test_hidden_blocks_reference_values() {
+        // With r = m + 1 honest blocks to wait for, pmf(k) = C(k + m, k) p^(m + 1) q^k.
+        let pmf = hidden_blocks_pmf(5, 0, 30).unwrap();
+        for (k, value) in pmf.into_iter().enumerate() {
+            assert_close(value, 0.7 * 0.3f64.powi(k as i32), 1e-12);
+        }
+        let pmf = hidden_blocks_pmf(5, 1, 30).unwrap();
+        for (k, value) in pmf.into_iter().enumerate() {
+            assert_close(value, (k + 1) as f64 * 0.49 * 0.3f64.powi(k as i32), 1e-12);
+        }
+
+        assert_eq!(hidden_blocks_at_least(0, 10, 30).unwrap(), 1.);
+        assert_close(hidden_blocks_at_least(3, 0, 30).unwrap(), 0.027, 1e-9);
+        assert_close(
+            hidden_blocks_at_least(2, 1, 20).unwrap(),
+            1. - 0.64 - 2. * 0.64 * 0.2,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn test_random_walk_bounds_gamblers_ruin() {
+        assert_eq!(random_walk_bound(0, 20).unwrap(), 0.);
+        for (adv_percent, k) in [(10, 1), (10, 5), (20, 10), (30, 10), (40, 100), (49, 20)] {
+            let bound = random_walk_bound(k, adv_percent).unwrap();
+            let q = adv_percent as f64 / 100.;
+            assert!(bound <= 1.);
+            assert!(bound >= (q / (1. - q)).powi(k as i32));
+        }
+        // Values of the upper bound, to catch changes of the series it sums.
+        assert_close(
+            random_walk_bound(5, 10).unwrap(),
+            7.490928861969597e-5,
+            1e-9,
+        );
+        assert_close(
+            random_walk_bound(10, 30).unwrap(),
+            6.484177036440052e-3,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn test_confirmation_risk_reference_values() {
+        // Without a lead only a private chain of length 0 saves the block: 1 - p^(m + 1).
+        assert_close(
+            confirmation_risk(20, 10, 0).unwrap() as f64,
+            1. - 0.8f64.powi(11),
+            1e-6,
+        );
+        // With a lead of 1: no hidden block then the walk, or more than one hidden block.
+        let p_m = 0.8f64.powi(11);
+        let expected = p_m * random_walk_bound(1, 20).unwrap() + 1. - p_m - 11. * p_m * 0.2;
+        assert_close(confirmation_risk(20, 10, 1).unwrap() as f64, expected, 1e-6);
+
+        for (adv_percent, m, adv, expected) in [
+            (10, 10, 3, 1.0987134e-1),
+            (20, 100, 30, 2.6342672e-1),
+            (30, 50, 40, 1.569853e-2),
+        ] {
+            assert_close(
+                confirmation_risk(adv_percent, m, adv).unwrap() as f64,
+                expected,
+                1e-6,
+            );
+        }
+
+        let curve = confirmation_risk_curve(20, 95..105, 30).unwrap();
+        assert_close(curve[5] as f64, 2.6342672e-1, 1e-4);
+    }
+}
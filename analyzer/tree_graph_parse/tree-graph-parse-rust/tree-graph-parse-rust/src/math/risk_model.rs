@@ -0,0 +1,135 @@
+//! Adversary models behind the confirmation risk.
+//!
+//! Every model answers the same question: the adversary owns `adv_percent`% of the mining power,
+//! the honest nodes have produced `m` blocks since the block's parent, and the block's subtree
+//! leads its heaviest sibling by `adv` blocks. How likely is the block to be reverted?
+
+use std::ops::Range;
+
+use statrs::distribution::{Discrete, DiscreteCDF, Poisson};
+
+use super::{
+    log_space::precise_confirmation_risk, normal_confirmation_risk, normal_confirmation_risk_batch,
+};
+
+pub trait RiskModel: Sync {
+    fn name(&self) -> &'static str;
+
+    fn confirmation_risk(&self, adv_percent: usize, m: usize, adv: usize) -> f32;
+
+    /// `confirmation_risk` for every `m` in `m_range` with a fixed `adv`.
+    fn confirmation_risk_batch(
+        &self, adv_percent: usize, m_range: Range<usize>, adv: usize,
+    ) -> Vec<f32> {
+        m_range
+            .map(|m| self.confirmation_risk(adv_percent, m, adv))
+            .collect()
+    }
+
+    /// `confirmation_risk` in `f64`, for thresholds below the `f32` clamp.
+    fn precise_confirmation_risk(&self, adv_percent: usize, m: usize, adv: usize) -> f64 {
+        self.confirmation_risk(adv_percent, m, adv) as f64
+    }
+}
+
+/// The default model: the adversary withholds blocks (negative binomial) while the honest nodes
+/// produce `m` blocks, then races the remaining deficit as a random walk.
+pub struct HiddenMaliciousRandomWalk;
+
+impl RiskModel for HiddenMaliciousRandomWalk {
+    fn name(&self) -> &'static str { "hidden-malicious" }
+
+    fn confirmation_risk(&self, adv_percent: usize, m: usize, adv: usize) -> f32 {
+        normal_confirmation_risk(adv_percent, m, adv)
+    }
+
+    fn confirmation_risk_batch(
+        &self, adv_percent: usize, m_range: Range<usize>, adv: usize,
+    ) -> Vec<f32> {
+        normal_confirmation_risk_batch(adv_percent, m_range, adv)
+    }
+
+    fn precise_confirmation_risk(&self, adv_percent: usize, m: usize, adv: usize) -> f64 {
+        precise_confirmation_risk(adv_percent, m, adv)
+    }
+}
+
+/// The private-chain attack of the Bitcoin whitepaper (section 11): the adversary's progress
+/// while the honest nodes produce `m` blocks is Poisson with mean `m * q / p`, and a deficit of
+/// `z` blocks is caught up with probability `(q / p)^z`.
+pub struct Nakamoto;
+
+impl Nakamoto {
+    fn risk(adv_percent: usize, m: usize, adv: usize) -> f64 {
+        let q = adv_percent as f64 / 100.;
+        let p = 1. - q;
+        assert!((0.0..0.5).contains(&q), "b 必须在 (0, 0.5) 之间");
+        if adv == 0 {
+            return 1.;
+        }
+        if q == 0. {
+            return 0.;
+        }
+
+        let ratio = q / p;
+        let lambda = m.max(1) as f64 * ratio;
+        let poisson = Poisson::new(lambda).unwrap();
+
+        let mut sum = poisson.sf(adv as u64);
+        for k in 0..=adv {
+            sum += poisson.pmf(k as u64) * ratio.powi((adv - k) as i32);
+        }
+        sum.min(1.)
+    }
+}
+
+impl RiskModel for Nakamoto {
+    fn name(&self) -> &'static str { "nakamoto" }
+
+    fn confirmation_risk(&self, adv_percent: usize, m: usize, adv: usize) -> f32 {
+        Self::risk(adv_percent, m, adv) as f32
+    }
+
+    fn precise_confirmation_risk(&self, adv_percent: usize, m: usize, adv: usize) -> f64 {
+        Self::risk(adv_percent, m, adv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nakamoto_whitepaper_table() {
+        // q = 0.1 and q = 0.3 rows of the whitepaper, where the attacker's progress is counted
+        // over the z blocks the honest chain needs.
+        for (q, z, expected) in [
+            (10, 1, 0.2045873),
+            (10, 5, 0.0009137),
+            (10, 10, 0.0000012),
+            (30, 5, 0.1773523),
+            (30, 10, 0.0416605),
+        ] {
+            let risk = Nakamoto.precise_confirmation_risk(q, z, z);
+            assert!((risk - expected).abs() < 1e-6, "q={q}% z={z}: {risk}");
+        }
+    }
+
+    #[test]
+    fn test_models_are_monotone() {
+        let models: [&dyn RiskModel; 2] = [&HiddenMaliciousRandomWalk, &Nakamoto];
+        for model in models {
+            assert!(model.confirmation_risk(20, 50, 10) > model.confirmation_risk(20, 50, 20));
+            assert!(model.confirmation_risk(30, 50, 20) > model.confirmation_risk(20, 50, 20));
+            let batch = model.confirmation_risk_batch(20, 40..42, 15);
+            for (m, risk) in (40..42).zip(batch) {
+                let expected = model.confirmation_risk(20, m, 15);
+                assert!(
+                    (risk - expected).abs() <= expected * 1e-4,
+                    "{}",
+                    model.name()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Monte-Carlo validation of `normal_confirmation_risk`.
+//!
+//! Each trial replays the scenario assumed by the analytical model: the honest subtree of the
+//! target block leads its heaviest sibling by `adv` blocks after `m` honest blocks have been
+//! observed, while the adversary has been secretly mining a competing subtree. The trial counts
+//! as a reversal if the adversary's private blocks ever overtake the honest advantage.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::normal_confirmation_risk;
+
+#[derive(Debug, Clone)]
+pub struct SimulationParams {
+    pub adv_percent: usize,
+    /// Honest blocks observed when the confirmation decision is made.
+    pub m: usize,
+    /// Observed subtree advantage of the target block.
+    pub adv: usize,
+    pub trials: usize,
+    /// Honest block generation rate, in blocks per second.
+    pub block_rate: f64,
+    /// Honest blocks produced within this many seconds of each other may fork each other and
+    /// do not always extend the honest subtree.
+    pub network_delay: f64,
+    /// The race is considered lost by the adversary after this many blocks.
+    pub max_race_blocks: usize,
+    pub seed: u64,
+}
+
+impl Default for SimulationParams {
+    fn default() -> Self {
+        SimulationParams {
+            adv_percent: 20,
+            m: 100,
+            adv: 10,
+            trials: 100_000,
+            block_rate: 2.,
+            network_delay: 0.,
+            max_race_blocks: 10_000,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationResult {
+    pub trials: usize,
+    pub reversals: usize,
+    pub empirical_risk: f64,
+    /// 95% Wilson score interval of the empirical risk.
+    pub ci_low: f64,
+    pub ci_high: f64,
+    pub analytical_risk: f64,
+}
+
+impl SimulationResult {
+    /// Whether the analytical value is an upper bound of the empirical risk within the
+    /// confidence interval.
+    pub fn analytical_bounds_empirical(&self) -> bool { self.analytical_risk >= self.ci_low }
+}
+
+pub fn simulate_reversal(params: &SimulationParams) -> SimulationResult {
+    let b = params.adv_percent as f64 / 100.;
+    assert!((0.0..0.5).contains(&b), "adv_percent must be in [0, 50)");
+
+    let mut rng = StdRng::seed_from_u64(params.seed);
+
+    // Probability that an honest block lands within the delay window of the previous honest
+    // block; half of those end up on a competing branch and do not extend the honest subtree.
+    let honest_rate = (1. - b) * params.block_rate;
+    let wasted_prob = 0.5 * (1. - (-honest_rate * params.network_delay).exp());
+
+    let mut reversals = 0;
+    for _ in 0..params.trials {
+        let hidden = hidden_blocks(&mut rng, params.m, b);
+        if hidden > params.adv || race(&mut rng, params, b, wasted_prob, hidden) {
+            reversals += 1;
+        }
+    }
+
+    let empirical_risk = reversals as f64 / params.trials as f64;
+    let (ci_low, ci_high) = wilson_interval(reversals, params.trials, 1.96);
+    SimulationResult {
+        trials: params.trials,
+        reversals,
+        empirical_risk,
+        ci_low,
+        ci_high,
+        analytical_risk: normal_confirmation_risk(params.adv_percent, params.m, params.adv) as f64,
+    }
+}
+
+/// Adversary blocks mined before the honest nodes produce `m + 1` blocks, i.e. a sample of
+/// NegativeBinomial(m + 1, 1 - b). Drawn block by block: the gamma-Poisson sampler shipped with
+/// statrs uses the wrong gamma parameterization.
+fn hidden_blocks(rng: &mut StdRng, m: usize, b: f64) -> usize {
+    let mut honest = 0;
+    let mut hidden = 0;
+    while honest <= m {
+        if rng.gen_bool(b) {
+            hidden += 1;
+        } else {
+            honest += 1;
+        }
+    }
+    hidden
+}
+
+/// Random walk of the remaining deficit of the adversary. Returns true once the adversary is
+/// strictly ahead; a tie is resolved in favour of the honest subtree, as in the analytical model.
+fn race(
+    rng: &mut StdRng, params: &SimulationParams, b: f64, wasted_prob: f64, hidden: usize,
+) -> bool {
+    // Catching up from this deficit has probability (b / (1 - b))^deficit < 1e-15.
+    let hopeless_deficit = (1e-15f64.ln() / (b / (1. - b)).ln()).ceil() as i64;
+
+    let mut deficit = (params.adv - hidden) as i64;
+    for _ in 0..params.max_race_blocks {
+        if deficit > hopeless_deficit {
+            return false;
+        }
+        if rng.gen_bool(b) {
+            deficit -= 1;
+            if deficit < 0 {
+                return true;
+            }
+        } else if !rng.gen_bool(wasted_prob) {
+            deficit += 1;
+        }
+    }
+    false
+}
+
+fn wilson_interval(successes: usize, trials: usize, z: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0., 1.);
+    }
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let denominator = 1. + z * z / n;
+    let center = (p + z * z / (2. * n)) / denominator;
+    let margin = z * (p * (1. - p) / n + z * z / (4. * n * n)).sqrt() / denominator;
+    ((center - margin).max(0.), (center + margin).min(1.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analytical_risk_bounds_simulation() {
+        for (adv_percent, m, adv) in [(10, 50, 12), (20, 50, 20), (30, 100, 40)] {
+            let result = simulate_reversal(&SimulationParams {
+                adv_percent,
+                m,
+                adv,
+                trials: 20_000,
+                seed: 42,
+                ..Default::default()
+            });
+            assert!(
+                result.analytical_bounds_empirical(),
+                "adv={adv_percent}% m={m} k={adv}: {result:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_zero_advantage_always_reverts() {
+        let result = simulate_reversal(&SimulationParams {
+            adv: 0,
+            trials: 100,
+            ..Default::default()
+        });
+        assert_eq!(result.reversals, 100);
+    }
+}
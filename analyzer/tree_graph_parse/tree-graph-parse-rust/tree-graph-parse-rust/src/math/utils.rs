@@ -1,48 +1,337 @@
 use std::{
     collections::HashMap,
-    sync::{LazyLock, RwLock},
+    hash::{BuildHasher, RandomState},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, LazyLock, Mutex, RwLock,
+    },
 };
 
-pub const BATCH_SIZE: usize = 64;
-static CACHE: LazyLock<RwLock<HashMap<CacheID, RwLock<Vec<f64>>>>> =
-    LazyLock::new(|| RwLock::new(HashMap::new()));
+#[cfg(feature = "native")]
+use super::disk_cache::preload;
+
+/// Without the `native` feature there is no disk cache to preload from.
+#[cfg(not(feature = "native"))]
+fn preload(_cache_id: CacheID) -> Vec<f64> { vec![] }
+
+/// Number of independently locked shards of the cache. Confirmation risk run on rayon over many
+/// graphs looks up a handful of cache ids from every thread; with a single map lock a thread
+/// inserting a new id would stall all of them.
+const SHARDS: usize = 16;
+
+const VALUE_BYTES: usize = std::mem::size_of::<f64>();
+
+/// Bounds on the in-memory cache, unlimited by default. Once one is exceeded, the least recently
+/// used ids are dropped until both hold again; the id just computed is never dropped, even if
+/// it alone is over the byte limit. A dropped id is recomputed when asked for again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheLimits {
+    pub max_entries: Option<usize>,
+    /// Bytes of cached values, not counting the maps holding them.
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Requests answered from cached values alone.
+    pub hits: u64,
+    /// Requests that computed values.
+    pub misses: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+struct CachedValues {
+    values: Vec<f64>,
+    /// Dropped from the cache: growth no longer counts towards its bytes.
+    evicted: bool,
+}
+
+struct CacheItem {
+    values: RwLock<CachedValues>,
+    last_used: AtomicU64,
+}
+
+type Shard = RwLock<HashMap<CacheID, Arc<CacheItem>>>;
+
+struct ShardedCache {
+    hasher: RandomState,
+    shards: [Shard; SHARDS],
+    limits: RwLock<CacheLimits>,
+    /// Counts lookups, to order ids by last use.
+    clock: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    entries: AtomicUsize,
+    bytes: AtomicUsize,
+    /// Held while evicting, so that concurrent misses do not drop more than needed.
+    evicting: Mutex<()>,
+}
+
+impl ShardedCache {
+    fn new() -> Self {
+        ShardedCache {
+            hasher: RandomState::new(),
+            shards: std::array::from_fn(|_| RwLock::new(HashMap::new())),
+            limits: RwLock::new(CacheLimits::default()),
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            entries: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            evicting: Mutex::new(()),
+        }
+    }
+
+    fn shard(&self, cache_id: &CacheID) -> &Shard {
+        &self.shards[self.hasher.hash_one(cache_id) as usize % SHARDS]
+    }
+
+    /// The values cached for `cache_id`, preloaded on first use. The shard lock is released on
+    /// return, so computing or copying values never blocks other ids of the shard.
+    fn item(&self, cache_id: CacheID) -> Arc<CacheItem> {
+        let now = self.clock.fetch_add(1, Ordering::Relaxed);
+        let shard = self.shard(&cache_id);
+        let cached = shard.read().unwrap().get(&cache_id).cloned();
+        let cache_item = match cached {
+            Some(cache_item) => cache_item,
+            None => shard
+                .write()
+                .unwrap()
+                .entry(cache_id)
+                .or_insert_with(|| {
+                    let values = preload(cache_id);
+                    self.entries.fetch_add(1, Ordering::Relaxed);
+                    self.bytes
+                        .fetch_add(values.len() * VALUE_BYTES, Ordering::Relaxed);
+                    Arc::new(CacheItem {
+                        values: RwLock::new(CachedValues {
+                            values,
+                            evicted: false,
+                        }),
+                        last_used: AtomicU64::new(now),
+                    })
+                })
+                .clone(),
+        };
+        cache_item.last_used.fetch_max(now, Ordering::Relaxed);
+        cache_item
+    }
+
+    fn compute_range_with(
+        &self, length: usize, cache_id: CacheID, extend: impl FnOnce(&mut Vec<f64>, usize),
+    ) -> Vec<f64> {
+        let cache_item = self.item(cache_id);
+        {
+            let cached = &*cache_item.values.read().unwrap();
+            if cached.values.len() >= length {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return cached.values[..length].to_vec();
+            }
+        }
+
+        let values = {
+            let cached = &mut *cache_item.values.write().unwrap();
+            let before = cached.values.len();
+            if before < length {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                extend(&mut cached.values, length);
+                if !cached.evicted {
+                    self.bytes.fetch_add(
+                        (cached.values.len() - before) * VALUE_BYTES,
+                        Ordering::Relaxed,
+                    );
+                }
+            } else {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            cached.values[..length].to_vec()
+        };
+        self.evict(Some(cache_id));
+        values
+    }
+
+    fn over_limits(&self) -> bool {
+        let limits = *self.limits.read().unwrap();
+        limits
+            .max_entries
+            .is_some_and(|max| self.entries.load(Ordering::Relaxed) > max)
+            || limits
+                .max_bytes
+                .is_some_and(|max| self.bytes.load(Ordering::Relaxed) > max)
+    }
+
+    /// Drop least recently used ids other than `keep` until the limits hold.
+    fn evict(&self, keep: Option<CacheID>) {
+        if !self.over_limits() {
+            return;
+        }
+        let _evicting = self.evicting.lock().unwrap();
+        while self.over_limits() {
+            let Some(cache_id) = self.least_recently_used(keep) else {
+                break;
+            };
+            let Some(cache_item) = self.shard(&cache_id).write().unwrap().remove(&cache_id) else {
+                continue;
+            };
+            let cached = &mut *cache_item.values.write().unwrap();
+            cached.evicted = true;
+            self.bytes
+                .fetch_sub(cached.values.len() * VALUE_BYTES, Ordering::Relaxed);
+            self.entries.fetch_sub(1, Ordering::Relaxed);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn least_recently_used(&self, keep: Option<CacheID>) -> Option<CacheID> {
+        let mut oldest: Option<(u64, CacheID)> = None;
+        for shard in &self.shards {
+            for (cache_id, cache_item) in shard.read().unwrap().iter() {
+                let last_used = cache_item.last_used.load(Ordering::Relaxed);
+                if Some(*cache_id) != keep && oldest.is_none_or(|(oldest, _)| last_used < oldest) {
+                    oldest = Some((last_used, *cache_id));
+                }
+            }
+        }
+        oldest.map(|(_, cache_id)| cache_id)
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.entries.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static CACHE: LazyLock<ShardedCache> = LazyLock::new(ShardedCache::new);
 
 #[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
 pub enum CacheID {
     HiddenMalicious(usize, usize),
     RandomWalk(usize),
+    LnRandomWalk(usize),
 }
 
 pub fn compute_range(
-    length: usize, cache_id: CacheID, compute: impl FnMut(usize) -> f64,
+    length: usize, cache_id: CacheID, mut compute: impl FnMut(usize) -> f64,
 ) -> Vec<f64> {
-    let read_guard = CACHE.read().unwrap();
-    if let Some(cache_item) = read_guard.get(&cache_id) {
-        compute_range_inner(length, cache_id, compute, cache_item)
-    } else {
-        std::mem::drop(read_guard);
-        CACHE.write().unwrap().entry(cache_id).or_default();
+    compute_range_with(length, cache_id, |cached, length| {
+        cached.extend((cached.len()..length).map(&mut compute))
+    })
+}
 
-        let cache_guard = &*CACHE.read().unwrap();
-        let cache_item = cache_guard.get(&cache_id).unwrap();
-        compute_range_inner(length, cache_id, compute, cache_item)
+/// `compute_range` for values that are cheaper to compute together: `extend` is called at most
+/// once, with the cached values, and must extend them to the given length.
+pub fn compute_range_with(
+    length: usize, cache_id: CacheID, extend: impl FnOnce(&mut Vec<f64>, usize),
+) -> Vec<f64> {
+    CACHE.compute_range_with(length, cache_id, extend)
+}
+
+/// Bound the cache shared by all risk computations of the process, evicting right away if it is
+/// already over the new limits.
+pub fn set_cache_limits(limits: CacheLimits) {
+    *CACHE.limits.write().unwrap() = limits;
+    CACHE.evict(None);
+}
+
+pub fn cache_limits() -> CacheLimits { *CACHE.limits.read().unwrap() }
+
+/// Counters since the start of the process, and the current size of the cache.
+pub fn cache_stats() -> CacheStats { CACHE.stats() }
+
+/// Copy of every cached vector, used to persist the cache.
+#[cfg(feature = "native")]
+pub(super) fn snapshot() -> Vec<(CacheID, Vec<f64>)> {
+    let mut snapshot = vec![];
+    for shard in &CACHE.shards {
+        snapshot.extend(shard.read().unwrap().iter().map(|(cache_id, cache_item)| {
+            (*cache_id, cache_item.values.read().unwrap().values.clone())
+        }));
     }
+    snapshot
 }
 
-fn compute_range_inner(
-    length: usize, _cache_id: CacheID, compute: impl FnMut(usize) -> f64,
-    cache_item: &RwLock<Vec<f64>>,
-) -> Vec<f64> {
-    {
-        let cached_vec = &*cache_item.read().unwrap();
-        if cached_vec.len() >= length {
-            return cached_vec[..length].to_vec();
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_compute_range() {
+        // Ids no risk model uses, so other tests never share these entries.
+        let cache_ids: Vec<CacheID> = (0..8)
+            .map(|i| CacheID::HiddenMalicious(usize::MAX, i))
+            .collect();
+        let computed = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let (cache_ids, computed) = (&cache_ids, &computed);
+                scope.spawn(move || {
+                    for (i, &cache_id) in cache_ids.iter().enumerate() {
+                        let length = 50 + 10 * ((thread + i) % 5);
+                        let values = compute_range(length, cache_id, |k| {
+                            computed.fetch_add(1, Ordering::Relaxed);
+                            (k * (i + 1)) as f64
+                        });
+                        assert!(values
+                            .iter()
+                            .enumerate()
+                            .all(|(k, &v)| v == (k * (i + 1)) as f64));
+                    }
+                });
+            }
+        });
+        // Every value was computed once, up to the longest length asked for each id.
+        assert_eq!(computed.load(Ordering::Relaxed), 8 * 90);
     }
 
-    {
-        let cached_vec = &mut *cache_item.write().unwrap();
-        cached_vec.extend((cached_vec.len()..length).map(compute));
-        cached_vec[..length].to_vec()
+    #[test]
+    fn test_evict_least_recently_used() {
+        // A cache of its own: the limits and counters of the global one are shared by all tests.
+        let cache = ShardedCache::new();
+        let id = |i| CacheID::HiddenMalicious(usize::MAX - 1, i);
+        let fill = |cached: &mut Vec<f64>, length| cached.resize(length, 1.);
+        *cache.limits.write().unwrap() = CacheLimits {
+            max_entries: Some(3),
+            max_bytes: Some(100 * VALUE_BYTES),
+        };
+
+        for i in 0..3 {
+            cache.compute_range_with(10, id(i), fill);
+        }
+        // Use 0 again, so 1 is the least recently used when 3 comes in.
+        cache.compute_range_with(5, id(0), fill);
+        cache.compute_range_with(10, id(3), fill);
+        let cached = |i| cache.shard(&id(i)).read().unwrap().contains_key(&id(i));
+        assert!(cached(0) && !cached(1) && cached(2) && cached(3));
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 4,
+                evictions: 1,
+                entries: 3,
+                bytes: 30 * VALUE_BYTES,
+            }
+        );
+
+        // Too large for the byte limit with anything else, but kept while in use.
+        cache.compute_range_with(95, id(2), fill);
+        assert!(!cached(0) && cached(2) && !cached(3));
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.stats().bytes, 95 * VALUE_BYTES);
+
+        cache.compute_range_with(200, id(2), fill);
+        assert_eq!(cache.stats().bytes, 200 * VALUE_BYTES);
+        cache.compute_range_with(10, id(0), fill);
+        assert!(cached(0) && !cached(2));
+        assert_eq!(cache.stats().bytes, 10 * VALUE_BYTES);
     }
 }
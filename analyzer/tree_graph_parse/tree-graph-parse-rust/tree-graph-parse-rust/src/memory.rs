@@ -0,0 +1,106 @@
+//! Estimated heap usage of a finalized graph, for capacity planning before loading many graphs
+//! at once.
+//!
+//! The numbers count allocated capacity plus the allocator-independent part of each container.
+//! B-tree node overhead is not included, so the indices are slight underestimates.
+
+use ethereum_types::H256;
+use std::{fmt, mem::size_of};
+
+use crate::{block::Block, graph::Graph};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryStats {
+    pub block_count: usize,
+    /// Points in all `subtree_size_series` and `subtree_adv_series`.
+    pub time_series_points: usize,
+
+    /// `Block` structs.
+    pub block_bytes: usize,
+    /// Hash to block id table: keys, ids and control bytes.
+    pub id_map_bytes: usize,
+    /// Referee lists, epoch sets and children lists.
+    pub edge_bytes: usize,
+    pub subtree_size_series_bytes: usize,
+    pub subtree_adv_series_bytes: usize,
+    /// Height and timestamp indices.
+    pub index_bytes: usize,
+    /// Past-set bitmaps held at the peak of finalize (only the live frontier is kept). Not part
+    /// of `total_bytes`: they are freed before the graph is returned, but must fit while loading.
+    pub past_set_bitmap_bytes: usize,
+}
+
+impl MemoryStats {
+    /// Resident size of the finalized graph.
+    pub fn total_bytes(&self) -> usize {
+        self.block_bytes
+            + self.id_map_bytes
+            + self.edge_bytes
+            + self.subtree_size_series_bytes
+            + self.subtree_adv_series_bytes
+            + self.index_bytes
+    }
+
+    /// Memory needed while the graph is being loaded.
+    pub fn peak_bytes(&self) -> usize { self.total_bytes() + self.past_set_bitmap_bytes }
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn mib(bytes: usize) -> f64 { bytes as f64 / (1 << 20) as f64 }
+
+        writeln!(f, "blocks              {:>12}", self.block_count)?;
+        writeln!(f, "time series points  {:>12}", self.time_series_points)?;
+        for (name, bytes) in [
+            ("blocks", self.block_bytes),
+            ("id map", self.id_map_bytes),
+            ("edges", self.edge_bytes),
+            ("subtree size series", self.subtree_size_series_bytes),
+            ("subtree adv series", self.subtree_adv_series_bytes),
+            ("indices", self.index_bytes),
+            ("total", self.total_bytes()),
+            ("past-set bitmaps", self.past_set_bitmap_bytes),
+            ("peak (load)", self.peak_bytes()),
+        ] {
+            writeln!(f, "{name:<20}{:>12.2} MiB", mib(bytes))?;
+        }
+        Ok(())
+    }
+}
+
+impl Graph {
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats {
+            block_count: self.blocks.len(),
+            block_bytes: self.blocks.capacity() * size_of::<Block>(),
+            id_map_bytes: self.id_map.capacity() * (size_of::<H256>() + size_of::<usize>() + 1),
+            past_set_bitmap_bytes: self.past_set_bitmap_bytes,
+            ..Default::default()
+        };
+
+        for block in &self.blocks {
+            stats.edge_bytes += block.referees.capacity() * size_of::<usize>()
+                + block.epoch_set.as_ref().map_or(0, |set| set.capacity()) * size_of::<usize>()
+                + block.children.capacity() * size_of::<usize>();
+
+            if let Some(series) = &block.subtree_size_series {
+                stats.time_series_points += series.len();
+                stats.subtree_size_series_bytes += series.heap_bytes();
+            }
+            if let Some(series) = &block.subtree_adv_series {
+                stats.time_series_points += series.len();
+                stats.subtree_adv_series_bytes += series.heap_bytes();
+            }
+        }
+
+        for index in [&self.height_index, &self.timestamp_index] {
+            stats.index_bytes += index.len() * (size_of::<u64>() + size_of::<Vec<usize>>())
+                + index
+                    .values()
+                    .map(|ids| ids.capacity() * size_of::<usize>())
+                    .sum::<usize>();
+        }
+
+        stats
+    }
+}
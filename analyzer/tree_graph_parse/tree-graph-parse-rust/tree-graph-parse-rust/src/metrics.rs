@@ -0,0 +1,199 @@
+//! Tree-graph health indicators: DAG width, fork rate, referee counts and orphan candidates.
+
+use ethereum_types::H256;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::Write,
+};
+
+use crate::graph::Graph;
+
+#[derive(Debug, Clone, Default)]
+pub struct HeightMetrics {
+    pub height: u64,
+    /// Number of blocks at this height.
+    pub width: usize,
+    /// Size of the epoch led by the pivot block at this height.
+    pub epoch_size: usize,
+    /// Non-pivot blocks in that epoch.
+    pub fork_count: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DagMetrics {
+    pub per_height: Vec<HeightMetrics>,
+    /// Number of referees -> number of blocks with that many referees.
+    pub referee_count_distribution: BTreeMap<usize, usize>,
+    /// Blocks that have no children and are not referenced by any other block, excluding the
+    /// pivot tip. Nothing builds on them, so they will not enter any epoch unless referenced
+    /// later.
+    pub orphan_candidates: Vec<H256>,
+}
+
+impl DagMetrics {
+    pub fn compute(graph: &Graph) -> Self {
+        let pivot_chain = graph.pivot_chain();
+        let pivot_tip = pivot_chain.last().map(|b| b.id);
+
+        let mut per_height: BTreeMap<u64, HeightMetrics> = BTreeMap::new();
+        for block in graph.blocks() {
+            let entry = per_height
+                .entry(block.height)
+                .or_insert_with(|| HeightMetrics {
+                    height: block.height,
+                    ..Default::default()
+                });
+            entry.width += 1;
+        }
+
+        for pivot in pivot_chain.iter().filter(|b| b.height > 0) {
+            let entry = per_height.get_mut(&pivot.height).unwrap();
+            entry.epoch_size = pivot.epoch_size();
+            entry.fork_count = pivot.epoch_size() - 1;
+        }
+
+        let mut referee_count_distribution = BTreeMap::new();
+        let mut referenced: HashSet<usize> = HashSet::new();
+        for block in graph.blocks().filter(|b| b.height > 0) {
+            *referee_count_distribution
+                .entry(block.referees.len())
+                .or_default() += 1;
+            referenced.extend(block.referees.iter().copied());
+        }
+
+        let mut orphans: Vec<_> = graph
+            .blocks()
+            .filter(|b| b.children.is_empty())
+            .filter(|b| !referenced.contains(&b.id))
+            .filter(|b| Some(b.id) != pivot_tip)
+            .collect();
+        orphans.sort_by_key(|b| b.id);
+
+        DagMetrics {
+            per_height: per_height.into_values().collect(),
+            referee_count_distribution,
+            orphan_candidates: orphans.into_iter().map(|b| b.hash).collect(),
+        }
+    }
+
+    /// Non-pivot blocks per pivot block, over all epochs; 0 without any epoch after genesis.
+    pub fn fork_rate(&self) -> f64 {
+        let epochs = self.per_height.iter().filter(|m| m.epoch_size > 0).count();
+        let forks: usize = self.per_height.iter().map(|m| m.fork_count).sum();
+        if epochs == 0 {
+            return 0.;
+        }
+        forks as f64 / epochs as f64
+    }
+
+    /// Blocks per height, genesis excluded; 0 without any block after genesis.
+    pub fn avg_width(&self) -> f64 {
+        let rows = self.per_height.iter().filter(|m| m.height > 0);
+        let (cnt, sum) = rows.fold((0, 0), |(cnt, sum), m| (cnt + 1, sum + m.width));
+        if cnt == 0 {
+            return 0.;
+        }
+        sum as f64 / cnt as f64
+    }
+
+    pub fn max_width(&self) -> usize { self.per_height.iter().map(|m| m.width).max().unwrap_or(0) }
+
+    pub fn export_csv(&self, filename: &str) -> Result<(), anyhow::Error> {
+        let mut file = File::create(filename)?;
+        writeln!(file, "height,width,epoch_size,fork_count")?;
+        for m in &self.per_height {
+            writeln!(
+                file,
+                "{},{},{},{}",
+                m.height, m.width, m.epoch_size, m.fork_count
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn export_referee_distribution_csv(&self, filename: &str) -> Result<(), anyhow::Error> {
+        let mut file = File::create(filename)?;
+        writeln!(file, "referee_count,block_count")?;
+        for (referees, blocks) in &self.referee_count_distribution {
+            writeln!(file, "{},{}", referees, blocks)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::RawBlock, generate::GenerateParams};
+
+    #[test]
+    fn test_empty_graph_metrics() {
+        // What `compute` gives for a graph of genesis alone.
+        let genesis_only = DagMetrics {
+            per_height: vec![HeightMetrics {
+                height: 0,
+                width: 1,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        for metrics in [genesis_only, DagMetrics::default()] {
+            assert_eq!(metrics.fork_rate(), 0.);
+            assert_eq!(metrics.avg_width(), 0.);
+        }
+    }
+
+    #[test]
+    fn test_small_graph_metrics() {
+        let hash = H256::from_low_u64_be;
+        // 0 <- 1 <- 3 and 0 <- 2, which 3 references: the epoch of 3 holds 2.
+        let graph = Graph::from_raw_blocks(vec![
+            RawBlock::genesis_block(hash(0)),
+            RawBlock::new(1, hash(1), hash(0), vec![], 1, 1, 0, 0),
+            RawBlock::new(1, hash(2), hash(0), vec![], 2, 2, 0, 0),
+            RawBlock::new(2, hash(3), hash(1), vec![hash(2)], 3, 3, 0, 0),
+        ])
+        .unwrap();
+
+        let metrics = DagMetrics::compute(&graph);
+        let rows: Vec<_> = metrics
+            .per_height
+            .iter()
+            .map(|m| (m.height, m.width, m.epoch_size, m.fork_count))
+            .collect();
+        assert_eq!(rows, vec![(0, 1, 0, 0), (1, 2, 1, 0), (2, 1, 2, 1)]);
+        assert_eq!(metrics.fork_rate(), 0.5);
+        assert_eq!(metrics.avg_width(), 1.5);
+        assert_eq!(metrics.max_width(), 2);
+        assert_eq!(
+            metrics.referee_count_distribution,
+            BTreeMap::from([(0, 2), (1, 1)])
+        );
+        assert!(metrics.orphan_candidates.is_empty());
+    }
+
+    #[test]
+    fn test_generated_graph_metrics() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 60,
+            ..Default::default()
+        })
+        .unwrap();
+        let metrics = DagMetrics::compute(&graph);
+
+        let widths: usize = metrics.per_height.iter().map(|m| m.width).sum();
+        assert_eq!(widths, graph.block_count());
+        let epochs: usize = metrics.per_height.iter().map(|m| m.epoch_size).sum();
+        let forks: usize = metrics.per_height.iter().map(|m| m.fork_count).sum();
+        let pivot_blocks = graph.pivot_chain().len() - 1;
+        assert_eq!(epochs, forks + pivot_blocks);
+        assert!(epochs + metrics.orphan_candidates.len() < graph.block_count());
+        assert_eq!(metrics.fork_rate(), forks as f64 / pivot_blocks as f64);
+        assert!(metrics.avg_width() >= 1.);
+        assert_eq!(
+            metrics.referee_count_distribution.values().sum::<usize>(),
+            graph.block_count() - 1
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! How finalize picks the pivot child among the children of a block.
+//!
+//! The GHOST rule follows the child with the heaviest subtree. The weight of a subtree is the sum
+//! of `block_weight` over its blocks, and siblings of equal weight are ordered by `tie_break`.
+//! Blocks weigh 1 unless GHAST adaptive weights were in force when they were mined (see
+//! `Block::ghast_weight`); on runs where GHAST never triggers, the weight is the subtree size.
+//! `FirstLogged` keeps the order the parser always used; `LargestHash` is the rule of the conflux
+//! node, so the parsed pivot chain matches the node's when two branches tie.
+
+use std::cmp::Ordering;
+
+use crate::block::Block;
+
+/// Weight of a heavy adaptive block, `heavy_block_difficulty_ratio` of the conflux node.
+pub const HEAVY_BLOCK_RATIO: u64 = 240;
+
+pub trait PivotRule: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Contribution of `block` to the weight of every subtree containing it.
+    fn block_weight(&self, block: &Block) -> u64 { block.ghast_weight(HEAVY_BLOCK_RATIO) }
+
+    /// Order of two siblings whose subtrees weigh the same; `Less` puts `a` first, making it the
+    /// pivot child if no sibling is heavier.
+    fn tie_break(&self, a: &Block, b: &Block) -> Ordering;
+}
+
+/// Ties go to the block logged first. The default.
+pub struct FirstLogged;
+
+impl PivotRule for FirstLogged {
+    fn name(&self) -> &'static str { "first-logged" }
+
+    fn tie_break(&self, a: &Block, b: &Block) -> Ordering { a.id.cmp(&b.id) }
+}
+
+/// Ties go to the larger block hash, as in the conflux node.
+pub struct LargestHash;
+
+impl PivotRule for LargestHash {
+    fn name(&self) -> &'static str { "largest-hash" }
+
+    fn tie_break(&self, a: &Block, b: &Block) -> Ordering { b.hash.cmp(&a.hash) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::RawBlock, graph::Graph};
+    use ethereum_types::H256;
+
+    /// 0 <- 1 and 0 <- 2 tie; block 2 has the larger hash.
+    fn tied_branches() -> Vec<RawBlock> {
+        let hash = H256::from_low_u64_be;
+        vec![
+            RawBlock::genesis_block(hash(0)),
+            RawBlock::new(1, hash(1), hash(0), vec![], 1, 1, 0, 0),
+            RawBlock::new(1, hash(2), hash(0), vec![], 2, 2, 0, 0),
+        ]
+    }
+
+    fn pivot_ids(graph: &Graph) -> Vec<usize> { graph.pivot_chain().iter().map(|b| b.id).collect() }
+
+    #[test]
+    fn test_tie_break() {
+        let graph = Graph::from_raw_blocks(tied_branches()).unwrap();
+        assert_eq!(pivot_ids(&graph), vec![0, 1]);
+
+        let graph = Graph::from_raw_blocks_with_rule(tied_branches(), &LargestHash).unwrap();
+        assert_eq!(pivot_ids(&graph), vec![0, 2]);
+        assert_eq!(
+            graph.replay_with(&LargestHash).last().unwrap().1,
+            crate::replay::BlockEvent::SwitchPivot {
+                id: 2,
+                fork_height: 0,
+                reverted: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_custom_block_weight() {
+        // Blocks with an odd timestamp weigh nothing, so the later branch outweighs the first.
+        struct EvenTimestamps;
+        impl PivotRule for EvenTimestamps {
+            fn name(&self) -> &'static str { "even-timestamps" }
+
+            fn block_weight(&self, block: &Block) -> u64 { (block.timestamp % 2 == 0) as u64 }
+
+            fn tie_break(&self, a: &Block, b: &Block) -> Ordering { a.id.cmp(&b.id) }
+        }
+
+        let graph = Graph::from_raw_blocks_with_rule(tied_branches(), &EvenTimestamps).unwrap();
+        assert_eq!(pivot_ids(&graph), vec![0, 2]);
+        // Subtree sizes still count blocks.
+        assert_eq!(graph.block(1).subtree_size, 1);
+
+        let mut replay = graph.replay_with(&EvenTimestamps);
+        replay.by_ref().for_each(drop);
+        assert_eq!(replay.pivot_chain(), pivot_ids(&graph));
+        assert_eq!(replay.subtree_weight(0), 2);
+    }
+
+    #[test]
+    fn test_adaptive_weights() {
+        let hash = H256::from_low_u64_be;
+        let adaptive = |raw: RawBlock, heavy| RawBlock {
+            adaptive: true,
+            heavy,
+            ..raw
+        };
+        // Branch 0 <- 1 <- 2 <- 3 has more blocks, but 2 and 3 are light adaptive blocks; the
+        // heavy adaptive block 5 outweighs it.
+        let raw_blocks = vec![
+            RawBlock::genesis_block(hash(0)),
+            RawBlock::new(1, hash(1), hash(0), vec![], 1, 1, 0, 0),
+            adaptive(
+                RawBlock::new(2, hash(2), hash(1), vec![], 2, 2, 0, 0),
+                false,
+            ),
+            adaptive(
+                RawBlock::new(3, hash(3), hash(2), vec![], 3, 3, 0, 0),
+                false,
+            ),
+            RawBlock::new(1, hash(4), hash(0), vec![], 4, 4, 0, 0),
+            adaptive(RawBlock::new(2, hash(5), hash(4), vec![], 5, 5, 0, 0), true),
+        ];
+        let graph = Graph::from_raw_blocks(raw_blocks).unwrap();
+        assert_eq!(pivot_ids(&graph), vec![0, 4, 5]);
+        assert_eq!(graph.block(1).subtree_size, 3);
+        // The lead the risk model sees is in the same weights.
+        assert_eq!(graph.genesis_block().subtree_adv_at(4), Some(0));
+        assert_eq!(
+            graph.genesis_block().subtree_adv_at(5),
+            Some(HEAVY_BLOCK_RATIO as i64)
+        );
+
+        let mut replay = graph.replay();
+        replay.by_ref().for_each(drop);
+        assert_eq!(replay.pivot_chain(), pivot_ids(&graph));
+        assert_eq!(replay.subtree_weight(4), 1 + HEAVY_BLOCK_RATIO);
+    }
+
+    #[test]
+    fn test_ghast_flags_parsed_from_log() {
+        let hash = H256::from_low_u64_be;
+        let block = |height: u64| {
+            RawBlock::new(
+                height,
+                hash(height + 1),
+                hash(height),
+                vec![],
+                height,
+                height,
+                0,
+                0,
+            )
+        };
+        let log: String = [(false, true), (true, true), (false, false)]
+            .into_iter()
+            .zip(1..)
+            .map(|((adaptive, heavy), height)| {
+                let block = RawBlock {
+                    adaptive,
+                    heavy,
+                    ..block(height)
+                };
+                block.to_log_line() + "\n"
+            })
+            .collect();
+        let graph = Graph::from_log(log.as_bytes()).unwrap();
+
+        let flags: Vec<_> = graph.blocks().map(|b| (b.adaptive, b.heavy)).collect();
+        assert_eq!(
+            flags,
+            vec![(false, false), (false, true), (true, true), (false, false)]
+        );
+    }
+}
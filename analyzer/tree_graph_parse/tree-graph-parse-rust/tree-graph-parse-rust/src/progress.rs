@@ -0,0 +1,195 @@
+//! Progress reporting for `Graph::load` and finalize.
+
+use std::{fmt, time::Duration};
+
+/// Lines between two `LoadProgress::Parsing` reports.
+pub const PARSE_REPORT_INTERVAL: u64 = 100_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadProgress {
+    /// Sent every `PARSE_REPORT_INTERVAL` lines and once when the log is fully read.
+    Parsing { lines: u64, blocks: u64 },
+    /// The log is fully read.
+    Parsed { blocks: u64, elapsed: Duration },
+    /// A finalize pass finished.
+    Phase {
+        name: &'static str,
+        elapsed: Duration,
+    },
+    /// The graph is ready.
+    Done { blocks: u64, elapsed: Duration },
+}
+
+impl fmt::Display for LoadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadProgress::Parsing { lines, blocks } => {
+                write!(f, "parsed {lines} lines, {blocks} blocks")
+            }
+            LoadProgress::Parsed { blocks, elapsed } => {
+                write!(f, "read {blocks} blocks in {elapsed:.2?}")
+            }
+            LoadProgress::Phase { name, elapsed } => write!(f, "{name} took {elapsed:.2?}"),
+            LoadProgress::Done { blocks, elapsed } => {
+                write!(f, "loaded {blocks} blocks in {elapsed:.2?}")
+            }
+        }
+    }
+}
+
+/// `Instant` where a clock is available; without the `native` feature (wasm) every elapsed time
+/// reads zero.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stopwatch {
+    #[cfg(feature = "native")]
+    start: std::time::Instant,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Stopwatch {
+            #[cfg(feature = "native")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn elapsed(&self) -> Duration { self.start.elapsed() }
+
+    #[cfg(not(feature = "native"))]
+    pub(crate) fn elapsed(&self) -> Duration { Duration::ZERO }
+}
+
+/// Reporter printing every event to stderr, prefixed by `label`.
+pub fn stderr_reporter(label: impl fmt::Display) -> impl FnMut(LoadProgress) {
+    move |progress| eprintln!("[{label}] {progress}")
+}
+
+#[cfg(all(test, feature = "native"))]
+mod tests {
+    use super::*;
+    use crate::{block::RawBlock, graph::Graph};
+    use std::io::Write;
+
+    fn log_line(height: u64, hash: u64, parent: u64, timestamp: u64) -> String {
+        let h256 = ethereum_types::H256::from_low_u64_be;
+        RawBlock::new(
+            height,
+            h256(hash),
+            h256(parent),
+            vec![],
+            timestamp,
+            timestamp,
+            0,
+            0,
+        )
+        .to_log_line()
+    }
+
+    fn write_log(name: &str, blocks: u64) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "tg_{name}_{}.conflux.log.new_blocks",
+            std::process::id()
+        ));
+        let mut file = std::fs::File::create(&path).unwrap();
+        for i in 1..=blocks {
+            writeln!(file, "{}", log_line(i, i + 1, i, i)).unwrap();
+            writeln!(file, "2025-01-01T00:00:00Z INFO unrelated line").unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_load_reports_progress() {
+        let path = write_log("progress", 3);
+
+        let mut events = vec![];
+        let graph =
+            Graph::load_with_progress(path.to_str().unwrap(), &mut |p| events.push(p)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(graph.blocks().count(), 4);
+        assert_eq!(
+            events[0],
+            LoadProgress::Parsing {
+                lines: 6,
+                blocks: 4
+            }
+        );
+        assert!(matches!(events[1], LoadProgress::Parsed { blocks: 4, .. }));
+        let phases: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                LoadProgress::Phase { name, .. } => Some(*name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(phases.first(), Some(&"set_parent"));
+        assert_eq!(phases.last(), Some(&"build_indices"));
+        assert!(matches!(
+            events.last(),
+            Some(LoadProgress::Done { blocks: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_single_pass_load_matches_load() {
+        let path = write_log("single_pass", 20);
+        let path_str = path.to_str().unwrap();
+        let graph = Graph::load(path_str).unwrap();
+        let streamed = Graph::load_single_pass(path_str, &mut |_| {}).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(streamed.blocks().count(), 21);
+        for block in graph.blocks() {
+            let other = streamed.get_block(&block.hash).unwrap();
+            assert_eq!(other.id, block.id);
+            assert_eq!(other.past_set_size, block.past_set_size);
+            assert_eq!(other.subtree_size, block.subtree_size);
+        }
+    }
+
+    #[test]
+    fn test_single_pass_load_resolves_later_blocks() {
+        // A block before genesis is known, a child logged before its parent, and a duplicate.
+        let path = std::env::temp_dir().join(format!(
+            "tg_single_pass_order_{}.conflux.log.new_blocks",
+            std::process::id()
+        ));
+        let mut file = std::fs::File::create(&path).unwrap();
+        for (height, hash) in [(3, 4), (1, 2), (4, 5), (2, 3), (4, 5)] {
+            writeln!(file, "{}", log_line(height, hash, hash - 1, height)).unwrap();
+        }
+        drop(file);
+        let path_str = path.to_str().unwrap();
+        let graph = Graph::load(path_str).unwrap();
+        let streamed = Graph::load_single_pass(path_str, &mut |_| {}).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(streamed.block_count(), 5);
+        for block in graph.blocks() {
+            let other = streamed.block(block.id);
+            assert_eq!(other.hash, block.hash);
+            assert_eq!(other.parent, block.parent);
+            assert_eq!(other.subtree_size, block.subtree_size);
+        }
+        assert_eq!(streamed.genesis_block().subtree_size, 5);
+    }
+
+    #[test]
+    fn test_from_log_matches_load() {
+        let path = write_log("from_log", 20);
+        let graph = Graph::load(path.to_str().unwrap()).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let parsed = Graph::from_log(content.as_bytes()).unwrap();
+        assert_eq!(parsed.block_count(), graph.block_count());
+        for block in graph.blocks() {
+            let other = parsed.block(block.id);
+            assert_eq!(other.hash, block.hash);
+            assert_eq!(other.past_set_size, block.past_set_size);
+        }
+        assert!(Graph::from_log("no blocks here\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Referee selection audit: the tips a block could have referenced against the ones it did.
+//!
+//! The tips available to a block are the blocks the logging node had received (by
+//! `log_timestamp`) strictly before the block's `timestamp` and that no block received by then
+//! builds on or references. A tip counts as referenced if it is in the past of the block, so
+//! referencing a descendant of it is enough. This is the logging node's view, not the miner's:
+//! tips may have reached the miner later, which is why a tip of the same second is not counted.
+//!
+//! A tip nobody ever references stays a tip forever and would count against every later block;
+//! such blocks are reported by `Graph::stale_blocks`, so tips older than `max_tip_age` are left
+//! out here.
+
+use ethereum_types::H256;
+use std::collections::{BTreeSet, HashSet};
+
+use crate::graph::Graph;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RefereeAudit {
+    pub id: usize,
+    pub hash: H256,
+    pub timestamp: u64,
+    /// Tips received before the block was created.
+    pub available_tips: usize,
+    pub referees: usize,
+    /// Available tips not in the past of the block, by id.
+    pub missed_tips: Vec<H256>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RefereeAuditSummary {
+    pub blocks: usize,
+    pub blocks_missing_tips: usize,
+    pub missed_tips: usize,
+    pub max_missed_tips: usize,
+}
+
+impl RefereeAuditSummary {
+    pub fn of(audits: &[RefereeAudit]) -> Self {
+        let mut summary = RefereeAuditSummary {
+            blocks: audits.len(),
+            ..Default::default()
+        };
+        for audit in audits.iter().filter(|a| !a.missed_tips.is_empty()) {
+            summary.blocks_missing_tips += 1;
+            summary.missed_tips += audit.missed_tips.len();
+            summary.max_missed_tips = summary.max_missed_tips.max(audit.missed_tips.len());
+        }
+        summary
+    }
+
+    /// Missed tips per block.
+    pub fn mean_missed_tips(&self) -> f64 { self.missed_tips as f64 / self.blocks.max(1) as f64 }
+}
+
+impl Graph {
+    /// One entry per block except genesis, by id. Tips received more than `max_tip_age` seconds
+    /// before a block was created are not available to it.
+    pub fn referee_audit(&self, max_tip_age: u64) -> Vec<RefereeAudit> {
+        let mut arrivals: Vec<usize> = (0..self.block_count()).collect();
+        arrivals.sort_by_key(|&id| (self.block(id).log_timestamp, id));
+        let mut created: Vec<usize> = (1..self.block_count()).collect();
+        created.sort_by_key(|&id| (self.block(id).timestamp, id));
+
+        let mut tips: BTreeSet<usize> = BTreeSet::new();
+        let mut built_on = vec![false; self.block_count()];
+        let mut arrived = arrivals.iter().peekable();
+        let mut audits = Vec::with_capacity(created.len());
+        for id in created {
+            let block = self.block(id);
+            while let Some(&&next) = arrived.peek() {
+                let next = self.block(next);
+                if next.log_timestamp >= block.timestamp {
+                    break;
+                }
+                arrived.next();
+                for &edge in next.parent.iter().chain(&next.referees) {
+                    built_on[edge] = true;
+                    tips.remove(&edge);
+                }
+                if !built_on[next.id] {
+                    tips.insert(next.id);
+                }
+            }
+
+            // Blocks are logged after their parent and referees, so ids decrease along every
+            // edge and nothing logged before the first available tip needs to be visited.
+            let oldest_log_timestamp = block.timestamp.saturating_sub(max_tip_age);
+            let available: Vec<usize> = tips
+                .iter()
+                .copied()
+                .filter(|&tip| tip < id && self.block(tip).log_timestamp >= oldest_log_timestamp)
+                .collect();
+            let mut missed: HashSet<usize> = available.iter().copied().collect();
+            if let Some(&lowest) = available.first() {
+                let mut visited = HashSet::new();
+                let mut stack = vec![id];
+                while let Some(current) = stack.pop() {
+                    let current = self.block(current);
+                    for &edge in current.parent.iter().chain(&current.referees) {
+                        if edge >= lowest && visited.insert(edge) {
+                            missed.remove(&edge);
+                            stack.push(edge);
+                        }
+                    }
+                }
+            }
+            let mut missed: Vec<usize> = missed.into_iter().collect();
+            missed.sort_unstable();
+
+            audits.push(RefereeAudit {
+                id,
+                hash: block.hash,
+                timestamp: block.timestamp,
+                available_tips: available.len(),
+                referees: block.referees.len(),
+                missed_tips: missed.into_iter().map(|tip| self.hash_of(tip)).collect(),
+            });
+        }
+        audits.sort_by_key(|audit| audit.id);
+        audits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::RawBlock;
+
+    #[test]
+    fn test_missed_tips() {
+        let hash = |n: u64| H256::from_low_u64_be(n);
+        let block = |height, n, parent, referees: &[u64], timestamp| {
+            RawBlock::new(
+                height,
+                hash(n),
+                hash(parent),
+                referees.iter().map(|&r| hash(r)).collect(),
+                timestamp,
+                timestamp + 1,
+                0,
+                0,
+            )
+        };
+        // 2 and 3 fork off genesis; 4 references 3 but 5 does not, and nothing references 5.
+        let graph = Graph::from_raw_blocks(vec![
+            RawBlock::genesis_block(hash(1)),
+            block(1, 2, 1, &[], 10),
+            block(1, 3, 1, &[], 10),
+            block(2, 4, 2, &[3], 20),
+            block(2, 5, 2, &[], 20),
+            block(3, 6, 4, &[], 30),
+            block(4, 7, 6, &[], 100),
+        ])
+        .unwrap();
+
+        let missed = |audits: &[RefereeAudit]| -> Vec<Vec<H256>> {
+            audits.iter().map(|a| a.missed_tips.clone()).collect()
+        };
+        let audits = graph.referee_audit(75);
+        assert_eq!(
+            missed(&audits),
+            vec![vec![], vec![], vec![], vec![hash(3)], vec![hash(5)], vec![]]
+        );
+        assert_eq!(audits[2].available_tips, 2);
+        assert_eq!(audits[5].available_tips, 1);
+
+        let summary = RefereeAuditSummary::of(&audits);
+        assert_eq!(summary.blocks_missing_tips, 2);
+        assert_eq!(summary.missed_tips, 2);
+        assert!((summary.mean_missed_tips() - 2. / 6.).abs() < 1e-9);
+
+        // Without an age limit the unreferenced 5 still counts against 7.
+        assert_eq!(graph.referee_audit(1000)[5].missed_tips, vec![hash(5)]);
+    }
+}
@@ -0,0 +1,349 @@
+//! Replay of a graph in arrival order.
+//!
+//! `Graph::replay` yields one event per block after genesis, in block id order, which is the
+//! order the node logged them. Between two events the `Replay` describes the node's view at that
+//! instant: which blocks are logged, their subtree weights and the pivot chain the GHOST rule
+//! picks over them. Weights and ties follow a `PivotRule`, `FirstLogged` unless given to
+//! `Graph::replay_with`, so the view after the last event matches `Graph::pivot_chain` of a graph
+//! finalized with the same rule. A block is assumed to be logged after its parent, which holds for
+//! conflux logs.
+
+use std::cmp::Ordering;
+
+use crate::{
+    block::Block,
+    graph::Graph,
+    pivot_rule::{FirstLogged, PivotRule},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockEvent {
+    /// The block became the new pivot tip.
+    ExtendPivot { id: usize },
+    /// The block made its branch outweigh the pivot chain above `fork_height`; the `reverted`
+    /// pivot blocks above it were replaced.
+    SwitchPivot {
+        id: usize,
+        fork_height: u64,
+        reverted: usize,
+    },
+    /// The block joined off the pivot chain.
+    OffPivot { id: usize },
+}
+
+impl BlockEvent {
+    pub fn id(&self) -> usize {
+        match *self {
+            BlockEvent::ExtendPivot { id }
+            | BlockEvent::SwitchPivot { id, .. }
+            | BlockEvent::OffPivot { id } => id,
+        }
+    }
+}
+
+/// Iterator of `(log_timestamp, BlockEvent)`, see the module documentation.
+///
+/// Applying a block walks only from it up to the pivot chain. The weight it adds to the pivot
+/// blocks below that point is kept in `pivot_adds` instead of being added to each of them.
+pub struct Replay<'a> {
+    graph: &'a Graph,
+    rule: &'a dyn PivotRule,
+    next_id: usize,
+    /// Weight of the logged blocks in the subtree of each block, itself included. For pivot
+    /// blocks, without the weight held in `pivot_adds`.
+    weights: Vec<u64>,
+    /// Number of ancestors of each logged block, its index on the pivot chain if it is on it.
+    depths: Vec<usize>,
+    pivot_adds: PrefixAdds,
+    /// Logged children of each block, in arrival order.
+    children: Vec<Vec<usize>>,
+    /// Genesis first.
+    pivot: Vec<usize>,
+}
+
+/// Weights added to prefixes of the pivot chain: the value at index `i` counts for the pivot
+/// blocks `0..=i`. A Fenwick tree, so that adding and summing take a logarithmic time.
+struct PrefixAdds {
+    values: Vec<u64>,
+    tree: Vec<u64>,
+    total: u64,
+}
+
+impl PrefixAdds {
+    fn new(len: usize) -> Self {
+        PrefixAdds {
+            values: vec![0; len],
+            tree: vec![0; len + 1],
+            total: 0,
+        }
+    }
+
+    fn add(&mut self, index: usize, weight: u64) {
+        self.values[index] += weight;
+        self.total += weight;
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            self.tree[i] += weight;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    /// Resets the value at `index`, returning it.
+    fn take(&mut self, index: usize) -> u64 {
+        let weight = std::mem::take(&mut self.values[index]);
+        self.total -= weight;
+        let mut i = index + 1;
+        while i < self.tree.len() {
+            self.tree[i] -= weight;
+            i += i & i.wrapping_neg();
+        }
+        weight
+    }
+
+    /// Sum of the values at `index..`: the weight added to the pivot block at `index`.
+    fn suffix(&self, index: usize) -> u64 {
+        let mut prefix = 0;
+        let mut i = index;
+        while i > 0 {
+            prefix += self.tree[i];
+            i &= i - 1;
+        }
+        self.total - prefix
+    }
+}
+
+impl Graph {
+    pub fn replay(&self) -> Replay<'_> { self.replay_with(&FirstLogged) }
+
+    pub fn replay_with<'a>(&'a self, rule: &'a dyn PivotRule) -> Replay<'a> {
+        let mut weights = vec![0; self.block_count()];
+        weights[0] = rule.block_weight(self.genesis_block());
+        Replay {
+            graph: self,
+            rule,
+            next_id: 1,
+            weights,
+            depths: vec![0; self.block_count()],
+            pivot_adds: PrefixAdds::new(self.block_count()),
+            children: vec![vec![]; self.block_count()],
+            pivot: vec![0],
+        }
+    }
+}
+
+impl<'a> Replay<'a> {
+    pub fn graph(&self) -> &'a Graph { self.graph }
+
+    /// Blocks logged so far, genesis included.
+    pub fn logged(&self) -> usize { self.next_id }
+
+    pub fn is_logged(&self, id: usize) -> bool { id < self.next_id }
+
+    pub fn tip(&self) -> &'a Block { self.graph.block(*self.pivot.last().unwrap()) }
+
+    /// Ids of the current pivot blocks, genesis first.
+    pub fn pivot_chain(&self) -> &[usize] { &self.pivot }
+
+    /// Weight of the logged blocks in the subtree of `id`, itself included; 0 if `id` is not
+    /// logged yet.
+    pub fn subtree_weight(&self, id: usize) -> u64 {
+        if self.on_pivot(id) {
+            self.weights[id] + self.pivot_adds.suffix(self.depths[id])
+        } else {
+            self.weights[id]
+        }
+    }
+
+    fn on_pivot(&self, id: usize) -> bool { self.pivot.get(self.depths[id]) == Some(&id) }
+
+    /// Whether `a` outweighs its sibling `b`.
+    fn heavier(&self, a: usize, b: usize) -> bool {
+        match self.subtree_weight(a).cmp(&self.subtree_weight(b)) {
+            Ordering::Equal => {
+                self.rule
+                    .tie_break(self.graph.block(a), self.graph.block(b))
+                    == Ordering::Less
+            }
+            ord => ord == Ordering::Greater,
+        }
+    }
+
+    fn heaviest_child(&self, id: usize) -> Option<usize> {
+        self.children[id].iter().copied().reduce(|best, child| {
+            if self.heavier(child, best) {
+                child
+            } else {
+                best
+            }
+        })
+    }
+
+    fn apply(&mut self, id: usize) -> BlockEvent {
+        let Some(parent) = self.graph.block(id).parent else {
+            unreachable!("genesis is never replayed")
+        };
+        self.depths[id] = self.depths[parent] + 1;
+        self.children[parent].push(id);
+
+        // Up to the pivot chain, which genesis is always on.
+        let weight = self.rule.block_weight(self.graph.block(id));
+        let mut branch = id;
+        while !self.on_pivot(self.graph.block(branch).parent.unwrap()) {
+            self.weights[branch] += weight;
+            branch = self.graph.block(branch).parent.unwrap();
+        }
+        self.weights[branch] += weight;
+        let fork = self.graph.block(branch).parent.unwrap();
+        let depth = self.depths[branch];
+        self.pivot_adds.add(depth - 1, weight);
+
+        let Some(&pivot) = self.pivot.get(depth) else {
+            // The parent is the pivot tip.
+            self.pivot.push(id);
+            return BlockEvent::ExtendPivot { id };
+        };
+        if !self.heavier(branch, pivot) {
+            return BlockEvent::OffPivot { id };
+        }
+
+        // The reverted blocks keep their weights; what they held for the blocks below them
+        // stays with the fork.
+        let reverted = self.pivot.len() - depth;
+        for index in depth..self.pivot.len() {
+            self.weights[self.pivot[index]] += self.pivot_adds.suffix(index);
+        }
+        let moved: u64 = (depth..self.pivot.len())
+            .map(|index| self.pivot_adds.take(index))
+            .sum();
+        self.pivot_adds.add(depth - 1, moved);
+        self.pivot.truncate(depth);
+        self.pivot.push(branch);
+        while let Some(next) = self.heaviest_child(*self.pivot.last().unwrap()) {
+            self.pivot.push(next);
+        }
+        BlockEvent::SwitchPivot {
+            id,
+            fork_height: self.graph.block(fork).height,
+            reverted,
+        }
+    }
+}
+
+impl Iterator for Replay<'_> {
+    type Item = (u64, BlockEvent);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_id >= self.graph.block_count() {
+            return None;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let event = self.apply(id);
+        Some((self.graph.block(id).log_timestamp, event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::RawBlock, generate::GenerateParams};
+    use ethereum_types::H256;
+
+    #[test]
+    fn test_replay_ends_at_final_view() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 300,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut replay = graph.replay();
+        let mut events = 0;
+        while let Some((_, event)) = replay.next() {
+            events += 1;
+            assert_eq!(event.id() + 1, replay.logged());
+            assert_eq!(replay.subtree_weight(0), replay.logged() as u64);
+        }
+        assert_eq!(events, graph.block_count() - 1);
+
+        let pivot_chain: Vec<_> = graph.pivot_chain().iter().map(|b| b.id).collect();
+        assert_eq!(replay.pivot_chain(), pivot_chain);
+        for block in graph.blocks() {
+            assert_eq!(replay.subtree_weight(block.id), block.subtree_size);
+        }
+    }
+
+    #[test]
+    fn test_replay_matches_recomputed_view() {
+        use crate::generate::LatencyModel;
+        // Long latencies, so that forks and pivot switches are frequent.
+        let graph = Graph::generate(&GenerateParams {
+            block_rate: 4.,
+            latency: LatencyModel::Uniform(2., 8.),
+            duration: 60,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut replay = graph.replay();
+        let mut switches = 0;
+        while let Some((_, event)) = replay.next() {
+            switches += matches!(event, BlockEvent::SwitchPivot { .. }) as usize;
+            let mut weights = vec![0; graph.block_count()];
+            for id in 0..replay.logged() {
+                let mut ancestor = Some(id);
+                while let Some(a) = ancestor {
+                    weights[a] += 1;
+                    ancestor = graph.block(a).parent;
+                }
+            }
+            for block in graph.blocks() {
+                assert_eq!(replay.subtree_weight(block.id), weights[block.id]);
+            }
+
+            let mut pivot = vec![0];
+            while let Some(next) = graph
+                .block(*pivot.last().unwrap())
+                .children
+                .iter()
+                .copied()
+                .filter(|&child| replay.is_logged(child))
+                .min_by_key(|&child| (std::cmp::Reverse(weights[child]), child))
+            {
+                pivot.push(next);
+            }
+            assert_eq!(replay.pivot_chain(), pivot);
+        }
+        assert!(switches > 0);
+    }
+
+    #[test]
+    fn test_replay_reports_pivot_switch() {
+        let hash = H256::from_low_u64_be;
+        // 0 <- 1, 0 <- 2 <- 3: block 3 moves the pivot chain from 1 to 2.
+        let graph = Graph::from_raw_blocks(vec![
+            RawBlock::genesis_block(hash(0)),
+            RawBlock::new(1, hash(1), hash(0), vec![], 1, 1, 0, 0),
+            RawBlock::new(1, hash(2), hash(0), vec![], 2, 2, 0, 0),
+            RawBlock::new(2, hash(3), hash(2), vec![], 3, 3, 0, 0),
+        ])
+        .unwrap();
+
+        let events: Vec<_> = graph.replay().collect();
+        assert_eq!(
+            events,
+            vec![
+                (1, BlockEvent::ExtendPivot { id: 1 }),
+                (2, BlockEvent::OffPivot { id: 2 }),
+                (
+                    3,
+                    BlockEvent::SwitchPivot {
+                        id: 3,
+                        fork_height: 0,
+                        reverted: 1
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! How much the confirmation time of each pivot block moves when the adversary gets stronger.
+//!
+//! A point estimate at one adversary power hides how close a block is to not confirming at all:
+//! a block whose subtree barely leads its siblings confirms late or never at a slightly higher
+//! power, while one with a comfortable lead hardly moves. Grouping the pivot chain into height
+//! regions shows the stretches of a run where confirmation was fragile.
+
+use anyhow::ensure;
+use ethereum_types::H256;
+
+use crate::{graph::Graph, math::risk::ADV_PERCENT_RANGE};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSensitivity {
+    pub height: u64,
+    pub hash: H256,
+    /// Seconds after the block timestamp until confirmation at the base adversary power.
+    pub confirm_time: Option<u64>,
+    /// The same at the base power plus the step.
+    pub perturbed_confirm_time: Option<u64>,
+    /// Extra confirmation seconds per percent of adversary power, if confirmed at both powers.
+    pub delta: Option<f64>,
+}
+
+impl BlockSensitivity {
+    /// Confirmed at the base power, but not at the higher one.
+    pub fn lost(&self) -> bool {
+        self.confirm_time.is_some() && self.perturbed_confirm_time.is_none()
+    }
+}
+
+/// Pivot blocks of heights `start_height..end_height`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensitivityRegion {
+    pub start_height: u64,
+    pub end_height: u64,
+    pub blocks: usize,
+    /// Mean and largest `BlockSensitivity::delta` over the blocks confirmed at both powers.
+    pub mean_delta: f64,
+    pub max_delta: f64,
+    /// Blocks confirmed at the base power only.
+    pub lost: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensitivityReport {
+    pub adv_percent: usize,
+    /// Percent of adversary power added to `adv_percent`, at most up to 49%.
+    pub step: usize,
+    pub risk_threshold: f64,
+    /// One entry per pivot block except genesis, by height.
+    pub blocks: Vec<BlockSensitivity>,
+    /// Consecutive regions covering the pivot chain, by height.
+    pub regions: Vec<SensitivityRegion>,
+}
+
+impl SensitivityReport {
+    /// The `n` regions that lost most blocks, then with the largest mean delta.
+    pub fn most_sensitive(&self, n: usize) -> Vec<&SensitivityRegion> {
+        let mut regions: Vec<_> = self.regions.iter().collect();
+        regions.sort_by(|a, b| {
+            b.lost
+                .cmp(&a.lost)
+                .then(b.mean_delta.total_cmp(&a.mean_delta))
+        });
+        regions.truncate(n);
+        regions
+    }
+}
+
+impl Graph {
+    /// Confirmation times under `risk_threshold` at `adv_percent` and `adv_percent + step`, for
+    /// every pivot block, grouped in regions of `region_heights` heights. The higher power is
+    /// capped at 49%, the most the risk models accept, and the report records the step taken.
+    pub fn risk_sensitivity(
+        &self, adv_percent: usize, step: usize, risk_threshold: f64, region_heights: u64,
+    ) -> Result<SensitivityReport, anyhow::Error> {
+        ensure!(step > 0, "sensitivity step must be positive");
+        ensure!(region_heights > 0, "region must span at least one height");
+        ensure!(
+            ADV_PERCENT_RANGE.contains(&adv_percent) && adv_percent < *ADV_PERCENT_RANGE.end(),
+            "adversary power must be in 1..49 percent to be perturbed, got {adv_percent}"
+        );
+        ensure!(
+            risk_threshold > 0. && risk_threshold < 1.,
+            "risk threshold must be in (0, 1)"
+        );
+        let step = step.min(ADV_PERCENT_RANGE.end() - adv_percent);
+        let blocks: Vec<BlockSensitivity> = self
+            .pivot_chain()
+            .into_iter()
+            .skip(1)
+            .map(|block| {
+                let confirm_time = |adv_percent| {
+                    self.confirmation_risk(block, adv_percent, risk_threshold)
+                        .map(|(offset, ..)| offset)
+                };
+                let (base, perturbed) =
+                    (confirm_time(adv_percent), confirm_time(adv_percent + step));
+                BlockSensitivity {
+                    height: block.height,
+                    hash: block.hash,
+                    confirm_time: base,
+                    perturbed_confirm_time: perturbed,
+                    delta: base
+                        .zip(perturbed)
+                        .map(|(base, perturbed)| (perturbed as f64 - base as f64) / step as f64),
+                }
+            })
+            .collect();
+
+        let mut regions: Vec<SensitivityRegion> = vec![];
+        for chunk in blocks.chunk_by(|a, b| a.height / region_heights == b.height / region_heights)
+        {
+            let start_height = chunk[0].height / region_heights * region_heights;
+            let deltas: Vec<f64> = chunk.iter().filter_map(|b| b.delta).collect();
+            regions.push(SensitivityRegion {
+                start_height,
+                end_height: start_height + region_heights,
+                blocks: chunk.len(),
+                mean_delta: match deltas.len() {
+                    0 => 0.,
+                    n => deltas.iter().sum::<f64>() / n as f64,
+                },
+                max_delta: deltas.iter().copied().fold(0., f64::max),
+                lost: chunk.iter().filter(|b| b.lost()).count(),
+            });
+        }
+
+        Ok(SensitivityReport {
+            adv_percent,
+            step,
+            risk_threshold,
+            blocks,
+            regions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::GenerateParams;
+
+    #[test]
+    fn test_stronger_adversary_never_confirms_earlier() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 300,
+            ..Default::default()
+        })
+        .unwrap();
+        let report = graph.risk_sensitivity(10, 5, 1e-6, 20).unwrap();
+
+        assert_eq!(report.blocks.len(), graph.pivot_chain().len() - 1);
+        assert!(report.blocks.iter().any(|b| b.delta.is_some()));
+        for block in &report.blocks {
+            assert!(block.delta.is_none_or(|delta| delta >= 0.));
+            // Whatever confirms against the stronger adversary confirms against the weaker one.
+            assert!(block.perturbed_confirm_time.is_none() || block.confirm_time.is_some());
+        }
+
+        assert_eq!(
+            report.regions.iter().map(|r| r.blocks).sum::<usize>(),
+            report.blocks.len()
+        );
+        assert_eq!(report.regions[0].start_height, 0);
+        let most_sensitive = report.most_sensitive(3);
+        assert!(most_sensitive.len() <= 3);
+        assert!(most_sensitive.windows(2).all(|w| w[0].lost >= w[1].lost));
+    }
+
+    #[test]
+    fn test_step_capped_below_half_the_power() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 120,
+            ..Default::default()
+        })
+        .unwrap();
+        let report = graph.risk_sensitivity(45, 5, 1e-6, 20).unwrap();
+        assert_eq!(report.step, 4);
+        assert_eq!(report.blocks.len(), graph.pivot_chain().len() - 1);
+
+        assert!(graph.risk_sensitivity(49, 1, 1e-6, 20).is_err());
+        assert!(graph.risk_sensitivity(10, 0, 1e-6, 20).is_err());
+        assert!(graph.risk_sensitivity(10, 5, 1e-6, 0).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Point queries and exports of the per-block time series computed at finalize.
+//!
+//! All timestamps are log timestamps in seconds, the time base of `subtree_size_series`.
+
+use std::{collections::BTreeSet, fs::File, io::Write};
+
+use crate::{block::Block, graph::Graph};
+
+impl Block {
+    /// Number of blocks in this block's subtree that had been received at `timestamp`.
+    pub fn subtree_size_at(&self, timestamp: u64) -> Option<u64> {
+        Some(*self.subtree_size_series.as_ref()?.at(timestamp)? as u64)
+    }
+
+    /// Change points of the subtree size, as (timestamp, size).
+    pub fn subtree_size_points(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.subtree_size_series
+            .iter()
+            .flat_map(|series| series.iter())
+            .map(|(ts, size)| (ts, *size as u64))
+    }
+
+    /// Lead of the heaviest child (in the final ordering) over its heaviest sibling at
+    /// `timestamp`, in the subtree weights of the pivot rule. Only pivot blocks carry this series.
+    pub fn subtree_adv_at(&self, timestamp: u64) -> Option<i64> {
+        Some(*self.subtree_adv_series.as_ref()?.at(timestamp)? as i64)
+    }
+
+    /// Change points of `subtree_adv_at`, as (timestamp, lead).
+    pub fn subtree_adv_points(&self) -> impl Iterator<Item = (u64, i64)> + '_ {
+        self.subtree_adv_series
+            .iter()
+            .flat_map(|series| series.iter())
+            .map(|(ts, adv)| (ts, *adv as i64))
+    }
+}
+
+/// One row of `Graph::block_series`: every value is the one in effect at `timestamp`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSeriesPoint {
+    pub timestamp: u64,
+    pub total_blocks: Option<u64>,
+    pub subtree_size: Option<u64>,
+    pub adversary_advantage: Option<i64>,
+}
+
+impl Graph {
+    /// Number of blocks received by `timestamp`, genesis excluded; the `m` side of the
+    /// confirmation risk.
+    pub fn total_blocks_at(&self, timestamp: u64) -> Option<u64> {
+        self.genesis_block().subtree_size_at(timestamp)
+    }
+
+    pub fn total_blocks_points(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.genesis_block().subtree_size_points()
+    }
+
+    /// Advantage of `block` over its siblings at `timestamp`, as used by the confirmation risk.
+    /// Only defined for blocks on the pivot chain.
+    pub fn adversary_advantage_at(&self, block: &Block, timestamp: u64) -> Option<i64> {
+        self.get_parent(block)?.subtree_adv_at(timestamp)
+    }
+
+    pub fn adversary_advantage_points<'a>(
+        &'a self, block: &'a Block,
+    ) -> impl Iterator<Item = (u64, i64)> + 'a {
+        self.get_parent(block)
+            .into_iter()
+            .flat_map(|parent| parent.subtree_adv_points())
+    }
+
+    /// Total blocks, subtree size and adversary advantage of `block` merged on the union of
+    /// their change points, starting when the block was received.
+    pub fn block_series(&self, block: &Block) -> Vec<BlockSeriesPoint> {
+        let timestamps: BTreeSet<u64> = self
+            .total_blocks_points()
+            .map(|(ts, _)| ts)
+            .chain(block.subtree_size_points().map(|(ts, _)| ts))
+            .chain(self.adversary_advantage_points(block).map(|(ts, _)| ts))
+            .filter(|&ts| ts >= block.log_timestamp)
+            .collect();
+
+        timestamps
+            .into_iter()
+            .map(|timestamp| BlockSeriesPoint {
+                timestamp,
+                total_blocks: self.total_blocks_at(timestamp),
+                subtree_size: block.subtree_size_at(timestamp),
+                adversary_advantage: self.adversary_advantage_at(block, timestamp),
+            })
+            .collect()
+    }
+
+    /// CSV with header `timestamp,total_blocks,subtree_size,adversary_advantage`; missing values
+    /// are left empty.
+    pub fn export_block_series_csv(
+        &self, block: &Block, filename: &str,
+    ) -> Result<(), anyhow::Error> {
+        fn cell<T: ToString>(value: Option<T>) -> String {
+            value.map_or(String::new(), |v| v.to_string())
+        }
+
+        let mut file = File::create(filename)?;
+        writeln!(
+            file,
+            "timestamp,total_blocks,subtree_size,adversary_advantage"
+        )?;
+        for point in self.block_series(block) {
+            writeln!(
+                file,
+                "{},{},{},{}",
+                point.timestamp,
+                cell(point.total_blocks),
+                cell(point.subtree_size),
+                cell(point.adversary_advantage)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// JSON object with the block hash and one array per series; missing values are `null`.
+    pub fn export_block_series_json(
+        &self, block: &Block, filename: &str,
+    ) -> Result<(), anyhow::Error> {
+        fn array<T: ToString>(values: impl Iterator<Item = Option<T>>) -> String {
+            let values: Vec<_> = values
+                .map(|v| v.map_or("null".to_string(), |v| v.to_string()))
+                .collect();
+            format!("[{}]", values.join(","))
+        }
+
+        let points = self.block_series(block);
+        let mut file = File::create(filename)?;
+        writeln!(file, "{{")?;
+        writeln!(file, "  \"hash\": \"{:?}\",", block.hash)?;
+        writeln!(
+            file,
+            "  \"timestamp\": {},",
+            array(points.iter().map(|p| Some(p.timestamp)))
+        )?;
+        writeln!(
+            file,
+            "  \"total_blocks\": {},",
+            array(points.iter().map(|p| p.total_blocks))
+        )?;
+        writeln!(
+            file,
+            "  \"subtree_size\": {},",
+            array(points.iter().map(|p| p.subtree_size))
+        )?;
+        writeln!(
+            file,
+            "  \"adversary_advantage\": {}",
+            array(points.iter().map(|p| p.adversary_advantage))
+        )?;
+        writeln!(file, "}}")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,376 @@
+//! Binary snapshot of a finalized graph, so a log is parsed and finalized only once.
+//!
+//! All integers are little-endian. Block ids and lengths are `u32`; `u32::MAX` encodes a missing
+//! id. The layout is:
+//!
+//! ```text
+//! magic "TGPG" | version u32 | block_count u32 | past_set_bitmap_bytes u64 | block*
+//! block: height u64 | hash [u8; 32] | parent id | referees (len, id*) | timestamp u64
+//!        | log_timestamp u64 | tx_count u64 | block_size u64 | author (flag u8, [u8; 20])
+//!        | ghast flags u8 (1 adaptive, 2 heavy) | children (len, id*)
+//!        | epoch_block id | epoch_set (flag u8, len, id*) | past_set_size u64 | subtree_size u64
+//!        | subtree_size_series | subtree_adv_series
+//! series: flag u8 | start_timestamp u32 | len | (offset u16, value u32/i16)*
+//! ```
+//!
+//! Hash lookups and the height/timestamp indices are rebuilt on load.
+
+use anyhow::{bail, ensure};
+use ethereum_types::{Address, H256};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+#[cfg(feature = "native")]
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+};
+
+use crate::{block::Block, graph::Graph, utils::time_series::TimeSeries};
+
+const MAGIC: &[u8; 4] = b"TGPG";
+const VERSION: u32 = 1;
+const NO_ID: u32 = u32::MAX;
+
+impl Graph {
+    #[cfg(feature = "native")]
+    pub fn save(&self, filename: &str) -> Result<(), anyhow::Error> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        self.write_snapshot(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "native")]
+    pub fn load_bin(filename: &str) -> Result<Self, anyhow::Error> {
+        Self::read_snapshot(&mut BufReader::new(File::open(filename)?))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write_snapshot(&mut bytes)
+            .expect("writing to a Vec cannot fail");
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        Self::read_snapshot(&mut bytes)
+    }
+
+    pub fn write_snapshot(&self, w: &mut impl Write) -> Result<(), anyhow::Error> {
+        ensure!(
+            self.blocks.len() < NO_ID as usize,
+            "too many blocks for a snapshot"
+        );
+
+        w.write_all(MAGIC)?;
+        write_u32(w, VERSION)?;
+        write_u32(w, self.blocks.len() as u32)?;
+        write_u64(w, self.past_set_bitmap_bytes as u64)?;
+
+        for block in &self.blocks {
+            write_u64(w, block.height)?;
+            w.write_all(block.hash.as_bytes())?;
+            write_id(w, block.parent)?;
+            write_ids(w, &block.referees)?;
+            write_u64(w, block.timestamp)?;
+            write_u64(w, block.log_timestamp)?;
+            write_u64(w, block.tx_count)?;
+            write_u64(w, block.block_size)?;
+            match &block.author {
+                Some(author) => {
+                    w.write_all(&[1])?;
+                    w.write_all(author.as_bytes())?;
+                }
+                None => w.write_all(&[0])?,
+            }
+            w.write_all(&[block.adaptive as u8 | (block.heavy as u8) << 1])?;
+            write_ids(w, &block.children)?;
+            write_id(w, block.epoch_block)?;
+            match &block.epoch_set {
+                Some(epoch_set) => {
+                    w.write_all(&[1])?;
+                    write_ids(w, epoch_set)?;
+                }
+                None => w.write_all(&[0])?,
+            }
+            write_u64(w, block.past_set_size)?;
+            write_u64(w, block.subtree_size)?;
+            write_series(w, &block.subtree_size_series, |w, v| {
+                w.write_all(&v.to_le_bytes())
+            })?;
+            write_series(w, &block.subtree_adv_series, |w, v| {
+                w.write_all(&v.to_le_bytes())
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn read_snapshot(r: &mut impl Read) -> Result<Self, anyhow::Error> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        ensure!(&magic == MAGIC, "not a graph snapshot");
+        let version = read_u32(r)?;
+        ensure!(version == VERSION, "unsupported snapshot version {version}");
+
+        let block_count = read_u32(r)? as usize;
+        let past_set_bitmap_bytes = read_u64(r)? as usize;
+
+        // The count is untrusted until the blocks are read: a crafted header must not allocate.
+        let mut blocks = Vec::with_capacity(block_count.min(1 << 16));
+        for id in 0..block_count {
+            let height = read_u64(r)?;
+            let mut hash = H256::zero();
+            r.read_exact(hash.as_bytes_mut())?;
+            let parent = read_id(r, block_count)?;
+            let referees = read_ids(r, block_count)?;
+            let timestamp = read_u64(r)?;
+            let log_timestamp = read_u64(r)?;
+            let tx_count = read_u64(r)?;
+            let block_size = read_u64(r)?;
+            let author = match read_u8(r)? {
+                0 => None,
+                _ => Some(Address::from(read_array::<20>(r)?)),
+            };
+            let ghast_flags = read_u8(r)?;
+            let children = read_ids(r, block_count)?;
+            let epoch_block = read_id(r, block_count)?;
+            let epoch_set = match read_u8(r)? {
+                0 => None,
+                _ => Some(read_ids(r, block_count)?),
+            };
+            let past_set_size = read_u64(r)?;
+            let subtree_size = read_u64(r)?;
+            let subtree_size_series = read_series(r, |r| Ok(u32::from_le_bytes(read_array(r)?)))?;
+            let subtree_adv_series = read_series(r, |r| Ok(i16::from_le_bytes(read_array(r)?)))?;
+
+            blocks.push(Block {
+                id,
+                height,
+                hash,
+                parent,
+                referees,
+                timestamp,
+                log_timestamp,
+                tx_count,
+                block_size,
+                author,
+                adaptive: ghast_flags & 1 != 0,
+                heavy: ghast_flags & 2 != 0,
+                children,
+                epoch_block,
+                epoch_set,
+                past_set_size,
+                subtree_size,
+                subtree_size_series,
+                subtree_adv_series,
+            });
+        }
+
+        check_structure(&blocks)?;
+        let id_map: HashMap<H256, usize> = blocks.iter().map(|b| (b.hash, b.id)).collect();
+        if id_map.len() != blocks.len() {
+            bail!("duplicate block hash in snapshot");
+        }
+
+        let mut graph = Graph {
+            blocks,
+            id_map,
+            height_index: Default::default(),
+            timestamp_index: Default::default(),
+            past_set_bitmap_bytes,
+        };
+        graph.build_indices();
+        Ok(graph)
+    }
+}
+
+/// The invariants of a finalized graph that the queries rely on to terminate without panicking:
+/// a genesis without a parent, children that name their parent, and pivot blocks that are their
+/// own epoch block.
+fn check_structure(blocks: &[Block]) -> Result<(), anyhow::Error> {
+    ensure!(!blocks.is_empty(), "snapshot has no genesis block");
+    ensure!(
+        blocks[0].parent.is_none(),
+        "genesis has a parent in snapshot"
+    );
+    for block in blocks {
+        for &child in &block.children {
+            ensure!(
+                blocks[child].parent == Some(block.id),
+                "block {} lists child {child} whose parent is not that block",
+                block.id
+            );
+        }
+    }
+    // Children point back to their parent, so following them from genesis cannot loop.
+    let mut pivot = Some(0);
+    while let Some(id) = pivot {
+        ensure!(
+            blocks[id].epoch_block == Some(id),
+            "pivot block {id} is not its own epoch block"
+        );
+        pivot = blocks[id].max_child();
+    }
+    Ok(())
+}
+
+fn write_u32(w: &mut impl Write, value: u32) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_u64(w: &mut impl Write, value: u64) -> std::io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_id(w: &mut impl Write, id: Option<usize>) -> std::io::Result<()> {
+    write_u32(w, id.map_or(NO_ID, |id| id as u32))
+}
+
+fn write_ids(w: &mut impl Write, ids: &[usize]) -> std::io::Result<()> {
+    write_u32(w, ids.len() as u32)?;
+    ids.iter().try_for_each(|&id| write_u32(w, id as u32))
+}
+
+fn write_series<W: Write, T: Clone>(
+    w: &mut W, series: &Option<TimeSeries<T>>,
+    write_value: impl Fn(&mut W, &T) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let Some(series) = series else {
+        return w.write_all(&[0]);
+    };
+    w.write_all(&[1])?;
+    write_u32(w, series.start_timestamp())?;
+    write_u32(w, series.len() as u32)?;
+    for (offset, value) in series.points() {
+        w.write_all(&offset.to_le_bytes())?;
+        write_value(w, value)?;
+    }
+    Ok(())
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u8(r: &mut impl Read) -> std::io::Result<u8> { Ok(read_array::<1>(r)?[0]) }
+
+fn read_u32(r: &mut impl Read) -> std::io::Result<u32> { Ok(u32::from_le_bytes(read_array(r)?)) }
+
+fn read_u64(r: &mut impl Read) -> std::io::Result<u64> { Ok(u64::from_le_bytes(read_array(r)?)) }
+
+fn read_id(r: &mut impl Read, block_count: usize) -> Result<Option<usize>, anyhow::Error> {
+    match read_u32(r)? {
+        NO_ID => Ok(None),
+        id if (id as usize) < block_count => Ok(Some(id as usize)),
+        id => bail!("block id {id} out of range"),
+    }
+}
+
+fn read_ids(r: &mut impl Read, block_count: usize) -> Result<Vec<usize>, anyhow::Error> {
+    let len = read_u32(r)? as usize;
+    let mut ids = Vec::with_capacity(len.min(block_count));
+    for _ in 0..len {
+        match read_id(r, block_count)? {
+            Some(id) => ids.push(id),
+            None => bail!("missing block id in list"),
+        }
+    }
+    Ok(ids)
+}
+
+fn read_series<R: Read, T: Clone>(
+    r: &mut R, read_value: impl Fn(&mut R) -> std::io::Result<T>,
+) -> Result<Option<TimeSeries<T>>, anyhow::Error> {
+    if read_u8(r)? == 0 {
+        return Ok(None);
+    }
+    let start_timestamp = read_u32(r)?;
+    let len = read_u32(r)? as usize;
+    let mut points = Vec::with_capacity(len.min(1 << 16));
+    for _ in 0..len {
+        let offset = u16::from_le_bytes(read_array(r)?);
+        points.push((offset, read_value(r)?));
+    }
+    Ok(Some(TimeSeries::from_points(start_timestamp, points)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::GenerateParams;
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 120,
+            mining_power: vec![1., 1.],
+            ..Default::default()
+        })
+        .unwrap();
+        let bytes = graph.to_bytes();
+        let loaded = Graph::from_bytes(&bytes).unwrap();
+
+        assert_eq!(loaded.block_count(), graph.block_count());
+        for (a, b) in graph.blocks().zip(loaded.blocks()) {
+            assert_eq!(a.hash, b.hash);
+            assert_eq!(a.parent, b.parent);
+            assert_eq!(a.referees, b.referees);
+            assert_eq!(a.author, b.author);
+            assert_eq!((a.adaptive, a.heavy), (b.adaptive, b.heavy));
+            assert_eq!(a.children, b.children);
+            assert_eq!(a.epoch_set, b.epoch_set);
+            assert_eq!(a.past_set_size, b.past_set_size);
+            assert!(a.subtree_size_series == b.subtree_size_series);
+            assert!(a.subtree_adv_series == b.subtree_adv_series);
+        }
+        assert_eq!(loaded.max_height(), graph.max_height());
+        assert_eq!(
+            loaded.avg_confirm_time(20, 1e-6),
+            graph.avg_confirm_time(20, 1e-6)
+        );
+
+        assert!(Graph::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Graph::from_bytes(b"nope").is_err());
+
+        // A header claiming the largest block count fails on the missing blocks.
+        let mut header = bytes[..8].to_vec();
+        header.extend_from_slice(&(NO_ID - 1).to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        assert!(Graph::from_bytes(&header).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_broken_structure() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 60,
+            mining_power: vec![1., 1.],
+            ..Default::default()
+        })
+        .unwrap();
+        let broken = |edit: &dyn Fn(&mut Graph)| {
+            let mut graph = Graph::from_bytes(&graph.to_bytes()).unwrap();
+            edit(&mut graph);
+            Graph::from_bytes(&graph.to_bytes())
+        };
+        assert!(broken(&|_| {}).is_ok());
+
+        // No genesis: the header alone, with block_count 0.
+        let mut empty = graph.to_bytes()[..8].to_vec();
+        empty.extend_from_slice(&0u32.to_le_bytes());
+        empty.extend_from_slice(&0u64.to_le_bytes());
+        assert!(Graph::from_bytes(&empty).is_err());
+
+        assert!(broken(&|g| g.blocks[0].parent = Some(1)).is_err());
+        // Genesis as its own child would make `pivot_chain` loop forever.
+        assert!(broken(&|g| g.blocks[0].children.insert(0, 0)).is_err());
+        assert!(broken(&|g| {
+            let tip = g.pivot_chain().last().unwrap().id;
+            g.blocks[tip].epoch_block = None;
+        })
+        .is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! Classification of the blocks off the pivot chain.
+//!
+//! A block that loses the pivot race still counts if a pivot block reaches it through referee
+//! edges, which puts it in that pivot block's epoch. A block no epoch includes was mined for
+//! nothing: near the end of a log it may just not be referenced yet, but elsewhere it points at
+//! a block that did not propagate or at miners that fail to reference the tips they see.
+//! Genesis is never classified.
+
+use ethereum_types::H256;
+
+use anyhow::ensure;
+use std::collections::BTreeMap;
+
+use crate::{block::Block, graph::Graph};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum StaleKind {
+    /// In an epoch, with a pivot block as parent.
+    PivotSibling,
+    /// In an epoch, forked off the pivot chain more than one block ago.
+    Referee,
+    /// In no epoch.
+    Unreferenced,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleBlock {
+    pub id: usize,
+    pub hash: H256,
+    pub kind: StaleKind,
+    /// Blocks between this block and the pivot chain along parent edges, itself included: 1 for
+    /// a sibling of a pivot block.
+    pub fork_depth: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StaleCounts {
+    pub pivot: usize,
+    pub pivot_sibling: usize,
+    pub referee: usize,
+    pub unreferenced: usize,
+}
+
+impl StaleCounts {
+    fn add(&mut self, kind: Option<StaleKind>) {
+        match kind {
+            None => self.pivot += 1,
+            Some(StaleKind::PivotSibling) => self.pivot_sibling += 1,
+            Some(StaleKind::Referee) => self.referee += 1,
+            Some(StaleKind::Unreferenced) => self.unreferenced += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.pivot + self.pivot_sibling + self.referee + self.unreferenced
+    }
+
+    pub fn count(&self, kind: StaleKind) -> usize {
+        match kind {
+            StaleKind::PivotSibling => self.pivot_sibling,
+            StaleKind::Referee => self.referee,
+            StaleKind::Unreferenced => self.unreferenced,
+        }
+    }
+
+    /// Fraction of all blocks of this kind, 0 without any block.
+    pub fn rate(&self, kind: StaleKind) -> f64 {
+        self.count(kind) as f64 / self.total().max(1) as f64
+    }
+}
+
+/// Blocks whose `timestamp` is in `[start, end)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleWindow {
+    pub start: u64,
+    pub end: u64,
+    pub counts: StaleCounts,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleReport {
+    pub total: StaleCounts,
+    /// Windows of `window` seconds from the earliest block timestamp, in time order. Windows
+    /// without any block are left out.
+    pub windows: Vec<StaleWindow>,
+}
+
+impl Graph {
+    /// Every block off the pivot chain, by id.
+    pub fn stale_blocks(&self) -> Vec<StaleBlock> {
+        let on_pivot = self.on_pivot_chain();
+        self.blocks()
+            .skip(1)
+            .filter(|block| !on_pivot[block.id])
+            .map(|block| StaleBlock {
+                id: block.id,
+                hash: block.hash,
+                kind: self.stale_kind(block, &on_pivot).unwrap(),
+                fork_depth: self.fork_depth(block, &on_pivot),
+            })
+            .collect()
+    }
+
+    /// Blocks of each kind over the whole graph and per window of `window` seconds.
+    pub fn stale_report(&self, window: u64) -> Result<StaleReport, anyhow::Error> {
+        ensure!(window > 0, "stale window must be positive");
+        let on_pivot = self.on_pivot_chain();
+        let mut total = StaleCounts::default();
+        let mut buckets: BTreeMap<u64, StaleCounts> = BTreeMap::new();
+        let first = self.blocks().skip(1).map(|b| b.timestamp).min();
+        for block in self.blocks().skip(1) {
+            let kind = self.stale_kind(block, &on_pivot);
+            total.add(kind);
+            if let Some(first) = first {
+                buckets
+                    .entry((block.timestamp - first) / window)
+                    .or_default()
+                    .add(kind);
+            }
+        }
+        let windows = buckets
+            .into_iter()
+            .map(|(index, counts)| {
+                let start = first.unwrap() + index * window;
+                StaleWindow {
+                    start,
+                    end: start + window,
+                    counts,
+                }
+            })
+            .collect();
+        Ok(StaleReport { total, windows })
+    }
+
+    fn on_pivot_chain(&self) -> Vec<bool> {
+        let mut on_pivot = vec![false; self.block_count()];
+        for block in self.pivot_chain() {
+            on_pivot[block.id] = true;
+        }
+        on_pivot
+    }
+
+    /// `None` for a pivot block.
+    fn stale_kind(&self, block: &Block, on_pivot: &[bool]) -> Option<StaleKind> {
+        if on_pivot[block.id] {
+            None
+        } else if block.epoch_block.is_none() {
+            Some(StaleKind::Unreferenced)
+        } else if block.parent.is_some_and(|parent| on_pivot[parent]) {
+            Some(StaleKind::PivotSibling)
+        } else {
+            Some(StaleKind::Referee)
+        }
+    }
+
+    fn fork_depth(&self, block: &Block, on_pivot: &[bool]) -> u64 {
+        let mut depth = 0;
+        let mut cursor = Some(block);
+        while let Some(block) = cursor.filter(|b| !on_pivot[b.id]) {
+            depth += 1;
+            cursor = self.get_parent(block);
+        }
+        depth
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::RawBlock,
+        generate::{GenerateParams, RefereePolicy},
+    };
+
+    #[test]
+    fn test_classify_off_pivot_blocks() {
+        let hash = |n: u64| H256::from_low_u64_be(n);
+        // Pivot chain 1 <- 2 <- 3 <- 6. Block 3 references 4, a sibling of 2, and 5 on top of
+        // it; nothing references 7, a sibling of 3.
+        let block = |height, n, parent, referees, timestamp| {
+            RawBlock::new(
+                height,
+                hash(n),
+                hash(parent),
+                referees,
+                timestamp,
+                timestamp,
+                0,
+                0,
+            )
+        };
+        let graph = Graph::from_raw_blocks(vec![
+            RawBlock::genesis_block(hash(1)),
+            block(1, 2, 1, vec![], 10),
+            block(1, 4, 1, vec![], 10),
+            block(2, 5, 4, vec![], 11),
+            block(2, 3, 2, vec![hash(4), hash(5)], 12),
+            block(2, 7, 2, vec![], 20),
+            block(3, 6, 3, vec![], 20),
+        ])
+        .unwrap();
+
+        let stale: Vec<_> = graph
+            .stale_blocks()
+            .into_iter()
+            .map(|b| (b.hash, b.kind, b.fork_depth))
+            .collect();
+        assert_eq!(
+            stale,
+            vec![
+                (hash(4), StaleKind::PivotSibling, 1),
+                (hash(5), StaleKind::Referee, 2),
+                (hash(7), StaleKind::Unreferenced, 1),
+            ]
+        );
+
+        assert!(graph.stale_report(0).is_err());
+        let report = graph.stale_report(10).unwrap();
+        assert_eq!(report.total.total(), 6);
+        assert_eq!(report.total.pivot, 3);
+        assert!((report.total.rate(StaleKind::Unreferenced) - 1. / 6.).abs() < 1e-9);
+        assert_eq!(report.windows.len(), 2);
+        assert_eq!(
+            report.windows[0].counts,
+            StaleCounts {
+                pivot: 2,
+                pivot_sibling: 1,
+                referee: 1,
+                unreferenced: 0,
+            }
+        );
+        assert_eq!(report.windows[1].counts.unreferenced, 1);
+        // Only the seconds 10, 11, 12 and 20 hold blocks.
+        let starts: Vec<u64> = graph
+            .stale_report(1)
+            .unwrap()
+            .windows
+            .iter()
+            .map(|w| w.start)
+            .collect();
+        assert_eq!(starts, vec![10, 11, 12, 20]);
+    }
+
+    #[test]
+    fn test_nothing_referenced_without_referees() {
+        let graph = Graph::generate(&GenerateParams {
+            referee_policy: RefereePolicy::NoReferees,
+            duration: 120,
+            ..Default::default()
+        })
+        .unwrap();
+        let stale = graph.stale_blocks();
+        assert!(!stale.is_empty());
+        assert!(stale.iter().all(|b| b.kind == StaleKind::Unreferenced));
+        assert_eq!(stale.len() + graph.pivot_chain().len(), graph.block_count());
+    }
+}
@@ -85,6 +85,53 @@ impl Bitmap {
         }
     }
 
+    /// 与另一个 Bitmap 按位与，只保留两者都为 1 的位（交集）
+    pub fn intersect(&mut self, other: &Bitmap) {
+        // 超出 other 长度的部分一定为 0，直接截断
+        self.inner.truncate(other.inner.len());
+
+        for (me, &input) in self.inner.iter_mut().zip(other.inner.iter()) {
+            *me &= input;
+        }
+    }
+
+    /// 从当前 Bitmap 中去掉 other 中为 1 的位（差集）
+    pub fn subtract(&mut self, other: &Bitmap) {
+        for (me, &input) in self.inner.iter_mut().zip(other.inner.iter()) {
+            *me &= !input;
+        }
+    }
+
+    /// 判断当前 Bitmap 是否为 other 的子集
+    pub fn is_subset_of(&self, other: &Bitmap) -> bool {
+        self.inner.iter().enumerate().all(|(idx, &byte)| {
+            let other_byte = other.inner.get(idx).copied().unwrap_or(0);
+            byte & !other_byte == 0
+        })
+    }
+
+    /// 统计两个 Bitmap 交集中为 1 的位数量，不产生中间 Bitmap
+    pub fn intersection_count(&self, other: &Bitmap) -> usize {
+        self.inner
+            .iter()
+            .zip(other.inner.iter())
+            .map(|(&a, &b)| (a & b).count_ones() as usize)
+            .sum()
+    }
+
+    /// 按从小到大的顺序遍历所有为 1 的位的下标
+    pub fn iter_ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter(|(_, &byte)| byte != 0)
+            .flat_map(|(byte_index, &byte)| {
+                (0..8)
+                    .filter(move |bit_offset| byte & (1 << bit_offset) != 0)
+                    .map(move |bit_offset| byte_index * 8 + bit_offset)
+            })
+    }
+
     /// 获取 Bitmap 可存储的位数量
     pub fn capacity(&self) -> usize { self.inner.len() * 8 }
 
@@ -102,3 +149,43 @@ impl Bitmap {
 impl Default for Bitmap {
     fn default() -> Self { Self::new() }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_bits(bits: &[usize]) -> Bitmap {
+        let mut bitmap = Bitmap::new();
+        for &bit in bits {
+            bitmap.set(bit);
+        }
+        bitmap
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let a = from_bits(&[1, 3, 9, 20]);
+        let b = from_bits(&[3, 9, 40]);
+
+        let mut inter = a.clone();
+        inter.intersect(&b);
+        assert_eq!(inter.iter_ones().collect::<Vec<_>>(), vec![3, 9]);
+        assert_eq!(a.intersection_count(&b), 2);
+
+        let mut diff = a.clone();
+        diff.subtract(&b);
+        assert_eq!(diff.iter_ones().collect::<Vec<_>>(), vec![1, 20]);
+
+        assert!(inter.is_subset_of(&a));
+        assert!(inter.is_subset_of(&b));
+        assert!(!a.is_subset_of(&b));
+        assert!(Bitmap::new().is_subset_of(&b));
+    }
+
+    #[test]
+    fn test_iter_ones() {
+        let bits = vec![0, 7, 8, 15, 64, 65];
+        assert_eq!(from_bits(&bits).iter_ones().collect::<Vec<_>>(), bits);
+        assert_eq!(Bitmap::with_capacity(100).iter_ones().count(), 0);
+    }
+}
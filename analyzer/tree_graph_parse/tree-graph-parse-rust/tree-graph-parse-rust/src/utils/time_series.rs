@@ -50,9 +50,28 @@ impl<T: Clone> TimeSeries<T> {
         }
     }
 
+    /// Rebuild a TimeSeries from `start_timestamp` and `points`
+    pub fn from_points(start_timestamp: u32, series: Vec<(u16, T)>) -> Self {
+        TimeSeries {
+            start_timestamp,
+            series,
+        }
+    }
+
     /// Get the start timestamp
     pub fn start_timestamp(&self) -> u32 { self.start_timestamp }
 
+    /// Stored points as (offset from the start timestamp, value)
+    pub fn points(&self) -> &[(u16, T)] { &self.series }
+
+    /// Number of stored points
+    pub fn len(&self) -> usize { self.series.len() }
+
+    pub fn is_empty(&self) -> bool { self.series.is_empty() }
+
+    /// Bytes allocated for the points
+    pub fn heap_bytes(&self) -> usize { self.series.capacity() * std::mem::size_of::<(u16, T)>() }
+
     /// Get the series data
     pub fn iter(&self) -> impl Iterator<Item = (u64, &T)> {
         self.series
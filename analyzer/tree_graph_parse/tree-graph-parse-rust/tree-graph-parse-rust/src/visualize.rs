@@ -0,0 +1,327 @@
+//! Node/edge exports for DAG visualizers: JSON, Graphviz DOT and GraphML.
+//!
+//! The JSON file is a single object `{"nodes": [...], "edges": [...]}`. Nodes and edges are flat
+//! objects keyed by the block hash, so d3 can use them as `nodes`/`links` directly and cytoscape
+//! after wrapping each one in `{"data": ...}`:
+//!
+//! ```text
+//! {"id": "0x..", "height": 12, "timestamp": .., "log_timestamp": .., "tx_count": ..,
+//!  "block_size": .., "subtree_size": .., "past_set_size": .., "pivot": true,
+//!  "epoch": "0x.." | null, "epoch_height": 12 | null}
+//! {"id": "0x..->0x..", "source": "0x..", "target": "0x..", "kind": "parent" | "referee"}
+//! ```
+//!
+//! DOT and GraphML carry the same node attributes (GraphML) or a height label with pivot blocks
+//! highlighted (DOT). In all formats edges point from a block to its parent or referee.
+
+use std::{collections::HashSet, fs::File, io::Write, ops::RangeInclusive};
+
+use crate::{block::Block, graph::Graph};
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Only blocks in this height range, and the edges between them.
+    pub height_range: Option<RangeInclusive<u64>>,
+    pub include_referees: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            height_range: None,
+            include_referees: true,
+        }
+    }
+}
+
+/// Blocks and edges picked by `ExportOptions`.
+struct Selection<'a> {
+    blocks: Vec<&'a Block>,
+    /// (source id, target id, kind)
+    edges: Vec<(usize, usize, &'static str)>,
+    pivot: HashSet<usize>,
+}
+
+impl Graph {
+    fn select(&self, options: &ExportOptions) -> Selection<'_> {
+        let blocks: Vec<&Block> = match &options.height_range {
+            Some(range) => self.blocks_in_height_range(range.clone()).collect(),
+            None => self.blocks().collect(),
+        };
+        let included: HashSet<usize> = blocks.iter().map(|b| b.id).collect();
+
+        let mut edges = vec![];
+        for block in &blocks {
+            if let Some(parent) = block.parent.filter(|id| included.contains(id)) {
+                edges.push((block.id, parent, "parent"));
+            }
+            if options.include_referees {
+                for &referee in block.referees.iter().filter(|id| included.contains(id)) {
+                    edges.push((block.id, referee, "referee"));
+                }
+            }
+        }
+
+        Selection {
+            blocks,
+            edges,
+            pivot: self.pivot_chain().into_iter().map(|b| b.id).collect(),
+        }
+    }
+
+    pub fn export_json(
+        &self, filename: &str, options: &ExportOptions,
+    ) -> Result<(), anyhow::Error> {
+        let Selection {
+            blocks,
+            edges,
+            pivot,
+        } = self.select(options);
+
+        let mut file = File::create(filename)?;
+        writeln!(file, "{{")?;
+
+        writeln!(file, "  \"nodes\": [")?;
+        for (idx, block) in blocks.iter().enumerate() {
+            let epoch_block = block.epoch_block.map(|id| self.block(id));
+            writeln!(
+                file,
+                "    {{\"id\": \"{:?}\", \"height\": {}, \"timestamp\": {}, \"log_timestamp\": {}, \
+                 \"tx_count\": {}, \"block_size\": {}, \"subtree_size\": {}, \
+                 \"past_set_size\": {}, \"pivot\": {}, \"epoch\": {}, \"epoch_height\": {}}}{}",
+                block.hash,
+                block.height,
+                block.timestamp,
+                block.log_timestamp,
+                block.tx_count,
+                block.block_size,
+                block.subtree_size,
+                block.past_set_size,
+                pivot.contains(&block.id),
+                epoch_block.map_or("null".to_string(), |b| format!("\"{:?}\"", b.hash)),
+                epoch_block.map_or("null".to_string(), |b| b.height.to_string()),
+                separator(idx, blocks.len()),
+            )?;
+        }
+        writeln!(file, "  ],")?;
+
+        writeln!(file, "  \"edges\": [")?;
+        for (idx, &(source, target, kind)) in edges.iter().enumerate() {
+            let (source, target) = (self.hash_of(source), self.hash_of(target));
+            writeln!(
+                file,
+                "    {{\"id\": \"{source:?}->{target:?}\", \"source\": \"{source:?}\", \
+                 \"target\": \"{target:?}\", \"kind\": \"{kind}\"}}{}",
+                separator(idx, edges.len()),
+            )?;
+        }
+        writeln!(file, "  ]")?;
+
+        writeln!(file, "}}")?;
+        Ok(())
+    }
+
+    /// Graphviz digraph; pivot blocks are filled, referee edges dashed.
+    pub fn export_dot(&self, filename: &str, options: &ExportOptions) -> Result<(), anyhow::Error> {
+        let Selection {
+            blocks,
+            edges,
+            pivot,
+        } = self.select(options);
+
+        let mut file = File::create(filename)?;
+        writeln!(file, "digraph tree_graph {{")?;
+        writeln!(file, "  rankdir=RL;")?;
+        writeln!(file, "  node [shape=box, fontsize=10];")?;
+        for block in &blocks {
+            let style = if pivot.contains(&block.id) {
+                ", style=filled, fillcolor=lightblue"
+            } else {
+                ""
+            };
+            writeln!(
+                file,
+                "  \"{:?}\" [label=\"{}\\n{}\"{}];",
+                block.hash,
+                block.height,
+                short_hash(block),
+                style
+            )?;
+        }
+        for &(source, target, kind) in &edges {
+            let style = if kind == "referee" {
+                " [style=dashed]"
+            } else {
+                ""
+            };
+            writeln!(
+                file,
+                "  \"{:?}\" -> \"{:?}\"{};",
+                self.hash_of(source),
+                self.hash_of(target),
+                style
+            )?;
+        }
+        writeln!(file, "}}")?;
+        Ok(())
+    }
+
+    /// GraphML with the node attributes of the JSON export and an edge `kind`.
+    pub fn export_graphml(
+        &self, filename: &str, options: &ExportOptions,
+    ) -> Result<(), anyhow::Error> {
+        const NODE_KEYS: [(&str, &str); 6] = [
+            ("height", "long"),
+            ("timestamp", "long"),
+            ("tx_count", "long"),
+            ("subtree_size", "long"),
+            ("pivot", "boolean"),
+            ("epoch_height", "long"),
+        ];
+
+        let Selection {
+            blocks,
+            edges,
+            pivot,
+        } = self.select(options);
+
+        let mut file = File::create(filename)?;
+        writeln!(file, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            file,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        for (name, ty) in NODE_KEYS {
+            writeln!(
+                file,
+                r#"  <key id="{name}" for="node" attr.name="{name}" attr.type="{ty}"/>"#
+            )?;
+        }
+        writeln!(
+            file,
+            r#"  <key id="kind" for="edge" attr.name="kind" attr.type="string"/>"#
+        )?;
+        writeln!(file, r#"  <graph id="tree_graph" edgedefault="directed">"#)?;
+
+        for block in &blocks {
+            let epoch_height = block
+                .epoch_block
+                .map_or(-1, |id| self.block(id).height as i64);
+            let values = [
+                block.height.to_string(),
+                block.timestamp.to_string(),
+                block.tx_count.to_string(),
+                block.subtree_size.to_string(),
+                pivot.contains(&block.id).to_string(),
+                epoch_height.to_string(),
+            ];
+            writeln!(file, r#"    <node id="{:?}">"#, block.hash)?;
+            for ((name, _), value) in NODE_KEYS.iter().zip(values) {
+                writeln!(file, r#"      <data key="{name}">{value}</data>"#)?;
+            }
+            writeln!(file, "    </node>")?;
+        }
+        for &(source, target, kind) in &edges {
+            writeln!(
+                file,
+                r#"    <edge source="{:?}" target="{:?}"><data key="kind">{kind}</data></edge>"#,
+                self.hash_of(source),
+                self.hash_of(target)
+            )?;
+        }
+
+        writeln!(file, "  </graph>")?;
+        writeln!(file, "</graphml>")?;
+        Ok(())
+    }
+}
+
+/// First 4 bytes of the hash, for labels.
+fn short_hash(block: &Block) -> String { format!("{:?}", block.hash)[..10].to_string() }
+
+fn separator(idx: usize, len: usize) -> &'static str {
+    if idx + 1 < len {
+        ","
+    } else {
+        ""
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::GenerateParams;
+
+    fn export(graph: &Graph, options: &ExportOptions) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "tg_export_json_{}_{:?}.json",
+            std::process::id(),
+            options.height_range
+        ));
+        graph.export_json(path.to_str().unwrap(), options).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_export_json_counts() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 120,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let content = export(&graph, &Default::default());
+        let nodes = content.matches("\"height\":").count();
+        let parent_edges = content.matches("\"kind\": \"parent\"").count();
+        let referee_edges = content.matches("\"kind\": \"referee\"").count();
+        let pivot_nodes = content.matches("\"pivot\": true").count();
+        assert_eq!(nodes, graph.block_count());
+        assert_eq!(parent_edges, graph.block_count() - 1);
+        assert_eq!(
+            referee_edges,
+            graph.blocks().map(|b| b.referees.len()).sum::<usize>()
+        );
+        assert_eq!(pivot_nodes, graph.pivot_chain().len());
+
+        let options = ExportOptions {
+            height_range: Some(5..=10),
+            include_referees: false,
+        };
+        let content = export(&graph, &options);
+        let in_range = graph.blocks_in_height_range(5..=10).count();
+        assert_eq!(content.matches("\"height\":").count(), in_range);
+        assert_eq!(
+            content.matches("\"kind\": \"parent\"").count(),
+            graph.blocks_in_height_range(6..=10).count()
+        );
+        assert_eq!(content.matches("\"kind\": \"referee\"").count(), 0);
+    }
+
+    #[test]
+    fn test_export_dot_and_graphml_counts() {
+        let graph = Graph::generate(&GenerateParams {
+            duration: 60,
+            ..Default::default()
+        })
+        .unwrap();
+        let options = ExportOptions::default();
+        let edge_count =
+            graph.block_count() - 1 + graph.blocks().map(|b| b.referees.len()).sum::<usize>();
+
+        let path = std::env::temp_dir().join(format!("tg_export_{}.dot", std::process::id()));
+        let path = path.to_str().unwrap();
+        graph.export_dot(path, &options).unwrap();
+        let dot = std::fs::read_to_string(path).unwrap();
+        assert_eq!(dot.matches("[label=").count(), graph.block_count());
+        assert_eq!(dot.matches(" -> ").count(), edge_count);
+        assert_eq!(dot.matches("fillcolor").count(), graph.pivot_chain().len());
+
+        graph.export_graphml(path, &options).unwrap();
+        let graphml = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(graphml.matches("<node ").count(), graph.block_count());
+        assert_eq!(graphml.matches("<edge ").count(), edge_count);
+    }
+}
@@ -0,0 +1,247 @@
+//! Browser bindings for the confirmation risk math and the DAG core.
+//!
+//! Build with `wasm-pack build --target web tree-graph-parse-wasm`. The core crate is compiled
+//! without its `native` feature: logs are passed in as text instead of being read from disk, and
+//! there is no math disk cache or thread pool. Errors are thrown as JS strings.
+
+use ethereum_types::H256;
+use std::str::FromStr;
+use tree_graph_parse_rust::{block::Block, graph::Graph, math};
+use wasm_bindgen::prelude::*;
+
+/// Probability that a block is reverted when the adversary owns `adv_percent`% of the mining
+/// power, `m` honest blocks were produced since its parent and it leads its heaviest sibling by
+/// `adv` blocks.
+#[wasm_bindgen(js_name = normalConfirmationRisk)]
+pub fn normal_confirmation_risk(adv_percent: u32, m: u32, adv: u32) -> Result<f32, String> {
+    check_adv_percent(adv_percent)?;
+    Ok(math::normal_confirmation_risk(
+        adv_percent as usize,
+        m as usize,
+        adv as usize,
+    ))
+}
+
+/// `normalConfirmationRisk` for every `m` in `[m_start, m_end)` with a fixed `adv`.
+#[wasm_bindgen(js_name = confirmationRiskCurve)]
+pub fn confirmation_risk_curve(
+    adv_percent: u32, adv: u32, m_start: u32, m_end: u32,
+) -> Result<Vec<f32>, String> {
+    check_adv_percent(adv_percent)?;
+    Ok(math::normal_confirmation_risk_batch(
+        adv_percent as usize,
+        m_start as usize..m_end as usize,
+        adv as usize,
+    ))
+}
+
+/// Confirmation of a pivot block, see `Graph::confirmation_risk`.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct Confirmation {
+    /// Seconds after the block timestamp.
+    pub time_offset: f64,
+    pub m: f64,
+    pub k: f64,
+    pub risk: f64,
+}
+
+/// Confirmation time statistics under one risk threshold, see `Graph::confirm_time_vs_risk`.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct ConfirmTime {
+    pub risk_threshold: f64,
+    pub avg: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub block_cnt: f64,
+}
+
+/// A finalized graph parsed from log text. Meant for small graphs: everything runs on the
+/// calling thread.
+#[wasm_bindgen]
+pub struct DagGraph(Graph);
+
+#[wasm_bindgen]
+impl DagGraph {
+    /// Parses the content of a `*.log.new_blocks` file; a full conflux log works as well.
+    #[wasm_bindgen(js_name = fromLog)]
+    pub fn from_log(log: &str) -> Result<DagGraph, String> {
+        Graph::from_log(log.as_bytes())
+            .map(DagGraph)
+            .map_err(|e| format!("{:#}", e))
+    }
+
+    #[wasm_bindgen(getter, js_name = blockCount)]
+    pub fn block_count(&self) -> usize { self.0.block_count() }
+
+    #[wasm_bindgen(getter, js_name = maxHeight)]
+    pub fn max_height(&self) -> f64 { self.0.max_height() as f64 }
+
+    /// Hashes of the pivot blocks, genesis first.
+    #[wasm_bindgen(js_name = pivotChain)]
+    pub fn pivot_chain(&self) -> Vec<String> {
+        self.0
+            .pivot_chain()
+            .iter()
+            .map(|block| format!("{:?}", block.hash))
+            .collect()
+    }
+
+    /// `undefined` if the risk never drops below `risk_threshold`.
+    #[wasm_bindgen(js_name = confirmationRisk)]
+    pub fn confirmation_risk(
+        &self, hash: &str, adv_percent: u32, risk_threshold: f64,
+    ) -> Result<Option<Confirmation>, String> {
+        check_adv_percent(adv_percent)?;
+        check_risk_threshold(risk_threshold)?;
+        let block = self.pivot_block(hash)?;
+        Ok(self
+            .0
+            .confirmation_risk(block, adv_percent as usize, risk_threshold)
+            .map(|(time_offset, m, k, risk)| Confirmation {
+                time_offset: time_offset as f64,
+                m: m as f64,
+                k: k as f64,
+                risk,
+            }))
+    }
+
+    /// Risk curve of a pivot block as `[offset, risk, offset, risk, ...]`, offsets in seconds
+    /// after the block timestamp.
+    #[wasm_bindgen(js_name = confirmationRiskSeries)]
+    pub fn confirmation_risk_series(
+        &self, hash: &str, adv_percent: u32,
+    ) -> Result<Vec<f64>, String> {
+        check_adv_percent(adv_percent)?;
+        let block = self.pivot_block(hash)?;
+        Ok(self
+            .0
+            .confirmation_risk_series(block, adv_percent as usize)
+            .into_iter()
+            .flat_map(|(offset, risk)| [offset as f64, risk as f64])
+            .collect())
+    }
+
+    /// Average confirmation time over the pivot chain, see `Graph::avg_confirm_time`.
+    #[wasm_bindgen(js_name = avgConfirmTime)]
+    pub fn avg_confirm_time(&self, adv_percent: u32, risk_threshold: f64) -> Result<f64, String> {
+        check_adv_percent(adv_percent)?;
+        check_risk_threshold(risk_threshold)?;
+        Ok(self
+            .0
+            .avg_confirm_time(adv_percent as usize, risk_threshold)
+            .0)
+    }
+
+    /// One entry per threshold in `risks`, in the same order.
+    #[wasm_bindgen(js_name = confirmTimeVsRisk)]
+    pub fn confirm_time_vs_risk(
+        &self, adv_percent: u32, risks: Vec<f64>,
+    ) -> Result<Vec<ConfirmTime>, String> {
+        check_adv_percent(adv_percent)?;
+        risks
+            .iter()
+            .try_for_each(|&risk| check_risk_threshold(risk))?;
+        Ok(self
+            .0
+            .confirm_time_vs_risk(adv_percent as usize, &risks)
+            .into_iter()
+            .map(|stats| ConfirmTime {
+                risk_threshold: stats.risk_threshold,
+                avg: stats.avg,
+                p50: stats.p50 as f64,
+                p90: stats.p90 as f64,
+                p99: stats.p99 as f64,
+                block_cnt: stats.block_cnt as f64,
+            })
+            .collect())
+    }
+
+    fn pivot_block(&self, hash: &str) -> Result<&Block, String> {
+        let hash = H256::from_str(hash.trim_start_matches("0x"))
+            .map_err(|e| format!("invalid block hash {hash}: {e}"))?;
+        let block = self
+            .0
+            .get_block(&hash)
+            .ok_or_else(|| format!("block {:?} not found", hash))?;
+        if block.height == 0 || block.epoch_block != Some(block.id) {
+            return Err("confirmation risk is only defined on the pivot chain".into());
+        }
+        Ok(block)
+    }
+}
+
+fn check_adv_percent(adv_percent: u32) -> Result<(), String> {
+    let range = math::risk::ADV_PERCENT_RANGE;
+    if !range.contains(&(adv_percent as usize)) {
+        return Err(format!(
+            "adv_percent must be in {}..={}, got {adv_percent}",
+            range.start(),
+            range.end()
+        ));
+    }
+    Ok(())
+}
+
+fn check_risk_threshold(risk_threshold: f64) -> Result<(), String> {
+    if !(risk_threshold > 0. && risk_threshold < 1.) {
+        return Err("risk_threshold must be in (0, 1)".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tree_graph_parse_rust::block::RawBlock;
+
+    fn log(blocks: u64) -> String {
+        let hash = H256::from_low_u64_be;
+        (1..=blocks)
+            .map(|i| {
+                let timestamp = 1735689600 + i;
+                let block =
+                    RawBlock::new(i, hash(i + 1), hash(i), vec![], timestamp, timestamp, 0, 0);
+                block.to_log_line() + "\n"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_graph_from_log_text() {
+        let graph = DagGraph::from_log(&log(200)).unwrap();
+        assert_eq!(graph.block_count(), 201);
+
+        let pivot_chain = graph.pivot_chain();
+        assert_eq!(pivot_chain.len(), 201);
+        let confirmation = graph
+            .confirmation_risk(&pivot_chain[10], 20, 1e-6)
+            .unwrap()
+            .unwrap();
+        assert!(confirmation.risk < 1e-6);
+        let series = graph
+            .confirmation_risk_series(&pivot_chain[10], 20)
+            .unwrap();
+        assert_eq!(series.len() % 2, 0);
+
+        let stats = graph.confirm_time_vs_risk(20, vec![1e-6]).unwrap();
+        let avg = graph.avg_confirm_time(20, 1e-6).unwrap();
+        assert!((stats[0].avg - avg).abs() < 1e-9);
+
+        assert!(graph.confirmation_risk(&pivot_chain[0], 20, 1e-6).is_err());
+        assert!(graph.avg_confirm_time(60, 1e-6).is_err());
+        assert!(graph.avg_confirm_time(0, 1e-6).is_err());
+        assert!(DagGraph::from_log("").is_err());
+    }
+
+    #[test]
+    fn test_risk_curve_matches_pointwise() {
+        let curve = confirmation_risk_curve(20, 15, 50, 60).unwrap();
+        for (m, risk) in (50..60).zip(curve) {
+            let expected = normal_confirmation_risk(20, m, 15).unwrap();
+            assert!((risk - expected).abs() <= expected * 1e-4);
+        }
+    }
+}